@@ -0,0 +1,117 @@
+use std::io;
+use std::io::prelude::*;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncBufRead, AsyncRead, AsyncWrite};
+
+use super::{cvt, AllowStd};
+
+/// Marks an `AllowStd` wrapping a `futures-io` stream.
+#[derive(Debug)]
+pub(crate) enum Futures {}
+
+type Io<T> = AllowStd<T, Futures>;
+
+impl<T: AsyncRead + Unpin> Read for Io<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (inner, cx) = self.parts()?;
+        cvt(Pin::new(inner).poll_read(cx, buf))
+    }
+}
+
+impl<T: AsyncBufRead + Unpin> BufRead for Io<T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let (inner, cx) = self.parts()?;
+        cvt(Pin::new(inner).poll_fill_buf(cx))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        Pin::new(self.get_mut()).consume(amt)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Write for Io<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (inner, cx) = self.parts()?;
+        cvt(Pin::new(inner).poll_write(cx, buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let (inner, cx) = self.parts()?;
+        cvt(Pin::new(inner).poll_flush(cx))
+    }
+}
+
+macro_rules! impl_read {
+    ($name:ident, $($bound:tt)+) => {
+        impl<R: $($bound)+> AsyncRead for $name<R> {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<io::Result<usize>> {
+                self.get_mut().poll_sync_read(cx, buf)
+            }
+        }
+    };
+}
+
+macro_rules! impl_write {
+    ($name:ident, $($bound:tt)+) => {
+        impl<W: $($bound)+> AsyncWrite for $name<W> {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                self.get_mut().poll_sync_write(cx, buf)
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                self.get_mut().poll_sync_flush(cx)
+            }
+
+            fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                let this = self.get_mut();
+                match this.poll_sync_finish(cx) {
+                    Poll::Ready(Ok(())) => Pin::new(this.get_mut()).poll_close(cx),
+                    other => other,
+                }
+            }
+        }
+    };
+}
+
+/// Encoders and decoders which read from an [`AsyncRead`] stream.
+///
+/// [`AsyncRead`]: https://docs.rs/futures-io/0.3/futures_io/trait.AsyncRead.html
+pub mod read {
+    use super::*;
+    use crate::read as sync;
+
+    readers!(sync, impl_read: AsyncRead + Unpin);
+}
+
+/// Encoders and decoders which read from an [`AsyncBufRead`] stream.
+///
+/// [`AsyncBufRead`]: https://docs.rs/futures-io/0.3/futures_io/trait.AsyncBufRead.html
+pub mod bufread {
+    use super::*;
+    use crate::bufread as sync;
+
+    readers!(sync, impl_read: AsyncBufRead + Unpin);
+}
+
+/// Encoders and decoders which write to an [`AsyncWrite`] stream.
+///
+/// Streams are finished, writing out any trailer, once they are closed.
+/// Dropping a stream never blocks and leaves it unfinished.
+///
+/// [`AsyncWrite`]: https://docs.rs/futures-io/0.3/futures_io/trait.AsyncWrite.html
+pub mod write {
+    use super::*;
+    use crate::write as sync;
+
+    writers!(sync, impl_write: AsyncWrite + Unpin);
+}
@@ -0,0 +1,303 @@
+//! Support for `async` streams, built on top of the synchronous types.
+//!
+//! All of the synchronous encoders and decoders are able to resume after their
+//! underlying stream returns `WouldBlock`. The types here wrap their stream in
+//! an `AllowStd`, which exposes an asynchronous stream through the `std::io`
+//! traits for the duration of a single `poll_*` call, translating `Pending`
+//! into `WouldBlock` and back.
+
+use std::fmt;
+use std::io;
+use std::marker;
+use std::ptr;
+use std::task::{Context, Poll};
+
+/// An asynchronous stream of type `T` from runtime `K`, exposed as a
+/// synchronous one.
+pub(crate) struct AllowStd<T, K> {
+    inner: T,
+    cx: *mut (),
+    _runtime: marker::PhantomData<K>,
+}
+
+// The context pointer is only set for the duration of a `poll_*` call, which
+// has exclusive access to the stream.
+unsafe impl<T: Send, K> Send for AllowStd<T, K> {}
+unsafe impl<T: Sync, K> Sync for AllowStd<T, K> {}
+
+impl<T, K> AllowStd<T, K> {
+    pub(crate) fn new(inner: T) -> AllowStd<T, K> {
+        AllowStd {
+            inner,
+            cx: ptr::null_mut(),
+            _runtime: marker::PhantomData,
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Returns the asynchronous stream along with the context of the current
+    /// `poll_*` call.
+    ///
+    /// Outside of `poll` every operation would block, so that for example
+    /// dropping an encoder never blocks to write out its trailer.
+    pub(crate) fn parts(&mut self) -> io::Result<(&mut T, &mut Context<'_>)> {
+        if self.cx.is_null() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let cx = unsafe { &mut *(self.cx as *mut Context<'_>) };
+        Ok((&mut self.inner, cx))
+    }
+}
+
+/// Translates a pending operation into `WouldBlock`.
+pub(crate) fn cvt<U>(poll: Poll<io::Result<U>>) -> io::Result<U> {
+    match poll {
+        Poll::Ready(r) => r,
+        Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+    }
+}
+
+impl<T: fmt::Debug, K> fmt::Debug for AllowStd<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllowStd")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// Runs the synchronous operation `f` on the stream `s` with the context of
+/// the current `poll_*` call, where `io` gives access to the `AllowStd` at the
+/// bottom of `s`.
+pub(crate) fn poll<S, T, K, U, F>(
+    cx: &mut Context<'_>,
+    s: &mut S,
+    io: fn(&mut S) -> &mut AllowStd<T, K>,
+    f: F,
+) -> Poll<io::Result<U>>
+where
+    F: FnOnce(&mut S) -> io::Result<U>,
+{
+    struct Reset<'a, S, T, K> {
+        s: &'a mut S,
+        io: fn(&mut S) -> &mut AllowStd<T, K>,
+    }
+
+    impl<'a, S, T, K> Drop for Reset<'a, S, T, K> {
+        fn drop(&mut self) {
+            (self.io)(self.s).cx = ptr::null_mut();
+        }
+    }
+
+    io(s).cx = cx as *mut Context<'_> as *mut ();
+    let reset = Reset { s, io };
+    match f(reset.s) {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
+        r => Poll::Ready(r),
+    }
+}
+
+/// Declares asynchronous versions of the synchronous readers in `sync`,
+/// implementing the asynchronous traits with the `impls` macro.
+///
+/// Types followed by `set_limits` forward that method to the synchronous type.
+macro_rules! readers {
+    ($sync:ident, $impls:ident: $($bound:tt)+) => {
+        readers! {
+            @types [$sync $impls] [$($bound)+]
+
+            /// A DEFLATE decoder, see the synchronous type of the same name.
+            DeflateDecoder() set_limits;
+            /// A DEFLATE encoder, see the synchronous type of the same name.
+            DeflateEncoder(level: crate::Compression);
+            /// A gzip decoder, see the synchronous type of the same name.
+            GzDecoder() set_limits;
+            /// A gzip encoder, see the synchronous type of the same name.
+            GzEncoder(level: crate::Compression);
+            /// A multi-member gzip decoder, see the synchronous type of the
+            /// same name.
+            MultiGzDecoder() set_limits;
+            /// A ZLIB decoder, see the synchronous type of the same name.
+            ZlibDecoder() set_limits;
+            /// A ZLIB encoder, see the synchronous type of the same name.
+            ZlibEncoder(level: crate::Compression);
+            /// A format-detecting decoder, see the synchronous type of the
+            /// same name.
+            AutoDecoder() set_limits;
+        }
+    };
+
+    (@types [$sync:ident $impls:ident] [$($bound:tt)+]) => {};
+
+    (@types [$sync:ident $impls:ident] [$($bound:tt)+]
+     $(#[$attr:meta])* $name:ident($($arg:ident: $ty:ty),*) $($extra:ident)*; $($rest:tt)*) => {
+        $(#[$attr])*
+        #[derive(Debug)]
+        pub struct $name<R> {
+            inner: $sync::$name<Io<R>>,
+        }
+
+        impl<R: $($bound)+> $name<R> {
+            /// Creates a new instance around the given stream.
+            pub fn new(r: R, $($arg: $ty),*) -> $name<R> {
+                $name {
+                    inner: $sync::$name::new(AllowStd::new(r), $($arg),*),
+                }
+            }
+        }
+
+        impl<R> $name<R> {
+            /// Acquires a reference to the underlying stream.
+            pub fn get_ref(&self) -> &R {
+                self.inner.get_ref().get_ref()
+            }
+
+            /// Acquires a mutable reference to the underlying stream.
+            ///
+            /// Note that mutation of the stream may result in surprising
+            /// results if this object is continued to be used.
+            pub fn get_mut(&mut self) -> &mut R {
+                self.inner.get_mut().get_mut()
+            }
+
+            /// Consumes this object, returning the underlying stream.
+            ///
+            /// Note that there may be buffered bytes which are not re-acquired
+            /// as part of this transition.
+            pub fn into_inner(self) -> R {
+                self.inner.into_inner().into_inner()
+            }
+
+            $(
+                /// Sets limits on the data produced by this decoder, see
+                /// [`DecompressLimits`].
+                ///
+                /// [`DecompressLimits`]: ../../struct.DecompressLimits.html
+                pub fn $extra(&mut self, limits: crate::DecompressLimits) {
+                    self.inner.$extra(limits);
+                }
+            )*
+        }
+
+        impl<R: $($bound)+> $name<R> {
+            fn poll_sync_read(
+                &mut self,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<io::Result<usize>> {
+                crate::async_io::poll(cx, &mut self.inner, |s| s.get_mut(), |s| s.read(buf))
+            }
+        }
+
+        $impls!($name, $($bound)+);
+
+        readers! { @types [$sync $impls] [$($bound)+] $($rest)* }
+    };
+}
+
+/// Declares asynchronous versions of the synchronous writers in `sync`,
+/// implementing the asynchronous traits with the `impls` macro.
+///
+/// Types followed by `set_limits` forward that method to the synchronous type.
+macro_rules! writers {
+    ($sync:ident, $impls:ident: $($bound:tt)+) => {
+        writers! {
+            @types [$sync $impls] [$($bound)+]
+
+            /// A DEFLATE decoder, see the synchronous type of the same name.
+            DeflateDecoder() set_limits;
+            /// A DEFLATE encoder, see the synchronous type of the same name.
+            DeflateEncoder(level: crate::Compression);
+            /// A gzip decoder, see the synchronous type of the same name.
+            GzDecoder() set_limits;
+            /// A gzip encoder, see the synchronous type of the same name.
+            GzEncoder(level: crate::Compression);
+            /// A ZLIB decoder, see the synchronous type of the same name.
+            ZlibDecoder() set_limits;
+            /// A ZLIB encoder, see the synchronous type of the same name.
+            ZlibEncoder(level: crate::Compression);
+            /// A format-detecting decoder, see the synchronous type of the
+            /// same name.
+            AutoDecoder() set_limits;
+        }
+    };
+
+    (@types [$sync:ident $impls:ident] [$($bound:tt)+]) => {};
+
+    (@types [$sync:ident $impls:ident] [$($bound:tt)+]
+     $(#[$attr:meta])* $name:ident($($arg:ident: $ty:ty),*) $($extra:ident)*; $($rest:tt)*) => {
+        $(#[$attr])*
+        #[derive(Debug)]
+        pub struct $name<W: $($bound)+> {
+            inner: $sync::$name<Io<W>>,
+        }
+
+        impl<W: $($bound)+> $name<W> {
+            /// Creates a new instance around the given stream.
+            pub fn new(w: W, $($arg: $ty),*) -> $name<W> {
+                $name {
+                    inner: $sync::$name::new(AllowStd::new(w), $($arg),*),
+                }
+            }
+
+            /// Acquires a reference to the underlying stream.
+            pub fn get_ref(&self) -> &W {
+                self.inner.get_ref().get_ref()
+            }
+
+            /// Acquires a mutable reference to the underlying stream.
+            ///
+            /// Note that mutating the output/input state of the stream may
+            /// corrupt this object, so care must be taken when using this
+            /// method.
+            pub fn get_mut(&mut self) -> &mut W {
+                self.inner.get_mut().get_mut()
+            }
+
+            $(
+                /// Sets limits on the data produced by this decoder, see
+                /// [`DecompressLimits`].
+                ///
+                /// [`DecompressLimits`]: ../../struct.DecompressLimits.html
+                pub fn $extra(&mut self, limits: crate::DecompressLimits) {
+                    self.inner.$extra(limits);
+                }
+            )*
+
+            fn poll_sync_write(
+                &mut self,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                crate::async_io::poll(cx, &mut self.inner, |s| s.get_mut(), |s| s.write(buf))
+            }
+
+            fn poll_sync_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                crate::async_io::poll(cx, &mut self.inner, |s| s.get_mut(), |s| s.flush())
+            }
+
+            fn poll_sync_finish(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                crate::async_io::poll(cx, &mut self.inner, |s| s.get_mut(), |s| s.try_finish())
+            }
+        }
+
+        $impls!($name, $($bound)+);
+
+        writers! { @types [$sync $impls] [$($bound)+] $($rest)* }
+    };
+}
+
+#[cfg(feature = "futures-io")]
+pub mod futures;
+#[cfg(feature = "tokio1")]
+pub mod tokio;
@@ -0,0 +1,125 @@
+use std::io;
+use std::io::prelude::*;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio1::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+use super::{cvt, AllowStd};
+
+/// Marks an `AllowStd` wrapping a tokio stream.
+#[derive(Debug)]
+pub(crate) enum Tokio {}
+
+type Io<T> = AllowStd<T, Tokio>;
+
+impl<T: AsyncRead + Unpin> Read for Io<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (inner, cx) = self.parts()?;
+        let mut buf = ReadBuf::new(buf);
+        cvt(Pin::new(inner).poll_read(cx, &mut buf))?;
+        Ok(buf.filled().len())
+    }
+}
+
+impl<T: AsyncBufRead + Unpin> BufRead for Io<T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let (inner, cx) = self.parts()?;
+        cvt(Pin::new(inner).poll_fill_buf(cx))
+    }
+
+    fn consume(&mut self, amt: usize) {
+        Pin::new(self.get_mut()).consume(amt)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Write for Io<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (inner, cx) = self.parts()?;
+        cvt(Pin::new(inner).poll_write(cx, buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let (inner, cx) = self.parts()?;
+        cvt(Pin::new(inner).poll_flush(cx))
+    }
+}
+
+macro_rules! impl_read {
+    ($name:ident, $($bound:tt)+) => {
+        impl<R: $($bound)+> AsyncRead for $name<R> {
+            fn poll_read(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                let n = match self.get_mut().poll_sync_read(cx, buf.initialize_unfilled()) {
+                    Poll::Ready(Ok(n)) => n,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => return Poll::Pending,
+                };
+                buf.advance(n);
+                Poll::Ready(Ok(()))
+            }
+        }
+    };
+}
+
+macro_rules! impl_write {
+    ($name:ident, $($bound:tt)+) => {
+        impl<W: $($bound)+> AsyncWrite for $name<W> {
+            fn poll_write(
+                self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                self.get_mut().poll_sync_write(cx, buf)
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                self.get_mut().poll_sync_flush(cx)
+            }
+
+            fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                let this = self.get_mut();
+                match this.poll_sync_finish(cx) {
+                    Poll::Ready(Ok(())) => Pin::new(this.get_mut()).poll_shutdown(cx),
+                    other => other,
+                }
+            }
+        }
+    };
+}
+
+/// Encoders and decoders which read from an [`AsyncRead`] stream.
+///
+/// [`AsyncRead`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncRead.html
+pub mod read {
+    use super::*;
+    use crate::read as sync;
+
+    readers!(sync, impl_read: AsyncRead + Unpin);
+}
+
+/// Encoders and decoders which read from an [`AsyncBufRead`] stream.
+///
+/// [`AsyncBufRead`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncBufRead.html
+pub mod bufread {
+    use super::*;
+    use crate::bufread as sync;
+
+    readers!(sync, impl_read: AsyncBufRead + Unpin);
+}
+
+/// Encoders and decoders which write to an [`AsyncWrite`] stream.
+///
+/// Streams are finished, writing out any trailer, once they are shut down.
+/// Dropping a stream never blocks and leaves it unfinished.
+///
+/// [`AsyncWrite`]: https://docs.rs/tokio/1/tokio/io/trait.AsyncWrite.html
+pub mod write {
+    use super::*;
+    use crate::write as sync;
+
+    writers!(sync, impl_write: AsyncWrite + Unpin);
+}
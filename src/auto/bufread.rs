@@ -0,0 +1,316 @@
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+
+#[cfg(feature = "tokio")]
+use futures::Poll;
+#[cfg(feature = "tokio")]
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use super::{detect_format, Fallback, Format, MAGIC_LEN};
+use crate::bufread::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use crate::DecompressLimits;
+
+/// A decoder which detects whether its input is gzip, zlib, raw DEFLATE or
+/// uncompressed data.
+///
+/// This structure implements a [`BufRead`] interface and takes a stream of
+/// data in any of the supported formats as input, providing the decompressed
+/// data when read from.
+///
+/// The format is detected from the first two bytes of the stream: gzip streams
+/// start with the magic bytes `1f 8b` and zlib streams with a CMF/FLG pair
+/// whose checksum is valid. Anything else is assumed to be raw DEFLATE data or
+/// passed through unmodified, depending on the [`Fallback`] policy. This is a
+/// heuristic, uncompressed data may happen to start with a valid zlib header.
+///
+/// Gzip streams are decoded as with a [`MultiGzDecoder`], so all members of a
+/// multi-member stream are read.
+///
+/// [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
+/// [`Fallback`]: ../enum.Fallback.html
+/// [`MultiGzDecoder`]: struct.MultiGzDecoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// # use flate2::Compression;
+/// # use flate2::write::ZlibEncoder;
+/// use flate2::Format;
+/// use flate2::bufread::AutoDecoder;
+///
+/// # fn main() {
+/// #   let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+/// #   e.write_all(b"Hello World").unwrap();
+/// #   let bytes = e.finish().unwrap();
+/// #   assert_eq!(decode(&bytes).unwrap(), (Format::Zlib, "Hello World".to_string()));
+/// # }
+/// #
+/// // Uncompresses a stream in any supported format, reporting the format.
+/// // Here &[u8] implements BufRead
+///
+/// fn decode(bytes: &[u8]) -> io::Result<(Format, String)> {
+///    let mut decoder = AutoDecoder::new(bytes);
+///    let format = decoder.detect()?;
+///    let mut s = String::new();
+///    decoder.read_to_string(&mut s)?;
+///    Ok((format, s))
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AutoDecoder<R> {
+    inner: Inner<R>,
+    fallback: Fallback,
+    limits: Option<DecompressLimits>,
+}
+
+#[derive(Debug)]
+enum Inner<R> {
+    Detecting(Option<Peeked<R>>),
+    Gzip(Box<MultiGzDecoder<Peeked<R>>>),
+    Zlib(ZlibDecoder<Peeked<R>>),
+    Deflate(DeflateDecoder<Peeked<R>>),
+    Uncompressed(Peeked<R>),
+}
+
+/// A reader which first yields the bytes that had to be consumed from the
+/// underlying reader to detect its format.
+#[derive(Debug)]
+struct Peeked<R> {
+    magic: [u8; MAGIC_LEN],
+    pos: usize,
+    len: usize,
+    inner: R,
+}
+
+impl<R: BufRead> Peeked<R> {
+    /// Returns the first bytes of the stream, without consuming them if the
+    /// underlying reader buffers enough of them at once.
+    fn peek(&mut self) -> io::Result<&[u8]> {
+        while self.len < MAGIC_LEN {
+            let n = {
+                let buf = match self.inner.fill_buf() {
+                    Ok(buf) => buf,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                if buf.is_empty() {
+                    break;
+                }
+                if self.len == 0 && buf.len() >= MAGIC_LEN {
+                    self.magic.copy_from_slice(&buf[..MAGIC_LEN]);
+                    return Ok(&self.magic);
+                }
+                let n = cmp::min(MAGIC_LEN - self.len, buf.len());
+                self.magic[self.len..self.len + n].copy_from_slice(&buf[..n]);
+                n
+            };
+            self.inner.consume(n);
+            self.len += n;
+        }
+        Ok(&self.magic[..self.len])
+    }
+}
+
+impl<R: BufRead> Read for Peeked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let available = self.fill_buf()?;
+            let n = cmp::min(available.len(), buf.len());
+            buf[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Peeked<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos < self.len {
+            Ok(&self.magic[self.pos..self.len])
+        } else {
+            self.inner.fill_buf()
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pos < self.len {
+            self.pos += amt;
+            debug_assert!(self.pos <= self.len);
+        } else {
+            self.inner.consume(amt);
+        }
+    }
+}
+
+impl<R: BufRead> AutoDecoder<R> {
+    /// Creates a new decoder from the given reader, falling back to raw
+    /// DEFLATE if the stream is neither gzip nor zlib.
+    pub fn new(r: R) -> AutoDecoder<R> {
+        AutoDecoder::with_fallback(r, Fallback::default())
+    }
+
+    /// Creates a new decoder from the given reader, treating streams which are
+    /// neither gzip nor zlib according to `fallback`.
+    pub fn with_fallback(r: R, fallback: Fallback) -> AutoDecoder<R> {
+        AutoDecoder {
+            inner: Inner::Detecting(Some(Peeked {
+                magic: [0; MAGIC_LEN],
+                pos: 0,
+                len: 0,
+                inner: r,
+            })),
+            fallback,
+            limits: None,
+        }
+    }
+
+    /// Detects the format of the stream, reading its first bytes if that
+    /// hasn't happened yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from reading the underlying stream. Detection is
+    /// resumed by the next call if the error was `WouldBlock`.
+    pub fn detect(&mut self) -> io::Result<Format> {
+        let format = match self.inner {
+            Inner::Detecting(ref mut r) => {
+                let fallback = self.fallback;
+                let magic = r.as_mut().unwrap().peek()?;
+                detect_format(magic, fallback)
+            }
+            _ => return Ok(self.format().unwrap()),
+        };
+        let r = match self.inner {
+            Inner::Detecting(ref mut r) => r.take().unwrap(),
+            _ => unreachable!(),
+        };
+        self.inner = match format {
+            Format::Gzip => Inner::Gzip(Box::new(MultiGzDecoder::new(r))),
+            Format::Zlib => Inner::Zlib(ZlibDecoder::new(r)),
+            Format::Deflate => Inner::Deflate(DeflateDecoder::new(r)),
+            Format::Uncompressed => Inner::Uncompressed(r),
+        };
+        if let Some(limits) = self.limits {
+            self.set_limits(limits);
+        }
+        Ok(format)
+    }
+}
+
+impl<R> AutoDecoder<R> {
+    /// Returns the format of the stream, or `None` if it hasn't been detected
+    /// yet.
+    ///
+    /// The format is detected by the first read, or by calling [`detect`].
+    ///
+    /// [`detect`]: #method.detect
+    pub fn format(&self) -> Option<Format> {
+        match self.inner {
+            Inner::Detecting(_) => None,
+            Inner::Gzip(_) => Some(Format::Gzip),
+            Inner::Zlib(_) => Some(Format::Zlib),
+            Inner::Deflate(_) => Some(Format::Deflate),
+            Inner::Uncompressed(_) => Some(Format::Uncompressed),
+        }
+    }
+
+    fn peeked(&self) -> &Peeked<R> {
+        match self.inner {
+            Inner::Detecting(ref r) => r.as_ref().unwrap(),
+            Inner::Gzip(ref d) => d.get_ref(),
+            Inner::Zlib(ref d) => d.get_ref(),
+            Inner::Deflate(ref d) => d.get_ref(),
+            Inner::Uncompressed(ref r) => r,
+        }
+    }
+
+    fn peeked_mut(&mut self) -> &mut Peeked<R> {
+        match self.inner {
+            Inner::Detecting(ref mut r) => r.as_mut().unwrap(),
+            Inner::Gzip(ref mut d) => d.get_mut(),
+            Inner::Zlib(ref mut d) => d.get_mut(),
+            Inner::Deflate(ref mut d) => d.get_mut(),
+            Inner::Uncompressed(ref mut r) => r,
+        }
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.peeked().inner
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.peeked_mut().inner
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    ///
+    /// Note that up to two bytes consumed from the reader to detect the
+    /// format may be lost if they haven't been decoded yet.
+    pub fn into_inner(self) -> R {
+        match self.inner {
+            Inner::Detecting(r) => r.unwrap().inner,
+            Inner::Gzip(d) => d.into_inner().inner,
+            Inner::Zlib(d) => d.into_inner().inner,
+            Inner::Deflate(d) => d.into_inner().inner,
+            Inner::Uncompressed(r) => r.inner,
+        }
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// The limits have no effect on uncompressed data.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = Some(limits);
+        match self.inner {
+            Inner::Gzip(ref mut d) => d.set_limits(limits),
+            Inner::Zlib(ref mut d) => d.set_limits(limits),
+            Inner::Deflate(ref mut d) => d.set_limits(limits),
+            Inner::Detecting(_) | Inner::Uncompressed(_) => {}
+        }
+    }
+}
+
+impl<R: BufRead> Read for AutoDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.detect()?;
+        match self.inner {
+            Inner::Gzip(ref mut d) => d.read(into),
+            Inner::Zlib(ref mut d) => d.read(into),
+            Inner::Deflate(ref mut d) => d.read(into),
+            Inner::Uncompressed(ref mut r) => r.read(into),
+            Inner::Detecting(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + BufRead> AsyncRead for AutoDecoder<R> {}
+
+impl<R: BufRead + Write> Write for AutoDecoder<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncWrite + BufRead> AsyncWrite for AutoDecoder<R> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.get_mut().shutdown()
+    }
+}
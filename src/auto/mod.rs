@@ -0,0 +1,167 @@
+pub mod bufread;
+pub mod read;
+pub mod write;
+
+/// The format of a stream, as detected by an `AutoDecoder`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Format {
+    /// A gzip stream, starting with the magic bytes `1f 8b`.
+    Gzip,
+    /// A zlib stream, starting with a valid CMF/FLG header pair.
+    Zlib,
+    /// A raw DEFLATE stream.
+    Deflate,
+    /// Data which is passed through as-is.
+    Uncompressed,
+}
+
+/// What an `AutoDecoder` assumes a stream to be when it is neither gzip nor
+/// zlib.
+///
+/// Raw DEFLATE streams have no header, so they can't be told apart from other
+/// data by their first bytes.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Fallback {
+    /// Decode the stream as raw DEFLATE data, this is the default.
+    #[default]
+    Deflate,
+    /// Pass the stream through unmodified.
+    Passthrough,
+}
+
+/// The number of bytes needed to detect a format.
+const MAGIC_LEN: usize = 2;
+
+/// Detects the format of a stream from its first `MAGIC_LEN` bytes, or fewer
+/// if the stream is shorter than that.
+fn detect_format(magic: &[u8], fallback: Fallback) -> Format {
+    match *magic {
+        [0x1f, 0x8b] => Format::Gzip,
+        [cmf, flg] if is_zlib_header(cmf, flg) => Format::Zlib,
+        _ => match fallback {
+            Fallback::Deflate => Format::Deflate,
+            Fallback::Passthrough => Format::Uncompressed,
+        },
+    }
+}
+
+/// Checks the CMF/FLG pair of RFC 1950: the DEFLATE method, a window of at
+/// most 32K, and a header checksum which is a multiple of 31.
+fn is_zlib_header(cmf: u8, flg: u8) -> bool {
+    cmf & 0x0f == 8 && cmf >> 4 <= 7 && ((cmf as u16) << 8 | flg as u16) % 31 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::io::prelude::*;
+
+    use super::{Fallback, Format};
+    use crate::auto::{bufread, read, write};
+    use crate::Compression;
+
+    const DATA: &[u8] = b"hello hello hello hello hello hello";
+
+    fn encodings() -> Vec<(Format, Vec<u8>)> {
+        let mut gz = crate::write::GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(DATA).unwrap();
+        let mut zlib = crate::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(DATA).unwrap();
+        let mut deflate = crate::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        deflate.write_all(DATA).unwrap();
+        vec![
+            (Format::Gzip, gz.finish().unwrap()),
+            (Format::Zlib, zlib.finish().unwrap()),
+            (Format::Deflate, deflate.finish().unwrap()),
+        ]
+    }
+
+    // Hands out a single byte per `fill_buf`.
+    struct OneByte<'a>(&'a [u8]);
+
+    impl<'a> Read for OneByte<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.fill_buf()?.len().min(buf.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.consume(n);
+            Ok(n)
+        }
+    }
+
+    impl<'a> BufRead for OneByte<'a> {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            Ok(&self.0[..self.0.len().min(1)])
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.0 = &self.0[amt..];
+        }
+    }
+
+    #[test]
+    fn detect() {
+        for (format, data) in encodings() {
+            let mut d = read::AutoDecoder::new(&data[..]);
+            assert_eq!(d.format(), None);
+            let mut out = Vec::new();
+            d.read_to_end(&mut out).unwrap();
+            assert_eq!(out, DATA);
+            assert_eq!(d.format(), Some(format));
+
+            let mut d = bufread::AutoDecoder::new(OneByte(&data));
+            assert_eq!(d.detect().unwrap(), format);
+            let mut out = Vec::new();
+            d.read_to_end(&mut out).unwrap();
+            assert_eq!(out, DATA);
+
+            let mut w = write::AutoDecoder::new(Vec::new());
+            for b in data.iter() {
+                w.write_all(&[*b]).unwrap();
+            }
+            assert_eq!(w.format(), Some(format));
+            assert_eq!(w.finish().unwrap(), DATA);
+        }
+    }
+
+    #[test]
+    fn passthrough() {
+        for data in [&b""[..], b"h", DATA].iter() {
+            let mut d = read::AutoDecoder::with_fallback(*data, Fallback::Passthrough);
+            let mut out = Vec::new();
+            d.read_to_end(&mut out).unwrap();
+            assert_eq!(out, *data);
+            assert_eq!(d.format(), Some(Format::Uncompressed));
+
+            let mut d = bufread::AutoDecoder::with_fallback(OneByte(data), Fallback::Passthrough);
+            let mut out = Vec::new();
+            d.read_to_end(&mut out).unwrap();
+            assert_eq!(out, *data);
+
+            let mut w = write::AutoDecoder::with_fallback(Vec::new(), Fallback::Passthrough);
+            w.write_all(data).unwrap();
+            assert_eq!(w.finish().unwrap(), *data);
+        }
+
+        // Plain text is raw DEFLATE by default, which it isn't.
+        let mut d = read::AutoDecoder::new(DATA);
+        assert!(d.read_to_end(&mut Vec::new()).is_err());
+        assert_eq!(d.format(), Some(Format::Deflate));
+    }
+
+    #[test]
+    fn zlib_header() {
+        for &(cmf, flg) in [(0x78, 0x01), (0x78, 0x5e), (0x78, 0x9c), (0x78, 0xda)].iter() {
+            assert_eq!(
+                super::detect_format(&[cmf, flg], Fallback::Passthrough),
+                Format::Zlib
+            );
+        }
+        // Bad checksum, window too large and the wrong method, respectively.
+        for &(cmf, flg) in [(0x78, 0x9d), (0x88, 0x98), (0x79, 0x9c)].iter() {
+            assert_eq!(
+                super::detect_format(&[cmf, flg], Fallback::Passthrough),
+                Format::Uncompressed
+            );
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::io;
+use std::io::prelude::*;
+
+#[cfg(feature = "tokio")]
+use futures::Poll;
+#[cfg(feature = "tokio")]
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use super::bufread;
+use super::{Fallback, Format};
+use crate::bufreader::BufReader;
+use crate::DecompressLimits;
+
+/// A decoder which detects whether its input is gzip, zlib, raw DEFLATE or
+/// uncompressed data.
+///
+/// This structure implements a [`Read`] interface and takes a stream of data
+/// in any of the supported formats as input, providing the decompressed data
+/// when read from.
+///
+/// The format is detected from the first two bytes of the stream, see
+/// [`bufread::AutoDecoder`] for the details.
+///
+/// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
+/// [`bufread::AutoDecoder`]: ../bufread/struct.AutoDecoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// # use flate2::Compression;
+/// # use flate2::write::GzEncoder;
+/// use flate2::{Fallback, Format};
+/// use flate2::read::AutoDecoder;
+///
+/// # fn main() {
+/// #   let mut e = GzEncoder::new(Vec::new(), Compression::default());
+/// #   e.write_all(b"Hello World").unwrap();
+/// #   let bytes = e.finish().unwrap();
+/// #   assert_eq!(decode_reader(&bytes).unwrap(), "Hello World");
+/// #   assert_eq!(decode_reader(b"Hello World").unwrap(), "Hello World");
+/// # }
+/// #
+/// // Uncompresses a gzip, zlib or uncompressed payload
+/// // Here &[u8] implements Read
+///
+/// fn decode_reader(bytes: &[u8]) -> io::Result<String> {
+///    let mut decoder = AutoDecoder::with_fallback(bytes, Fallback::Passthrough);
+///    let mut s = String::new();
+///    decoder.read_to_string(&mut s)?;
+///    println!("payload was {:?}", decoder.format().unwrap());
+///    Ok(s)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AutoDecoder<R> {
+    inner: bufread::AutoDecoder<BufReader<R>>,
+}
+
+impl<R: Read> AutoDecoder<R> {
+    /// Creates a new decoder from the given reader, falling back to raw
+    /// DEFLATE if the stream is neither gzip nor zlib.
+    pub fn new(r: R) -> AutoDecoder<R> {
+        AutoDecoder::with_fallback(r, Fallback::default())
+    }
+
+    /// Creates a new decoder from the given reader, treating streams which are
+    /// neither gzip nor zlib according to `fallback`.
+    pub fn with_fallback(r: R, fallback: Fallback) -> AutoDecoder<R> {
+        AutoDecoder {
+            inner: bufread::AutoDecoder::with_fallback(BufReader::new(r), fallback),
+        }
+    }
+
+    /// Detects the format of the stream, reading its first bytes if that
+    /// hasn't happened yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from reading the underlying stream. Detection is
+    /// resumed by the next call if the error was `WouldBlock`.
+    pub fn detect(&mut self) -> io::Result<Format> {
+        self.inner.detect()
+    }
+}
+
+impl<R> AutoDecoder<R> {
+    /// Returns the format of the stream, or `None` if it hasn't been detected
+    /// yet.
+    ///
+    /// The format is detected by the first read, or by calling [`detect`].
+    ///
+    /// [`detect`]: #method.detect
+    pub fn format(&self) -> Option<Format> {
+        self.inner.format()
+    }
+
+    /// Acquires a reference to the underlying stream
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref().get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying stream
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut().get_mut()
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    ///
+    /// Note that there may be buffered bytes which are not re-acquired as part
+    /// of this transition. It's recommended to only call this function after
+    /// EOF has been reached.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner().into_inner()
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// The limits have no effect on uncompressed data.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+}
+
+impl<R: Read> Read for AutoDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(into)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead> AsyncRead for AutoDecoder<R> {}
+
+impl<R: Read + Write> Write for AutoDecoder<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncWrite + AsyncRead> AsyncWrite for AutoDecoder<R> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.get_mut().shutdown()
+    }
+}
@@ -0,0 +1,291 @@
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+
+#[cfg(feature = "tokio")]
+use futures::Poll;
+#[cfg(feature = "tokio")]
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use super::{detect_format, Fallback, Format, MAGIC_LEN};
+use crate::write::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use crate::DecompressLimits;
+
+/// A decoder which detects whether its input is gzip, zlib, raw DEFLATE or
+/// uncompressed data.
+///
+/// This structure implements a [`Write`] and will emit a stream of decompressed
+/// data when fed a stream of data in any of the supported formats.
+///
+/// The format is detected from the first two bytes written, see
+/// [`bufread::AutoDecoder`] for the details. Unlike the reading decoders,
+/// gzip streams are decoded as with a [`GzDecoder`], so only their first
+/// member is decoded.
+///
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`bufread::AutoDecoder`]: ../bufread/struct.AutoDecoder.html
+/// [`GzDecoder`]: struct.GzDecoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io;
+/// # use flate2::Compression;
+/// # use flate2::write::DeflateEncoder;
+/// use flate2::Format;
+/// use flate2::write::AutoDecoder;
+///
+/// # fn main() {
+/// #    let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+/// #    e.write_all(b"Hello World").unwrap();
+/// #    let bytes = e.finish().unwrap();
+/// #    assert_eq!(decode_writer(bytes).unwrap(), "Hello World");
+/// # }
+/// #
+/// // Uncompresses a gzip, zlib or raw DEFLATE payload
+/// // Here Vec<u8> implements Write
+///
+/// fn decode_writer(bytes: Vec<u8>) -> io::Result<String> {
+///    let mut writer = Vec::new();
+///    let mut decoder = AutoDecoder::new(writer);
+///    decoder.write_all(&bytes[..])?;
+///    assert_eq!(decoder.format(), Some(Format::Deflate));
+///    writer = decoder.finish()?;
+///    let return_string = String::from_utf8(writer).expect("String parsing error");
+///    Ok(return_string)
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AutoDecoder<W: Write> {
+    inner: Inner<W>,
+    magic: Vec<u8>,
+    fallback: Fallback,
+    limits: Option<DecompressLimits>,
+}
+
+#[derive(Debug)]
+enum Inner<W: Write> {
+    Detecting(Option<W>),
+    Gzip(GzDecoder<W>),
+    Zlib(ZlibDecoder<W>),
+    Deflate(DeflateDecoder<W>),
+    Uncompressed(W),
+}
+
+impl<W: Write> AutoDecoder<W> {
+    /// Creates a new decoder which will write uncompressed data to the stream,
+    /// falling back to raw DEFLATE if the data is neither gzip nor zlib.
+    ///
+    /// When this decoder is dropped or unwrapped the final pieces of data will
+    /// be flushed.
+    pub fn new(w: W) -> AutoDecoder<W> {
+        AutoDecoder::with_fallback(w, Fallback::default())
+    }
+
+    /// Creates a new decoder which will write uncompressed data to the stream,
+    /// treating data which is neither gzip nor zlib according to `fallback`.
+    pub fn with_fallback(w: W, fallback: Fallback) -> AutoDecoder<W> {
+        AutoDecoder {
+            inner: Inner::Detecting(Some(w)),
+            magic: Vec::with_capacity(MAGIC_LEN),
+            fallback,
+            limits: None,
+        }
+    }
+
+    /// Returns the format of the data, or `None` if not enough of it has been
+    /// written to detect it yet.
+    pub fn format(&self) -> Option<Format> {
+        match self.inner {
+            Inner::Detecting(_) => None,
+            Inner::Gzip(_) => Some(Format::Gzip),
+            Inner::Zlib(_) => Some(Format::Zlib),
+            Inner::Deflate(_) => Some(Format::Deflate),
+            Inner::Uncompressed(_) => Some(Format::Uncompressed),
+        }
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        match self.inner {
+            Inner::Detecting(ref w) => w.as_ref().unwrap(),
+            Inner::Gzip(ref d) => d.get_ref(),
+            Inner::Zlib(ref d) => d.get_ref(),
+            Inner::Deflate(ref d) => d.get_ref(),
+            Inner::Uncompressed(ref w) => w,
+        }
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutating the output/input state of the stream may corrupt this
+    /// object, so care must be taken when using this method.
+    pub fn get_mut(&mut self) -> &mut W {
+        match self.inner {
+            Inner::Detecting(ref mut w) => w.as_mut().unwrap(),
+            Inner::Gzip(ref mut d) => d.get_mut(),
+            Inner::Zlib(ref mut d) => d.get_mut(),
+            Inner::Deflate(ref mut d) => d.get_mut(),
+            Inner::Uncompressed(ref mut w) => w,
+        }
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// The limits have no effect on uncompressed data.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = Some(limits);
+        match self.inner {
+            Inner::Gzip(ref mut d) => d.set_limits(limits),
+            Inner::Zlib(ref mut d) => d.set_limits(limits),
+            Inner::Deflate(ref mut d) => d.set_limits(limits),
+            Inner::Detecting(_) | Inner::Uncompressed(_) => {}
+        }
+    }
+
+    /// Attempt to finish this output stream, writing out final chunks of data.
+    ///
+    /// Note that this function can only be used once data has finished being
+    /// written to the output stream. After this function is called then further
+    /// calls to `write` may result in a panic.
+    ///
+    /// # Panics
+    ///
+    /// Attempts to write data to this stream may result in a panic after this
+    /// function is called.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        if let Inner::Detecting(_) = self.inner {
+            self.dispatch();
+        }
+        self.write_magic()?;
+        match self.inner {
+            Inner::Gzip(ref mut d) => d.try_finish(),
+            Inner::Zlib(ref mut d) => d.try_finish(),
+            Inner::Deflate(ref mut d) => d.try_finish(),
+            Inner::Uncompressed(ref mut w) => w.flush(),
+            Inner::Detecting(_) => unreachable!(),
+        }
+    }
+
+    /// Consumes this decoder, flushing the output stream.
+    ///
+    /// This will flush the underlying data stream and then return the contained
+    /// writer if the flush succeeded.
+    ///
+    /// Note that this function may not be suitable to call in a situation where
+    /// the underlying stream is an asynchronous I/O stream. To finish a stream
+    /// the `try_finish` (or `shutdown`) method should be used instead. To
+    /// re-acquire ownership of a stream it is safe to call this method after
+    /// `try_finish` or `shutdown` has returned `Ok`.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        match self.inner {
+            Inner::Gzip(d) => d.finish(),
+            Inner::Zlib(d) => d.finish(),
+            Inner::Deflate(d) => d.finish(),
+            Inner::Uncompressed(w) => Ok(w),
+            Inner::Detecting(_) => unreachable!(),
+        }
+    }
+
+    /// Picks a decoder based on the bytes written so far.
+    fn dispatch(&mut self) {
+        let w = match self.inner {
+            Inner::Detecting(ref mut w) => w.take().unwrap(),
+            _ => unreachable!(),
+        };
+        self.inner = match detect_format(&self.magic, self.fallback) {
+            Format::Gzip => Inner::Gzip(GzDecoder::new(w)),
+            Format::Zlib => Inner::Zlib(ZlibDecoder::new(w)),
+            Format::Deflate => Inner::Deflate(DeflateDecoder::new(w)),
+            Format::Uncompressed => Inner::Uncompressed(w),
+        };
+        if let Some(limits) = self.limits {
+            self.set_limits(limits);
+        }
+    }
+
+    /// Feeds the bytes held back for detection to the decoder.
+    fn write_magic(&mut self) -> io::Result<()> {
+        while !self.magic.is_empty() {
+            let n = match self.inner {
+                Inner::Gzip(ref mut d) => d.write(&self.magic)?,
+                Inner::Zlib(ref mut d) => d.write(&self.magic)?,
+                Inner::Deflate(ref mut d) => d.write(&self.magic)?,
+                Inner::Uncompressed(ref mut w) => w.write(&self.magic)?,
+                Inner::Detecting(_) => unreachable!(),
+            };
+            if n == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.magic.drain(..n);
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for AutoDecoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Inner::Detecting(_) = self.inner {
+            let n = cmp::min(MAGIC_LEN - self.magic.len(), buf.len());
+            self.magic.extend_from_slice(&buf[..n]);
+            if self.magic.len() == MAGIC_LEN {
+                self.dispatch();
+            }
+            return Ok(n);
+        }
+        self.write_magic()?;
+        match self.inner {
+            Inner::Gzip(ref mut d) => d.write(buf),
+            Inner::Zlib(ref mut d) => d.write(buf),
+            Inner::Deflate(ref mut d) => d.write(buf),
+            Inner::Uncompressed(ref mut w) => w.write(buf),
+            Inner::Detecting(_) => unreachable!(),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Inner::Detecting(Some(ref mut w)) = self.inner {
+            return w.flush();
+        }
+        self.write_magic()?;
+        match self.inner {
+            Inner::Gzip(ref mut d) => d.flush(),
+            Inner::Zlib(ref mut d) => d.flush(),
+            Inner::Deflate(ref mut d) => d.flush(),
+            Inner::Uncompressed(ref mut w) => w.flush(),
+            Inner::Detecting(_) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncWrite> AsyncWrite for AutoDecoder<W> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.try_finish()?;
+        self.get_mut().shutdown()
+    }
+}
+
+impl<W: Read + Write> Read for AutoDecoder<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.get_mut().read(buf)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncRead + AsyncWrite> AsyncRead for AutoDecoder<W> {}
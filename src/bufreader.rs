@@ -63,6 +63,18 @@ impl<R> BufReader<R> {
         self.inner
     }
 
+    /// Returns the underlying reader along with the bytes which were read
+    /// from it but not consumed yet.
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        let buffered = self.buf[self.pos..self.cap].to_vec();
+        (self.inner, buffered)
+    }
+
+    pub fn reset_buffer(&mut self) {
+        self.pos = 0;
+        self.cap = 0;
+    }
+
     pub fn reset(&mut self, inner: R) -> R {
         self.pos = 0;
         self.cap = 0;
@@ -0,0 +1,701 @@
+//! A small DEFLATE decoder which walks a stream one block at a time.
+//!
+//! None of the backends expose where the boundaries between DEFLATE blocks
+//! fall in the compressed bit stream, which is exactly what is needed to build
+//! random access points into an existing stream. This decoder is much slower
+//! than the backends but reports the bit position of every block header.
+
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+
+use crate::mem::{add_offset, io_error};
+use crate::{DecompressError, DecompressErrorKind};
+
+/// Size of the sliding window of a DEFLATE stream.
+pub const WINDOW_SIZE: usize = 32 * 1024;
+
+const MAX_BITS: usize = 15;
+/// Codes of up to this many bits are decoded with a single table lookup.
+const FAST_BITS: u32 = 9;
+const MAX_LCODES: usize = 286;
+const MAX_DCODES: usize = 30;
+const FIX_LCODES: usize = 288;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Errors are positioned at the start of the stream here, and moved to the
+/// failing byte by `BlockWalker::step`.
+fn invalid(kind: DecompressErrorKind) -> io::Error {
+    io_error(DecompressError::new(kind).at(0))
+}
+
+/// Canonical Huffman decoding table.
+struct Huffman {
+    count: [u16; MAX_BITS + 1],
+    symbol: Vec<u16>,
+    /// Indexed by the next `FAST_BITS` bits of input, the symbol shifted
+    /// left by 4 bits and the length of its code, or 0 if the code is longer.
+    fast: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+        if count[0] as usize == lengths.len() {
+            // No codes at all, which is only valid if nothing is decoded
+            // with this table.
+            return Ok(Huffman {
+                count,
+                symbol: Vec::new(),
+                fast: vec![0; 1 << FAST_BITS],
+            });
+        }
+
+        // Reject over-subscribed sets of lengths, incomplete sets are
+        // tolerated and will fail when an unused code is decoded.
+        let mut left = 1i32;
+        for &count in &count[1..] {
+            left <<= 1;
+            left -= count as i32;
+            if left < 0 {
+                return Err(invalid(DecompressErrorKind::InvalidHuffmanCode));
+            }
+        }
+
+        let mut offs = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offs[len + 1] = offs[len] + count[len];
+        }
+        let mut symbol = vec![0; lengths.len()];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbol[offs[len as usize] as usize] = sym as u16;
+                offs[len as usize] += 1;
+            }
+        }
+
+        // Codes are sent starting from their most significant bit, so the
+        // table is indexed by the reversed code, for every value of the bits
+        // following a short code.
+        let mut next = [0u32; MAX_BITS + 1];
+        for len in 2..=MAX_BITS {
+            next[len] = (next[len - 1] + u32::from(count[len - 1])) << 1;
+        }
+        let mut fast = vec![0; 1 << FAST_BITS];
+        for (sym, &len) in lengths.iter().enumerate() {
+            let len = u32::from(len);
+            if len == 0 {
+                continue;
+            }
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            if len > FAST_BITS {
+                continue;
+            }
+            let reversed = (code.reverse_bits() >> (32 - len)) as usize;
+            let entry = ((sym as u16) << 4) | len as u16;
+            for fill in 0..1 << (FAST_BITS - len) {
+                fast[reversed | (fill << len)] = entry;
+            }
+        }
+        Ok(Huffman {
+            count,
+            symbol,
+            fast,
+        })
+    }
+
+    fn fixed() -> (Huffman, Huffman) {
+        let mut lengths = [0u8; FIX_LCODES];
+        for (sym, len) in lengths.iter_mut().enumerate() {
+            *len = match sym {
+                0..=143 => 8,
+                144..=255 => 9,
+                256..=279 => 7,
+                _ => 8,
+            };
+        }
+        let lencode = Huffman::new(&lengths).unwrap();
+        let distcode = Huffman::new(&[5; MAX_DCODES]).unwrap();
+        (lencode, distcode)
+    }
+}
+
+/// The default amount of output `BlockWalker::step` produces before returning
+/// in the middle of a block.
+pub const OUTPUT_CHUNK: usize = 64 * 1024;
+
+/// Where `BlockWalker::step` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// The output limit was reached in the middle of a block.
+    Output,
+    /// A block ended, and it wasn't the final block of the stream.
+    Block,
+    /// The final block of the stream ended.
+    Final,
+}
+
+/// The part of a block the walker is in.
+enum Mode {
+    Header,
+    /// A stored block with this many bytes left to copy.
+    Stored(usize),
+    Fixed,
+    Dynamic(Box<(Huffman, Huffman)>),
+}
+
+/// Decodes a raw DEFLATE stream from `R` block by block.
+///
+/// The walker never reads further from the underlying reader than the byte
+/// containing the last bit it needs, so once the final block has been decoded
+/// and `align` has been called the reader is positioned directly after the
+/// compressed data.
+///
+/// Only the last 32 KiB of output and the output of the current call to
+/// `step` are kept, so long blocks are decoded in several steps.
+pub struct BlockWalker<R> {
+    inner: R,
+    bit_buf: u32,
+    bit_cnt: u32,
+    pos: u64,
+    history: Vec<u8>,
+    fresh: usize,
+    max_output: usize,
+    mode: Mode,
+    last: bool,
+    fixed: Option<Box<(Huffman, Huffman)>>,
+}
+
+impl<R: BufRead> BlockWalker<R> {
+    pub fn new(inner: R) -> BlockWalker<R> {
+        BlockWalker {
+            inner,
+            bit_buf: 0,
+            bit_cnt: 0,
+            pos: 0,
+            history: Vec::new(),
+            fresh: 0,
+            max_output: OUTPUT_CHUNK,
+            mode: Mode::Header,
+            last: false,
+            fixed: None,
+        }
+    }
+
+    /// Sets the amount of output after which `step` returns in the middle of
+    /// a block. A match may take the output up to 257 bytes past the limit.
+    pub fn set_max_output(&mut self, max: usize) {
+        self.max_output = max.max(1);
+    }
+
+    /// Returns the number of bits consumed so far.
+    pub fn bit_position(&self) -> u64 {
+        self.pos * 8 - u64::from(self.bit_cnt)
+    }
+
+    /// Returns the up to 32 KiB of output preceding the current position.
+    pub fn window(&self) -> &[u8] {
+        let start = self.history.len().saturating_sub(WINDOW_SIZE);
+        &self.history[start..]
+    }
+
+    /// Returns the output produced by the most recent call to `step`.
+    pub fn output(&self) -> &[u8] {
+        &self.history[self.fresh..]
+    }
+
+    /// Discards all history, for example when starting a new stream.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.fresh = 0;
+        self.bit_buf = 0;
+        self.bit_cnt = 0;
+        self.mode = Mode::Header;
+    }
+
+    /// Discards the padding bits after the final block.
+    pub fn align(&mut self) {
+        self.bit_buf = 0;
+        self.bit_cnt = 0;
+    }
+
+    /// Returns whether the underlying reader has no more data.
+    pub fn is_eof(&mut self) -> io::Result<bool> {
+        Ok(self.inner.fill_buf()?.is_empty())
+    }
+
+    /// Decodes until the end of the current block, or until the output limit
+    /// is reached.
+    pub fn step(&mut self) -> io::Result<Step> {
+        self.walk().map_err(|e| add_offset(e, self.pos))
+    }
+
+    fn walk(&mut self) -> io::Result<Step> {
+        if self.history.len() > WINDOW_SIZE {
+            let excess = self.history.len() - WINDOW_SIZE;
+            self.history.drain(..excess);
+        }
+        self.fresh = self.history.len();
+
+        loop {
+            let done = match mem::replace(&mut self.mode, Mode::Header) {
+                Mode::Header => {
+                    self.last = self.bits(1)? == 1;
+                    self.mode = match self.bits(2)? {
+                        0 => Mode::Stored(self.stored_header()?),
+                        1 => Mode::Fixed,
+                        2 => Mode::Dynamic(self.dynamic()?),
+                        _ => return Err(invalid(DecompressErrorKind::InvalidBlockType)),
+                    };
+                    continue;
+                }
+                Mode::Stored(remaining) => {
+                    let remaining = self.stored(remaining)?;
+                    self.mode = Mode::Stored(remaining);
+                    remaining == 0
+                }
+                Mode::Fixed => {
+                    // The fixed tables are built once and put back afterwards.
+                    let fixed = self
+                        .fixed
+                        .take()
+                        .unwrap_or_else(|| Box::new(Huffman::fixed()));
+                    let res = self.codes(&fixed.0, &fixed.1);
+                    self.fixed = Some(fixed);
+                    self.mode = Mode::Fixed;
+                    res?
+                }
+                Mode::Dynamic(tables) => {
+                    let res = self.codes(&tables.0, &tables.1);
+                    self.mode = Mode::Dynamic(tables);
+                    res?
+                }
+            };
+            if !done {
+                return Ok(Step::Output);
+            }
+            self.mode = Mode::Header;
+            return Ok(if self.last { Step::Final } else { Step::Block });
+        }
+    }
+
+    /// Returns whether the output limit of the current step was reached.
+    fn is_full(&self) -> bool {
+        self.history.len() - self.fresh >= self.max_output
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = match self.inner.fill_buf()?.first() {
+            Some(&byte) => byte,
+            None => return Err(invalid(DecompressErrorKind::TruncatedInput)),
+        };
+        self.inner.consume(1);
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bits(&mut self, need: u32) -> io::Result<u32> {
+        while self.bit_cnt < need {
+            let byte = self.byte()?;
+            self.bit_buf |= u32::from(byte) << self.bit_cnt;
+            self.bit_cnt += 8;
+        }
+        let val = self.bit_buf & ((1 << need) - 1);
+        self.bit_buf >>= need;
+        self.bit_cnt -= need;
+        Ok(val)
+    }
+
+    /// Reads the header of a stored block, returning its length.
+    fn stored_header(&mut self) -> io::Result<usize> {
+        self.align();
+        let mut header = [0; 4];
+        for slot in header.iter_mut() {
+            *slot = self.byte()?;
+        }
+        let len = u16::from(header[0]) | (u16::from(header[1]) << 8);
+        let nlen = u16::from(header[2]) | (u16::from(header[3]) << 8);
+        if len != !nlen {
+            return Err(invalid(DecompressErrorKind::InvalidStoredBlock));
+        }
+        Ok(len as usize)
+    }
+
+    /// Copies stored data until `remaining` bytes have been copied or the
+    /// output limit is reached, returning the number of bytes left.
+    fn stored(&mut self, mut remaining: usize) -> io::Result<usize> {
+        while remaining > 0 && !self.is_full() {
+            let room = self.max_output - (self.history.len() - self.fresh);
+            let n = {
+                let buf = self.inner.fill_buf()?;
+                if buf.is_empty() {
+                    return Err(invalid(DecompressErrorKind::TruncatedInput));
+                }
+                let n = remaining.min(room).min(buf.len());
+                self.history.extend_from_slice(&buf[..n]);
+                n
+            };
+            self.inner.consume(n);
+            self.pos += n as u64;
+            remaining -= n;
+        }
+        Ok(remaining)
+    }
+
+    /// Returns the next `need` bits without consuming them, or `None` if
+    /// that many bits aren't buffered yet.
+    fn peek(&mut self, need: u32) -> Option<u32> {
+        let mut buf = u64::from(self.bit_buf);
+        let mut cnt = self.bit_cnt;
+        if cnt < need {
+            let input = self.inner.fill_buf().ok()?;
+            for &byte in input {
+                if cnt >= need {
+                    break;
+                }
+                buf |= u64::from(byte) << cnt;
+                cnt += 8;
+            }
+        }
+        if cnt < need {
+            return None;
+        }
+        Some((buf & ((1 << need) - 1)) as u32)
+    }
+
+    fn decode(&mut self, h: &Huffman) -> io::Result<usize> {
+        if let Some(bits) = self.peek(FAST_BITS) {
+            let entry = h.fast[bits as usize];
+            if entry != 0 {
+                self.bits(u32::from(entry & 0xf))?;
+                return Ok(usize::from(entry >> 4));
+            }
+        }
+
+        // Long codes, invalid codes and the end of the input are decoded a
+        // bit at a time.
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..=MAX_BITS {
+            code |= self.bits(1)? as i32;
+            let count = h.count[len] as i32;
+            if code - count < first {
+                return Ok(h.symbol[(index + (code - first)) as usize] as usize);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(invalid(DecompressErrorKind::InvalidHuffmanCode))
+    }
+
+    /// Decodes codes until the end of the block, returning true, or until
+    /// the output limit is reached, returning false.
+    fn codes(&mut self, lencode: &Huffman, distcode: &Huffman) -> io::Result<bool> {
+        while !self.is_full() {
+            let symbol = self.decode(lencode)?;
+            if symbol < 256 {
+                self.history.push(symbol as u8);
+            } else if symbol == 256 {
+                return Ok(true);
+            } else {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err(invalid(DecompressErrorKind::InvalidHuffmanCode));
+                }
+                let len = LENGTH_BASE[symbol] as usize
+                    + self.bits(u32::from(LENGTH_EXTRA[symbol]))? as usize;
+
+                let symbol = self.decode(distcode)?;
+                if symbol >= DIST_BASE.len() {
+                    return Err(invalid(DecompressErrorKind::InvalidHuffmanCode));
+                }
+                let dist =
+                    DIST_BASE[symbol] as usize + self.bits(u32::from(DIST_EXTRA[symbol]))? as usize;
+                if dist > self.history.len() {
+                    return Err(invalid(DecompressErrorKind::DistanceTooFarBack));
+                }
+                let start = self.history.len() - dist;
+                for i in start..start + len {
+                    let byte = self.history[i];
+                    self.history.push(byte);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn dynamic(&mut self) -> io::Result<Box<(Huffman, Huffman)>> {
+        let nlen = self.bits(5)? as usize + 257;
+        let ndist = self.bits(5)? as usize + 1;
+        let ncode = self.bits(4)? as usize + 4;
+        if nlen > MAX_LCODES || ndist > MAX_DCODES {
+            return Err(invalid(DecompressErrorKind::InvalidHuffmanCode));
+        }
+
+        let mut lengths = [0u8; MAX_LCODES + MAX_DCODES];
+        for &idx in CODE_LENGTH_ORDER.iter().take(ncode) {
+            lengths[idx] = self.bits(3)? as u8;
+        }
+        let lencode = Huffman::new(&lengths[..19])?;
+
+        let mut index = 0;
+        while index < nlen + ndist {
+            let symbol = self.decode(&lencode)?;
+            if symbol < 16 {
+                lengths[index] = symbol as u8;
+                index += 1;
+                continue;
+            }
+            let (len, repeat) = match symbol {
+                16 => {
+                    if index == 0 {
+                        return Err(invalid(DecompressErrorKind::InvalidHuffmanCode));
+                    }
+                    (lengths[index - 1], 3 + self.bits(2)? as usize)
+                }
+                17 => (0, 3 + self.bits(3)? as usize),
+                _ => (0, 11 + self.bits(7)? as usize),
+            };
+            if index + repeat > nlen + ndist {
+                return Err(invalid(DecompressErrorKind::InvalidHuffmanCode));
+            }
+            for slot in lengths[index..index + repeat].iter_mut() {
+                *slot = len;
+            }
+            index += repeat;
+        }
+
+        if lengths[256] == 0 {
+            return Err(invalid(DecompressErrorKind::InvalidHuffmanCode));
+        }
+        let lencode = Huffman::new(&lengths[..nlen])?;
+        let distcode = Huffman::new(&lengths[nlen..nlen + ndist])?;
+        Ok(Box::new((lencode, distcode)))
+    }
+}
+
+/// Reads whole bytes from the underlying reader, such as the framing around a
+/// DEFLATE stream. Must only be used on a byte boundary.
+impl<R: BufRead> Read for BlockWalker<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        debug_assert_eq!(self.bit_cnt, 0);
+        let n = self.inner.read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Accumulates bits in DEFLATE order.
+struct BitWriter {
+    buf: Vec<u8>,
+    acc: u32,
+    cnt: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, bits: u32) {
+        self.acc |= value << self.cnt;
+        self.cnt += bits;
+        while self.cnt >= 8 {
+            self.buf.push(self.acc as u8);
+            self.acc >>= 8;
+            self.cnt -= 8;
+        }
+    }
+
+    // Huffman codes are packed starting with their most significant bit.
+    fn put_code(&mut self, code: u32, bits: u32) {
+        let reversed = code.reverse_bits() >> (32 - bits);
+        self.put(reversed, bits);
+    }
+
+    fn put_literal(&mut self, byte: u8) {
+        if byte < 144 {
+            self.put_code(0x30 + u32::from(byte), 8);
+        } else {
+            self.put_code(0x190 + u32::from(byte - 144), 9);
+        }
+    }
+
+    fn put_fixed_header(&mut self) {
+        // BFINAL = 0, BTYPE = 01
+        self.put(0, 1);
+        self.put(1, 2);
+    }
+
+    fn put_end_of_block(&mut self) {
+        self.put_code(0, 7);
+    }
+}
+
+/// A synthetic raw DEFLATE prefix which primes an inflater so that it can
+/// resume decoding in the middle of another stream.
+pub struct Priming {
+    /// Bytes to feed to the inflater before the original stream.
+    pub bytes: Vec<u8>,
+    /// Number of output bytes produced by `bytes` which must be discarded.
+    pub discard: usize,
+}
+
+/// Builds a prefix which leaves an inflater with `window` as its history and
+/// positioned `bit` bits into the byte `first`.
+///
+/// The window is emitted as literals in fixed Huffman blocks and padded with
+/// empty blocks so that the prefix ends `bit` bits into its final byte. That
+/// byte is then merged with `first`, which is the byte of the original stream
+/// that the next block header starts in, so the caller should continue by
+/// feeding the original stream from the byte after `first`. When `bit` is 0 the
+/// prefix is byte aligned and `first` is not used.
+///
+/// Unlike `inflatePrime` and `inflateSetDictionary` this works on every
+/// backend as it only relies on the inflater decoding ordinary blocks.
+pub fn prime(window: &[u8], bit: u8, first: u8) -> Priming {
+    debug_assert!(bit < 8);
+    debug_assert!(window.len() <= WINDOW_SIZE);
+
+    let mut window_bits = 0u32;
+    if !window.is_empty() {
+        window_bits = 3 + 7;
+        for &byte in window {
+            window_bits += if byte < 144 { 8 } else { 9 };
+        }
+    }
+
+    // An empty fixed block is 10 bits long, and a block with a single 9 bit
+    // literal is 19 bits long, which between them can pad to any residue.
+    let mut delta = (u32::from(bit) + 8 - window_bits % 8) % 8;
+    let junk = delta % 2 == 1;
+    if junk {
+        delta = (delta + 8 - 3) % 8;
+    }
+    let empty_blocks = delta / 2;
+
+    let mut w = BitWriter {
+        buf: Vec::with_capacity(window.len() * 9 / 8 + 16),
+        acc: 0,
+        cnt: 0,
+    };
+    if junk {
+        // The junk byte is emitted before the window so it's never
+        // referenced by the distances of the original stream.
+        w.put_fixed_header();
+        w.put_literal(144);
+        w.put_end_of_block();
+    }
+    if !window.is_empty() {
+        w.put_fixed_header();
+        for &byte in window {
+            w.put_literal(byte);
+        }
+        w.put_end_of_block();
+    }
+    for _ in 0..empty_blocks {
+        w.put_fixed_header();
+        w.put_end_of_block();
+    }
+
+    debug_assert_eq!(w.cnt, u32::from(bit));
+    let mut bytes = w.buf;
+    if bit > 0 {
+        let mask = (1u8 << bit) - 1;
+        bytes.push((w.acc as u8 & mask) | (first & !mask));
+    }
+    Priming {
+        bytes,
+        discard: window.len() + junk as usize,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+    use std::io::BufReader;
+
+    use super::{BlockWalker, Step, OUTPUT_CHUNK, WINDOW_SIZE};
+    use crate::write::DeflateEncoder;
+    use crate::{CompressOptions, Compression, Strategy};
+
+    fn walk<R: BufRead>(r: R, max_output: usize) -> Vec<u8> {
+        let mut walker = BlockWalker::new(r);
+        walker.set_max_output(max_output);
+        let mut out = Vec::new();
+        loop {
+            let step = walker.step().unwrap();
+            assert!(walker.history.len() <= WINDOW_SIZE + max_output + 257);
+            out.extend_from_slice(walker.output());
+            if step == Step::Final {
+                return out;
+            }
+        }
+    }
+
+    #[test]
+    fn walk_blocks() {
+        let mut data = crate::random_bytes().take(64 * 1024).collect::<Vec<_>>();
+        data.extend(b"abcabcabc".iter().cycle().take(64 * 1024));
+        for level in &[Compression::none(), Compression::default()] {
+            for strategy in &[Strategy::Default, Strategy::Fixed] {
+                let options = CompressOptions::new(*level).strategy(*strategy);
+                let mut e = DeflateEncoder::new_with_options(Vec::new(), &options).unwrap();
+                e.write_all(&data).unwrap();
+                let compressed = e.finish().unwrap();
+
+                assert_eq!(walk(&compressed[..], OUTPUT_CHUNK), data);
+                assert_eq!(walk(&compressed[..], 1000), data);
+                // A one byte buffer takes the bit by bit path for every code.
+                let r = BufReader::with_capacity(1, &compressed[..]);
+                assert_eq!(walk(r, OUTPUT_CHUNK), data);
+            }
+        }
+    }
+
+    #[test]
+    fn long_block() {
+        let data = vec![0; 4 * 1024 * 1024];
+        let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&data).unwrap();
+        let compressed = e.finish().unwrap();
+
+        let mut walker = BlockWalker::new(&compressed[..]);
+        let mut len = 0;
+        let mut partial = 0;
+        loop {
+            let step = walker.step().unwrap();
+            assert!(walker.history.len() <= WINDOW_SIZE + OUTPUT_CHUNK + 257);
+            assert!(walker.output().iter().all(|&b| b == 0));
+            len += walker.output().len();
+            match step {
+                Step::Output => partial += 1,
+                Step::Block => {}
+                Step::Final => break,
+            }
+        }
+        assert_eq!(len, data.len());
+        assert!(partial > 0);
+    }
+}
@@ -7,8 +7,9 @@ use futures::Poll;
 #[cfg(feature = "tokio")]
 use tokio_io::{AsyncRead, AsyncWrite};
 
+use crate::dictionary;
 use crate::zio;
-use crate::{Compress, Decompress};
+use crate::{Compress, CompressOptions, Decompress, DecompressLimits};
 
 /// A DEFLATE encoder, or compressor.
 ///
@@ -56,6 +57,43 @@ impl<R: BufRead> DeflateEncoder<R> {
             data: Compress::new(level, false),
         }
     }
+
+    /// Creates a new encoder like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(r: R, options: &CompressOptions) -> io::Result<DeflateEncoder<R>> {
+        Ok(deflate_encoder(
+            r,
+            Compress::new_with_options(options, false)?,
+        ))
+    }
+
+    /// Creates a new encoder like `new`, which compresses with the given
+    /// preset dictionary.
+    ///
+    /// The same dictionary is needed to decompress the data. It is discarded
+    /// by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the backend in use doesn't
+    /// support dictionaries.
+    pub fn new_with_dictionary(
+        r: R,
+        level: crate::Compression,
+        dictionary: &[u8],
+    ) -> io::Result<DeflateEncoder<R>> {
+        Ok(deflate_encoder(
+            r,
+            dictionary::compressor(level, false, dictionary)?,
+        ))
+    }
+}
+
+pub fn deflate_encoder<R>(r: R, data: Compress) -> DeflateEncoder<R> {
+    DeflateEncoder { obj: r, data }
 }
 
 pub fn reset_encoder_data<R>(zlib: &mut DeflateEncoder<R>) {
@@ -174,7 +212,13 @@ pub struct DeflateDecoder<R> {
 }
 
 pub fn reset_decoder_data<R>(zlib: &mut DeflateDecoder<R>) {
-    zlib.data = Decompress::new(false);
+    zlib.data.reset(false);
+}
+
+/// Resets the decoder for the next member of a gzip stream, which counts
+/// towards the same limits as the members before it.
+pub fn next_member<R>(zlib: &mut DeflateDecoder<R>) {
+    zlib.data.reset_continued(false);
 }
 
 impl<R: BufRead> DeflateDecoder<R> {
@@ -186,6 +230,20 @@ impl<R: BufRead> DeflateDecoder<R> {
             data: Decompress::new(false),
         }
     }
+
+    /// Creates a new decoder like `new`, for data compressed with the given
+    /// preset dictionary. The dictionary is discarded by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the backend in use doesn't
+    /// support dictionaries.
+    pub fn new_with_dictionary(r: R, dictionary: &[u8]) -> io::Result<DeflateDecoder<R>> {
+        Ok(DeflateDecoder {
+            obj: r,
+            data: dictionary::raw_decompressor(dictionary)?,
+        })
+    }
 }
 
 impl<R> DeflateDecoder<R> {
@@ -239,6 +297,14 @@ impl<R> DeflateDecoder<R> {
     pub fn total_out(&self) -> u64 {
         self.data.total_out()
     }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.data.set_limits(limits);
+    }
 }
 
 impl<R: BufRead> Read for DeflateDecoder<R> {
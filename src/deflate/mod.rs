@@ -1,3 +1,4 @@
+pub mod blocks;
 pub mod bufread;
 pub mod read;
 pub mod write;
@@ -8,8 +9,8 @@ mod tests {
 
     use rand::{thread_rng, Rng};
 
-    use super::{read, write};
-    use crate::Compression;
+    use super::{bufread, read, write};
+    use crate::{Compression, DecompressLimits, LimitExceeded};
 
     #[test]
     fn roundtrip() {
@@ -190,4 +191,43 @@ mod tests {
             v == w.finish().unwrap().finish().unwrap()
         }
     }
+
+    fn limit_of(err: &std::io::Error) -> Option<LimitExceeded> {
+        err.get_ref()
+            .and_then(|e| e.downcast_ref::<LimitExceeded>())
+            .cloned()
+    }
+
+    #[test]
+    fn limits() {
+        let data = vec![0; 1024 * 1024];
+        let mut e = write::DeflateEncoder::new(Vec::new(), Compression::best());
+        e.write_all(&data).unwrap();
+        let bomb = e.finish().unwrap();
+        let limits = DecompressLimits::new().max_output(100_000);
+
+        let mut d = read::DeflateDecoder::new(&bomb[..]);
+        d.set_limits(limits);
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(limit_of(&err), Some(LimitExceeded::Output(100_000)));
+
+        let mut d = bufread::DeflateDecoder::new(&bomb[..]);
+        d.set_limits(DecompressLimits::new().max_ratio(10, 1024));
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(limit_of(&err), Some(LimitExceeded::Ratio(10)));
+
+        let mut w = write::DeflateDecoder::new(Vec::new());
+        w.set_limits(limits);
+        let err = w
+            .write_all(&bomb)
+            .and_then(|()| w.try_finish())
+            .unwrap_err();
+        assert_eq!(limit_of(&err), Some(LimitExceeded::Output(100_000)));
+
+        let mut d = read::DeflateDecoder::new(&bomb[..]);
+        d.set_limits(DecompressLimits::new().max_output(data.len() as u64));
+        let mut v = Vec::new();
+        d.read_to_end(&mut v).unwrap();
+        assert!(v == data);
+    }
 }
@@ -8,6 +8,7 @@ use tokio_io::{AsyncRead, AsyncWrite};
 
 use super::bufread;
 use crate::bufreader::BufReader;
+use crate::{CompressOptions, DecompressLimits};
 
 /// A DEFLATE encoder, or compressor.
 ///
@@ -50,6 +51,37 @@ impl<R: Read> DeflateEncoder<R> {
             inner: bufread::DeflateEncoder::new(BufReader::new(r), level),
         }
     }
+
+    /// Creates a new encoder like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(r: R, options: &CompressOptions) -> io::Result<DeflateEncoder<R>> {
+        Ok(DeflateEncoder {
+            inner: bufread::DeflateEncoder::new_with_options(BufReader::new(r), options)?,
+        })
+    }
+
+    /// Creates a new encoder like `new`, which compresses with the given
+    /// preset dictionary.
+    ///
+    /// The same dictionary is needed to decompress the data. It is discarded
+    /// by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the backend in use doesn't
+    /// support dictionaries.
+    pub fn new_with_dictionary(
+        r: R,
+        level: crate::Compression,
+        dictionary: &[u8],
+    ) -> io::Result<DeflateEncoder<R>> {
+        let inner =
+            bufread::DeflateEncoder::new_with_dictionary(BufReader::new(r), level, dictionary)?;
+        Ok(DeflateEncoder { inner })
+    }
 }
 
 impl<R> DeflateEncoder<R> {
@@ -185,6 +217,18 @@ impl<R: Read> DeflateDecoder<R> {
             inner: bufread::DeflateDecoder::new(BufReader::with_buf(buf, r)),
         }
     }
+
+    /// Creates a new decoder like `new`, for data compressed with the given
+    /// preset dictionary. The dictionary is discarded by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the backend in use doesn't
+    /// support dictionaries.
+    pub fn new_with_dictionary(r: R, dictionary: &[u8]) -> io::Result<DeflateDecoder<R>> {
+        let inner = bufread::DeflateDecoder::new_with_dictionary(BufReader::new(r), dictionary)?;
+        Ok(DeflateDecoder { inner })
+    }
 }
 
 impl<R> DeflateDecoder<R> {
@@ -220,11 +264,44 @@ impl<R> DeflateDecoder<R> {
     ///
     /// Note that there may be buffered bytes which are not re-acquired as part
     /// of this transition. It's recommended to only call this function after
-    /// EOF has been reached.
+    /// EOF has been reached, or to use `into_parts` instead.
     pub fn into_inner(self) -> R {
         self.inner.into_inner().into_inner()
     }
 
+    /// Consumes this decoder, returning the underlying reader along with the
+    /// bytes which were read from it but not decompressed.
+    ///
+    /// After the end of the stream has been reached, these bytes followed by
+    /// the rest of the underlying reader are exactly the data following the
+    /// compressed stream. To leave the underlying reader positioned right
+    /// after the stream instead, create the decoder with `new_with_buf` and a
+    /// one byte buffer, at the cost of reading the input a byte at a time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::prelude::*;
+    /// use flate2::Compression;
+    /// use flate2::read::DeflateDecoder;
+    /// use flate2::write::DeflateEncoder;
+    ///
+    /// let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+    /// e.write_all(b"Hello World").unwrap();
+    /// let mut bytes = e.finish().unwrap();
+    /// bytes.extend_from_slice(b"more data");
+    ///
+    /// let mut d = DeflateDecoder::new(&bytes[..]);
+    /// let mut s = String::new();
+    /// d.read_to_string(&mut s).unwrap();
+    /// let (rest, buffered) = d.into_parts();
+    /// assert_eq!(buffered, b"more data");
+    /// assert!(rest.is_empty());
+    /// ```
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        self.inner.into_inner().into_parts()
+    }
+
     /// Returns the number of bytes that the decompressor has consumed.
     ///
     /// Note that this will likely be smaller than what the decompressor
@@ -237,6 +314,14 @@ impl<R> DeflateDecoder<R> {
     pub fn total_out(&self) -> u64 {
         self.inner.total_out()
     }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
 }
 
 impl<R: Read> Read for DeflateDecoder<R> {
@@ -6,8 +6,9 @@ use futures::Poll;
 #[cfg(feature = "tokio")]
 use tokio_io::{AsyncRead, AsyncWrite};
 
+use crate::dictionary;
 use crate::zio;
-use crate::{Compress, Decompress};
+use crate::{Compress, CompressOptions, Decompress, DecompressLimits};
 
 /// A DEFLATE encoder, or compressor.
 ///
@@ -48,6 +49,37 @@ impl<W: Write> DeflateEncoder<W> {
         }
     }
 
+    /// Creates a new encoder like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(w: W, options: &CompressOptions) -> io::Result<DeflateEncoder<W>> {
+        Ok(DeflateEncoder {
+            inner: zio::Writer::new(w, Compress::new_with_options(options, false)?),
+        })
+    }
+
+    /// Creates a new encoder like `new`, which compresses with the given
+    /// preset dictionary.
+    ///
+    /// The same dictionary is needed to decompress the data. It is discarded
+    /// by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the backend in use doesn't
+    /// support dictionaries.
+    pub fn new_with_dictionary(
+        w: W,
+        level: crate::Compression,
+        dictionary: &[u8],
+    ) -> io::Result<DeflateEncoder<W>> {
+        Ok(DeflateEncoder {
+            inner: zio::Writer::new(w, dictionary::compressor(level, false, dictionary)?),
+        })
+    }
+
     /// Acquires a reference to the underlying writer.
     pub fn get_ref(&self) -> &W {
         self.inner.get_ref()
@@ -232,6 +264,19 @@ impl<W: Write> DeflateDecoder<W> {
         }
     }
 
+    /// Creates a new decoder like `new`, for data compressed with the given
+    /// preset dictionary. The dictionary is discarded by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the backend in use doesn't
+    /// support dictionaries.
+    pub fn new_with_dictionary(w: W, dictionary: &[u8]) -> io::Result<DeflateDecoder<W>> {
+        Ok(DeflateDecoder {
+            inner: zio::Writer::new(w, dictionary::raw_decompressor(dictionary)?),
+        })
+    }
+
     /// Acquires a reference to the underlying writer.
     pub fn get_ref(&self) -> &W {
         self.inner.get_ref()
@@ -319,6 +364,14 @@ impl<W: Write> DeflateDecoder<W> {
     pub fn total_out(&self) -> u64 {
         self.inner.data.total_out()
     }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.data.set_limits(limits);
+    }
 }
 
 impl<W: Write> Write for DeflateDecoder<W> {
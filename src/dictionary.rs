@@ -0,0 +1,258 @@
+//! Preset dictionaries for zlib and raw DEFLATE streams.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+use crate::{Compress, Compression, Decompress, DecompressError};
+
+/// A set of preset dictionaries, identified by their Adler-32 checksum.
+///
+/// A zlib stream compressed with a preset dictionary records the checksum of
+/// the dictionary in its header (the `DICTID` field). The zlib decoders look
+/// the dictionary up in the registry given to their `set_dictionaries`
+/// method, so streams compressed with any of several dictionaries can be
+/// decoded without knowing ahead of time which one they need.
+///
+/// Cloning a registry doesn't copy the dictionaries themselves.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, DictionaryRegistry};
+/// use flate2::read::ZlibDecoder;
+/// use flate2::write::ZlibEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// # // miniz.c has no support for preset dictionaries.
+/// # if cfg!(all(feature = "miniz-sys", not(feature = "any_zlib"))) {
+/// #     return Ok(());
+/// # }
+/// let mut dictionaries = DictionaryRegistry::new();
+/// dictionaries.insert(&b"content-type: text/html"[..]);
+/// dictionaries.insert(&b"accept-encoding: gzip"[..]);
+///
+/// let dictionary = b"accept-encoding: gzip";
+/// let mut e = ZlibEncoder::new_with_dictionary(Vec::new(), Compression::default(), dictionary)?;
+/// e.write_all(b"accept-encoding: gzip, deflate")?;
+/// let bytes = e.finish()?;
+///
+/// let mut d = ZlibDecoder::new(&bytes[..]);
+/// d.set_dictionaries(dictionaries);
+/// let mut s = String::new();
+/// d.read_to_string(&mut s)?;
+/// assert_eq!(s, "accept-encoding: gzip, deflate");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Default)]
+pub struct DictionaryRegistry {
+    dictionaries: BTreeMap<u32, Arc<[u8]>>,
+}
+
+impl DictionaryRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> DictionaryRegistry {
+        DictionaryRegistry::default()
+    }
+
+    /// Adds a dictionary, returning its Adler-32 checksum, which is how the
+    /// header of a zlib stream refers to it.
+    ///
+    /// A dictionary with the same checksum is replaced.
+    pub fn insert<D: Into<Vec<u8>>>(&mut self, dictionary: D) -> u32 {
+        let dictionary = dictionary.into();
+        let id = adler32(&dictionary);
+        self.dictionaries.insert(id, Arc::from(dictionary));
+        id
+    }
+
+    /// Returns the dictionary with the given Adler-32 checksum, if any.
+    pub fn get(&self, id: u32) -> Option<&[u8]> {
+        self.dictionaries.get(&id).map(|d| &d[..])
+    }
+
+    /// Returns the number of dictionaries in the registry.
+    pub fn len(&self) -> usize {
+        self.dictionaries.len()
+    }
+
+    /// Returns whether the registry has no dictionaries.
+    pub fn is_empty(&self) -> bool {
+        self.dictionaries.is_empty()
+    }
+
+    /// Gives `data` the dictionary that `err` says is needed, returning
+    /// whether it was found and accepted.
+    pub(crate) fn supply(&self, data: &mut Decompress, err: &io::Error) -> bool {
+        let id = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<DecompressError>())
+            .and_then(DecompressError::needs_dictionary);
+        match id.and_then(|id| self.get(id)) {
+            Some(dictionary) => data.set_dictionary(dictionary).is_ok(),
+            None => false,
+        }
+    }
+}
+
+impl fmt::Debug for DictionaryRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ids = self.dictionaries.keys().collect::<Vec<_>>();
+        f.debug_struct("DictionaryRegistry")
+            .field("ids", &ids)
+            .finish()
+    }
+}
+
+/// Creates a compressor primed with a preset dictionary.
+pub(crate) fn compressor(
+    level: Compression,
+    zlib_header: bool,
+    dictionary: &[u8],
+) -> io::Result<Compress> {
+    let mut data = Compress::new(level, zlib_header);
+    data.set_dictionary(dictionary)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(data)
+}
+
+/// Creates a decompressor for raw DEFLATE streams primed with a preset
+/// dictionary.
+pub(crate) fn raw_decompressor(dictionary: &[u8]) -> io::Result<Decompress> {
+    let mut data = Decompress::new(false);
+    data.set_dictionary(dictionary)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok(data)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    // The largest number of bytes before `b` could overflow.
+    const CHUNK: usize = 5552;
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(CHUNK) {
+        for &byte in chunk {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    use std::io;
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    use std::io::prelude::*;
+
+    use super::adler32;
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    use super::DictionaryRegistry;
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    use crate::{bufread, read, write, Compression};
+
+    #[test]
+    fn checksum() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        let big = vec![0xff; 100_000];
+        let slow = big.iter().fold((1u64, 0u64), |(a, b), &x| {
+            let a = (a + u64::from(x)) % 65521;
+            (a, (b + a) % 65521)
+        });
+        assert_eq!(adler32(&big), ((slow.1 << 16) | slow.0) as u32);
+    }
+
+    #[test]
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    fn zlib_decoders_pick_dictionary() {
+        let dictionaries = [&b"first dictionary of words"[..], b"second dictionary"];
+        let mut registry = DictionaryRegistry::new();
+        for d in &dictionaries {
+            registry.insert(*d);
+        }
+        assert_eq!(registry.len(), 2);
+
+        for d in &dictionaries {
+            let data = b"second dictionary of words".repeat(10);
+            let mut e = write::ZlibEncoder::new_with_dictionary(Vec::new(), Compression::best(), d)
+                .unwrap();
+            e.write_all(&data).unwrap();
+            let bytes = e.finish().unwrap();
+
+            let mut r = read::ZlibDecoder::new(&bytes[..]);
+            let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+            let mut out = Vec::new();
+            let mut r = read::ZlibDecoder::new_with_buf(&bytes[..], vec![0; 3]);
+            r.set_dictionaries(registry.clone());
+            r.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+
+            let mut out = Vec::new();
+            let mut r = bufread::ZlibDecoder::new(&bytes[..]);
+            r.set_dictionaries(registry.clone());
+            r.read_to_end(&mut out).unwrap();
+            assert_eq!(out, data);
+
+            let mut w = write::ZlibDecoder::new(Vec::new());
+            w.set_dictionaries(registry.clone());
+            for chunk in bytes.chunks(4) {
+                w.write_all(chunk).unwrap();
+            }
+            assert_eq!(w.finish().unwrap(), data);
+        }
+
+        let mut e =
+            write::ZlibEncoder::new_with_dictionary(Vec::new(), Compression::best(), b"x").unwrap();
+        e.write_all(b"xxxx").unwrap();
+        let bytes = e.finish().unwrap();
+        let mut r = read::ZlibDecoder::new(&bytes[..]);
+        r.set_dictionaries(registry);
+        let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+        let inner = err.get_ref().unwrap();
+        let inner = inner.downcast_ref::<crate::DecompressError>().unwrap();
+        assert_eq!(inner.needs_dictionary(), Some(adler32(b"x")));
+    }
+
+    #[test]
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    fn deflate_with_dictionary() {
+        let dictionary = b"the quick brown fox jumps over the lazy dog";
+        let data = b"the lazy dog jumps over the quick brown fox".repeat(4);
+
+        let mut e =
+            read::DeflateEncoder::new_with_dictionary(&data[..], Compression::best(), dictionary)
+                .unwrap();
+        let mut bytes = Vec::new();
+        e.read_to_end(&mut bytes).unwrap();
+
+        let mut w =
+            write::DeflateEncoder::new_with_dictionary(Vec::new(), Compression::best(), dictionary)
+                .unwrap();
+        w.write_all(&data).unwrap();
+        assert_eq!(w.finish().unwrap(), bytes);
+
+        let mut out = Vec::new();
+        read::DeflateDecoder::new_with_dictionary(&bytes[..], dictionary)
+            .unwrap()
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, data);
+
+        let mut w = write::DeflateDecoder::new_with_dictionary(Vec::new(), dictionary).unwrap();
+        w.write_all(&bytes).unwrap();
+        assert_eq!(w.finish().unwrap(), data);
+
+        let mut out = Vec::new();
+        let result = read::DeflateDecoder::new(&bytes[..]).read_to_end(&mut out);
+        assert!(result.is_err() || out != data);
+    }
+}
@@ -10,7 +10,7 @@ use std::ptr;
 pub use libc::{c_int, c_uint, c_void, size_t};
 
 use super::*;
-use crate::mem::{self, FlushDecompress, Status};
+use crate::mem::{self, DecompressErrorKind, FlushDecompress, Status};
 
 pub struct StreamWrapper {
     pub inner: Box<mz_stream>,
@@ -160,6 +160,8 @@ impl Direction for DirDecompress {
 #[derive(Debug)]
 pub struct Inflate {
     pub inner: Stream<DirDecompress>,
+    #[cfg(feature = "any_zlib")]
+    window_bits: u8,
 }
 
 impl InflateBackend for Inflate {
@@ -168,6 +170,13 @@ impl InflateBackend for Inflate {
             window_bits > 8 && window_bits < 16,
             "window_bits must be within 9 ..= 15"
         );
+        // miniz.c only supports 32 KiB windows, which can decode any stream
+        // written with a smaller window.
+        let window_bits = if cfg!(feature = "any_zlib") {
+            window_bits
+        } else {
+            MZ_DEFAULT_WINDOW_BITS as u8
+        };
         unsafe {
             let mut state = StreamWrapper::default();
             let ret = mz_inflateInit2(
@@ -186,6 +195,8 @@ impl InflateBackend for Inflate {
                     total_out: 0,
                     _marker: marker::PhantomData,
                 },
+                #[cfg(feature = "any_zlib")]
+                window_bits,
             }
         }
     }
@@ -210,7 +221,8 @@ impl InflateBackend for Inflate {
         self.inner.total_out += (raw.next_out as usize - output.as_ptr() as usize) as u64;
 
         match rc {
-            MZ_DATA_ERROR | MZ_STREAM_ERROR => mem::decompress_failed(),
+            MZ_DATA_ERROR => mem::decompress_failed(error_kind(raw)),
+            MZ_STREAM_ERROR => mem::decompress_failed(DecompressErrorKind::Corrupt),
             MZ_OK => Ok(Status::Ok),
             MZ_BUF_ERROR => Ok(Status::BufError),
             MZ_STREAM_END => Ok(Status::StreamEnd),
@@ -222,9 +234,9 @@ impl InflateBackend for Inflate {
     #[cfg(feature = "any_zlib")]
     fn reset(&mut self, zlib_header: bool) {
         let bits = if zlib_header {
-            MZ_DEFAULT_WINDOW_BITS
+            self.window_bits as c_int
         } else {
-            -MZ_DEFAULT_WINDOW_BITS
+            -(self.window_bits as c_int)
         };
         unsafe {
             inflateReset2(&mut *self.inner.stream_wrapper, bits);
@@ -237,6 +249,70 @@ impl InflateBackend for Inflate {
     fn reset(&mut self, zlib_header: bool) {
         *self = Self::make(zlib_header, MZ_DEFAULT_WINDOW_BITS as u8);
     }
+
+    #[cfg(feature = "any_zlib")]
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, DecompressError> {
+        let stream = &mut *self.inner.stream_wrapper;
+        let rc = unsafe {
+            assert!(dictionary.len() < uInt::max_value() as usize);
+            inflateSetDictionary(stream, dictionary.as_ptr(), dictionary.len() as uInt)
+        };
+
+        match rc {
+            MZ_STREAM_ERROR => Err(DecompressError::new(DecompressErrorKind::Corrupt)),
+            MZ_DATA_ERROR => {
+                let mut err = DecompressError::new(DecompressErrorKind::NeedsDictionary);
+                err.0.needs_dictionary = Some(stream.adler as u32);
+                Err(err)
+            }
+            MZ_OK => Ok(stream.adler as u32),
+            c => panic!("unknown return code: {}", c),
+        }
+    }
+
+    /// miniz.c has no support for preset dictionaries.
+    #[cfg(not(feature = "any_zlib"))]
+    fn set_dictionary(&mut self, _dictionary: &[u8]) -> Result<u32, DecompressError> {
+        Err(DecompressError::new(DecompressErrorKind::Corrupt))
+    }
+}
+
+/// Maps the message zlib leaves in the stream after a data error to the kind
+/// of the error.
+#[cfg(feature = "any_zlib")]
+fn error_kind(raw: &mz_stream) -> DecompressErrorKind {
+    if raw.msg.is_null() {
+        return DecompressErrorKind::Corrupt;
+    }
+    let msg = unsafe { std::ffi::CStr::from_ptr(raw.msg) };
+    match msg.to_bytes() {
+        b"invalid block type" => DecompressErrorKind::InvalidBlockType,
+        b"invalid stored block lengths" => DecompressErrorKind::InvalidStoredBlock,
+        b"invalid distance too far back" => DecompressErrorKind::DistanceTooFarBack,
+        b"too many length or distance symbols"
+        | b"invalid code lengths set"
+        | b"invalid bit length repeat"
+        | b"invalid code -- missing end-of-block"
+        | b"invalid literal/lengths set"
+        | b"invalid distances set"
+        | b"invalid literal/length code"
+        | b"invalid distance code" => DecompressErrorKind::InvalidHuffmanCode,
+        // The stored checksum is filled in by `Decompress`.
+        b"incorrect data check" => DecompressErrorKind::ChecksumMismatch {
+            expected: 0,
+            actual: raw.adler as u32,
+        },
+        b"incorrect header check" => DecompressErrorKind::BadMagic,
+        b"unknown compression method" => DecompressErrorKind::UnsupportedMethod,
+        b"invalid window size" => DecompressErrorKind::InvalidHeader,
+        _ => DecompressErrorKind::Corrupt,
+    }
+}
+
+/// Miniz does not say why decompression failed.
+#[cfg(not(feature = "any_zlib"))]
+fn error_kind(_raw: &mz_stream) -> DecompressErrorKind {
+    DecompressErrorKind::Corrupt
 }
 
 impl Backend for Inflate {
@@ -254,10 +330,15 @@ impl Backend for Inflate {
 #[derive(Debug)]
 pub struct Deflate {
     pub inner: Stream<DirCompress>,
+    /// The second byte of the zlib header as zlib would write it, if the
+    /// stream has one.
+    #[cfg(not(feature = "any_zlib"))]
+    flg: Option<u8>,
 }
 
 impl DeflateBackend for Deflate {
-    fn make(level: Compression, zlib_header: bool, window_bits: u8) -> Self {
+    fn make(options: &CompressOptions, zlib_header: bool) -> Self {
+        let window_bits = options.window_bits;
         assert!(
             window_bits > 8 && window_bits < 16,
             "window_bits must be within 9 ..= 15"
@@ -266,15 +347,15 @@ impl DeflateBackend for Deflate {
             let mut state = StreamWrapper::default();
             let ret = mz_deflateInit2(
                 &mut *state,
-                level.0 as c_int,
+                options.level.0 as c_int,
                 MZ_DEFLATED,
                 if zlib_header {
                     window_bits as c_int
                 } else {
                     -(window_bits as c_int)
                 },
-                8,
-                MZ_DEFAULT_STRATEGY,
+                options.mem_level as c_int,
+                options.strategy as c_int,
             );
             assert_eq!(ret, 0);
             Deflate {
@@ -284,6 +365,13 @@ impl DeflateBackend for Deflate {
                     total_out: 0,
                     _marker: marker::PhantomData,
                 },
+                #[cfg(not(feature = "any_zlib"))]
+                flg: if zlib_header {
+                    let level = header_level(options.level.0 as u8, options.strategy);
+                    Some(add_fcheck(0x78, level << 6))
+                } else {
+                    None
+                },
             }
         }
     }
@@ -303,8 +391,21 @@ impl DeflateBackend for Deflate {
 
         // Unfortunately the total counters provided by zlib might be only
         // 32 bits wide and overflow while processing large amounts of data.
+        let written = raw.next_out as usize - output.as_ptr() as usize;
         self.inner.total_in += (raw.next_in as usize - input.as_ptr() as usize) as u64;
-        self.inner.total_out += (raw.next_out as usize - output.as_ptr() as usize) as u64;
+        self.inner.total_out += written as u64;
+
+        // miniz.c derives the level in the zlib header differently than zlib
+        // does, so rewrite it.
+        #[cfg(not(feature = "any_zlib"))]
+        {
+            let before = self.inner.total_out - written as u64;
+            if let Some(flg) = self.flg {
+                if before < 2 && self.inner.total_out >= 2 {
+                    output[1 - before as usize] = flg;
+                }
+            }
+        }
 
         match rc {
             MZ_OK => Ok(Status::Ok),
@@ -321,6 +422,46 @@ impl DeflateBackend for Deflate {
         let rc = unsafe { mz_deflateReset(&mut *self.inner.stream_wrapper) };
         assert_eq!(rc, MZ_OK);
     }
+
+    #[cfg(feature = "any_zlib")]
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, CompressError> {
+        let stream = &mut *self.inner.stream_wrapper;
+        let rc = unsafe {
+            assert!(dictionary.len() < uInt::max_value() as usize);
+            deflateSetDictionary(stream, dictionary.as_ptr(), dictionary.len() as uInt)
+        };
+
+        match rc {
+            MZ_STREAM_ERROR => Err(CompressError(())),
+            MZ_OK => Ok(stream.adler as u32),
+            c => panic!("unknown return code: {}", c),
+        }
+    }
+
+    #[cfg(feature = "any_zlib")]
+    fn set_level(&mut self, level: Compression) -> Result<(), CompressError> {
+        let stream = &mut *self.inner.stream_wrapper;
+
+        let rc = unsafe { deflateParams(stream, level.0 as c_int, MZ_DEFAULT_STRATEGY) };
+
+        match rc {
+            MZ_OK => Ok(()),
+            MZ_BUF_ERROR => Err(CompressError(())),
+            c => panic!("unknown return code: {}", c),
+        }
+    }
+
+    /// miniz.c has no support for preset dictionaries.
+    #[cfg(not(feature = "any_zlib"))]
+    fn set_dictionary(&mut self, _dictionary: &[u8]) -> Result<u32, CompressError> {
+        Err(CompressError(()))
+    }
+
+    /// miniz.c can't change parameters of a stream.
+    #[cfg(not(feature = "any_zlib"))]
+    fn set_level(&mut self, _level: Compression) -> Result<(), CompressError> {
+        Err(CompressError(()))
+    }
 }
 
 impl Backend for Deflate {
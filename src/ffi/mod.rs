@@ -1,6 +1,9 @@
 //! This module contains backend-specific code.
 
-use crate::mem::{CompressError, DecompressError, FlushCompress, FlushDecompress, Status};
+#[cfg(not(feature = "any_zlib"))]
+use crate::mem::Strategy;
+use crate::mem::{CompressError, CompressOptions, DecompressError, Status};
+use crate::mem::{FlushCompress, FlushDecompress};
 use crate::Compression;
 
 /// Traits specifying the interface of the backends.
@@ -21,10 +24,11 @@ pub trait InflateBackend: Backend {
         flush: FlushDecompress,
     ) -> Result<Status, DecompressError>;
     fn reset(&mut self, zlib_header: bool);
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, DecompressError>;
 }
 
 pub trait DeflateBackend: Backend {
-    fn make(level: Compression, zlib_header: bool, window_bits: u8) -> Self;
+    fn make(options: &CompressOptions, zlib_header: bool) -> Self;
     fn compress(
         &mut self,
         input: &[u8],
@@ -32,6 +36,35 @@ pub trait DeflateBackend: Backend {
         flush: FlushCompress,
     ) -> Result<Status, CompressError>;
     fn reset(&mut self);
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, CompressError>;
+    fn set_level(&mut self, level: Compression) -> Result<(), CompressError>;
+}
+
+/// Fills in the check bits of the second byte of a zlib header.
+#[cfg(not(feature = "any_zlib"))]
+fn add_fcheck(cmf: u8, flg: u8) -> u8 {
+    let flg = flg & !0x1f;
+    let rem = ((u16::from(cmf) << 8) | u16::from(flg)) % 31;
+    if rem == 0 {
+        flg
+    } else {
+        flg | (31 - rem) as u8
+    }
+}
+
+/// The compression level recorded in the zlib header, as zlib sets it. The
+/// miniz backends derive it differently, so their headers are rewritten.
+#[cfg(not(feature = "any_zlib"))]
+fn header_level(level: u8, strategy: Strategy) -> u8 {
+    if strategy as i32 >= Strategy::HuffmanOnly as i32 || level < 2 {
+        0
+    } else if level < 6 {
+        1
+    } else if level == 6 {
+        2
+    } else {
+        3
+    }
 }
 
 // Default to Rust implementation unless explicitly opted in to a different backend.
@@ -1,10 +1,12 @@
 //! Implementation for miniz_oxide rust backend.
 
+use std::cmp;
 use std::convert::TryInto;
 use std::fmt;
 
-use miniz_oxide::deflate::core::CompressorOxide;
-use miniz_oxide::inflate::stream::InflateState;
+use miniz_oxide::deflate::core::{create_comp_flags_from_zip_params, CompressorOxide};
+use miniz_oxide::inflate::core::TINFL_LZ_DICT_SIZE;
+use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide};
 pub use miniz_oxide::*;
 
 pub const MZ_NO_FLUSH: isize = MZFlush::None as isize;
@@ -14,15 +16,13 @@ pub const MZ_FULL_FLUSH: isize = MZFlush::Full as isize;
 pub const MZ_FINISH: isize = MZFlush::Finish as isize;
 
 use super::*;
-use crate::mem;
+use crate::mem::{self, DecompressErrorKind, Strategy};
+use crate::zlib;
+use miniz_oxide::inflate::TINFLStatus;
 
-fn format_from_bool(zlib_header: bool) -> DataFormat {
-    if zlib_header {
-        DataFormat::Zlib
-    } else {
-        DataFormat::Raw
-    }
-}
+/// The flag in the second byte of a zlib header which is set when the stream
+/// was compressed with a preset dictionary.
+const FDICT: u8 = 0x20;
 
 pub struct Inflate {
     inner: Box<InflateState>,
@@ -30,6 +30,54 @@ pub struct Inflate {
     total_out: u64,
 }
 
+/// The state of a decompression stream.
+///
+/// This follows `miniz_oxide::inflate::stream`, except that the zlib header is
+/// parsed here and the window is kept within reach, both of which are needed
+/// to support preset dictionaries.
+struct InflateState {
+    decomp: DecompressorOxide,
+
+    /// The window of recently decompressed data, some of which may not have
+    /// been copied out yet.
+    dict: [u8; TINFL_LZ_DICT_SIZE],
+    dict_ofs: usize,
+    dict_avail: usize,
+
+    first_call: bool,
+    has_flushed: bool,
+    last_status: TINFLStatus,
+
+    zlib_header: bool,
+    window_bits: u8,
+    header: Header,
+}
+
+/// Progress through the zlib header of a stream.
+enum Header {
+    /// The stream has no header, or it was passed on to miniz_oxide.
+    Done,
+
+    /// The first `len` bytes of the header have been read.
+    Partial { buf: [u8; 6], len: usize },
+
+    /// The header asks for a preset dictionary with the given checksum.
+    NeedDict { cmf: u8, flg: u8, dictid: u32 },
+}
+
+impl Header {
+    fn new(zlib_header: bool) -> Header {
+        if zlib_header {
+            Header::Partial {
+                buf: [0; 6],
+                len: 0,
+            }
+        } else {
+            Header::Done
+        }
+    }
+}
+
 impl fmt::Debug for Inflate {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         write!(
@@ -40,6 +88,230 @@ impl fmt::Debug for Inflate {
     }
 }
 
+impl InflateState {
+    fn reset(&mut self, zlib_header: bool, window_bits: u8) {
+        self.decomp.init();
+        self.dict_ofs = 0;
+        self.dict_avail = 0;
+        self.first_call = true;
+        self.has_flushed = false;
+        self.last_status = TINFLStatus::NeedsMoreInput;
+        self.zlib_header = zlib_header;
+        self.window_bits = window_bits;
+        self.header = Header::new(zlib_header);
+    }
+
+    /// Reads as much of the zlib header as is needed from the start of
+    /// `input`, returning the number of bytes consumed and the result to
+    /// return early with, if any.
+    fn read_header(&mut self, input: &[u8]) -> (usize, Option<Result<Status, DecompressError>>) {
+        let mut consumed = 0;
+        loop {
+            let (buf, len) = match self.header {
+                Header::Done => return (consumed, None),
+                Header::NeedDict { dictid, .. } => {
+                    return (consumed, Some(mem::decompress_need_dict(dictid)))
+                }
+                Header::Partial {
+                    ref mut buf,
+                    ref mut len,
+                } => (buf, len),
+            };
+            let need = if *len >= 2 && buf[1] & FDICT != 0 {
+                6
+            } else {
+                2
+            };
+            if *len < need {
+                let n = cmp::min(need - *len, input.len() - consumed);
+                buf[*len..*len + n].copy_from_slice(&input[consumed..consumed + n]);
+                *len += n;
+                consumed += n;
+                if *len < need {
+                    let status = if consumed > 0 {
+                        Status::Ok
+                    } else {
+                        Status::BufError
+                    };
+                    return (consumed, Some(Ok(status)));
+                }
+                if need == 2 {
+                    let kind = match zlib::header_error(buf[0], buf[1], self.window_bits) {
+                        Some(kind) => kind,
+                        // Read the dictionary checksum next, if there is one.
+                        None => continue,
+                    };
+                    self.header = Header::Done;
+                    self.last_status = TINFLStatus::Failed;
+                    return (consumed, Some(mem::decompress_failed(kind)));
+                }
+            }
+
+            let (cmf, flg) = (buf[0], buf[1]);
+            if flg & FDICT != 0 {
+                let dictid = u32::from_be_bytes([buf[2], buf[3], buf[4], buf[5]]);
+                self.header = Header::NeedDict { cmf, flg, dictid };
+            } else {
+                self.start(cmf, flg);
+            }
+        }
+    }
+
+    /// Passes a zlib header on to miniz_oxide, which doesn't support preset
+    /// dictionaries, so the flag for them is cleared.
+    fn start(&mut self, cmf: u8, flg: u8) {
+        let header = [cmf, add_fcheck(cmf, flg & !FDICT)];
+        let flags = inflate_flags::TINFL_FLAG_PARSE_ZLIB_HEADER
+            | inflate_flags::TINFL_FLAG_HAS_MORE_INPUT
+            | inflate_flags::TINFL_FLAG_COMPUTE_ADLER32;
+        let (status, _, _) = decompress(
+            &mut self.decomp,
+            &header,
+            &mut self.dict,
+            self.dict_ofs,
+            flags,
+        );
+        debug_assert_eq!(status, TINFLStatus::NeedsMoreInput);
+        self.header = Header::Done;
+    }
+
+    fn inflate(&mut self, input: &[u8], output: &mut [u8], flush: MZFlush) -> StreamResult {
+        let mut bytes_consumed = 0;
+        let mut bytes_written = 0;
+        let mut next_in = input;
+        let mut next_out = output;
+
+        if flush == MZFlush::Full {
+            return stream_error(MZError::Stream);
+        }
+
+        let mut decomp_flags = inflate_flags::TINFL_FLAG_COMPUTE_ADLER32;
+        if self.zlib_header {
+            decomp_flags |= inflate_flags::TINFL_FLAG_PARSE_ZLIB_HEADER;
+        }
+
+        let first_call = self.first_call;
+        self.first_call = false;
+        if (self.last_status as i32) < 0 {
+            return stream_error(MZError::Data);
+        }
+
+        if self.has_flushed && (flush != MZFlush::Finish) {
+            return stream_error(MZError::Stream);
+        }
+        self.has_flushed |= flush == MZFlush::Finish;
+
+        if (flush == MZFlush::Finish) && first_call {
+            decomp_flags |= inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+
+            let (status, in_bytes, out_bytes) =
+                decompress(&mut self.decomp, next_in, next_out, 0, decomp_flags);
+            self.last_status = status;
+
+            let status = if (status as i32) < 0 {
+                Err(MZError::Data)
+            } else if status != TINFLStatus::Done {
+                self.last_status = TINFLStatus::Failed;
+                Err(MZError::Buf)
+            } else {
+                Ok(MZStatus::StreamEnd)
+            };
+            return StreamResult {
+                bytes_consumed: in_bytes,
+                bytes_written: out_bytes,
+                status,
+            };
+        }
+
+        if flush != MZFlush::Finish {
+            decomp_flags |= inflate_flags::TINFL_FLAG_HAS_MORE_INPUT;
+        }
+
+        if self.dict_avail != 0 {
+            bytes_written += self.push_dict_out(&mut next_out);
+            let status = if (self.last_status == TINFLStatus::Done) && (self.dict_avail == 0) {
+                MZStatus::StreamEnd
+            } else {
+                MZStatus::Ok
+            };
+            return StreamResult {
+                bytes_consumed,
+                bytes_written,
+                status: Ok(status),
+            };
+        }
+
+        let orig_in_len = next_in.len();
+        let status = loop {
+            let (status, in_bytes, out_bytes) = decompress(
+                &mut self.decomp,
+                next_in,
+                &mut self.dict,
+                self.dict_ofs,
+                decomp_flags,
+            );
+            self.last_status = status;
+
+            next_in = &next_in[in_bytes..];
+            bytes_consumed += in_bytes;
+
+            self.dict_avail = out_bytes;
+            bytes_written += self.push_dict_out(&mut next_out);
+
+            if (status as i32) < 0 {
+                break Err(MZError::Data);
+            }
+
+            if (status == TINFLStatus::NeedsMoreInput) && orig_in_len == 0 {
+                break Err(MZError::Buf);
+            }
+
+            if flush == MZFlush::Finish {
+                if status == TINFLStatus::Done {
+                    break if self.dict_avail != 0 {
+                        Err(MZError::Buf)
+                    } else {
+                        Ok(MZStatus::StreamEnd)
+                    };
+                } else if next_out.is_empty() {
+                    break Err(MZError::Buf);
+                }
+            } else {
+                let empty_buf = next_in.is_empty() || next_out.is_empty();
+                if (status == TINFLStatus::Done) || empty_buf || (self.dict_avail != 0) {
+                    break if (status == TINFLStatus::Done) && (self.dict_avail == 0) {
+                        Ok(MZStatus::StreamEnd)
+                    } else {
+                        Ok(MZStatus::Ok)
+                    };
+                }
+            }
+        };
+        StreamResult {
+            bytes_consumed,
+            bytes_written,
+            status,
+        }
+    }
+
+    fn push_dict_out(&mut self, next_out: &mut &mut [u8]) -> usize {
+        let n = cmp::min(self.dict_avail, next_out.len());
+        next_out[..n].copy_from_slice(&self.dict[self.dict_ofs..self.dict_ofs + n]);
+        *next_out = &mut std::mem::take(next_out)[n..];
+        self.dict_avail -= n;
+        self.dict_ofs = (self.dict_ofs + n) & (TINFL_LZ_DICT_SIZE - 1);
+        n
+    }
+}
+
+fn stream_error(error: MZError) -> StreamResult {
+    StreamResult {
+        bytes_consumed: 0,
+        bytes_written: 0,
+        status: Err(error),
+    }
+}
+
 impl InflateBackend for Inflate {
     fn make(zlib_header: bool, window_bits: u8) -> Self {
         assert!(
@@ -47,10 +319,22 @@ impl InflateBackend for Inflate {
             "window_bits must be within 9 ..= 15"
         );
 
-        let format = format_from_bool(zlib_header);
+        let mut inner = Box::new(InflateState {
+            decomp: DecompressorOxide::new(),
+            dict: [0; TINFL_LZ_DICT_SIZE],
+            dict_ofs: 0,
+            dict_avail: 0,
+            first_call: true,
+            has_flushed: false,
+            last_status: TINFLStatus::NeedsMoreInput,
+            zlib_header,
+            window_bits,
+            header: Header::Done,
+        });
+        inner.reset(zlib_header, window_bits);
 
         Inflate {
-            inner: InflateState::new_boxed(format),
+            inner,
             total_in: 0,
             total_out: 0,
         }
@@ -64,7 +348,13 @@ impl InflateBackend for Inflate {
     ) -> Result<Status, DecompressError> {
         let flush = MZFlush::new(flush as i32).unwrap();
 
-        let res = inflate::stream::inflate(&mut self.inner, input, output, flush);
+        let (header_len, early) = self.inner.read_header(input);
+        self.total_in += header_len as u64;
+        if let Some(result) = early {
+            return result;
+        }
+
+        let res = self.inner.inflate(&input[header_len..], output, flush);
         self.total_in += res.bytes_consumed as u64;
         self.total_out += res.bytes_written as u64;
 
@@ -73,21 +363,56 @@ impl InflateBackend for Inflate {
                 MZStatus::Ok => Ok(Status::Ok),
                 MZStatus::StreamEnd => Ok(Status::StreamEnd),
                 MZStatus::NeedDict => {
-                    mem::decompress_need_dict(self.inner.decompressor().adler32().unwrap_or(0))
+                    mem::decompress_need_dict(self.inner.decomp.adler32().unwrap_or(0))
                 }
             },
             Err(status) => match status {
                 MZError::Buf => Ok(Status::BufError),
-                _ => mem::decompress_failed(),
+                // The stored checksum is filled in by `Decompress`.
+                _ if self.inner.last_status == TINFLStatus::Adler32Mismatch => {
+                    mem::decompress_failed(DecompressErrorKind::ChecksumMismatch {
+                        expected: 0,
+                        actual: self.inner.decomp.adler32().unwrap_or(0),
+                    })
+                }
+                _ => mem::decompress_failed(DecompressErrorKind::Corrupt),
             },
         }
     }
 
     fn reset(&mut self, zlib_header: bool) {
-        self.inner.reset(format_from_bool(zlib_header));
+        let window_bits = self.inner.window_bits;
+        self.inner.reset(zlib_header, window_bits);
         self.total_in = 0;
         self.total_out = 0;
     }
+
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, DecompressError> {
+        let adler = mz_adler32_oxide(MZ_ADLER32_INIT, dictionary);
+        let state = &mut *self.inner;
+        match state.header {
+            Header::NeedDict { cmf, flg, dictid } => {
+                if adler != dictid {
+                    let mut err = DecompressError::new(DecompressErrorKind::NeedsDictionary);
+                    err.0.needs_dictionary = Some(dictid);
+                    return Err(err);
+                }
+                state.start(cmf, flg);
+            }
+            // Raw streams can be given a dictionary until they produce output.
+            Header::Done if !state.zlib_header && self.total_out == 0 => {}
+            _ => return Err(DecompressError::new(DecompressErrorKind::Corrupt)),
+        }
+
+        // The window is filled with the end of the dictionary, just as if it
+        // had been decompressed before the stream.
+        let dictionary = &dictionary[dictionary.len().saturating_sub(TINFL_LZ_DICT_SIZE)..];
+        state.dict[..dictionary.len()].copy_from_slice(dictionary);
+        state.dict_ofs = dictionary.len() & (TINFL_LZ_DICT_SIZE - 1);
+        // Decompressing straight into the output would lose the dictionary.
+        state.first_call = false;
+        Ok(adler)
+    }
 }
 
 impl Backend for Inflate {
@@ -106,6 +431,23 @@ pub struct Deflate {
     inner: Box<CompressorOxide>,
     total_in: u64,
     total_out: u64,
+    level: u8,
+    strategy: Strategy,
+    zlib_header: bool,
+    framing: Option<Framing>,
+}
+
+/// The zlib header and trailer of a stream with a preset dictionary, which
+/// miniz_oxide can't write itself. The compressor writes a raw stream instead
+/// and this wraps it.
+struct Framing {
+    dictid: u32,
+    adler: u32,
+    buf: [u8; 6],
+    pos: usize,
+    len: usize,
+    started: bool,
+    finished: bool,
 }
 
 impl fmt::Debug for Deflate {
@@ -118,24 +460,104 @@ impl fmt::Debug for Deflate {
     }
 }
 
+impl Deflate {
+    fn compressor(level: u8, strategy: Strategy, zlib_header: bool) -> CompressorOxide {
+        // Only the sign of the window bits matters here, selecting the header.
+        let window_bits = if zlib_header {
+            MZ_DEFAULT_WINDOW_BITS
+        } else {
+            -MZ_DEFAULT_WINDOW_BITS
+        };
+        let flags = create_comp_flags_from_zip_params(level.into(), window_bits, strategy as i32);
+        CompressorOxide::new(flags)
+    }
+}
+
+impl Framing {
+    fn deflate(
+        &mut self,
+        inner: &mut CompressorOxide,
+        input: &[u8],
+        output: &mut [u8],
+        flush: MZFlush,
+        level: u8,
+    ) -> StreamResult {
+        if !self.started {
+            let cmf = 0x78;
+            let id = self.dictid.to_be_bytes();
+            self.buf = [
+                cmf,
+                add_fcheck(cmf, (level << 6) | FDICT),
+                id[0],
+                id[1],
+                id[2],
+                id[3],
+            ];
+            self.len = 6;
+            self.started = true;
+        }
+
+        let mut written = self.drain(output);
+        let mut consumed = 0;
+        let mut status = Err(MZError::Buf);
+        if self.pos == self.len && !self.finished {
+            let res = deflate::stream::deflate(inner, input, &mut output[written..], flush);
+            consumed = res.bytes_consumed;
+            written += res.bytes_written;
+            status = res.status;
+            self.adler = mz_adler32_oxide(self.adler, &input[..consumed]);
+            if status == Ok(MZStatus::StreamEnd) {
+                self.buf[..4].copy_from_slice(&self.adler.to_be_bytes());
+                self.pos = 0;
+                self.len = 4;
+                self.finished = true;
+                written += self.drain(&mut output[written..]);
+            }
+        }
+
+        if self.finished && self.pos == self.len {
+            status = Ok(MZStatus::StreamEnd);
+        } else if status == Ok(MZStatus::StreamEnd) || (written > 0 && status.is_err()) {
+            status = Ok(MZStatus::Ok);
+        }
+        StreamResult {
+            bytes_consumed: consumed,
+            bytes_written: written,
+            status,
+        }
+    }
+
+    fn drain(&mut self, output: &mut [u8]) -> usize {
+        let n = cmp::min(self.len - self.pos, output.len());
+        output[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        n
+    }
+}
+
 impl DeflateBackend for Deflate {
-    fn make(level: Compression, zlib_header: bool, window_bits: u8) -> Self {
-        assert!(
-            window_bits > 8 && window_bits < 16,
-            "window_bits must be within 9 ..= 15"
+    fn make(options: &CompressOptions, zlib_header: bool) -> Self {
+        // miniz_oxide always uses a 32 KiB window, so smaller windows are
+        // rejected up front by `CompressOptions::check`.
+        assert_eq!(
+            options.window_bits, 15,
+            "window_bits must be 15 with this backend"
         );
 
         // Check in case the integer value changes at some point.
-        debug_assert!(level.level() <= 10);
+        debug_assert!(options.level.level() <= 10);
 
-        let mut inner: Box<CompressorOxide> = Box::default();
-        let format = format_from_bool(zlib_header);
-        inner.set_format_and_level(format, level.level().try_into().unwrap_or(1));
+        let level = options.level.level().try_into().unwrap_or(1);
+        let inner = Deflate::compressor(level, options.strategy, zlib_header);
 
         Deflate {
-            inner,
+            inner: Box::new(inner),
             total_in: 0,
             total_out: 0,
+            level,
+            strategy: options.strategy,
+            zlib_header,
+            framing: None,
         }
     }
 
@@ -146,7 +568,20 @@ impl DeflateBackend for Deflate {
         flush: FlushCompress,
     ) -> Result<Status, CompressError> {
         let flush = MZFlush::new(flush as i32).unwrap();
-        let res = deflate::stream::deflate(&mut self.inner, input, output, flush);
+        let level = header_level(self.level, self.strategy);
+        let res = match self.framing {
+            Some(ref mut framing) => framing.deflate(&mut self.inner, input, output, flush, level),
+            None => deflate::stream::deflate(&mut self.inner, input, output, flush),
+        };
+        // miniz_oxide derives the level in the zlib header differently than
+        // zlib does, so rewrite it to match the header of a dictionary stream.
+        if self.zlib_header
+            && self.framing.is_none()
+            && self.total_out < 2
+            && self.total_out + res.bytes_written as u64 >= 2
+        {
+            output[1 - self.total_out as usize] = add_fcheck(0x78, level << 6);
+        }
         self.total_in += res.bytes_consumed as u64;
         self.total_out += res.bytes_written as u64;
 
@@ -166,7 +601,65 @@ impl DeflateBackend for Deflate {
     fn reset(&mut self) {
         self.total_in = 0;
         self.total_out = 0;
-        self.inner.reset();
+        if self.framing.take().is_some() {
+            *self.inner = Deflate::compressor(self.level, self.strategy, self.zlib_header);
+        } else {
+            self.inner.reset();
+        }
+    }
+
+    fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, CompressError> {
+        if self.total_in != 0 || self.total_out != 0 {
+            return Err(CompressError(()));
+        }
+        let adler = match self.framing {
+            Some(ref framing) => framing.dictid,
+            None => MZ_ADLER32_INIT,
+        };
+        let adler = mz_adler32_oxide(adler, dictionary);
+        if self.zlib_header && self.framing.is_none() {
+            *self.inner = Deflate::compressor(self.level, self.strategy, false);
+        }
+
+        // Compress the dictionary and throw the output away, leaving the
+        // dictionary in the window for the data that follows to refer to. The
+        // sync flush ends the output on a byte boundary.
+        let mut input = &dictionary[dictionary.len().saturating_sub(TINFL_LZ_DICT_SIZE)..];
+        let mut sink = [0; 1024];
+        loop {
+            let res = deflate::stream::deflate(&mut self.inner, input, &mut sink, MZFlush::Sync);
+            match res.status {
+                Ok(_) | Err(MZError::Buf) => {}
+                Err(_) => return Err(CompressError(())),
+            }
+            input = &input[res.bytes_consumed..];
+            if input.is_empty() && res.bytes_written < sink.len() {
+                break;
+            }
+        }
+
+        if self.zlib_header {
+            self.framing = Some(Framing {
+                dictid: adler,
+                adler: MZ_ADLER32_INIT,
+                buf: [0; 6],
+                pos: 0,
+                len: 0,
+                started: false,
+                finished: false,
+            });
+        }
+        Ok(adler)
+    }
+
+    fn set_level(&mut self, level: Compression) -> Result<(), CompressError> {
+        if level.level() > 10 {
+            return Err(CompressError(()));
+        }
+        self.level = level.level() as u8;
+        self.strategy = Strategy::Default;
+        self.inner.set_compression_level_raw(self.level);
+        Ok(())
     }
 }
 
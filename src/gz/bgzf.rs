@@ -0,0 +1,784 @@
+//! The BGZF (blocked gzip) format used by htslib.
+//!
+//! A BGZF file is a series of gzip members, each at most 64 KiB long and
+//! recording its own size in a `BC` extra subfield, followed by an empty
+//! member which marks the end of the file. Because every member can be
+//! decompressed on its own, positions in the uncompressed data are addressed
+//! by virtual offsets combining the offset of a member in the file with an
+//! offset into its uncompressed data.
+
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use super::bufread::{check_trailer, corrupt, read_gz_header};
+use super::{ExtraSubfields, GzBuilder};
+use crate::crc::Crc;
+use crate::mem::{add_offset, io_error};
+use crate::{Compress, Compression, Decompress, DecompressErrorKind, DecompressLimits};
+use crate::{FlushCompress, FlushDecompress, Status};
+
+/// The largest size of a block, including its header and trailer.
+const MAX_BLOCK_SIZE: usize = 64 * 1024;
+
+/// The amount of data compressed into each block, chosen like htslib so that
+/// even incompressible data fits in `MAX_BLOCK_SIZE`.
+const BLOCK_DATA_SIZE: usize = 0xff00;
+
+const HEADER_SIZE: usize = 18;
+const TRAILER_SIZE: usize = 8;
+
+static EOF_MARKER: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43, 0x02, 0x00,
+    0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// A position in the uncompressed data of a BGZF file.
+///
+/// The upper 48 bits hold the offset in the compressed file of the block
+/// containing the position and the lower 16 bits hold the offset within that
+/// block's uncompressed data, matching the representation used by htslib.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VirtualOffset(u64);
+
+impl VirtualOffset {
+    /// Creates a virtual offset from the offset of a block in the compressed
+    /// file and an offset into its uncompressed data.
+    ///
+    /// Only the lower 48 bits of `compressed` are used.
+    pub fn new(compressed: u64, uncompressed: u16) -> VirtualOffset {
+        VirtualOffset((compressed << 16) | u64::from(uncompressed))
+    }
+
+    /// Returns the offset in the compressed file of the block this position
+    /// lies in.
+    pub fn compressed(&self) -> u64 {
+        self.0 >> 16
+    }
+
+    /// Returns the offset of this position within the uncompressed data of
+    /// its block.
+    pub fn uncompressed(&self) -> u16 {
+        self.0 as u16
+    }
+}
+
+impl From<u64> for VirtualOffset {
+    fn from(offset: u64) -> VirtualOffset {
+        VirtualOffset(offset)
+    }
+}
+
+impl From<VirtualOffset> for u64 {
+    fn from(offset: VirtualOffset) -> u64 {
+        offset.0
+    }
+}
+
+/// The contents of a `.gzi` index, mapping uncompressed offsets in a BGZF
+/// file to the blocks containing them.
+///
+/// The index holds the compressed and uncompressed offset of the start of
+/// every block but the first, in the order they appear in the file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GziIndex {
+    entries: Vec<(u64, u64)>,
+}
+
+impl GziIndex {
+    /// Creates an empty index, describing a file with a single block.
+    pub fn new() -> GziIndex {
+        GziIndex {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Builds the index of the BGZF file read from `r`.
+    ///
+    /// Only the block headers and trailers are inspected, the compressed data
+    /// is skipped without being decompressed.
+    pub fn build<R: Read>(mut r: R) -> io::Result<GziIndex> {
+        let mut index = GziIndex::new();
+        let mut compressed = 0;
+        let mut uncompressed = 0;
+        while let Some(header) = read_block_header(&mut r).map_err(|e| add_offset(e, compressed))? {
+            let skip = (header.size - header.len - TRAILER_SIZE) as u64;
+            let end = compressed + header.size as u64;
+            if io::copy(&mut r.by_ref().take(skip), &mut io::sink())? != skip {
+                return Err(truncated(end));
+            }
+            let mut trailer = [0; TRAILER_SIZE];
+            r.read_exact(&mut trailer).map_err(|_| truncated(end))?;
+            compressed = end;
+            let len = le_u32(&trailer[4..]);
+            if len > 0 {
+                uncompressed += u64::from(len);
+                index.entries.push((compressed, uncompressed));
+            }
+        }
+        Ok(index)
+    }
+
+    /// Returns the `(compressed, uncompressed)` offset pairs of this index.
+    pub fn entries(&self) -> &[(u64, u64)] {
+        &self.entries
+    }
+
+    /// Translates an offset in the uncompressed data into a virtual offset
+    /// that can be passed to [`Reader::seek`].
+    ///
+    /// Returns `None` if the offset lies too far past the last block in the
+    /// index to be addressed.
+    ///
+    /// [`Reader::seek`]: struct.Reader.html#method.seek
+    pub fn virtual_offset(&self, offset: u64) -> Option<VirtualOffset> {
+        let (compressed, uncompressed) = match self
+            .entries
+            .binary_search_by_key(&offset, |&(_, uncompressed)| uncompressed)
+        {
+            Ok(i) => self.entries[i],
+            Err(0) => (0, 0),
+            Err(i) => self.entries[i - 1],
+        };
+        let delta = offset - uncompressed;
+        if delta > u64::from(u16::MAX) {
+            return None;
+        }
+        Some(VirtualOffset::new(compressed, delta as u16))
+    }
+
+    /// Writes this index in the `.gzi` format used by `bgzip`.
+    ///
+    /// The format is the number of entries followed by each entry's
+    /// compressed and uncompressed offset, all as little endian 64-bit
+    /// integers.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for &(compressed, uncompressed) in &self.entries {
+            w.write_all(&compressed.to_le_bytes())?;
+            w.write_all(&uncompressed.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads an index in the `.gzi` format used by `bgzip`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the entries are not in
+    /// increasing order.
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<GziIndex> {
+        let count = read_u64(&mut r)?;
+        let mut entries = Vec::with_capacity(cmp::min(count, 1024) as usize);
+        for _ in 0..count {
+            let compressed = read_u64(&mut r)?;
+            let uncompressed = read_u64(&mut r)?;
+            if let Some(&(prev_compressed, prev_uncompressed)) = entries.last() {
+                if compressed <= prev_compressed || uncompressed <= prev_uncompressed {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "gzi index entries out of order",
+                    ));
+                }
+            }
+            entries.push((compressed, uncompressed));
+        }
+        Ok(GziIndex { entries })
+    }
+}
+
+/// A BGZF encoder.
+///
+/// Data written to this encoder is split into blocks of just under 64 KiB
+/// which are each compressed into a gzip member carrying the BGZF `BC` extra
+/// subfield. Finishing the encoder writes the standard empty end-of-file
+/// block.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io::Cursor;
+/// use flate2::Compression;
+/// use flate2::bgzf::{Reader, Writer};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut w = Writer::new(Vec::new(), Compression::default());
+/// w.write_all(b"first record\n")?;
+/// let second = w.virtual_position();
+/// w.write_all(b"second record\n")?;
+/// let file = w.finish()?;
+///
+/// let mut r = Reader::new(Cursor::new(file));
+/// r.seek(second)?;
+/// let mut line = String::new();
+/// r.read_line(&mut line)?;
+/// assert_eq!(line, "second record\n");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Writer<W: Write> {
+    inner: Option<W>,
+    data: Compress,
+    header: Vec<u8>,
+    buf: Vec<u8>,
+    block: Vec<u8>,
+    compressed: u64,
+    uncompressed: u64,
+    index: GziIndex,
+    eof_written: bool,
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a new encoder which will write BGZF blocks compressed with the
+    /// given level to `w`.
+    pub fn new(w: W, level: Compression) -> Writer<W> {
+        Writer {
+            inner: Some(w),
+            data: Compress::new(level, false),
+            header: GzBuilder::new()
+                .extra(vec![b'B', b'C', 2, 0, 0, 0])
+                .into_header(level),
+            buf: Vec::with_capacity(BLOCK_DATA_SIZE),
+            block: vec![0; MAX_BLOCK_SIZE],
+            compressed: 0,
+            uncompressed: 0,
+            index: GziIndex::new(),
+            eof_written: false,
+        }
+    }
+
+    /// Returns the virtual offset at which the next byte written will be
+    /// found.
+    pub fn virtual_position(&self) -> VirtualOffset {
+        VirtualOffset::new(self.compressed, self.buf.len() as u16)
+    }
+
+    /// Returns the index of the blocks written so far.
+    ///
+    /// Once the encoder is finished this can be saved as the `.gzi` index of
+    /// the output.
+    pub fn index(&self) -> &GziIndex {
+        &self.index
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutation of the writer may result in surprising results if
+    /// this encoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Attempt to finish this output stream, writing out the last block and
+    /// the end-of-file marker.
+    ///
+    /// # Panics
+    ///
+    /// Attempts to write data to this stream may result in a panic after this
+    /// function is called.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        if !self.eof_written {
+            self.get_mut().write_all(&EOF_MARKER)?;
+            self.eof_written = true;
+        }
+        Ok(())
+    }
+
+    /// Finish encoding this stream, returning the underlying writer once the
+    /// encoding is done.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        self.data.reset();
+        let status = self.data.compress(
+            &self.buf,
+            &mut self.block[HEADER_SIZE..MAX_BLOCK_SIZE - TRAILER_SIZE],
+            FlushCompress::Finish,
+        )?;
+        if status != Status::StreamEnd {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "compressed data does not fit in a BGZF block",
+            ));
+        }
+
+        let end = HEADER_SIZE + self.data.total_out() as usize;
+        let size = end + TRAILER_SIZE;
+        self.block[..HEADER_SIZE].copy_from_slice(&self.header);
+        self.block[HEADER_SIZE - 2..HEADER_SIZE]
+            .copy_from_slice(&((size - 1) as u16).to_le_bytes());
+        let mut crc = Crc::new();
+        crc.update(&self.buf);
+        self.block[end..end + 4].copy_from_slice(&crc.sum().to_le_bytes());
+        self.block[end + 4..size].copy_from_slice(&(self.buf.len() as u32).to_le_bytes());
+        self.inner
+            .as_mut()
+            .unwrap()
+            .write_all(&self.block[..size])?;
+
+        self.compressed += size as u64;
+        self.uncompressed += self.buf.len() as u64;
+        self.index
+            .entries
+            .push((self.compressed, self.uncompressed));
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        assert!(!self.eof_written);
+        if self.buf.len() == BLOCK_DATA_SIZE {
+            self.write_block()?;
+        }
+        let n = cmp::min(buf.len(), BLOCK_DATA_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    /// Writes out the data buffered so far as a block, even if it is short,
+    /// and flushes the underlying writer.
+    fn flush(&mut self) -> io::Result<()> {
+        assert!(!self.eof_written);
+        self.write_block()?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for Writer<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// A BGZF decoder.
+///
+/// This decodes the blocks of a BGZF file one at a time, verifying the size
+/// and checksum of each, and can seek to any virtual offset when the
+/// underlying reader implements `Seek`. Plain gzip files without the BGZF
+/// block size field are rejected.
+#[derive(Debug)]
+pub struct Reader<R> {
+    inner: R,
+    data: Decompress,
+    block: Vec<u8>,
+    compressed: Vec<u8>,
+    pos: usize,
+    offset: u64,
+    next: u64,
+}
+
+impl<R: Read> Reader<R> {
+    /// Creates a new decoder reading a BGZF file from `r`.
+    pub fn new(r: R) -> Reader<R> {
+        Reader {
+            inner: r,
+            data: Decompress::new(false),
+            block: Vec::new(),
+            compressed: Vec::new(),
+            pos: 0,
+            offset: 0,
+            next: 0,
+        }
+    }
+
+    /// Returns the virtual offset of the next byte to be read.
+    pub fn virtual_position(&self) -> VirtualOffset {
+        if self.pos < self.block.len() {
+            VirtualOffset::new(self.offset, self.pos as u16)
+        } else {
+            VirtualOffset::new(self.next, 0)
+        }
+    }
+
+    fn read_block(&mut self) -> io::Result<bool> {
+        self.offset = self.next;
+        self.block.clear();
+        self.pos = 0;
+        let offset = self.offset;
+        let header = match read_block_header(&mut self.inner).map_err(|e| add_offset(e, offset))? {
+            Some(header) => header,
+            None => return Ok(false),
+        };
+
+        let end = offset + header.size as u64;
+        let len = header.size - header.len - TRAILER_SIZE;
+        self.compressed.resize(len + TRAILER_SIZE, 0);
+        self.inner
+            .read_exact(&mut self.compressed)
+            .map_err(|_| truncated(end))?;
+        let (compressed, trailer) = self.compressed.split_at(len);
+        let size = le_u32(&trailer[4..]) as usize;
+        let trailer_offset = end - TRAILER_SIZE as u64;
+        if size > MAX_BLOCK_SIZE {
+            return Err(corrupt(
+                DecompressErrorKind::InvalidHeader,
+                trailer_offset + 4,
+            ));
+        }
+
+        self.block.reserve(size + 1);
+        self.data.reset_continued(false);
+        let data_offset = offset + header.len as u64;
+        let status = self
+            .data
+            .decompress_vec(compressed, &mut self.block, FlushDecompress::Finish)
+            .map_err(|e| add_offset(io_error(e), data_offset))?;
+        if status != Status::StreamEnd || self.data.total_in() != len as u64 {
+            let at = data_offset + self.data.total_in();
+            return Err(corrupt(DecompressErrorKind::Corrupt, at));
+        }
+        let mut crc = Crc::new();
+        crc.update(&self.block);
+        check_trailer(le_u32(&trailer[..4]), size as u32, &crc, trailer_offset)?;
+
+        self.next += header.size as u64;
+        Ok(true)
+    }
+}
+
+impl<R: Read + Seek> Reader<R> {
+    /// Moves to the given virtual offset, so that the next byte read is the
+    /// one found at that position.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no valid block at the compressed offset
+    /// or if the block is shorter than the uncompressed offset.
+    pub fn seek(&mut self, offset: VirtualOffset) -> io::Result<()> {
+        if offset.compressed() != self.offset || self.block.is_empty() {
+            self.inner.seek(SeekFrom::Start(offset.compressed()))?;
+            self.next = offset.compressed();
+            self.read_block()?;
+        }
+        let pos = offset.uncompressed() as usize;
+        if pos > self.block.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "virtual offset is past the end of its block",
+            ));
+        }
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+impl<R> Reader<R> {
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying reader.
+    ///
+    /// Note that mutation of the reader may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// The limits apply to the output of all blocks read so far taken
+    /// together, including blocks read again after seeking.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.data.set_limits(limits);
+    }
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let data = self.fill_buf()?;
+            let n = cmp::min(data.len(), into.len());
+            into[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for Reader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.block.len() {
+            if !self.read_block()? {
+                break;
+            }
+        }
+        Ok(&self.block[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = cmp::min(self.pos + amt, self.block.len());
+    }
+}
+
+struct BlockHeader {
+    size: usize,
+    len: usize,
+}
+
+struct CountingReader<R> {
+    inner: R,
+    count: usize,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(into)?;
+        self.count += n;
+        Ok(n)
+    }
+}
+
+/// Reads the header of the next block, returning `None` at the end of the
+/// stream.
+fn read_block_header<R: Read>(r: &mut R) -> io::Result<Option<BlockHeader>> {
+    let mut first = [0];
+    loop {
+        match r.read(&mut first) {
+            Ok(0) => return Ok(None),
+            Ok(_) => break,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut counter = CountingReader {
+        inner: (&first[..]).chain(r),
+        count: 0,
+    };
+    let header = read_gz_header(&mut counter)?;
+    let size = match header.extra().and_then(block_size) {
+        Some(bsize) => bsize as usize + 1,
+        None => return Err(bad_block()),
+    };
+    if size < counter.count + TRAILER_SIZE {
+        return Err(bad_block());
+    }
+    Ok(Some(BlockHeader {
+        size,
+        len: counter.count,
+    }))
+}
+
+/// Finds the `BC` subfield in a gzip extra field, returning the total size of
+/// the block minus one.
+pub(crate) fn block_size(extra: &[u8]) -> Option<u16> {
+    ExtraSubfields::new(extra)
+        .filter_map(Result::ok)
+        .find(|s| s.id() == *b"BC" && s.data().len() == 2)
+        .map(|s| u16::from_le_bytes([s.data()[0], s.data()[1]]))
+}
+
+/// Errors in a block header are positioned relative to the start of the
+/// block, and moved by the callers of `read_block_header`.
+fn bad_block() -> io::Error {
+    corrupt(DecompressErrorKind::InvalidHeader, 0)
+}
+
+fn truncated(offset: u64) -> io::Error {
+    corrupt(DecompressErrorKind::TruncatedInput, offset)
+}
+
+fn le_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+    use std::io::Cursor;
+
+    use super::{GziIndex, Reader, VirtualOffset, Writer, EOF_MARKER, MAX_BLOCK_SIZE};
+    use crate::read::MultiGzDecoder;
+    use crate::write::GzEncoder;
+    use crate::{Compression, DecompressLimits, LimitExceeded};
+
+    fn records() -> Vec<String> {
+        (0..20_000)
+            .map(|i| format!("record {}\t{}\n", i, i * 7919 % 65_537))
+            .collect()
+    }
+
+    fn write_records(records: &[String]) -> (Vec<u8>, Vec<VirtualOffset>, GziIndex) {
+        let mut w = Writer::new(Vec::new(), Compression::default());
+        let mut offsets = Vec::new();
+        for record in records {
+            offsets.push(w.virtual_position());
+            w.write_all(record.as_bytes()).unwrap();
+        }
+        w.try_finish().unwrap();
+        let index = w.index().clone();
+        (w.finish().unwrap(), offsets, index)
+    }
+
+    #[test]
+    fn roundtrip() {
+        let records = records();
+        let (file, _, index) = write_records(&records);
+        assert!(file.ends_with(&EOF_MARKER));
+        assert!(index.entries().len() > 2);
+        assert_eq!(
+            GziIndex::build(&file[..]).unwrap().entries()[..3],
+            index.entries()[..3]
+        );
+        assert_eq!(GziIndex::build(&file[..]).unwrap(), index);
+
+        let mut start = 0;
+        for &(end, _) in index.entries() {
+            assert!((end - start) as usize <= MAX_BLOCK_SIZE);
+            start = end;
+        }
+
+        let expected = records.concat();
+        let mut s = String::new();
+        Reader::new(&file[..]).read_to_string(&mut s).unwrap();
+        assert_eq!(s, expected);
+
+        s.clear();
+        MultiGzDecoder::new(&file[..])
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, expected);
+    }
+
+    #[test]
+    fn incompressible() {
+        let v = crate::random_bytes().take(200_000).collect::<Vec<_>>();
+        let mut w = Writer::new(Vec::new(), Compression::best());
+        w.write_all(&v).unwrap();
+        let file = w.finish().unwrap();
+        let mut res = Vec::new();
+        Reader::new(&file[..]).read_to_end(&mut res).unwrap();
+        assert!(res == v);
+    }
+
+    #[test]
+    fn empty() {
+        let file = Writer::new(Vec::new(), Compression::default())
+            .finish()
+            .unwrap();
+        assert_eq!(file, &EOF_MARKER[..]);
+        let mut v = Vec::new();
+        Reader::new(&file[..]).read_to_end(&mut v).unwrap();
+        assert!(v.is_empty());
+    }
+
+    #[test]
+    fn seek_virtual_offsets() {
+        let records = records();
+        let (file, offsets, _) = write_records(&records);
+        let mut r = Reader::new(Cursor::new(file));
+        for i in (0..records.len()).rev().step_by(997) {
+            r.seek(offsets[i]).unwrap();
+            assert_eq!(r.virtual_position(), offsets[i]);
+            let mut line = String::new();
+            r.read_line(&mut line).unwrap();
+            assert_eq!(line, records[i]);
+        }
+    }
+
+    #[test]
+    fn gzi_roundtrip() {
+        let records = records();
+        let (file, _, index) = write_records(&records);
+        let mut gzi = Vec::new();
+        index.write_to(&mut gzi).unwrap();
+        assert_eq!(gzi.len(), 8 + 16 * index.entries().len());
+        let index = GziIndex::read_from(&gzi[..]).unwrap();
+
+        let expected = records.concat();
+        let mut r = Reader::new(Cursor::new(file));
+        for &offset in &[0, 1, 65_279, 65_280, 200_000, expected.len() as u64 - 5] {
+            r.seek(index.virtual_offset(offset).unwrap()).unwrap();
+            let mut buf = [0; 5];
+            r.read_exact(&mut buf).unwrap();
+            let offset = offset as usize;
+            assert_eq!(&buf[..], &expected.as_bytes()[offset..offset + 5]);
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let records = records();
+        let (file, _, _) = write_records(&records);
+        let expected = records.concat();
+
+        let mut r = Reader::new(&file[..]);
+        r.set_limits(DecompressLimits::new().max_output(100_000));
+        let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+        let limit = err.get_ref().unwrap().downcast_ref::<LimitExceeded>();
+        assert_eq!(limit, Some(&LimitExceeded::Output(100_000)));
+
+        let mut r = Reader::new(&file[..]);
+        r.set_limits(DecompressLimits::new().max_output(expected.len() as u64));
+        let mut s = String::new();
+        r.read_to_string(&mut s).unwrap();
+        assert_eq!(s, expected);
+    }
+
+    #[test]
+    fn rejects_plain_gzip() {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"not blocked").unwrap();
+        let file = e.finish().unwrap();
+        let mut v = Vec::new();
+        assert!(Reader::new(&file[..]).read_to_end(&mut v).is_err());
+        assert!(GziIndex::build(&file[..]).is_err());
+    }
+
+    #[test]
+    fn corrupt_checksum() {
+        let mut w = Writer::new(Vec::new(), Compression::default());
+        w.write_all(b"hello world").unwrap();
+        let mut file = w.finish().unwrap();
+        let pos = file.len() - EOF_MARKER.len() - 8;
+        file[pos] ^= 1;
+        let mut v = Vec::new();
+        assert!(Reader::new(&file[..]).read_to_end(&mut v).is_err());
+    }
+}
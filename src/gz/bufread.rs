@@ -8,11 +8,13 @@ use futures::Poll;
 #[cfg(feature = "tokio")]
 use tokio_io::{AsyncRead, AsyncWrite};
 
-use super::{GzBuilder, GzHeader};
-use super::{FCOMMENT, FEXTRA, FHCRC, FNAME};
-use crate::crc::CrcReader;
+use super::{GzBuilder, GzHeader, GzMemberInfo, HeaderLimitExceeded, TrailingData};
+use super::{FCOMMENT, FEXTRA, FHCRC, FNAME, FTEXT};
+use crate::crc::{Crc, CrcReader};
 use crate::deflate;
-use crate::Compression;
+use crate::mem::{add_offset, io_error};
+use crate::DecompressLimits;
+use crate::{Compress, CompressOptions, Compression, DecompressError, DecompressErrorKind};
 
 fn copy(into: &mut [u8], from: &[u8], pos: &mut usize) -> usize {
     let min = cmp::min(into.len(), from.len() - *pos);
@@ -23,98 +25,302 @@ fn copy(into: &mut [u8], from: &[u8], pos: &mut usize) -> usize {
     return min;
 }
 
-pub(crate) fn corrupt() -> io::Error {
-    io::Error::new(
-        io::ErrorKind::InvalidInput,
-        "corrupt gzip stream does not have a matching checksum",
-    )
+pub(crate) fn corrupt(kind: DecompressErrorKind, offset: u64) -> io::Error {
+    io_error(DecompressError::new(kind).at(offset))
 }
 
-fn bad_header() -> io::Error {
-    io::Error::new(io::ErrorKind::InvalidInput, "invalid gzip header")
+/// Reads the gzip trailer found at `offset`, returning its CRC and length.
+pub(crate) fn read_trailer<R: Read>(r: &mut R, offset: u64) -> io::Result<(u32, u32)> {
+    let mut buf = [0; 8];
+    match r.read_exact(&mut buf) {
+        Ok(()) => Ok(finish(&buf)),
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            Err(corrupt(DecompressErrorKind::TruncatedInput, offset))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Checks the CRC and length stored in a gzip trailer found at `offset`
+/// against those of the decompressed data.
+pub(crate) fn check_trailer(crc: u32, amt: u32, actual: &Crc, offset: u64) -> io::Result<()> {
+    if crc != actual.sum() {
+        let kind = DecompressErrorKind::ChecksumMismatch {
+            expected: crc,
+            actual: actual.sum(),
+        };
+        return Err(corrupt(kind, offset));
+    }
+    if amt != actual.amount() {
+        let kind = DecompressErrorKind::LengthMismatch {
+            expected: amt,
+            actual: actual.amount(),
+        };
+        return Err(corrupt(kind, offset + 4));
+    }
+    Ok(())
 }
 
-fn read_le_u16<R: Read>(r: &mut R) -> io::Result<u16> {
-    let mut b = [0; 2];
-    r.read_exact(&mut b)?;
-    Ok((b[0] as u16) | ((b[1] as u16) << 8))
+/// Limits on the size of the variable length fields of a gzip header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HeaderLimits {
+    pub(crate) extra: usize,
+    pub(crate) filename: usize,
+    pub(crate) comment: usize,
 }
 
-pub(crate) fn read_gz_header<R: Read>(r: &mut R) -> io::Result<GzHeader> {
-    let mut crc_reader = CrcReader::new(r);
-    let mut header = [0; 10];
-    crc_reader.read_exact(&mut header)?;
-
-    let id1 = header[0];
-    let id2 = header[1];
-    if id1 != 0x1f || id2 != 0x8b {
-        return Err(bad_header());
-    }
-    let cm = header[2];
-    if cm != 8 {
-        return Err(bad_header());
-    }
-
-    let flg = header[3];
-    let mtime = ((header[4] as u32) << 0)
-        | ((header[5] as u32) << 8)
-        | ((header[6] as u32) << 16)
-        | ((header[7] as u32) << 24);
-    let _xfl = header[8];
-    let os = header[9];
-
-    let extra = if flg & FEXTRA != 0 {
-        let xlen = read_le_u16(&mut crc_reader)?;
-        let mut extra = vec![0; xlen as usize];
-        crc_reader.read_exact(&mut extra)?;
-        Some(extra)
-    } else {
-        None
-    };
-    let filename = if flg & FNAME != 0 {
-        // wow this is slow
-        let mut b = Vec::new();
-        for byte in crc_reader.by_ref().bytes() {
-            let byte = byte?;
-            if byte == 0 {
-                break;
-            }
-            b.push(byte);
+impl Default for HeaderLimits {
+    fn default() -> HeaderLimits {
+        HeaderLimits {
+            extra: DEFAULT_FIELD_LIMIT,
+            filename: DEFAULT_FIELD_LIMIT,
+            comment: DEFAULT_FIELD_LIMIT,
         }
-        Some(b)
-    } else {
-        None
-    };
-    let comment = if flg & FCOMMENT != 0 {
-        // wow this is slow
-        let mut b = Vec::new();
-        for byte in crc_reader.by_ref().bytes() {
-            let byte = byte?;
-            if byte == 0 {
-                break;
+    }
+}
+
+/// Default cap on the length of the `extra`, `filename` and `comment` fields.
+pub(crate) const DEFAULT_FIELD_LIMIT: usize = 64 * 1024;
+
+#[derive(Debug)]
+enum HeaderState {
+    Fixed,
+    ExtraLen,
+    Extra(usize),
+    Filename,
+    Comment,
+    Crc(u16),
+    Done,
+}
+
+/// An incremental parser for gzip headers.
+///
+/// Bytes are only consumed from the reader once they have been stored, so
+/// parsing can be resumed after a `WouldBlock` error.
+#[derive(Debug)]
+pub(crate) struct GzHeaderParser {
+    state: HeaderState,
+    flags: u8,
+    header: GzHeader,
+    crc: Crc,
+    buf: Vec<u8>,
+    limits: HeaderLimits,
+}
+
+impl GzHeaderParser {
+    pub(crate) fn new(limits: HeaderLimits) -> GzHeaderParser {
+        GzHeaderParser {
+            state: HeaderState::Fixed,
+            flags: 0,
+            header: GzHeader::default(),
+            crc: Crc::new(),
+            buf: Vec::with_capacity(10),
+            limits,
+        }
+    }
+
+    /// Returns the number of bytes of the header consumed so far.
+    pub(crate) fn consumed(&self) -> u64 {
+        self.crc.amount().into()
+    }
+
+    /// Resets the parser to read another header with the same limits.
+    pub(crate) fn reset(&mut self) {
+        *self = GzHeaderParser::new(self.limits);
+    }
+
+    pub(crate) fn parse<R: BufRead>(&mut self, r: &mut R) -> io::Result<GzHeader> {
+        loop {
+            self.state = match self.state {
+                HeaderState::Fixed => {
+                    // Check the magic bytes and method as soon as they're
+                    // available, so that short garbage isn't reported as a
+                    // truncated header.
+                    self.fill(r, 2)?;
+                    if self.buf[0] != 0x1f || self.buf[1] != 0x8b {
+                        return Err(corrupt(DecompressErrorKind::BadMagic, 0));
+                    }
+                    self.fill(r, 3)?;
+                    if self.buf[2] != 8 {
+                        return Err(corrupt(DecompressErrorKind::UnsupportedMethod, 2));
+                    }
+                    self.fill(r, 10)?;
+                    let header = &self.buf;
+                    self.flags = header[3];
+                    self.header.mtime = (header[4] as u32)
+                        | ((header[5] as u32) << 8)
+                        | ((header[6] as u32) << 16)
+                        | ((header[7] as u32) << 24);
+                    self.header.xfl = header[8];
+                    self.header.operating_system = header[9];
+                    self.header.text = self.flags & FTEXT != 0;
+                    self.header.header_crc = self.flags & FHCRC != 0;
+                    self.buf.clear();
+                    HeaderState::ExtraLen
+                }
+                HeaderState::ExtraLen if self.flags & FEXTRA != 0 => {
+                    self.fill(r, 2)?;
+                    let xlen = (self.buf[0] as usize) | ((self.buf[1] as usize) << 8);
+                    if xlen > self.limits.extra {
+                        return Err(HeaderLimitExceeded::Extra(self.limits.extra).into());
+                    }
+                    self.buf.clear();
+                    HeaderState::Extra(xlen)
+                }
+                HeaderState::ExtraLen => HeaderState::Filename,
+                HeaderState::Extra(len) => {
+                    self.fill(r, len)?;
+                    self.header.extra = Some(mem::take(&mut self.buf));
+                    HeaderState::Filename
+                }
+                HeaderState::Filename if self.flags & FNAME != 0 => {
+                    let limit = self.limits.filename;
+                    self.fill_string(r, limit, HeaderLimitExceeded::Filename(limit))?;
+                    self.header.filename = Some(mem::take(&mut self.buf));
+                    HeaderState::Comment
+                }
+                HeaderState::Filename => HeaderState::Comment,
+                HeaderState::Comment => {
+                    if self.flags & FCOMMENT != 0 {
+                        let limit = self.limits.comment;
+                        self.fill_string(r, limit, HeaderLimitExceeded::Comment(limit))?;
+                        self.header.comment = Some(mem::take(&mut self.buf));
+                    }
+                    HeaderState::Crc(self.crc.sum() as u16)
+                }
+                HeaderState::Crc(calced_crc) => {
+                    if self.flags & FHCRC != 0 {
+                        let offset = self.consumed();
+                        self.fill(r, 2)?;
+                        let stored_crc = (self.buf[0] as u16) | ((self.buf[1] as u16) << 8);
+                        if calced_crc != stored_crc {
+                            let kind = DecompressErrorKind::ChecksumMismatch {
+                                expected: stored_crc.into(),
+                                actual: calced_crc.into(),
+                            };
+                            return Err(corrupt(kind, offset));
+                        }
+                        self.buf.clear();
+                    }
+                    HeaderState::Done
+                }
+                HeaderState::Done => return Ok(mem::take(&mut self.header)),
+            };
+        }
+    }
+
+    /// Reads into `buf` until it holds `len` bytes.
+    fn fill<R: BufRead>(&mut self, r: &mut R, len: usize) -> io::Result<()> {
+        while self.buf.len() < len {
+            let available = match r.fill_buf() {
+                Ok(buf) if !buf.is_empty() => buf,
+                Ok(_) => {
+                    return Err(corrupt(
+                        DecompressErrorKind::TruncatedInput,
+                        self.consumed(),
+                    ))
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let n = cmp::min(len - self.buf.len(), available.len());
+            self.buf.extend_from_slice(&available[..n]);
+            self.crc.update(&available[..n]);
+            r.consume(n);
+        }
+        Ok(())
+    }
+
+    /// Reads a zero-terminated string into `buf`, failing with `exceeded` if
+    /// it is longer than `limit` bytes.
+    fn fill_string<R: BufRead>(
+        &mut self,
+        r: &mut R,
+        limit: usize,
+        exceeded: HeaderLimitExceeded,
+    ) -> io::Result<()> {
+        loop {
+            let available = match r.fill_buf() {
+                Ok(buf) if !buf.is_empty() => buf,
+                Ok(_) => {
+                    return Err(corrupt(
+                        DecompressErrorKind::TruncatedInput,
+                        self.consumed(),
+                    ))
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            let (n, done) = match available.iter().position(|&b| b == 0) {
+                Some(i) => (i, true),
+                None => (available.len(), false),
+            };
+            if self.buf.len() + n > limit {
+                return Err(exceeded.into());
+            }
+            self.buf.extend_from_slice(&available[..n]);
+            let consumed = if done { n + 1 } else { n };
+            self.crc.update(&available[..consumed]);
+            r.consume(consumed);
+            if done {
+                return Ok(());
             }
-            b.push(byte);
         }
-        Some(b)
-    } else {
-        None
-    };
+    }
+}
 
-    if flg & FHCRC != 0 {
-        let calced_crc = crc_reader.crc().sum() as u16;
-        let stored_crc = read_le_u16(&mut crc_reader)?;
-        if calced_crc != stored_crc {
-            return Err(corrupt());
+/// Adapts a `Read` into a `BufRead` which never reads further ahead than
+/// the byte being looked at.
+struct Unbuffered<'a, R: 'a> {
+    inner: &'a mut R,
+    byte: [u8; 1],
+    full: bool,
+}
+
+impl<'a, R: Read> Read for Unbuffered<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let available = self.fill_buf()?;
+            let n = cmp::min(available.len(), into.len());
+            into[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<'a, R: Read> BufRead for Unbuffered<'a, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if !self.full {
+            self.full = self.inner.read(&mut self.byte)? == 1;
         }
+        Ok(if self.full { &self.byte[..] } else { &[] })
     }
 
-    Ok(GzHeader {
-        extra: extra,
-        filename: filename,
-        comment: comment,
-        operating_system: os,
-        mtime: mtime,
-    })
+    fn consume(&mut self, amt: usize) {
+        if amt > 0 {
+            self.full = false;
+        }
+    }
+}
+
+/// Reads a gzip header from `r` with the default limits, consuming exactly
+/// the bytes of the header.
+pub(crate) fn read_gz_header<R: Read>(r: &mut R) -> io::Result<GzHeader> {
+    read_gz_header_with(r, HeaderLimits::default())
+}
+
+pub(crate) fn read_gz_header_with<R: Read>(
+    r: &mut R,
+    limits: HeaderLimits,
+) -> io::Result<GzHeader> {
+    let mut r = Unbuffered {
+        inner: r,
+        byte: [0],
+        full: false,
+    };
+    GzHeaderParser::new(limits).parse(&mut r)
 }
 
 /// A gzip streaming encoder
@@ -155,10 +361,10 @@ pub struct GzEncoder<R> {
     eof: bool,
 }
 
-pub fn gz_encoder<R: BufRead>(header: Vec<u8>, r: R, lvl: Compression) -> GzEncoder<R> {
+pub fn gz_encoder<R: BufRead>(header: Vec<u8>, r: R, data: Compress) -> GzEncoder<R> {
     let crc = CrcReader::new(r);
     GzEncoder {
-        inner: deflate::bufread::DeflateEncoder::new(crc, lvl),
+        inner: deflate::bufread::deflate_encoder(crc, data),
         header: header,
         pos: 0,
         eof: false,
@@ -177,6 +383,15 @@ impl<R: BufRead> GzEncoder<R> {
         GzBuilder::new().buf_read(r, level)
     }
 
+    /// Creates a new encoder like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(r: R, options: &CompressOptions) -> io::Result<GzEncoder<R>> {
+        GzBuilder::new().buf_read_with_options(r, options)
+    }
+
     fn read_footer(&mut self, into: &mut [u8]) -> io::Result<usize> {
         if self.pos == 8 {
             return Ok(0);
@@ -217,7 +432,7 @@ impl<R> GzEncoder<R> {
 }
 
 #[inline]
-fn finish(buf: &[u8; 8]) -> (u32, u32) {
+pub(crate) fn finish(buf: &[u8; 8]) -> (u32, u32) {
     let crc = ((buf[0] as u32) << 0)
         | ((buf[1] as u32) << 8)
         | ((buf[2] as u32) << 16)
@@ -242,13 +457,17 @@ impl<R: BufRead> Read for GzEncoder<R> {
             let tmp = into;
             into = &mut tmp[amt..];
         }
-        match self.inner.read(into)? {
-            0 => {
+        match self.inner.read(into) {
+            Ok(0) => {
                 self.eof = true;
                 self.pos = 0;
-                self.read_footer(into)
+                Ok(amt + self.read_footer(into)?)
             }
-            n => Ok(amt + n),
+            Ok(n) => Ok(amt + n),
+            // The header bytes have already been handed out, so report them
+            // and leave the error to the next read.
+            Err(_) if amt > 0 => Ok(amt),
+            Err(e) => Err(e),
         }
     }
 }
@@ -300,85 +519,73 @@ impl<R: BufRead + Write> Write for GzEncoder<R> {
 pub struct GzDecoder<R> {
     inner: GzState,
     header: Option<GzHeader>,
+    parser: GzHeaderParser,
     reader: CrcReader<deflate::bufread::DeflateDecoder<R>>,
     multi: bool,
+    // Whether reading stops at the end of each member, see `next_member`.
+    split: bool,
+    // Offset of the current member in the compressed stream.
+    offset: u64,
+    // The current member, once its trailer has been verified.
+    member: Option<GzMemberInfo>,
+    trailing: TrailingData,
+    // Zero bytes skipped after the current member.
+    padding: u64,
 }
 
 #[derive(Debug)]
 enum GzState {
-    Header(Vec<u8>),
+    Header,
     Body,
     Finished(usize, [u8; 8]),
+    // Between two members, when reading stops at the end of each member.
+    Member,
     Err(io::Error),
     End,
 }
 
-/// A small adapter which reads data originally from `buf` and then reads all
-/// further data from `reader`. This will also buffer all data read from
-/// `reader` into `buf` for reuse on a further call.
-struct Buffer<'a, T: 'a> {
-    buf: &'a mut Vec<u8>,
-    buf_cur: usize,
-    buf_max: usize,
-    reader: &'a mut T,
-}
+pub(crate) fn gz_decoder<R: BufRead>(mut r: R, limits: HeaderLimits) -> GzDecoder<R> {
+    let mut parser = GzHeaderParser::new(limits);
+    let mut header = None;
 
-impl<'a, T> Buffer<'a, T> {
-    fn new(buf: &'a mut Vec<u8>, reader: &'a mut T) -> Buffer<'a, T> {
-        Buffer {
-            reader,
-            buf_cur: 0,
-            buf_max: buf.len(),
-            buf,
+    let state = match parser.parse(&mut r) {
+        Ok(hdr) => {
+            header = Some(hdr);
+            GzState::Body
         }
-    }
-}
+        Err(ref err) if io::ErrorKind::WouldBlock == err.kind() => GzState::Header,
+        Err(err) => GzState::Err(err),
+    };
 
-impl<'a, T: Read> Read for Buffer<'a, T> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        if self.buf_cur == self.buf_max {
-            let len = self.reader.read(buf)?;
-            self.buf.extend_from_slice(&buf[..len]);
-            Ok(len)
-        } else {
-            let len = (&self.buf[self.buf_cur..self.buf_max]).read(buf)?;
-            self.buf_cur += len;
-            Ok(len)
-        }
+    GzDecoder {
+        inner: state,
+        reader: CrcReader::new(deflate::bufread::DeflateDecoder::new(r)),
+        parser,
+        multi: false,
+        split: false,
+        header,
+        offset: 0,
+        member: None,
+        trailing: TrailingData::Stop,
+        padding: 0,
     }
 }
 
 impl<R: BufRead> GzDecoder<R> {
     /// Creates a new decoder from the given reader, immediately parsing the
     /// gzip header.
-    pub fn new(mut r: R) -> GzDecoder<R> {
-        let mut buf = Vec::with_capacity(10); // minimum header length
-        let mut header = None;
-
-        let result = {
-            let mut reader = Buffer::new(&mut buf, &mut r);
-            read_gz_header(&mut reader)
-        };
-
-        let state = match result {
-            Ok(hdr) => {
-                header = Some(hdr);
-                GzState::Body
-            }
-            Err(ref err) if io::ErrorKind::WouldBlock == err.kind() => GzState::Header(buf),
-            Err(err) => GzState::Err(err),
-        };
-
-        GzDecoder {
-            inner: state,
-            reader: CrcReader::new(deflate::bufread::DeflateDecoder::new(r)),
-            multi: false,
-            header,
-        }
+    pub fn new(r: R) -> GzDecoder<R> {
+        gz_decoder(r, HeaderLimits::default())
     }
 
     fn multi(mut self, flag: bool) -> GzDecoder<R> {
         self.multi = flag;
+        self.trailing = TrailingData::Error;
+        self
+    }
+
+    pub(crate) fn trailing_data(mut self, policy: TrailingData) -> GzDecoder<R> {
+        self.trailing = policy;
         self
     }
 }
@@ -406,6 +613,14 @@ impl<R> GzDecoder<R> {
     pub fn into_inner(self) -> R {
         self.reader.into_inner().into_inner()
     }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.reader.get_mut().set_limits(limits);
+    }
 }
 
 impl<R: BufRead> Read for GzDecoder<R> {
@@ -413,23 +628,26 @@ impl<R: BufRead> Read for GzDecoder<R> {
         let GzDecoder {
             inner,
             header,
+            parser,
             reader,
             multi,
+            split,
+            offset,
+            member,
+            trailing,
+            padding,
         } = self;
 
         loop {
             *inner = match mem::replace(inner, GzState::End) {
-                GzState::Header(mut buf) => {
-                    let result = {
-                        let mut reader = Buffer::new(&mut buf, reader.get_mut().get_mut());
-                        read_gz_header(&mut reader)
-                    };
+                GzState::Header => {
+                    let result = parser.parse(reader.get_mut().get_mut());
                     let hdr = result.map_err(|err| {
                         if io::ErrorKind::WouldBlock == err.kind() {
-                            *inner = GzState::Header(buf);
+                            *inner = GzState::Header;
                         }
 
-                        err
+                        member_error(err, *offset)
                     })?;
                     *header = Some(hdr);
                     GzState::Body
@@ -445,7 +663,7 @@ impl<R: BufRead> Read for GzDecoder<R> {
                             *inner = GzState::Body;
                         }
 
-                        err
+                        add_offset(err, *offset + parser.consumed())
                     })?;
 
                     match n {
@@ -457,6 +675,7 @@ impl<R: BufRead> Read for GzDecoder<R> {
                     }
                 }
                 GzState::Finished(pos, mut buf) => {
+                    let trailer = *offset + parser.consumed() + reader.get_ref().total_in();
                     if pos < buf.len() {
                         let n = reader
                             .get_mut()
@@ -464,7 +683,8 @@ impl<R: BufRead> Read for GzDecoder<R> {
                             .read(&mut buf[pos..])
                             .and_then(|n| {
                                 if n == 0 {
-                                    Err(io::ErrorKind::UnexpectedEof.into())
+                                    let kind = DecompressErrorKind::TruncatedInput;
+                                    Err(corrupt(kind, trailer + pos as u64))
                                 } else {
                                     Ok(n)
                                 }
@@ -481,14 +701,18 @@ impl<R: BufRead> Read for GzDecoder<R> {
                     } else {
                         let (crc, amt) = finish(&buf);
 
-                        if crc != reader.crc().sum() || amt != reader.crc().amount() {
-                            return Err(corrupt());
-                        } else if *multi {
-                            let is_eof = reader
-                                .get_mut()
-                                .get_mut()
-                                .fill_buf()
-                                .map(|buf| buf.is_empty())
+                        check_trailer(crc, amt, reader.crc(), trailer)?;
+                        *member = Some(GzMemberInfo {
+                            start: *offset,
+                            end: trailer + 8,
+                            crc,
+                            size: amt,
+                        });
+                        if *split {
+                            GzState::Member
+                        } else {
+                            let r = reader.get_mut().get_mut();
+                            let more = after_member(r, *multi, *trailing, trailer + 8, padding)
                                 .map_err(|err| {
                                     if io::ErrorKind::WouldBlock == err.kind() {
                                         *inner = GzState::Finished(pos, buf);
@@ -497,19 +721,24 @@ impl<R: BufRead> Read for GzDecoder<R> {
                                     err
                                 })?;
 
-                            if is_eof {
-                                GzState::End
-                            } else {
+                            if more {
+                                *offset = trailer + 8;
                                 reader.reset();
-                                reader.get_mut().reset_data();
+                                deflate::bufread::next_member(reader.get_mut());
                                 header.take();
-                                GzState::Header(Vec::with_capacity(10))
+                                member.take();
+                                parser.reset();
+                                GzState::Header
+                            } else {
+                                GzState::End
                             }
-                        } else {
-                            GzState::End
                         }
                     }
                 }
+                GzState::Member => {
+                    *inner = GzState::Member;
+                    return Ok(0);
+                }
                 GzState::Err(err) => return Err(err),
                 GzState::End => return Ok(0),
             };
@@ -517,6 +746,132 @@ impl<R: BufRead> Read for GzDecoder<R> {
     }
 }
 
+impl<R: BufRead> GzDecoder<R> {
+    /// Moves on to the next member, skipping what's left of the current one,
+    /// and parses its header. Returns `false` at the end of the stream.
+    ///
+    /// From the first call on, reading stops at the end of each member.
+    fn next_member(&mut self) -> io::Result<bool> {
+        if self.split {
+            let mut scratch = [0; 8 * 1024];
+            loop {
+                match self.inner {
+                    // A previous call stopped while parsing the header.
+                    GzState::Header => break,
+                    GzState::Member => {
+                        let end = self.member.as_ref().map_or(self.offset, |m| m.end());
+                        let r = self.reader.get_mut().get_mut();
+                        if !after_member(r, self.multi, self.trailing, end, &mut self.padding)? {
+                            self.inner = GzState::End;
+                            return Ok(false);
+                        }
+                        self.offset = end;
+                        self.member = None;
+                        self.padding = 0;
+                        self.reader.reset();
+                        deflate::bufread::next_member(self.reader.get_mut());
+                        self.header = None;
+                        self.parser.reset();
+                        self.inner = GzState::Header;
+                        break;
+                    }
+                    GzState::End => return Ok(false),
+                    _ => while self.read(&mut scratch)? > 0 {},
+                }
+            }
+        }
+        self.split = true;
+
+        match mem::replace(&mut self.inner, GzState::End) {
+            GzState::Header => match self.parser.parse(self.reader.get_mut().get_mut()) {
+                Ok(header) => {
+                    self.header = Some(header);
+                    self.inner = GzState::Body;
+                }
+                Err(err) => {
+                    if io::ErrorKind::WouldBlock == err.kind() {
+                        self.inner = GzState::Header;
+                    }
+                    return Err(member_error(err, self.offset));
+                }
+            },
+            GzState::Err(err) => return Err(err),
+            state => self.inner = state,
+        }
+        Ok(true)
+    }
+}
+
+impl<R> GzDecoder<R> {
+    /// Returns the number of bytes of the compressed stream consumed so far,
+    /// including headers, trailers and any zero padding which was skipped.
+    ///
+    /// Once the decoder has reached the end of the stream this is the exact
+    /// length of the gzip data, with any trailing data left unconsumed in the
+    /// underlying reader.
+    pub fn total_in(&self) -> u64 {
+        let body = self.offset + self.parser.consumed() + self.reader.get_ref().total_in();
+        match (&self.inner, &self.member) {
+            (GzState::Member, Some(member)) | (GzState::End, Some(member)) => {
+                member.end() + self.padding
+            }
+            (GzState::Finished(pos, _), _) => body + *pos as u64,
+            _ => body,
+        }
+    }
+}
+
+/// Decides whether another member follows the member ending at `end`,
+/// consuming or rejecting whatever else follows according to `policy`.
+fn after_member<R: BufRead>(
+    r: &mut R,
+    multi: bool,
+    policy: TrailingData,
+    end: u64,
+    padding: &mut u64,
+) -> io::Result<bool> {
+    if !multi && policy == TrailingData::Stop {
+        return Ok(false);
+    }
+    loop {
+        let buf = r.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(false);
+        }
+        let magic = buf[0] == 0x1f && (buf.len() < 2 || buf[1] == 0x8b);
+        if multi && magic && *padding == 0 {
+            return Ok(true);
+        }
+        let zeros = buf.iter().take_while(|&&b| b == 0).count();
+        match policy {
+            TrailingData::Stop => return Ok(false),
+            TrailingData::IgnoreZeros if zeros > 0 => {
+                r.consume(zeros);
+                *padding += zeros as u64;
+            }
+            _ => {
+                let kind = DecompressErrorKind::TrailingGarbage;
+                return Err(corrupt(kind, end + *padding));
+            }
+        }
+    }
+}
+
+/// Positions an error from the header of the member at `offset` in the
+/// stream, reporting anything but a gzip header after the first member as
+/// trailing garbage.
+pub(crate) fn member_error(err: io::Error, offset: u64) -> io::Error {
+    let bad_magic = err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<DecompressError>())
+        .is_some_and(|e| e.kind() == DecompressErrorKind::BadMagic);
+    if offset > 0 && bad_magic {
+        corrupt(DecompressErrorKind::TrailingGarbage, offset)
+    } else {
+        add_offset(err, offset)
+    }
+}
+
 #[cfg(feature = "tokio")]
 impl<R: AsyncRead + BufRead> AsyncRead for GzDecoder<R> {}
 
@@ -580,12 +935,24 @@ impl<R: AsyncWrite + BufRead> AsyncWrite for GzDecoder<R> {
 #[derive(Debug)]
 pub struct MultiGzDecoder<R>(GzDecoder<R>);
 
+pub(crate) fn multi_gz_decoder<R: BufRead>(
+    r: R,
+    limits: HeaderLimits,
+    trailing: Option<TrailingData>,
+) -> MultiGzDecoder<R> {
+    let decoder = gz_decoder(r, limits).multi(true);
+    MultiGzDecoder(match trailing {
+        Some(policy) => decoder.trailing_data(policy),
+        None => decoder,
+    })
+}
+
 impl<R: BufRead> MultiGzDecoder<R> {
     /// Creates a new decoder from the given reader, immediately parsing the
     /// (first) gzip header. If the gzip stream contains multiple members all will
     /// be decoded.
     pub fn new(r: R) -> MultiGzDecoder<R> {
-        MultiGzDecoder(GzDecoder::new(r).multi(true))
+        multi_gz_decoder(r, HeaderLimits::default(), None)
     }
 }
 
@@ -595,6 +962,12 @@ impl<R> MultiGzDecoder<R> {
         self.0.header()
     }
 
+    /// Returns the number of bytes of the compressed stream consumed so far,
+    /// including headers, trailers and any zero padding which was skipped.
+    pub fn total_in(&self) -> u64 {
+        self.0.total_in()
+    }
+
     /// Acquires a reference to the underlying reader.
     pub fn get_ref(&self) -> &R {
         self.0.get_ref()
@@ -612,6 +985,56 @@ impl<R> MultiGzDecoder<R> {
     pub fn into_inner(self) -> R {
         self.0.into_inner()
     }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.0.set_limits(limits);
+    }
+}
+
+impl<R: BufRead> MultiGzDecoder<R> {
+    /// Returns the next member of the stream, or `None` once all members
+    /// have been decoded.
+    ///
+    /// The first call returns the member being decoded. From then on reading
+    /// from this decoder stops at the end of each member, and whatever is
+    /// left of a member is skipped and verified when moving on to the next.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::prelude::*;
+    /// use flate2::{Compression, GzBuilder};
+    /// use flate2::bufread::MultiGzDecoder;
+    ///
+    /// let mut bytes = Vec::new();
+    /// for name in &["a.log", "b.log"] {
+    ///     let mut e = GzBuilder::new()
+    ///         .filename(*name)
+    ///         .write(Vec::new(), Compression::default());
+    ///     e.write_all(b"log line\n").unwrap();
+    ///     bytes.extend(e.finish().unwrap());
+    /// }
+    ///
+    /// let mut gz = MultiGzDecoder::new(&bytes[..]);
+    /// while let Some(mut member) = gz.next_member().unwrap() {
+    ///     let name = member.header().filename().unwrap().to_vec();
+    ///     let mut data = String::new();
+    ///     member.read_to_string(&mut data).unwrap();
+    ///     let info = member.info().unwrap();
+    ///     println!("{:?}: bytes {}..{}", name, info.start(), info.end());
+    /// }
+    /// ```
+    pub fn next_member(&mut self) -> io::Result<Option<GzMember<'_, R>>> {
+        if self.0.next_member()? {
+            Ok(Some(GzMember { inner: &mut self.0 }))
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 impl<R: BufRead> Read for MultiGzDecoder<R> {
@@ -639,3 +1062,38 @@ impl<R: AsyncWrite + BufRead> AsyncWrite for MultiGzDecoder<R> {
         self.get_mut().shutdown()
     }
 }
+
+/// A single member of a multi-member gzip stream, returned by
+/// [`MultiGzDecoder::next_member`].
+///
+/// Reading from this yields the decompressed data of the member only.
+///
+/// [`MultiGzDecoder::next_member`]: struct.MultiGzDecoder.html#method.next_member
+#[derive(Debug)]
+pub struct GzMember<'a, R: 'a> {
+    inner: &'a mut GzDecoder<R>,
+}
+
+impl<'a, R> GzMember<'a, R> {
+    /// Returns the header of this member.
+    pub fn header(&self) -> &GzHeader {
+        self.inner.header.as_ref().unwrap()
+    }
+
+    /// Returns the offset of this member in the compressed stream.
+    pub fn start(&self) -> u64 {
+        self.inner.offset
+    }
+
+    /// Returns the position and checksums of this member once all of its
+    /// data has been read and its trailer has been verified.
+    pub fn info(&self) -> Option<&GzMemberInfo> {
+        self.inner.member.as_ref()
+    }
+}
+
+impl<'a, R: BufRead> Read for GzMember<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(into)
+    }
+}
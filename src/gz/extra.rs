@@ -0,0 +1,283 @@
+use std::fmt;
+use std::io;
+
+use super::GzHeader;
+
+/// The largest size of the extra field of a gzip header.
+const MAX_EXTRA_LEN: usize = 0xffff;
+
+/// A subfield of the extra field of a gzip header.
+///
+/// Each subfield is identified by two bytes, `SI1` and `SI2`, followed by a
+/// little endian length and that many bytes of data. For example BGZF stores
+/// the size of each block in a `BC` subfield and dictzip its chunk table in an
+/// `RA` subfield.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExtraSubfield<'a> {
+    id: [u8; 2],
+    data: &'a [u8],
+}
+
+impl<'a> ExtraSubfield<'a> {
+    /// Returns the two identifying bytes of this subfield.
+    pub fn id(&self) -> [u8; 2] {
+        self.id
+    }
+
+    /// Returns the data of this subfield.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// An iterator over the subfields of the extra field of a gzip header.
+///
+/// Each item is a subfield, or an error of kind `InvalidData` if the rest of
+/// the extra field is too short for the length a subfield declares, after
+/// which the iteration ends.
+///
+/// # Examples
+///
+/// ```
+/// use flate2::{Compression, GzBuilder, GzHeader};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let e = GzBuilder::new()
+///     .subfield(*b"BC", &[0x1b, 0x00])
+///     .subfield(*b"ID", b"trace-42")
+///     .write(Vec::new(), Compression::default());
+/// let bytes = e.finish()?;
+///
+/// let (header, _) = GzHeader::parse(&bytes)?;
+/// let ids = header
+///     .subfields()
+///     .map(|s| s.map(|s| s.id()))
+///     .collect::<std::io::Result<Vec<_>>>()?;
+/// assert_eq!(ids, [*b"BC", *b"ID"]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ExtraSubfields<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> ExtraSubfields<'a> {
+    /// Creates an iterator over the subfields of the given extra field.
+    pub fn new(extra: &'a [u8]) -> ExtraSubfields<'a> {
+        ExtraSubfields { rest: extra }
+    }
+}
+
+impl<'a> Iterator for ExtraSubfields<'a> {
+    type Item = io::Result<ExtraSubfield<'a>>;
+
+    fn next(&mut self) -> Option<io::Result<ExtraSubfield<'a>>> {
+        let rest = self.rest;
+        if rest.is_empty() {
+            return None;
+        }
+        let len = match rest.get(2..4) {
+            Some(len) => u16::from_le_bytes([len[0], len[1]]) as usize,
+            None => 0,
+        };
+        if rest.len() < 4 + len {
+            self.rest = &[];
+            return Some(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "gzip extra subfield is longer than the extra field",
+            )));
+        }
+        self.rest = &rest[4 + len..];
+        Some(Ok(ExtraSubfield {
+            id: [rest[0], rest[1]],
+            data: &rest[4..4 + len],
+        }))
+    }
+}
+
+/// Appends a subfield to an extra field, failing with `InvalidInput` if the
+/// identifier is reserved or the extra field would become too long.
+pub(crate) fn append_subfield(extra: &mut Vec<u8>, id: [u8; 2], data: &[u8]) -> io::Result<()> {
+    if id[1] == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "gzip extra subfield ids with a second byte of 0 are reserved",
+        ));
+    }
+    if extra.len() + 4 + data.len() > MAX_EXTRA_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "gzip extra field would be longer than 65535 bytes",
+        ));
+    }
+    extra.extend_from_slice(&id);
+    extra.extend_from_slice(&(data.len() as u16).to_le_bytes());
+    extra.extend_from_slice(data);
+    Ok(())
+}
+
+type SubfieldDecoder<T> = Box<dyn Fn(&[u8]) -> io::Result<T> + Send + Sync>;
+
+/// A set of decoders turning the data of known extra subfields into values
+/// of type `T`, typically an enum with a variant for each kind of subfield.
+///
+/// # Examples
+///
+/// ```
+/// use std::io;
+/// use flate2::{Compression, GzBuilder, GzHeader, SubfieldRegistry};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Known {
+///     BlockSize(u16),
+///     TraceId(String),
+/// }
+///
+/// # fn main() -> io::Result<()> {
+/// let mut registry = SubfieldRegistry::new();
+/// registry.register(*b"BC", |data| match data {
+///     &[a, b] => Ok(Known::BlockSize(u16::from_le_bytes([a, b]))),
+///     _ => Err(io::Error::new(io::ErrorKind::InvalidData, "bad BC subfield")),
+/// });
+/// registry.register(*b"ID", |data| {
+///     Ok(Known::TraceId(String::from_utf8_lossy(data).into_owned()))
+/// });
+///
+/// let e = GzBuilder::new()
+///     .subfield(*b"ID", b"trace-42")
+///     .subfield(*b"XX", b"unknown")
+///     .write(Vec::new(), Compression::default());
+/// let (header, _) = GzHeader::parse(&e.finish()?)?;
+/// let values = registry.decode_all(&header)?;
+/// assert_eq!(values, [Known::TraceId("trace-42".to_string())]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct SubfieldRegistry<T> {
+    decoders: Vec<([u8; 2], SubfieldDecoder<T>)>,
+}
+
+impl<T> SubfieldRegistry<T> {
+    /// Creates a registry with no known subfields.
+    pub fn new() -> SubfieldRegistry<T> {
+        SubfieldRegistry {
+            decoders: Vec::new(),
+        }
+    }
+
+    /// Registers the decoder for subfields with the given identifier,
+    /// replacing any previous one.
+    pub fn register<F>(&mut self, id: [u8; 2], decoder: F)
+    where
+        F: Fn(&[u8]) -> io::Result<T> + Send + Sync + 'static,
+    {
+        self.decoders.retain(|&(known, _)| known != id);
+        self.decoders.push((id, Box::new(decoder)));
+    }
+
+    /// Decodes a subfield, returning `None` if its identifier isn't known.
+    pub fn decode(&self, subfield: &ExtraSubfield) -> Option<io::Result<T>> {
+        self.decoders
+            .iter()
+            .find(|&&(id, _)| id == subfield.id())
+            .map(|(_, decoder)| decoder(subfield.data()))
+    }
+
+    /// Decodes all known subfields of a header, in order, skipping unknown
+    /// ones.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error from parsing the extra field or from a
+    /// decoder.
+    pub fn decode_all(&self, header: &GzHeader) -> io::Result<Vec<T>> {
+        let mut values = Vec::new();
+        for subfield in header.subfields() {
+            if let Some(value) = self.decode(&subfield?) {
+                values.push(value?);
+            }
+        }
+        Ok(values)
+    }
+}
+
+impl<T> Default for SubfieldRegistry<T> {
+    fn default() -> SubfieldRegistry<T> {
+        SubfieldRegistry::new()
+    }
+}
+
+impl<T> fmt::Debug for SubfieldRegistry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ids = self.decoders.iter().map(|&(id, _)| id).collect::<Vec<_>>();
+        f.debug_struct("SubfieldRegistry")
+            .field("ids", &ids)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExtraSubfields;
+    use crate::{GzBuilder, GzHeader};
+    use std::io;
+
+    #[test]
+    fn malformed_subfields() {
+        let subfields = |extra: &[u8]| {
+            ExtraSubfields::new(extra)
+                .map(|s| s.map(|s| (s.id(), s.data().to_vec())))
+                .collect::<Vec<_>>()
+        };
+        let ok = subfields(b"AB\x02\x00hiCD\x00\x00");
+        assert_eq!(ok.len(), 2);
+        assert_eq!(*ok[0].as_ref().unwrap(), (*b"AB", b"hi".to_vec()));
+        assert_eq!(*ok[1].as_ref().unwrap(), (*b"CD", Vec::new()));
+
+        for extra in &[&b"AB\x03\x00hi"[..], b"AB\x00", b"A"] {
+            let res = subfields(extra);
+            assert_eq!(res.len(), 1);
+            let err = res.into_iter().next().unwrap().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        assert!(subfields(b"").is_empty());
+    }
+
+    #[test]
+    fn subfield_limit() {
+        let mut header = GzHeader::new();
+        header.add_subfield(*b"AB", &[7; 0xffff - 8]).unwrap();
+        let err = header.add_subfield(*b"CD", &[1]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        header.add_subfield(*b"CD", &[]).unwrap();
+        assert_eq!(header.extra().unwrap().len(), 0xffff);
+        assert_eq!(header.subfields().count(), 2);
+
+        let result = std::panic::catch_unwind(|| {
+            GzBuilder::new()
+                .subfield(*b"AB", &[0; 0x8000])
+                .subfield(*b"AB", &[0; 0x8000])
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn failed_subfield_leaves_header_unchanged() {
+        let mut header = GzHeader::new();
+        let err = header.add_subfield(*b"AB", &[0; 0x10000]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(header.extra(), None);
+
+        let err = header.add_subfield([b'A', 0], b"hi").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(header.extra(), None);
+
+        header.add_subfield(*b"AB", b"hi").unwrap();
+        assert!(header.add_subfield([b'C', 0], b"hi").is_err());
+        assert_eq!(header.extra(), Some(&b"AB\x02\x00hi"[..]));
+
+        let result = std::panic::catch_unwind(|| GzBuilder::new().subfield([b'A', 0], b"hi"));
+        assert!(result.is_err());
+    }
+}
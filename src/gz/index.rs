@@ -0,0 +1,734 @@
+//! Random access into gzip streams.
+//!
+//! This follows the approach of zlib's `zran.c`: the gzip stream is decoded
+//! once to record access points at DEFLATE block boundaries roughly every
+//! `spacing` bytes of uncompressed output. Each access point stores the
+//! position of the block in the compressed stream along with the 32 KiB of
+//! output preceding it, which is all that's needed to resume decompression
+//! from that point.
+
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use super::bufread::{
+    check_trailer, corrupt, read_gz_header, read_trailer, GzHeaderParser, HeaderLimits,
+};
+use crate::bufreader::BufReader;
+use crate::crc::Crc;
+use crate::deflate::blocks::{self, BlockWalker, Step};
+use crate::mem::{add_offset, io_error};
+use crate::{Compression, Decompress, DecompressErrorKind, DecompressLimits};
+use crate::{FlushDecompress, Status};
+
+const MAGIC: &[u8; 8] = b"FL2GZIDX";
+const VERSION: u32 = 1;
+
+/// The default distance, in uncompressed bytes, between access points.
+pub const DEFAULT_SPACING: u64 = 1024 * 1024;
+
+/// A point in a gzip stream from which decompression can be resumed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccessPoint {
+    compressed_offset: u64,
+    bit_offset: u8,
+    uncompressed_offset: u64,
+    window: Vec<u8>,
+}
+
+impl AccessPoint {
+    /// Returns the offset of the byte in the compressed stream containing the
+    /// first bit of the DEFLATE block this point refers to.
+    pub fn compressed_offset(&self) -> u64 {
+        self.compressed_offset
+    }
+
+    /// Returns the number of bits of the byte at `compressed_offset` which
+    /// belong to the previous block, in the range 0 to 7.
+    pub fn bit_offset(&self) -> u8 {
+        self.bit_offset
+    }
+
+    /// Returns the offset in the uncompressed data of this point.
+    pub fn uncompressed_offset(&self) -> u64 {
+        self.uncompressed_offset
+    }
+
+    /// Returns the up to 32 KiB of uncompressed data preceding this point
+    /// within its gzip member.
+    pub fn window(&self) -> &[u8] {
+        &self.window
+    }
+}
+
+/// An index of access points into a gzip stream.
+///
+/// The index can be built from any gzip stream, including streams that consist
+/// of several members, and saved alongside it with [`write_to`] so it only has
+/// to be built once.
+///
+/// [`write_to`]: #method.write_to
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::io::{Cursor, SeekFrom};
+/// use flate2::Compression;
+/// use flate2::index::{GzIndex, IndexedGzDecoder};
+/// use flate2::write::GzEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let data = (0..100_000u32).map(|i| format!("line {}\n", i)).collect::<String>();
+/// let mut e = GzEncoder::new(Vec::new(), Compression::default());
+/// e.write_all(data.as_bytes())?;
+/// let gz = e.finish()?;
+///
+/// let index = GzIndex::build(&gz[..], 64 * 1024)?;
+/// let mut d = IndexedGzDecoder::new(Cursor::new(gz), index);
+/// d.seek(SeekFrom::Start(500_000))?;
+/// let mut buf = [0; 16];
+/// d.read_exact(&mut buf)?;
+/// assert_eq!(&buf[..], &data.as_bytes()[500_000..500_016]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GzIndex {
+    spacing: u64,
+    compressed_len: u64,
+    uncompressed_len: u64,
+    points: Vec<AccessPoint>,
+}
+
+impl GzIndex {
+    /// Builds an index by decompressing the whole gzip stream read from `r`.
+    ///
+    /// Access points are recorded at the first DEFLATE block boundary after
+    /// each `spacing` bytes of uncompressed data, and at the start of the
+    /// first gzip member. Offsets are relative to the current position of
+    /// `r`, which should be the start of the gzip stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails or if the stream is not a valid
+    /// gzip stream, including checksum mismatches in any member.
+    pub fn build<R: Read>(r: R, spacing: u64) -> io::Result<GzIndex> {
+        let mut walker = BlockWalker::new(BufReader::new(r));
+        let mut points = Vec::new();
+        let mut total = 0u64;
+        let mut last = 0u64;
+
+        loop {
+            let start = walker.bit_position() / 8;
+            read_gz_header(&mut walker).map_err(|e| add_offset(e, start))?;
+            walker.reset();
+            let mut crc = Crc::new();
+            let mut boundary = true;
+            loop {
+                if boundary && (points.is_empty() || total - last >= spacing) {
+                    let bits = walker.bit_position();
+                    points.push(AccessPoint {
+                        compressed_offset: bits / 8,
+                        bit_offset: (bits % 8) as u8,
+                        uncompressed_offset: total,
+                        window: walker.window().to_vec(),
+                    });
+                    last = total;
+                }
+                let step = walker.step()?;
+                crc.update(walker.output());
+                total += walker.output().len() as u64;
+                match step {
+                    Step::Output => boundary = false,
+                    Step::Block => boundary = true,
+                    Step::Final => break,
+                }
+            }
+
+            walker.align();
+            let offset = walker.bit_position() / 8;
+            let (sum, amt) = read_trailer(&mut walker, offset)?;
+            check_trailer(sum, amt, &crc, offset)?;
+            if walker.is_eof()? {
+                break;
+            }
+        }
+
+        Ok(GzIndex {
+            spacing,
+            compressed_len: walker.bit_position() / 8,
+            uncompressed_len: total,
+            points,
+        })
+    }
+
+    /// Returns the spacing this index was built with.
+    pub fn spacing(&self) -> u64 {
+        self.spacing
+    }
+
+    /// Returns the length of the indexed gzip stream.
+    pub fn compressed_len(&self) -> u64 {
+        self.compressed_len
+    }
+
+    /// Returns the length of the uncompressed data of the indexed stream.
+    pub fn uncompressed_len(&self) -> u64 {
+        self.uncompressed_len
+    }
+
+    /// Returns the access points of this index, ordered by offset.
+    pub fn points(&self) -> &[AccessPoint] {
+        &self.points
+    }
+
+    /// Returns the last access point at or before the uncompressed `offset`.
+    pub fn point_before(&self, offset: u64) -> Option<&AccessPoint> {
+        let idx = match self
+            .points
+            .binary_search_by_key(&offset, |p| p.uncompressed_offset)
+        {
+            Ok(idx) => idx,
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        Some(&self.points[idx])
+    }
+
+    /// Serializes this index to `w`.
+    ///
+    /// The format starts with a magic number and a version, all integers are
+    /// little endian and each window is stored as raw DEFLATE data.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&self.spacing.to_le_bytes())?;
+        w.write_all(&self.compressed_len.to_le_bytes())?;
+        w.write_all(&self.uncompressed_len.to_le_bytes())?;
+        w.write_all(&(self.points.len() as u64).to_le_bytes())?;
+        for point in &self.points {
+            let mut e = crate::write::DeflateEncoder::new(Vec::new(), Compression::default());
+            e.write_all(&point.window)?;
+            let window = e.finish()?;
+
+            w.write_all(&point.compressed_offset.to_le_bytes())?;
+            w.write_all(&[point.bit_offset])?;
+            w.write_all(&point.uncompressed_offset.to_le_bytes())?;
+            w.write_all(&(point.window.len() as u32).to_le_bytes())?;
+            w.write_all(&(window.len() as u32).to_le_bytes())?;
+            w.write_all(&window)?;
+        }
+        Ok(())
+    }
+
+    /// Deserializes an index previously written with `write_to`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the data is not an index or
+    /// was written by an unsupported version of this format.
+    pub fn read_from<R: Read>(mut r: R) -> io::Result<GzIndex> {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(bad_index("not a gzip index"));
+        }
+        if read_u32(&mut r)? != VERSION {
+            return Err(bad_index("unsupported gzip index version"));
+        }
+        let spacing = read_u64(&mut r)?;
+        let compressed_len = read_u64(&mut r)?;
+        let uncompressed_len = read_u64(&mut r)?;
+        let count = read_u64(&mut r)?;
+
+        let mut points = Vec::new();
+        for _ in 0..count {
+            let compressed_offset = read_u64(&mut r)?;
+            let mut bit_offset = [0];
+            r.read_exact(&mut bit_offset)?;
+            let uncompressed_offset = read_u64(&mut r)?;
+            let window_len = read_u32(&mut r)? as usize;
+            let stored_len = read_u32(&mut r)? as u64;
+            if bit_offset[0] > 7 || window_len > blocks::WINDOW_SIZE {
+                return Err(bad_index("corrupt gzip index access point"));
+            }
+
+            let mut window = Vec::with_capacity(window_len);
+            crate::read::DeflateDecoder::new(r.by_ref().take(stored_len))
+                .read_to_end(&mut window)?;
+            if window.len() != window_len {
+                return Err(bad_index("corrupt gzip index window"));
+            }
+            if let Some(prev) = points.last() {
+                let prev: &AccessPoint = prev;
+                if prev.uncompressed_offset > uncompressed_offset {
+                    return Err(bad_index("gzip index access points out of order"));
+                }
+            }
+            points.push(AccessPoint {
+                compressed_offset,
+                bit_offset: bit_offset[0],
+                uncompressed_offset,
+                window,
+            });
+        }
+        if points.is_empty() {
+            return Err(bad_index("gzip index has no access points"));
+        }
+
+        Ok(GzIndex {
+            spacing,
+            compressed_len,
+            uncompressed_len,
+            points,
+        })
+    }
+}
+
+fn bad_index(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(u32::from_le_bytes(b))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut b = [0; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_le_bytes(b))
+}
+
+/// A gzip decoder which can seek within the uncompressed data using a
+/// [`GzIndex`].
+///
+/// Seeking is done by moving the underlying reader to the closest preceding
+/// access point and decompressing forward from there, so a seek costs at most
+/// about `spacing` bytes of decompression. Reading from the start without
+/// seeking behaves like `MultiGzDecoder`.
+///
+/// [`GzIndex`]: struct.GzIndex.html
+#[derive(Debug)]
+pub struct IndexedGzDecoder<R> {
+    inner: BufReader<R>,
+    index: GzIndex,
+    data: Decompress,
+    prefix: Vec<u8>,
+    prefix_pos: usize,
+    skip: u64,
+    pos: u64,
+    target: Option<u64>,
+    crc: Option<Crc>,
+    started: bool,
+    done: bool,
+    // Position in `inner` of the first byte `data` reads after `prefix`.
+    offset: u64,
+}
+
+impl<R: Read + Seek> IndexedGzDecoder<R> {
+    /// Creates a new decoder reading the gzip stream `r` described by
+    /// `index`.
+    ///
+    /// Offsets in the index are interpreted as absolute positions in `r`.
+    pub fn new(r: R, index: GzIndex) -> IndexedGzDecoder<R> {
+        IndexedGzDecoder {
+            inner: BufReader::new(r),
+            index,
+            data: Decompress::new(false),
+            prefix: Vec::new(),
+            prefix_pos: 0,
+            skip: 0,
+            pos: 0,
+            target: Some(0),
+            crc: None,
+            started: false,
+            done: false,
+            offset: 0,
+        }
+    }
+
+    /// Returns the position in `inner` that decompression has reached.
+    fn position(&self) -> u64 {
+        let total_in = self.data.total_in();
+        self.offset + total_in.saturating_sub(self.prefix.len() as u64)
+    }
+
+    fn reposition(&mut self, target: u64) -> io::Result<()> {
+        // Moving forward by less than the spacing is cheaper than restarting
+        // from an access point.
+        if self.started
+            && !self.done
+            && target >= self.pos
+            && target - self.pos <= self.index.spacing
+        {
+            self.skip += target - self.pos;
+            self.pos = target;
+            return Ok(());
+        }
+
+        let point = self
+            .index
+            .point_before(target)
+            .unwrap_or(&self.index.points[0]);
+        self.inner
+            .get_mut()
+            .seek(SeekFrom::Start(point.compressed_offset))?;
+        self.inner.reset_buffer();
+        let mut first = [0];
+        if point.bit_offset > 0 {
+            self.inner.read_exact(&mut first)?;
+        }
+        let priming = blocks::prime(&point.window, point.bit_offset, first[0]);
+
+        self.data.reset_continued(false);
+        self.prefix = priming.bytes;
+        self.prefix_pos = 0;
+        self.skip = priming.discard as u64 + target.saturating_sub(point.uncompressed_offset);
+        self.pos = target;
+        self.offset = point.compressed_offset + u64::from(point.bit_offset > 0);
+        self.started = true;
+        self.done = false;
+        // The checksum of a member can only be verified when it is decoded
+        // from its start.
+        self.crc = if point.window.is_empty() && point.bit_offset == 0 {
+            Some(Crc::new())
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    fn end_of_member(&mut self) -> io::Result<()> {
+        let offset = self.position();
+        let (sum, amt) = read_trailer(&mut self.inner, offset)?;
+        if let Some(crc) = self.crc.take() {
+            check_trailer(sum, amt, &crc, offset)?;
+        }
+        if self.inner.fill_buf()?.is_empty() {
+            self.done = true;
+        } else {
+            let mut parser = GzHeaderParser::new(HeaderLimits::default());
+            parser
+                .parse(&mut self.inner)
+                .map_err(|e| add_offset(e, offset + 8))?;
+            self.data.reset_continued(false);
+            self.prefix.clear();
+            self.prefix_pos = 0;
+            self.offset = offset + 8 + parser.consumed();
+            self.crc = Some(Crc::new());
+        }
+        Ok(())
+    }
+
+    fn decompress(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.done {
+                return Ok(0);
+            }
+            let from_prefix = self.prefix_pos < self.prefix.len();
+            let (ret, read, consumed, eof) = {
+                let input = if from_prefix {
+                    &self.prefix[self.prefix_pos..]
+                } else {
+                    self.inner.fill_buf()?
+                };
+                let eof = input.is_empty();
+                let before_out = self.data.total_out();
+                let before_in = self.data.total_in();
+                let ret = self.data.decompress(input, into, FlushDecompress::None);
+                (
+                    ret,
+                    (self.data.total_out() - before_out) as usize,
+                    (self.data.total_in() - before_in) as usize,
+                    eof,
+                )
+            };
+            if from_prefix {
+                self.prefix_pos += consumed;
+            } else {
+                self.inner.consume(consumed);
+            }
+
+            let status = ret.map_err(|e| io_error(e.at(self.position())))?;
+            if let Some(ref mut crc) = self.crc {
+                crc.update(&into[..read]);
+            }
+            if status == Status::StreamEnd {
+                self.end_of_member()?;
+            } else if read == 0 && eof {
+                return Err(corrupt(
+                    DecompressErrorKind::TruncatedInput,
+                    self.position(),
+                ));
+            }
+            if read > 0 || into.is_empty() {
+                return Ok(read);
+            }
+        }
+    }
+}
+
+impl<R> IndexedGzDecoder<R> {
+    /// Returns the index used by this decoder.
+    pub fn index(&self) -> &GzIndex {
+        &self.index
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.inner.get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying reader.
+    ///
+    /// Note that mutation of the reader may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.inner.get_mut()
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner.into_inner()
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// The limits apply to all data decompressed so far taken together,
+    /// including data decompressed again after seeking.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.data.set_limits(limits);
+    }
+}
+
+impl<R: Read + Seek> Read for IndexedGzDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if let Some(target) = self.target.take() {
+            self.reposition(target)?;
+        }
+
+        let mut scratch = [0; 8 * 1024];
+        while self.skip > 0 {
+            let len = cmp::min(self.skip, scratch.len() as u64) as usize;
+            let n = self.decompress(&mut scratch[..len])?;
+            if n == 0 {
+                self.skip = 0;
+                break;
+            }
+            self.skip -= n as u64;
+        }
+
+        let n = self.decompress(into)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for IndexedGzDecoder<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let current = self.target.unwrap_or(self.pos);
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => offset(self.index.uncompressed_len, n),
+            SeekFrom::Current(n) => offset(current, n),
+        };
+        match target {
+            Some(n) => {
+                self.target = Some(n);
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+fn offset(base: u64, delta: i64) -> Option<u64> {
+    if delta >= 0 {
+        base.checked_add(delta as u64)
+    } else {
+        base.checked_sub(delta.wrapping_neg() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp;
+    use std::io::prelude::*;
+    use std::io::{Cursor, SeekFrom};
+
+    use rand::{thread_rng, Rng};
+
+    use super::{GzIndex, IndexedGzDecoder};
+    use crate::write::GzEncoder;
+    use crate::{Compression, DecompressLimits, GzBuilder, LimitExceeded};
+
+    fn sample(len: usize) -> Vec<u8> {
+        let mut rng = thread_rng();
+        let words: Vec<Vec<u8>> = (0..256)
+            .map(|_| {
+                (0..rng.gen_range(1, 10))
+                    .map(|_| rng.gen_range(b'a', b'z'))
+                    .collect()
+            })
+            .collect();
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            data.extend_from_slice(&words[rng.gen_range(0, words.len())]);
+            data.push(b' ');
+        }
+        data.truncate(len);
+        data
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn seek_everywhere() {
+        let data = sample(600 * 1024);
+        let gz = gzip(&data);
+        let index = GzIndex::build(&gz[..], 32 * 1024).unwrap();
+        assert!(index.points().len() > 2);
+        assert_eq!(index.uncompressed_len(), data.len() as u64);
+        assert_eq!(index.compressed_len(), gz.len() as u64);
+
+        let mut d = IndexedGzDecoder::new(Cursor::new(&gz[..]), index);
+        for &pos in [0, 1, 70_000, 599 * 1024, 12_345, 300_000, 300_001].iter() {
+            d.seek(SeekFrom::Start(pos as u64)).unwrap();
+            let mut buf = [0; 1000];
+            let n = d.read(&mut buf).unwrap();
+            assert!(n > 0);
+            assert_eq!(&buf[..n], &data[pos..pos + n]);
+        }
+
+        d.seek(SeekFrom::Start(0)).unwrap();
+        let mut all = Vec::new();
+        d.read_to_end(&mut all).unwrap();
+        assert!(all == data);
+    }
+
+    #[test]
+    fn multiple_members() {
+        let a = sample(100 * 1024);
+        let b = sample(70 * 1024);
+        let mut gz = gzip(&a);
+        gz.extend(gzip(&b));
+        let mut gz2 = GzBuilder::new()
+            .filename("b")
+            .comment("second")
+            .write(Vec::new(), Compression::fast());
+        gz2.write_all(&b).unwrap();
+        gz.extend(gz2.finish().unwrap());
+        let mut expected = a.clone();
+        expected.extend(&b);
+        expected.extend(&b);
+
+        let index = GzIndex::build(&gz[..], 16 * 1024).unwrap();
+        let mut d = IndexedGzDecoder::new(Cursor::new(&gz[..]), index);
+        d.seek(SeekFrom::End(-150 * 1024)).unwrap();
+        let mut rest = Vec::new();
+        d.read_to_end(&mut rest).unwrap();
+        assert!(rest[..] == expected[expected.len() - 150 * 1024..]);
+    }
+
+    #[test]
+    fn external_gzip() {
+        let gz = include_bytes!("../../tests/good-file.gz");
+        let data = include_bytes!("../../tests/good-file.txt");
+        let index = GzIndex::build(&gz[..], 1024).unwrap();
+
+        let mut d = IndexedGzDecoder::new(Cursor::new(&gz[..]), index.clone());
+        for point in index.points() {
+            let pos = point.uncompressed_offset() as usize;
+            d.seek(SeekFrom::Start(pos as u64 + 3)).unwrap();
+            let mut buf = vec![0; 2000];
+            d.read_exact(&mut buf).unwrap();
+            assert!(buf[..] == data[pos + 3..pos + 2003]);
+        }
+    }
+
+    #[test]
+    fn points_near_member_start() {
+        // Sync flushes give access points inside the first window, where the
+        // priming data is longer than the offset of the point itself.
+        let mut rng = thread_rng();
+        let data: Vec<u8> = (0..40_000).map(|_| rng.gen_range(b'a', b'z')).collect();
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        for chunk in data.chunks(333) {
+            e.write_all(chunk).unwrap();
+            e.flush().unwrap();
+        }
+        let gz = e.finish().unwrap();
+        let index = GzIndex::build(&gz[..], 100).unwrap();
+        assert!(index.points().len() > 100);
+
+        let mut d = IndexedGzDecoder::new(Cursor::new(&gz[..]), index.clone());
+        for point in index.points() {
+            let pos = point.uncompressed_offset() as usize;
+            assert_eq!(d.seek(SeekFrom::Start(pos as u64)).unwrap(), pos as u64);
+            let mut buf = vec![0; cmp::min(500, data.len() - pos)];
+            d.read_exact(&mut buf).unwrap();
+            assert!(buf[..] == data[pos..pos + buf.len()]);
+            assert_eq!(
+                d.seek(SeekFrom::Current(1)).unwrap(),
+                (pos + buf.len() + 1) as u64
+            );
+        }
+    }
+
+    #[test]
+    fn serialize_roundtrip() {
+        let data = sample(200 * 1024);
+        let gz = gzip(&data);
+        let index = GzIndex::build(&gz[..], 50 * 1024).unwrap();
+
+        let mut saved = Vec::new();
+        index.write_to(&mut saved).unwrap();
+        let loaded = GzIndex::read_from(&saved[..]).unwrap();
+        assert_eq!(loaded, index);
+
+        saved[8] = 2;
+        assert!(GzIndex::read_from(&saved[..]).is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let data = sample(300 * 1024);
+        let gz = gzip(&data);
+        let index = GzIndex::build(&gz[..], 32 * 1024).unwrap();
+
+        let mut d = IndexedGzDecoder::new(Cursor::new(&gz[..]), index.clone());
+        d.set_limits(DecompressLimits::new().max_output(100_000));
+        d.seek(SeekFrom::Start(50_000)).unwrap();
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        let limit = err.get_ref().unwrap().downcast_ref::<LimitExceeded>();
+        assert_eq!(limit, Some(&LimitExceeded::Output(100_000)));
+
+        let mut d = IndexedGzDecoder::new(Cursor::new(&gz[..]), index);
+        d.set_limits(DecompressLimits::new().max_output(data.len() as u64));
+        let mut all = Vec::new();
+        d.read_to_end(&mut all).unwrap();
+        assert!(all == data);
+    }
+
+    #[test]
+    fn corrupt_trailer() {
+        let mut gz = gzip(&sample(1000));
+        let len = gz.len();
+        gz[len - 8] ^= 1;
+        assert!(GzIndex::build(&gz[..], 100).is_err());
+    }
+}
@@ -0,0 +1,499 @@
+//! Crash-safe append-only gzip logs.
+//!
+//! A log is written as a series of gzip members. Within a member the
+//! compressor is regularly sync-flushed, which ends the current DEFLATE block
+//! on a byte boundary, and members are ended after a fixed amount of data. If
+//! the writing process dies, the file is left with some complete members
+//! followed by a truncated one, and everything up to the last flushed block
+//! can still be recovered with [`Reader`], which stops quietly at the damage
+//! instead of failing like [`read::MultiGzDecoder`].
+//!
+//! [`Reader`]: struct.Reader.html
+//! [`read::MultiGzDecoder`]: ../read/struct.MultiGzDecoder.html
+
+use std::cmp;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+use super::bufread::{check_trailer, read_gz_header, read_trailer};
+use super::GzMemberInfo;
+use crate::bufreader::BufReader;
+use crate::crc::Crc;
+use crate::deflate::blocks::{BlockWalker, Step};
+use crate::write::MultiGzEncoder;
+use crate::Compression;
+
+/// The default amount of uncompressed data written between sync flushes.
+pub const DEFAULT_SYNC_INTERVAL: u64 = 64 * 1024;
+
+/// The default amount of uncompressed data written to each gzip member.
+pub const DEFAULT_MEMBER_SIZE: u64 = 1024 * 1024;
+
+/// A gzip log writer.
+///
+/// Data written is compressed into gzip members of up to
+/// [`DEFAULT_MEMBER_SIZE`] bytes each, and the compressor is sync-flushed
+/// whenever [`DEFAULT_SYNC_INTERVAL`] bytes have been written since the last
+/// flush, before accepting more data. Calling `flush` syncs explicitly, which
+/// is useful to bound the loss when writes are infrequent.
+///
+/// [`DEFAULT_MEMBER_SIZE`]: constant.DEFAULT_MEMBER_SIZE.html
+/// [`DEFAULT_SYNC_INTERVAL`]: constant.DEFAULT_SYNC_INTERVAL.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::Compression;
+/// use flate2::gzlog::{Reader, Writer};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut w = Writer::new(Vec::new(), Compression::default());
+/// w.write_all(b"first entry\n")?;
+/// w.flush()?;
+/// let synced = w.get_ref().len();
+/// w.write_all(b"second entry\n")?;
+/// let log = w.finish()?;
+///
+/// // Only the synced part of the log survived a crash.
+/// let mut r = Reader::new(&log[..synced]);
+/// let mut s = String::new();
+/// r.read_to_string(&mut s)?;
+/// assert_eq!(s, "first entry\n");
+/// assert_eq!(r.lost(), 0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Writer<W: Write> {
+    inner: MultiGzEncoder<W>,
+    sync_interval: u64,
+    unsynced: u64,
+}
+
+impl<W: Write> Writer<W> {
+    /// Creates a new log writer which will write data compressed with the
+    /// given level to `w`.
+    pub fn new(w: W, level: Compression) -> Writer<W> {
+        let mut inner = MultiGzEncoder::new(w, level);
+        inner.set_max_member_in(Some(DEFAULT_MEMBER_SIZE));
+        Writer {
+            inner,
+            sync_interval: DEFAULT_SYNC_INTERVAL,
+            unsynced: 0,
+        }
+    }
+
+    /// Configures the amount of uncompressed data written between sync
+    /// flushes.
+    ///
+    /// A smaller interval loses less data in a crash but compresses worse.
+    /// An interval of zero is treated as one byte.
+    pub fn set_sync_interval(&mut self, bytes: u64) {
+        self.sync_interval = cmp::max(bytes, 1);
+    }
+
+    /// Configures the amount of uncompressed data written to each member.
+    pub fn set_member_size(&mut self, bytes: u64) {
+        self.inner.set_max_member_in(Some(bytes));
+    }
+
+    /// Returns the positions of the members finished so far, relative to
+    /// where this writer started writing.
+    pub fn members(&self) -> &[GzMemberInfo] {
+        self.inner.members()
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutation of the writer may result in surprising results if
+    /// this writer is continued to be used.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    /// Attempt to finish the log, ending the current member.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete the log, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.inner.try_finish()
+    }
+
+    /// Finish the log, returning the underlying writer once the current
+    /// member has been ended.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete the log, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(self) -> io::Result<W> {
+        self.inner.finish()
+    }
+}
+
+impl Writer<File> {
+    /// Opens an existing log for appending.
+    ///
+    /// The log is recovered as with [`Reader`]. If it ends with a damaged
+    /// member, the recovered data of that member is rewritten as a complete
+    /// member and the rest of the file is truncated, so that new data is
+    /// appended after an intact log. Returns the writer along with the number
+    /// of bytes of the file which couldn't be recovered, see
+    /// [`Reader::lost`].
+    ///
+    /// The file must be opened for both reading and writing, and not in
+    /// append mode, as the writer seeks within it.
+    ///
+    /// [`Reader`]: struct.Reader.html
+    /// [`Reader::lost`]: struct.Reader.html#method.lost
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading, writing or truncating the file fails.
+    pub fn append(mut file: File, level: Compression) -> io::Result<(Writer<File>, u64)> {
+        file.seek(SeekFrom::Start(0))?;
+        let mut r = Reader::new(&mut file);
+        io::copy(&mut r, &mut io::sink())?;
+        let (complete, intact, lost) = (r.complete_len(), r.intact_len(), r.lost());
+
+        let mut tail = Vec::new();
+        if intact > complete {
+            file.seek(SeekFrom::Start(complete))?;
+            Reader::new(&mut file).read_to_end(&mut tail)?;
+        }
+
+        file.seek(SeekFrom::Start(complete))?;
+        let mut w = Writer::new(file, level);
+        if intact + lost > complete {
+            w.write_all(&tail)?;
+            w.inner.finish_member()?;
+            let end = complete + w.members().last().map_or(0, |m| m.end());
+            w.get_mut().set_len(end)?;
+        }
+        Ok((w, lost))
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.unsynced >= self.sync_interval {
+            self.flush()?;
+        }
+        let n = cmp::min(buf.len() as u64, self.sync_interval - self.unsynced) as usize;
+        let n = self.inner.write(&buf[..n])?;
+        self.unsynced += n as u64;
+        Ok(n)
+    }
+
+    /// Sync-flushes the compressor and flushes the underlying writer, so that
+    /// all data written so far can be recovered.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+}
+
+/// The largest amount of data of a single block `Reader` holds back until the
+/// block is complete.
+const MAX_BLOCK_OUTPUT: usize = 1024 * 1024;
+
+#[derive(Debug)]
+enum State {
+    Header,
+    Blocks,
+    Done,
+}
+
+/// A reader recovering the data of a possibly damaged gzip log.
+///
+/// This decodes gzip members one DEFLATE block at a time and returns the data
+/// of every complete block, verifying the trailer of each complete member.
+/// At the first sign of damage, such as a truncated member, a checksum
+/// mismatch or data which isn't a gzip member, it stops and reports the end
+/// of the data as if the stream had ended there.
+///
+/// Since the data of a block is returned as soon as it is decoded, damage
+/// within a complete member is only noticed at its trailer, after its data
+/// has been returned. Truncation, the damage left by a crash, never causes
+/// wrong data to be returned.
+///
+/// To bound memory use, a block producing more than 1 MiB of data, which the
+/// [`Writer`] only writes if the sync interval is raised above that, is
+/// returned in parts as it is decoded. If such a block is truncated, the
+/// parts returned before the damage was found are kept.
+///
+/// [`Writer`]: struct.Writer.html
+///
+/// The accessors describing the recovery are only meaningful once `read` has
+/// returned 0.
+pub struct Reader<R> {
+    walker: BlockWalker<BufReader<R>>,
+    state: State,
+    crc: Crc,
+    pos: usize,
+    complete: u64,
+    intact: u64,
+    lost: u64,
+}
+
+impl<R: Read> Reader<R> {
+    /// Creates a new reader recovering the log read from `r`.
+    pub fn new(r: R) -> Reader<R> {
+        let mut walker = BlockWalker::new(BufReader::new(r));
+        walker.set_max_output(MAX_BLOCK_OUTPUT);
+        Reader {
+            walker,
+            state: State::Header,
+            crc: Crc::new(),
+            pos: 0,
+            complete: 0,
+            intact: 0,
+            lost: 0,
+        }
+    }
+
+    /// Returns the length of the prefix of the stream made of complete,
+    /// verified members.
+    pub fn complete_len(&self) -> u64 {
+        self.complete
+    }
+
+    /// Returns the length of the prefix of the stream which was recovered,
+    /// up to the end of the last complete DEFLATE block.
+    pub fn intact_len(&self) -> u64 {
+        self.intact
+    }
+
+    /// Returns the number of bytes of the stream after the recovered prefix,
+    /// which is zero for an undamaged log or one truncated right after a
+    /// flush.
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        if self.walker.is_eof()? {
+            self.state = State::Done;
+            return Ok(());
+        }
+        match read_gz_header(&mut self.walker) {
+            Ok(_) => {}
+            Err(ref e) if is_damage(e) => return self.stop(),
+            Err(e) => return Err(e),
+        }
+        self.walker.reset();
+        self.crc.reset();
+        self.pos = 0;
+        self.state = State::Blocks;
+        Ok(())
+    }
+
+    fn block(&mut self) -> io::Result<()> {
+        let step = match self.walker.step() {
+            Ok(step) => step,
+            Err(ref e) if is_damage(e) => {
+                self.pos = self.walker.output().len();
+                return self.stop();
+            }
+            Err(e) => return Err(e),
+        };
+        self.pos = 0;
+        self.crc.update(self.walker.output());
+        if step == Step::Output {
+            return Ok(());
+        }
+        self.intact = self.walker.bit_position().div_ceil(8);
+        if step == Step::Block {
+            return Ok(());
+        }
+
+        self.walker.align();
+        let offset = self.walker.bit_position() / 8;
+        let crc = &self.crc;
+        let walker = &mut self.walker;
+        match read_trailer(walker, offset)
+            .and_then(|(sum, amt)| check_trailer(sum, amt, crc, offset))
+        {
+            Ok(()) => {}
+            Err(ref e) if is_damage(e) => return self.stop(),
+            Err(e) => return Err(e),
+        }
+        self.complete = self.walker.bit_position() / 8;
+        self.intact = self.complete;
+        self.state = State::Header;
+        Ok(())
+    }
+
+    fn stop(&mut self) -> io::Result<()> {
+        self.walker.align();
+        let end = self.walker.bit_position() / 8 + io::copy(&mut self.walker, &mut io::sink())?;
+        self.lost = end - self.intact;
+        self.state = State::Done;
+        Ok(())
+    }
+}
+
+fn is_damage(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput | io::ErrorKind::UnexpectedEof
+    )
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let out = &self.walker.output()[self.pos..];
+            if !out.is_empty() {
+                let n = cmp::min(buf.len(), out.len());
+                buf[..n].copy_from_slice(&out[..n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            match self.state {
+                State::Header => self.header()?,
+                State::Blocks => self.block()?,
+                State::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for Reader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Reader")
+            .field("state", &self.state)
+            .field("complete", &self.complete)
+            .field("intact", &self.intact)
+            .field("lost", &self.lost)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::prelude::*;
+
+    use super::{Reader, Writer};
+    use crate::read::MultiGzDecoder;
+    use crate::Compression;
+
+    fn entries(range: std::ops::Range<usize>) -> Vec<u8> {
+        range
+            .flat_map(|i| format!("entry number {}\n", i).into_bytes())
+            .collect()
+    }
+
+    fn recover(log: &[u8]) -> (Vec<u8>, u64, u64) {
+        let mut r = Reader::new(log);
+        let mut data = Vec::new();
+        r.read_to_end(&mut data).unwrap();
+        assert_eq!(r.intact_len() + r.lost(), log.len() as u64);
+        assert!(r.complete_len() <= r.intact_len());
+        (data, r.complete_len(), r.lost())
+    }
+
+    #[test]
+    fn recover_every_prefix() {
+        let data = entries(0..2000);
+        let mut w = Writer::new(Vec::new(), Compression::default());
+        w.set_sync_interval(1000);
+        w.set_member_size(7000);
+        let mut syncs = vec![(0, 0)];
+        for chunk in data.chunks(300) {
+            w.write_all(chunk).unwrap();
+            w.flush().unwrap();
+            syncs.push((w.get_ref().len(), syncs.last().unwrap().1 + chunk.len()));
+        }
+        let log = w.finish().unwrap();
+        let mut all = Vec::new();
+        MultiGzDecoder::new(&log[..]).read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+
+        let (all, complete, lost) = recover(&log);
+        assert_eq!(all, data);
+        assert_eq!(complete, log.len() as u64);
+        assert_eq!(lost, 0);
+
+        for cut in (0..log.len()).step_by(37) {
+            let (recovered, _, _) = recover(&log[..cut]);
+            assert_eq!(&recovered[..], &data[..recovered.len()]);
+            let synced = syncs.iter().rev().find(|s| s.0 <= cut).unwrap().1;
+            assert!(recovered.len() >= synced, "cut at {}", cut);
+        }
+
+        let mut garbage = log.clone();
+        garbage.extend_from_slice(b"not gzip");
+        let (recovered, complete, lost) = recover(&garbage);
+        assert_eq!(recovered, data);
+        assert_eq!(complete, log.len() as u64);
+        assert_eq!(lost, 8);
+    }
+
+    #[test]
+    fn long_blocks() {
+        let data = entries(0..200_000);
+        let mut w = Writer::new(Vec::new(), Compression::default());
+        w.set_sync_interval(8 * 1024 * 1024);
+        w.set_member_size(8 * 1024 * 1024);
+        w.write_all(&data).unwrap();
+        let log = w.finish().unwrap();
+
+        let (all, complete, lost) = recover(&log);
+        assert_eq!(all, data);
+        assert_eq!(complete, log.len() as u64);
+        assert_eq!(lost, 0);
+
+        let (recovered, complete, lost) = recover(&log[..log.len() / 2]);
+        assert_eq!(&recovered[..], &data[..recovered.len()]);
+        assert_eq!(complete, 0);
+        assert!(lost > 0);
+    }
+
+    #[test]
+    fn append_after_crash() {
+        let data = entries(0..500);
+        let mut w = Writer::new(Vec::new(), Compression::default());
+        w.set_sync_interval(512);
+        w.set_member_size(4096);
+        w.write_all(&data).unwrap();
+        let log = w.finish().unwrap();
+        let cut = log.len() - 10;
+        let (recovered, _, lost) = recover(&log[..cut]);
+        assert!(lost > 0);
+
+        let path = std::env::temp_dir().join(format!("flate2-gzlog-{}.gz", std::process::id()));
+        fs::write(&path, &log[..cut]).unwrap();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let (mut w, appended_lost) = Writer::append(file, Compression::default()).unwrap();
+        assert_eq!(appended_lost, lost);
+        w.write_all(b"after the crash\n").unwrap();
+        w.finish().unwrap();
+
+        let mut expected = recovered;
+        expected.extend_from_slice(b"after the crash\n");
+        let mut all = Vec::new();
+        MultiGzDecoder::new(fs::File::open(&path).unwrap())
+            .read_to_end(&mut all)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(all, expected);
+    }
+}
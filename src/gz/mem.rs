@@ -0,0 +1,809 @@
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::slice;
+
+use super::bufread::{finish, GzHeaderParser, HeaderLimits};
+use super::{GzBuilder, GzHeader};
+use crate::crc::Crc;
+use crate::{Compress, CompressError, CompressOptions, Compression, Decompress};
+use crate::{DecompressError, DecompressErrorKind, DecompressLimits};
+use crate::{FlushCompress, FlushDecompress, Status};
+
+/// Raw in-memory compression of a gzip stream.
+///
+/// This is the gzip counterpart of [`Compress`]: it frames the raw deflate
+/// stream with a gzip header, given by the [`GzBuilder`] it was created from,
+/// and a trailer holding the CRC-32 and length of the data, without doing any
+/// I/O of its own.
+///
+/// [`Compress`]: struct.Compress.html
+/// [`GzBuilder`]: struct.GzBuilder.html
+///
+/// # Examples
+///
+/// ```
+/// use flate2::{Compression, FlushCompress, GzBuilder, Status};
+///
+/// let mut gz = GzBuilder::new()
+///     .filename("hello.txt")
+///     .compress(Compression::default());
+/// let mut out = Vec::with_capacity(128);
+/// let status = gz
+///     .compress_vec(b"Hello World", &mut out, FlushCompress::Finish)
+///     .unwrap();
+/// assert_eq!(status, Status::StreamEnd);
+/// assert_eq!(&out[..2], &[0x1f, 0x8b]);
+/// ```
+#[derive(Debug)]
+pub struct GzCompress {
+    inner: Compress,
+    crc: Crc,
+    header: Vec<u8>,
+    // Header or trailer bytes which haven't been handed out yet.
+    pending: Vec<u8>,
+    pos: usize,
+    finished: bool,
+    total_out: u64,
+}
+
+impl GzCompress {
+    /// Creates a new object ready for compressing data into a gzip stream
+    /// with a default header.
+    ///
+    /// For header configuration, see the `GzBuilder` type.
+    pub fn new(level: Compression) -> GzCompress {
+        GzBuilder::new().compress(level)
+    }
+
+    pub(crate) fn make(header: Vec<u8>, inner: Compress) -> GzCompress {
+        GzCompress {
+            inner,
+            crc: Crc::new(),
+            pending: header.clone(),
+            header,
+            pos: 0,
+            finished: false,
+            total_out: 0,
+        }
+    }
+
+    /// Creates a new object like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(options: &CompressOptions) -> io::Result<GzCompress> {
+        GzBuilder::new().compress_with_options(options)
+    }
+
+    /// Returns the total number of input bytes which have been processed by
+    /// this compression object.
+    pub fn total_in(&self) -> u64 {
+        self.inner.total_in()
+    }
+
+    /// Returns the total number of output bytes which have been produced by
+    /// this compression object, including the gzip header and trailer.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Performs the equivalent of replacing this compression state with a
+    /// freshly allocated copy, which writes the same header.
+    ///
+    /// This can be used to start another member of a multi-member stream
+    /// once the current one has been finished.
+    pub fn reset(&mut self) {
+        self.inner.reset();
+        self.crc.reset();
+        self.pending = self.header.clone();
+        self.pos = 0;
+        self.finished = false;
+        self.total_out = 0;
+    }
+
+    /// Compresses the input data into the output, consuming only as much
+    /// input as needed and writing as much output as possible.
+    ///
+    /// The header is written before any compressed data. Once the input has
+    /// been compressed with `FlushCompress::Finish`, the trailer is written
+    /// and `Status::StreamEnd` is returned after its last byte.
+    ///
+    /// To learn how much data was consumed or how much output was produced, use
+    /// the `total_in` and `total_out` functions before/after this is called.
+    pub fn compress(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: FlushCompress,
+    ) -> Result<Status, CompressError> {
+        let mut written = self.drain(output);
+        if self.pos < self.pending.len() {
+            return Ok(if written > 0 {
+                Status::Ok
+            } else {
+                Status::BufError
+            });
+        }
+        if self.finished {
+            return Ok(Status::StreamEnd);
+        }
+
+        let before_in = self.inner.total_in();
+        let before_out = self.inner.total_out();
+        let status = self.inner.compress(input, &mut output[written..], flush)?;
+        let consumed = (self.inner.total_in() - before_in) as usize;
+        let produced = (self.inner.total_out() - before_out) as usize;
+        self.crc.update(&input[..consumed]);
+        self.total_out += produced as u64;
+        written += produced;
+
+        match status {
+            Status::StreamEnd => {
+                let (sum, amt) = (self.crc.sum(), self.crc.amount());
+                let mut trailer = Vec::with_capacity(8);
+                trailer.extend_from_slice(&sum.to_le_bytes());
+                trailer.extend_from_slice(&amt.to_le_bytes());
+                self.pending = trailer;
+                self.pos = 0;
+                self.finished = true;
+                self.drain(&mut output[written..]);
+                if self.pos < self.pending.len() {
+                    Ok(Status::Ok)
+                } else {
+                    Ok(Status::StreamEnd)
+                }
+            }
+            Status::BufError if written > 0 => Ok(Status::Ok),
+            status => Ok(status),
+        }
+    }
+
+    /// Compresses the input data into the extra space of the output, consuming
+    /// only as much input as needed and writing as much output as possible.
+    ///
+    /// This function has the same semantics as `compress`, except that the
+    /// length of `vec` is managed by this function. This will not reallocate
+    /// the vector provided or attempt to grow it, so space for the output must
+    /// be reserved in the output vector by the caller before calling this
+    /// function.
+    pub fn compress_vec(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        flush: FlushCompress,
+    ) -> Result<Status, CompressError> {
+        let cap = output.capacity();
+        let len = output.len();
+
+        unsafe {
+            let before = self.total_out();
+            let ret = {
+                let ptr = output.as_mut_ptr().add(len);
+                let out = slice::from_raw_parts_mut(ptr, cap - len);
+                self.compress(input, out, flush)
+            };
+            output.set_len((self.total_out() - before) as usize + len);
+            ret
+        }
+    }
+
+    /// Copies as many pending header or trailer bytes into `output` as fit.
+    fn drain(&mut self, output: &mut [u8]) -> usize {
+        let n = cmp::min(output.len(), self.pending.len() - self.pos);
+        output[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        self.total_out += n as u64;
+        n
+    }
+}
+
+/// Raw in-memory decompression of a gzip stream.
+///
+/// This is the gzip counterpart of [`Decompress`]: it parses the gzip header
+/// of each member, decompresses its data and verifies the CRC-32 and length
+/// in its trailer, without doing any I/O of its own. Input may be handed
+/// over in pieces of any size, including in the middle of a header or
+/// trailer.
+///
+/// A decoder created with `multi` set decodes all members of a multi-member
+/// stream, while one without stops after the first member and leaves any
+/// input after it unconsumed.
+///
+/// [`Decompress`]: struct.Decompress.html
+///
+/// # Examples
+///
+/// ```
+/// use flate2::{Compression, FlushCompress, FlushDecompress, GzCompress};
+/// use flate2::{GzDecompress, Status};
+///
+/// let mut gz = GzCompress::new(Compression::default());
+/// let mut compressed = Vec::with_capacity(128);
+/// gz.compress_vec(b"Hello World", &mut compressed, FlushCompress::Finish)
+///     .unwrap();
+///
+/// let mut gz = GzDecompress::new(false);
+/// let mut out = Vec::with_capacity(128);
+/// for byte in compressed.chunks(1) {
+///     gz.decompress_vec(byte, &mut out, FlushDecompress::None).unwrap();
+/// }
+/// let status = gz
+///     .decompress_vec(&[], &mut out, FlushDecompress::Finish)
+///     .unwrap();
+/// assert_eq!(status, Status::StreamEnd);
+/// assert_eq!(out, b"Hello World");
+/// assert_eq!(gz.header().unwrap().operating_system(), 255);
+/// ```
+#[derive(Debug)]
+pub struct GzDecompress {
+    inner: Decompress,
+    state: DecodeState,
+    parser: GzHeaderParser,
+    header: Option<GzHeader>,
+    crc: Crc,
+    multi: bool,
+    total_in: u64,
+    total_out: u64,
+    // Offset of the current member in the compressed stream.
+    offset: u64,
+    // The headers of all members so far, when they are being kept.
+    headers: Option<Vec<GzHeader>>,
+}
+
+#[derive(Debug)]
+enum DecodeState {
+    Header,
+    Body,
+    Trailer(usize, [u8; 8]),
+    // Between two members of a multi-member stream.
+    Member,
+    End,
+}
+
+impl GzDecompress {
+    /// Creates a new object ready for decompressing a gzip stream.
+    ///
+    /// The `multi` argument indicates whether all members of the stream are
+    /// decoded, or only the first one.
+    pub fn new(multi: bool) -> GzDecompress {
+        GzDecompress::make(multi, HeaderLimits::default())
+    }
+
+    pub(crate) fn make(multi: bool, limits: HeaderLimits) -> GzDecompress {
+        GzDecompress {
+            inner: Decompress::new(false),
+            state: DecodeState::Header,
+            parser: GzHeaderParser::new(limits),
+            header: None,
+            crc: Crc::new(),
+            multi,
+            total_in: 0,
+            total_out: 0,
+            offset: 0,
+            headers: None,
+        }
+    }
+
+    /// Keeps the headers of all members from now on, see `headers`.
+    pub(crate) fn keep_headers(mut self) -> GzDecompress {
+        self.headers = Some(Vec::new());
+        self
+    }
+
+    /// Returns the headers of all members parsed so far, if they are being
+    /// kept.
+    pub(crate) fn headers(&self) -> &[GzHeader] {
+        self.headers.as_ref().map_or(&[], |headers| &headers[..])
+    }
+
+    /// Returns whether the input so far ends at the end of a member.
+    pub(crate) fn at_member_end(&self) -> bool {
+        matches!(self.state, DecodeState::Member | DecodeState::End)
+    }
+
+    /// Returns the header of the member being decoded, once it has been
+    /// parsed.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.header.as_ref()
+    }
+
+    /// Returns the total number of input bytes which have been processed by
+    /// this decompression object, including gzip headers and trailers.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Returns the total number of output bytes which have been produced by
+    /// this decompression object.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Decompresses the input data into the output, consuming only as much
+    /// input as needed and writing as much output as possible.
+    ///
+    /// `Status::StreamEnd` is returned once the trailer of the first member
+    /// has been verified, or for a decoder of all members, once the trailer
+    /// of a member has been verified and no input remains after it in a
+    /// call passing `FlushDecompress::Finish`.
+    ///
+    /// To learn how much data was consumed or how much output was produced, use
+    /// the `total_in` and `total_out` functions before/after this is called.
+    ///
+    /// # Errors
+    ///
+    /// If the input is not a valid gzip stream this returns a
+    /// `DecompressError`, whose offset counts from the start of the stream.
+    /// Header fields longer than the limits of the [`GzDecoderBuilder`] this
+    /// was created from are reported as `DecompressErrorKind::InvalidHeader`.
+    ///
+    /// [`GzDecoderBuilder`]: struct.GzDecoderBuilder.html
+    pub fn decompress(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: FlushDecompress,
+    ) -> Result<Status, DecompressError> {
+        let mut read = 0;
+        let mut written = 0;
+        loop {
+            match self.state {
+                DecodeState::Header => {
+                    let mut available = Input {
+                        buf: &input[read..],
+                        pos: 0,
+                    };
+                    let result = self.parser.parse(&mut available);
+                    read += available.pos;
+                    self.total_in += available.pos as u64;
+                    match result {
+                        Ok(header) => {
+                            if let Some(ref mut headers) = self.headers {
+                                headers.push(header.clone());
+                            }
+                            self.header = Some(header);
+                            self.state = DecodeState::Body;
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(self.header_error(e)),
+                    }
+                }
+                DecodeState::Body => {
+                    let before_in = self.inner.total_in();
+                    let before_out = self.inner.total_out();
+                    let start = self.offset + self.parser.consumed();
+                    let status = self
+                        .inner
+                        .decompress(&input[read..], &mut output[written..], flush)
+                        .map_err(|mut err| {
+                            err.0.offset = err.0.offset.map(|offset| offset + start);
+                            err
+                        })?;
+                    let consumed = (self.inner.total_in() - before_in) as usize;
+                    let produced = (self.inner.total_out() - before_out) as usize;
+                    self.crc.update(&output[written..written + produced]);
+                    read += consumed;
+                    written += produced;
+                    self.total_in += consumed as u64;
+                    self.total_out += produced as u64;
+                    match status {
+                        Status::StreamEnd => self.state = DecodeState::Trailer(0, [0; 8]),
+                        _ => break,
+                    }
+                }
+                DecodeState::Trailer(pos, mut buf) => {
+                    let n = cmp::min(buf.len() - pos, input.len() - read);
+                    buf[pos..pos + n].copy_from_slice(&input[read..read + n]);
+                    read += n;
+                    self.total_in += n as u64;
+                    if pos + n < buf.len() {
+                        self.state = DecodeState::Trailer(pos + n, buf);
+                        break;
+                    }
+                    self.check_trailer(&buf)?;
+                    self.state = if self.multi {
+                        DecodeState::Member
+                    } else {
+                        DecodeState::End
+                    };
+                }
+                DecodeState::Member => {
+                    if read == input.len() {
+                        break;
+                    }
+                    self.offset = self.total_in;
+                    self.inner.reset_continued(false);
+                    self.parser.reset();
+                    self.header = None;
+                    self.crc.reset();
+                    self.state = DecodeState::Header;
+                }
+                DecodeState::End => break,
+            }
+        }
+
+        Ok(match self.state {
+            DecodeState::End => Status::StreamEnd,
+            DecodeState::Member if flush == FlushDecompress::Finish => Status::StreamEnd,
+            _ if read > 0 || written > 0 => Status::Ok,
+            _ => Status::BufError,
+        })
+    }
+
+    /// Decompresses the input data into the extra space in the output vector
+    /// specified by `output`.
+    ///
+    /// This function has the same semantics as `decompress`, except that the
+    /// length of `vec` is managed by this function. This will not reallocate
+    /// the vector provided or attempt to grow it, so space for the output must
+    /// be reserved in the output vector by the caller before calling this
+    /// function.
+    pub fn decompress_vec(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        flush: FlushDecompress,
+    ) -> Result<Status, DecompressError> {
+        let cap = output.capacity();
+        let len = output.len();
+
+        unsafe {
+            let before = self.total_out();
+            let ret = {
+                let ptr = output.as_mut_ptr().add(len);
+                let out = slice::from_raw_parts_mut(ptr, cap - len);
+                self.decompress(input, out, flush)
+            };
+            output.set_len((self.total_out() - before) as usize + len);
+            ret
+        }
+    }
+
+    /// Performs the equivalent of replacing this decompression state with a
+    /// freshly allocated copy, keeping its header limits, its limits on the
+    /// output and whether it decodes all members.
+    pub fn reset(&mut self) {
+        self.inner.reset(false);
+        self.state = DecodeState::Header;
+        self.parser.reset();
+        self.header = None;
+        self.crc.reset();
+        self.total_in = 0;
+        self.total_out = 0;
+        self.offset = 0;
+        if let Some(ref mut headers) = self.headers {
+            headers.clear();
+        }
+    }
+
+    /// Sets limits on the output of this stream, counting all members
+    /// together, see [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+
+    /// Returns the limits on the output of this stream.
+    pub fn limits(&self) -> DecompressLimits {
+        self.inner.limits()
+    }
+
+    /// Converts an error from the header parser, reporting anything but a
+    /// gzip header after the first member as trailing garbage.
+    fn header_error(&self, err: io::Error) -> DecompressError {
+        let err = match err.into_inner().map(|e| e.downcast::<DecompressError>()) {
+            Some(Ok(err)) => *err,
+            // Only fields longer than their limit fail without a
+            // `DecompressError`.
+            _ => {
+                let err = DecompressError::new(DecompressErrorKind::InvalidHeader);
+                return err.at(self.offset + self.parser.consumed());
+            }
+        };
+        if self.offset > 0 && err.kind() == DecompressErrorKind::BadMagic {
+            DecompressError::new(DecompressErrorKind::TrailingGarbage).at(self.offset)
+        } else {
+            let offset = err.offset().unwrap_or(0) + self.offset;
+            err.at(offset)
+        }
+    }
+
+    fn check_trailer(&self, buf: &[u8; 8]) -> Result<(), DecompressError> {
+        let (crc, amt) = finish(buf);
+        let offset = self.total_in - 8;
+        let kind = if crc != self.crc.sum() {
+            DecompressErrorKind::ChecksumMismatch {
+                expected: crc,
+                actual: self.crc.sum(),
+            }
+        } else if amt != self.crc.amount() {
+            DecompressErrorKind::LengthMismatch {
+                expected: amt,
+                actual: self.crc.amount(),
+            }
+        } else {
+            return Ok(());
+        };
+        let offset = match kind {
+            DecompressErrorKind::LengthMismatch { .. } => offset + 4,
+            _ => offset,
+        };
+        Err(DecompressError::new(kind).at(offset))
+    }
+}
+
+/// The input of a single call, which reports running out as `WouldBlock` so
+/// that the header parser can resume with the input of the next call.
+struct Input<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Read for Input<'a> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let available = self.fill_buf()?;
+            let n = cmp::min(available.len(), into.len());
+            into[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<'a> BufRead for Input<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buf.len() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+
+    use super::{GzCompress, GzDecompress};
+    use crate::read::{GzDecoder, MultiGzDecoder};
+    use crate::{Compression, DecompressErrorKind, FlushCompress, FlushDecompress};
+    use crate::{GzBuilder, GzDecoderBuilder, Status};
+
+    fn compress(mut gz: GzCompress, data: &[u8], chunk: usize) -> Vec<u8> {
+        let mut result = Vec::new();
+        let mut out = vec![0; chunk];
+        let mut read = 0;
+        loop {
+            let before_in = gz.total_in();
+            let before_out = gz.total_out();
+            let end = std::cmp::min(read + chunk, data.len());
+            let flush = if end == data.len() {
+                FlushCompress::Finish
+            } else {
+                FlushCompress::None
+            };
+            let status = gz.compress(&data[read..end], &mut out, flush).unwrap();
+            read += (gz.total_in() - before_in) as usize;
+            result.extend_from_slice(&out[..(gz.total_out() - before_out) as usize]);
+            if status == Status::StreamEnd {
+                return result;
+            }
+        }
+    }
+
+    fn decompress(gz: &mut GzDecompress, data: &[u8], chunk: usize) -> Vec<u8> {
+        let mut result = Vec::new();
+        let mut out = vec![0; chunk];
+        let mut read = 0;
+        loop {
+            let before_in = gz.total_in();
+            let before_out = gz.total_out();
+            let end = std::cmp::min(read + chunk, data.len());
+            let flush = if end == data.len() {
+                FlushDecompress::Finish
+            } else {
+                FlushDecompress::None
+            };
+            let status = gz.decompress(&data[read..end], &mut out, flush).unwrap();
+            read += (gz.total_in() - before_in) as usize;
+            result.extend_from_slice(&out[..(gz.total_out() - before_out) as usize]);
+            if status == Status::StreamEnd {
+                return result;
+            }
+        }
+    }
+
+    fn sample() -> Vec<u8> {
+        (0..50_000u32)
+            .map(|i| (i % 251) as u8 ^ (i >> 7) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn compress_matches_encoder() {
+        let data = sample();
+        let mut e = GzBuilder::new()
+            .filename("sample.bin")
+            .comment("a comment")
+            .write(Vec::new(), Compression::default());
+        e.write_all(&data).unwrap();
+        let expected = e.finish().unwrap();
+
+        for &chunk in &[1, 7, 4096, 100_000] {
+            let gz = GzBuilder::new()
+                .filename("sample.bin")
+                .comment("a comment")
+                .compress(Compression::default());
+            assert_eq!(compress(gz, &data, chunk), expected);
+        }
+    }
+
+    #[test]
+    fn decompress_roundtrip() {
+        let data = sample();
+        let compressed = compress(
+            GzBuilder::new()
+                .filename("sample.bin")
+                .extra(vec![1, 2, 3])
+                .compress(Compression::best()),
+            &data,
+            1024,
+        );
+
+        let mut d = GzDecoder::new(&compressed[..]);
+        let mut decoded = Vec::new();
+        d.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+
+        for &chunk in &[1, 3, 4096, 100_000] {
+            let mut gz = GzDecompress::new(false);
+            assert_eq!(decompress(&mut gz, &compressed, chunk), data);
+            let header = gz.header().unwrap();
+            assert_eq!(header.filename(), Some(&b"sample.bin"[..]));
+            assert_eq!(header.extra(), Some(&[1, 2, 3][..]));
+            assert_eq!(gz.total_in(), compressed.len() as u64);
+            assert_eq!(gz.total_out(), data.len() as u64);
+        }
+    }
+
+    #[test]
+    fn first_member_only() {
+        let mut stream = compress(GzCompress::new(Compression::fast()), b"first", 64);
+        let first = stream.len();
+        stream.extend(compress(
+            GzCompress::new(Compression::fast()),
+            b"second",
+            64,
+        ));
+
+        let mut gz = GzDecompress::new(false);
+        let mut out = [0; 64];
+        let status = gz
+            .decompress(&stream, &mut out, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(gz.total_in(), first as u64);
+        assert_eq!(&out[..gz.total_out() as usize], b"first");
+    }
+
+    #[test]
+    fn multiple_members() {
+        let data = sample();
+        let mut stream = Vec::new();
+        for part in data.chunks(20_000) {
+            stream.extend(compress(GzCompress::new(Compression::default()), part, 512));
+        }
+
+        let mut d = MultiGzDecoder::new(&stream[..]);
+        let mut decoded = Vec::new();
+        d.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+
+        for &chunk in &[1, 5, 100_000] {
+            let mut gz = GzDecompress::new(true);
+            assert_eq!(decompress(&mut gz, &stream, chunk), data);
+            assert_eq!(gz.total_in(), stream.len() as u64);
+        }
+    }
+
+    #[test]
+    fn reset_starts_another_member() {
+        let mut gz = GzCompress::new(Compression::default());
+        let mut stream = Vec::with_capacity(256);
+        gz.compress_vec(b"one", &mut stream, FlushCompress::Finish)
+            .unwrap();
+        gz.reset();
+        gz.compress_vec(b"two", &mut stream, FlushCompress::Finish)
+            .unwrap();
+
+        let mut decoded = String::new();
+        MultiGzDecoder::new(&stream[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "onetwo");
+    }
+
+    #[test]
+    fn errors() {
+        let mut stream = compress(GzCompress::new(Compression::default()), b"hello", 64);
+
+        let mut gz = GzDecompress::new(false);
+        let err = gz
+            .decompress(b"garbage", &mut [0; 64], FlushDecompress::Finish)
+            .unwrap_err();
+        assert_eq!(err.kind(), DecompressErrorKind::BadMagic);
+        assert_eq!(err.offset(), Some(0));
+
+        let first = stream.len() as u64;
+        stream.extend_from_slice(b"garbage");
+        let mut gz = GzDecompress::new(true);
+        let err = gz
+            .decompress(&stream, &mut [0; 64], FlushDecompress::Finish)
+            .unwrap_err();
+        assert_eq!(err.kind(), DecompressErrorKind::TrailingGarbage);
+        assert_eq!(err.offset(), Some(first));
+
+        let crc = first as usize - 8;
+        stream[crc] ^= 1;
+        let mut gz = GzDecompress::new(false);
+        let err = gz
+            .decompress(&stream, &mut [0; 64], FlushDecompress::Finish)
+            .unwrap_err();
+        match err.kind() {
+            DecompressErrorKind::ChecksumMismatch { .. } => {}
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+        assert_eq!(err.offset(), Some(crc as u64));
+    }
+
+    #[test]
+    fn header_limits() {
+        let stream = compress(
+            GzBuilder::new()
+                .filename("a-long-file-name")
+                .compress(Compression::default()),
+            b"hello",
+            64,
+        );
+        let mut gz = GzDecoderBuilder::new().max_filename_len(4).decompress();
+        let err = gz
+            .decompress(&stream, &mut [0; 64], FlushDecompress::Finish)
+            .unwrap_err();
+        assert_eq!(err.kind(), DecompressErrorKind::InvalidHeader);
+    }
+
+    #[test]
+    fn truncated() {
+        let stream = compress(GzCompress::new(Compression::default()), b"hello", 64);
+        let mut gz = GzDecompress::new(false);
+        let mut out = [0; 64];
+        let status = gz
+            .decompress(
+                &stream[..stream.len() - 3],
+                &mut out,
+                FlushDecompress::Finish,
+            )
+            .unwrap();
+        assert_eq!(status, Status::Ok);
+        let status = gz
+            .decompress(&[], &mut out, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(status, Status::BufError);
+        let status = gz
+            .decompress(
+                &stream[stream.len() - 3..],
+                &mut out,
+                FlushDecompress::Finish,
+            )
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+    }
+}
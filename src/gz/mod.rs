@@ -1,43 +1,241 @@
-use std::ffi::CString;
+use std::cmp;
+use std::convert::TryFrom;
+use std::env;
+use std::error::Error;
+use std::ffi::{CString, OsStr, OsString};
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use std::time;
 
+use self::bufread::HeaderLimits;
 use crate::bufreader::BufReader;
-use crate::Compression;
+use crate::crc::Crc;
+use crate::{Compress, CompressOptions, Compression};
 
+pub use self::extra::{ExtraSubfield, ExtraSubfields, SubfieldRegistry};
+pub use self::mem::{GzCompress, GzDecompress};
+
+pub static FTEXT: u8 = 1 << 0;
 pub static FHCRC: u8 = 1 << 1;
 pub static FEXTRA: u8 = 1 << 2;
 pub static FNAME: u8 = 1 << 3;
 pub static FCOMMENT: u8 = 1 << 4;
 
+pub mod bgzf;
 pub mod bufread;
+mod extra;
+pub mod index;
+pub mod log;
+mod mem;
+pub mod parallel;
 pub mod read;
+pub mod salvage;
 pub mod write;
 
 /// A structure representing the header of a gzip stream.
 ///
 /// The header can contain metadata about the file that was compressed, if
-/// present.
-#[derive(PartialEq, Clone, Debug, Default)]
+/// present. Every field of the header is kept when parsing, so a parsed
+/// header can be written back out byte for byte, apart from reserved flag
+/// bits.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, GzBuilder, GzHeader};
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut e = GzBuilder::new()
+///     .filename("data.txt")
+///     .header_crc(true)
+///     .write(Vec::new(), Compression::best());
+/// e.write_all(b"Hello World")?;
+/// let original = e.finish()?;
+///
+/// // Recompress the data with the original header.
+/// let (header, len) = GzHeader::parse(&original)?;
+/// assert_eq!(header.filename(), Some(&b"data.txt"[..]));
+/// let e = GzBuilder::from_header(&header)?.write(Vec::new(), Compression::fast());
+/// let recompressed = e.finish()?;
+/// assert_eq!(recompressed[..len], original[..len]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(PartialEq, Clone, Debug)]
 pub struct GzHeader {
     extra: Option<Vec<u8>>,
     filename: Option<Vec<u8>>,
     comment: Option<Vec<u8>>,
     operating_system: u8,
     mtime: u32,
+    text: bool,
+    xfl: u8,
+    header_crc: bool,
+}
+
+impl Default for GzHeader {
+    fn default() -> GzHeader {
+        GzHeader::new()
+    }
 }
 
 impl GzHeader {
+    /// Creates a header with no optional fields, an `mtime` and `xfl` of 0
+    /// and an `operating_system` of 255 (unknown).
+    ///
+    /// This is the same header as `GzHeader::default()`.
+    pub fn new() -> GzHeader {
+        GzHeader {
+            extra: None,
+            filename: None,
+            comment: None,
+            operating_system: 255,
+            mtime: 0,
+            text: false,
+            xfl: 0,
+            header_crc: false,
+        }
+    }
+
+    /// Parses a complete gzip header from the start of `bytes`, returning it
+    /// along with the length of the header in bytes.
+    ///
+    /// Anything following the header, such as the compressed data, is
+    /// ignored. No limits are placed on the length of the header's fields.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` doesn't start with a valid gzip header,
+    /// including a truncated one, or if the header CRC doesn't match.
+    pub fn parse(bytes: &[u8]) -> io::Result<(GzHeader, usize)> {
+        let mut input = bytes;
+        let limits = HeaderLimits {
+            extra: usize::max_value(),
+            filename: usize::max_value(),
+            comment: usize::max_value(),
+        };
+        let header = bufread::read_gz_header_with(&mut input, limits)?;
+        Ok((header, bytes.len() - input.len()))
+    }
+
+    /// Writes this header to `w`, including a header CRC if
+    /// [`has_header_crc`] is set.
+    ///
+    /// [`has_header_crc`]: #method.has_header_crc
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the `extra` field is longer
+    /// than 65535 bytes or if `filename` or `comment` contain a zero byte,
+    /// which can't be represented, and any error from writing to `w`.
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        self.check()?;
+        w.write_all(&self.encode())
+    }
+
+    /// Checks that every field of this header can be represented.
+    fn check(&self) -> io::Result<()> {
+        if self.extra.iter().any(|e| e.len() > 0xffff) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "gzip extra field is longer than 65535 bytes",
+            ));
+        }
+        if self
+            .filename
+            .iter()
+            .chain(&self.comment)
+            .any(|s| s.contains(&0))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "gzip filename or comment contains a zero byte",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Serializes this header, which is assumed to be representable.
+    fn encode(&self) -> Vec<u8> {
+        let mut flg = 0;
+        let mut header = vec![0u8; 10];
+        if let Some(ref v) = self.extra {
+            flg |= FEXTRA;
+            header.extend_from_slice(&(v.len() as u16).to_le_bytes());
+            header.extend_from_slice(v);
+        }
+        if let Some(ref filename) = self.filename {
+            flg |= FNAME;
+            header.extend_from_slice(filename);
+            header.push(0);
+        }
+        if let Some(ref comment) = self.comment {
+            flg |= FCOMMENT;
+            header.extend_from_slice(comment);
+            header.push(0);
+        }
+        if self.text {
+            flg |= FTEXT;
+        }
+        if self.header_crc {
+            flg |= FHCRC;
+        }
+        header[0] = 0x1f;
+        header[1] = 0x8b;
+        header[2] = 8;
+        header[3] = flg;
+        header[4..8].copy_from_slice(&self.mtime.to_le_bytes());
+        header[8] = self.xfl;
+        header[9] = self.operating_system;
+        if self.header_crc {
+            let mut crc = Crc::new();
+            crc.update(&header);
+            header.extend_from_slice(&(crc.sum() as u16).to_le_bytes());
+        }
+        header
+    }
+
     /// Returns the `filename` field of this gzip stream's header, if present.
     pub fn filename(&self) -> Option<&[u8]> {
         self.filename.as_ref().map(|s| &s[..])
     }
 
+    /// Returns the `filename` field decoded from ISO 8859-1 (Latin-1), which
+    /// RFC 1952 specifies as its encoding.
+    pub fn filename_as_os_string(&self) -> Option<OsString> {
+        self.filename.as_ref().map(|name| {
+            name.iter()
+                .map(|&b| char::from(b))
+                .collect::<String>()
+                .into()
+        })
+    }
+
+    /// Returns the `filename` field as a path to extract the stream to, or
+    /// `None` if there is no `filename` or it has no final component.
+    ///
+    /// Only the final component of the `filename` is kept, so joining the
+    /// path onto a directory never leads outside that directory.
+    pub fn filename_as_path(&self) -> Option<PathBuf> {
+        let name = self.filename_as_os_string()?;
+        Path::new(&name).file_name().map(PathBuf::from)
+    }
+
     /// Returns the `extra` field of this gzip stream's header, if present.
     pub fn extra(&self) -> Option<&[u8]> {
         self.extra.as_ref().map(|s| &s[..])
     }
 
+    /// Returns an iterator over the subfields of the `extra` field of this
+    /// gzip stream's header, which is empty if there is no `extra` field.
+    pub fn subfields(&self) -> ExtraSubfields<'_> {
+        ExtraSubfields::new(self.extra().unwrap_or(&[]))
+    }
+
     /// Returns the `comment` field of this gzip stream's header, if present.
     pub fn comment(&self) -> Option<&[u8]> {
         self.comment.as_ref().map(|s| &s[..])
@@ -80,6 +278,125 @@ impl GzHeader {
             Some(datetime)
         }
     }
+
+    /// Sets the modification time of `file` to the `mtime` field, as when
+    /// extracting the stream, doing nothing if no time stamp is available.
+    ///
+    /// On some platforms the file needs to be opened for writing.
+    pub fn restore_mtime(&self, file: &File) -> io::Result<()> {
+        match self.mtime_as_datetime() {
+            Some(mtime) => file.set_modified(mtime),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns whether the `FTEXT` flag is set, which hints that the data is
+    /// probably text.
+    pub fn is_text(&self) -> bool {
+        self.text
+    }
+
+    /// Returns the `XFL` (extra flags) byte, which is 2 if the compressor
+    /// used its slowest algorithm and 4 if it used its fastest one.
+    pub fn xfl(&self) -> u8 {
+        self.xfl
+    }
+
+    /// Returns whether the header is protected by a header CRC (`FHCRC`).
+    pub fn has_header_crc(&self) -> bool {
+        self.header_crc
+    }
+
+    /// Sets the `extra` field.
+    pub fn set_extra(&mut self, extra: Option<Vec<u8>>) {
+        self.extra = extra;
+    }
+
+    /// Appends a subfield to the `extra` field, creating it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput`, leaving the `extra` field
+    /// unchanged, if it would become longer than 65535 bytes or if the second
+    /// byte of `id` is 0, which RFC 1952 reserves.
+    pub fn add_subfield(&mut self, id: [u8; 2], data: &[u8]) -> io::Result<()> {
+        let mut extra = self.extra.clone().unwrap_or_default();
+        extra::append_subfield(&mut extra, id, data)?;
+        self.extra = Some(extra);
+        Ok(())
+    }
+
+    /// Sets the `filename` field, which shouldn't contain a zero byte.
+    pub fn set_filename(&mut self, filename: Option<Vec<u8>>) {
+        self.filename = filename;
+    }
+
+    /// Sets the `comment` field, which shouldn't contain a zero byte.
+    pub fn set_comment(&mut self, comment: Option<Vec<u8>>) {
+        self.comment = comment;
+    }
+
+    /// Sets the `operating_system` field.
+    pub fn set_operating_system(&mut self, os: u8) {
+        self.operating_system = os;
+    }
+
+    /// Sets the `mtime` field.
+    pub fn set_mtime(&mut self, mtime: u32) {
+        self.mtime = mtime;
+    }
+
+    /// Sets the `FTEXT` flag.
+    pub fn set_text(&mut self, text: bool) {
+        self.text = text;
+    }
+
+    /// Sets the `XFL` byte.
+    pub fn set_xfl(&mut self, xfl: u8) {
+        self.xfl = xfl;
+    }
+
+    /// Sets whether a header CRC is written.
+    pub fn set_header_crc(&mut self, header_crc: bool) {
+        self.header_crc = header_crc;
+    }
+}
+
+/// The position and checksums of a gzip member which has been decoded and
+/// verified, see [`MultiGzDecoder::next_member`].
+///
+/// [`MultiGzDecoder::next_member`]: bufread/struct.MultiGzDecoder.html#method.next_member
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct GzMemberInfo {
+    start: u64,
+    end: u64,
+    crc: u32,
+    size: u32,
+}
+
+impl GzMemberInfo {
+    /// Returns the offset of the first byte of the member's header in the
+    /// compressed stream.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the offset just past the member's trailer in the compressed
+    /// stream.
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// Returns the CRC-32 of the member's data, as stored in its trailer.
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Returns the length of the member's data modulo 2<sup>32</sup>, as
+    /// stored in the `ISIZE` field of its trailer.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
 }
 
 /// A builder structure to create a new gzip Encoder.
@@ -115,6 +432,10 @@ pub struct GzBuilder {
     comment: Option<CString>,
     operating_system: Option<u8>,
     mtime: u32,
+    text: bool,
+    xfl: Option<u8>,
+    header_crc: bool,
+    max_mtime: Option<u32>,
 }
 
 impl GzBuilder {
@@ -126,7 +447,124 @@ impl GzBuilder {
             comment: None,
             operating_system: None,
             mtime: 0,
+            text: false,
+            xfl: None,
+            header_crc: false,
+            max_mtime: None,
+        }
+    }
+
+    /// Create a new builder for compressing the file at `path`.
+    ///
+    /// The `filename` field is set to the final component of the path,
+    /// encoded as ISO 8859-1 (Latin-1) as RFC 1952 requires, and the `mtime`
+    /// field to the modification time of the file. A modification time that
+    /// doesn't fit the `mtime` field, such as one before 1970, leaves it 0.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the metadata of the file can't be read, or one of
+    /// kind `InvalidInput` if the file name can't be encoded as Latin-1.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// use std::io;
+    /// use flate2::{Compression, GzBuilder};
+    ///
+    /// # fn main() -> io::Result<()> {
+    /// let mut input = File::open("release.tar")?;
+    /// let output = File::create("release.tar.gz")?;
+    /// let mut gz = GzBuilder::from_path("release.tar")?
+    ///     .reproducible()?
+    ///     .write(output, Compression::best());
+    /// io::copy(&mut input, &mut gz)?;
+    /// gz.finish()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<GzBuilder> {
+        let path = path.as_ref();
+        let metadata = fs::metadata(path)?;
+        let mut builder = GzBuilder::new();
+        if let Some(name) = path.file_name() {
+            builder.filename = Some(latin1_file_name(name)?);
+        }
+        builder.mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|mtime| mtime.duration_since(time::UNIX_EPOCH).ok())
+            .and_then(|since| u32::try_from(since.as_secs()).ok())
+            .unwrap_or(0);
+        Ok(builder)
+    }
+
+    /// Make the header reproducible, so that compressing the same data always
+    /// produces the same bytes.
+    ///
+    /// If the `SOURCE_DATE_EPOCH` environment variable is set, the `mtime`
+    /// field is clamped to it, following the reproducible builds
+    /// specification, and otherwise it is always 0. The `operating_system`
+    /// field is 255, meaning unknown.
+    ///
+    /// These are the only fields which describe the machine or the time the
+    /// data was compressed at. All other fields, including the header CRC,
+    /// are written as configured, so they are reproducible if the values
+    /// given for them are.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if `SOURCE_DATE_EPOCH` isn't a
+    /// number of seconds, or if an `operating_system` other than 255 was
+    /// configured.
+    ///
+    /// Only the fields configured before this is called are checked. Setters
+    /// called afterwards bypass the reproducible mode: a later `mtime` is
+    /// still clamped, but a later `operating_system` is written as given.
+    pub fn reproducible(self) -> io::Result<GzBuilder> {
+        let epoch = source_date_epoch(env::var_os("SOURCE_DATE_EPOCH"))?;
+        self.reproducible_at(epoch)
+    }
+
+    fn reproducible_at(mut self, max_mtime: u32) -> io::Result<GzBuilder> {
+        if self.operating_system.unwrap_or(255) != 255 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "operating system in a reproducible gzip header",
+            ));
         }
+        self.operating_system = Some(255);
+        self.max_mtime = Some(max_mtime);
+        Ok(self)
+    }
+
+    /// Create a new builder which writes the given header.
+    ///
+    /// Every field of the header is used as is, including `xfl`, which
+    /// otherwise depends on the compression level.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the header can't be
+    /// written, as with [`GzHeader::write_to`].
+    ///
+    /// [`GzHeader::write_to`]: struct.GzHeader.html#method.write_to
+    pub fn from_header(header: &GzHeader) -> io::Result<GzBuilder> {
+        header.check()?;
+        // `check` rules out zero bytes.
+        let string = |s: &Option<Vec<u8>>| s.clone().map(|s| CString::new(s).unwrap());
+        Ok(GzBuilder {
+            extra: header.extra.clone(),
+            filename: string(&header.filename),
+            comment: string(&header.comment),
+            operating_system: Some(header.operating_system),
+            mtime: header.mtime,
+            text: header.text,
+            xfl: Some(header.xfl),
+            header_crc: header.header_crc,
+            max_mtime: None,
+        })
     }
 
     /// Configure the `mtime` field in the gzip header.
@@ -136,6 +574,9 @@ impl GzBuilder {
     }
 
     /// Configure the `operating_system` field in the gzip header.
+    ///
+    /// When called after [`reproducible`](#method.reproducible), this is not
+    /// checked: the given value replaces the 255 and is written as is.
     pub fn operating_system(mut self, os: u8) -> GzBuilder {
         self.operating_system = Some(os);
         self
@@ -147,6 +588,18 @@ impl GzBuilder {
         self
     }
 
+    /// Append a subfield with the given identifier to the `extra` field in
+    /// the gzip header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `extra` field would become longer than 65535 bytes or if
+    /// the second byte of `id` is 0.
+    pub fn subfield(mut self, id: [u8; 2], data: &[u8]) -> GzBuilder {
+        extra::append_subfield(self.extra.get_or_insert_with(Vec::new), id, data).unwrap();
+        self
+    }
+
     /// Configure the `filename` field in the gzip header.
     ///
     /// # Panics
@@ -167,12 +620,51 @@ impl GzBuilder {
         self
     }
 
+    /// Configure the `FTEXT` flag in the gzip header.
+    pub fn text(mut self, text: bool) -> GzBuilder {
+        self.text = text;
+        self
+    }
+
+    /// Configure the `XFL` byte in the gzip header, which by default is set
+    /// from the compression level.
+    pub fn xfl(mut self, xfl: u8) -> GzBuilder {
+        self.xfl = Some(xfl);
+        self
+    }
+
+    /// Configure whether the gzip header is protected by a header CRC.
+    pub fn header_crc(mut self, header_crc: bool) -> GzBuilder {
+        self.header_crc = header_crc;
+        self
+    }
+
     /// Consume this builder, creating a writer encoder in the process.
     ///
     /// The data written to the returned encoder will be compressed and then
     /// written out to the supplied parameter `w`.
     pub fn write<W: Write>(self, w: W, lvl: Compression) -> write::GzEncoder<W> {
-        write::gz_encoder(self.into_header(lvl), w, lvl)
+        write::gz_encoder(self.into_header(lvl), w, Compress::new(lvl, false))
+    }
+
+    /// Consume this builder, creating a writer encoder which splits its
+    /// output into several gzip members, each with this header.
+    pub fn write_multi<W: Write>(self, w: W, lvl: Compression) -> write::MultiGzEncoder<W> {
+        write::multi_gz_encoder(self.into_header(lvl), w, lvl)
+    }
+
+    /// Consume this builder, creating a writer encoder with the given
+    /// compression options in the process.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn write_with_options<W: Write>(
+        self,
+        w: W,
+        options: &CompressOptions,
+    ) -> io::Result<write::GzEncoder<W>> {
+        let data = Compress::new_with_options(options, false)?;
+        Ok(write::gz_encoder(self.into_header(options.level), w, data))
     }
 
     /// Consume this builder, creating a reader encoder in the process.
@@ -183,6 +675,20 @@ impl GzBuilder {
         read::gz_encoder(self.buf_read(BufReader::new(r), lvl))
     }
 
+    /// Consume this builder, creating a reader encoder with the given
+    /// compression options in the process.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn read_with_options<R: Read>(
+        self,
+        r: R,
+        options: &CompressOptions,
+    ) -> io::Result<read::GzEncoder<R>> {
+        let inner = self.buf_read_with_options(BufReader::new(r), options)?;
+        Ok(read::gz_encoder(inner))
+    }
+
     /// Consume this builder, creating a reader encoder in the process.
     ///
     /// Data read from the returned encoder will be the compressed version of
@@ -191,7 +697,68 @@ impl GzBuilder {
     where
         R: BufRead,
     {
-        bufread::gz_encoder(self.into_header(lvl), r, lvl)
+        bufread::gz_encoder(self.into_header(lvl), r, Compress::new(lvl, false))
+    }
+
+    /// Consume this builder, creating a reader encoder with the given
+    /// compression options in the process.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn buf_read_with_options<R>(
+        self,
+        r: R,
+        options: &CompressOptions,
+    ) -> io::Result<bufread::GzEncoder<R>>
+    where
+        R: BufRead,
+    {
+        let data = Compress::new_with_options(options, false)?;
+        Ok(bufread::gz_encoder(
+            self.into_header(options.level),
+            r,
+            data,
+        ))
+    }
+
+    /// Consume this builder, creating an in-memory compressor in the process.
+    ///
+    /// The returned [`GzCompress`] writes the header configured here in
+    /// front of the compressed data, see its documentation for more details.
+    ///
+    /// [`GzCompress`]: struct.GzCompress.html
+    pub fn compress(self, lvl: Compression) -> GzCompress {
+        GzCompress::make(self.into_header(lvl), Compress::new(lvl, false))
+    }
+
+    /// Consume this builder, creating an in-memory compressor with the given
+    /// compression options in the process.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn compress_with_options(self, options: &CompressOptions) -> io::Result<GzCompress> {
+        let data = Compress::new_with_options(options, false)?;
+        Ok(GzCompress::make(self.into_header(options.level), data))
+    }
+
+    /// Consume this builder, creating a multi-threaded writer encoder in the
+    /// process.
+    ///
+    /// The data written to the returned encoder will be compressed in blocks
+    /// of `block_size` bytes on `threads` worker threads and then written out
+    /// to the supplied parameter `w`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn write_parallel<W: Write>(
+        self,
+        w: W,
+        lvl: Compression,
+        threads: usize,
+        block_size: usize,
+    ) -> parallel::ParGzEncoder<W> {
+        parallel::par_gz_encoder(self.into_header(lvl), w, lvl, threads, block_size)
     }
 
     fn into_header(self, lvl: Compression) -> Vec<u8> {
@@ -201,54 +768,264 @@ impl GzBuilder {
             comment,
             operating_system,
             mtime,
+            text,
+            xfl,
+            header_crc,
+            max_mtime,
         } = self;
-        let mut flg = 0;
-        let mut header = vec![0u8; 10];
-        match extra {
-            Some(v) => {
-                flg |= FEXTRA;
-                header.push((v.len() >> 0) as u8);
-                header.push((v.len() >> 8) as u8);
-                header.extend(v);
-            }
-            None => {}
-        }
-        match filename {
-            Some(filename) => {
-                flg |= FNAME;
-                header.extend(filename.as_bytes_with_nul().iter().map(|x| *x));
-            }
-            None => {}
-        }
-        match comment {
-            Some(comment) => {
-                flg |= FCOMMENT;
-                header.extend(comment.as_bytes_with_nul().iter().map(|x| *x));
-            }
-            None => {}
-        }
-        header[0] = 0x1f;
-        header[1] = 0x8b;
-        header[2] = 8;
-        header[3] = flg;
-        header[4] = (mtime >> 0) as u8;
-        header[5] = (mtime >> 8) as u8;
-        header[6] = (mtime >> 16) as u8;
-        header[7] = (mtime >> 24) as u8;
-        header[8] = if lvl.0 >= Compression::best().0 {
+        let xfl = xfl.unwrap_or(if lvl.0 >= Compression::best().0 {
             2
         } else if lvl.0 <= Compression::fast().0 {
             4
         } else {
             0
+        });
+        GzHeader {
+            extra,
+            filename: filename.map(CString::into_bytes),
+            comment: comment.map(CString::into_bytes),
+            // Typically this byte indicates what OS the gz stream was created
+            // on, but in an effort to have cross-platform reproducible streams
+            // just default this value to 255. I'm not sure that if we
+            // "correctly" set this it'd do anything anyway...
+            operating_system: operating_system.unwrap_or(255),
+            mtime: max_mtime.map_or(mtime, |max| cmp::min(mtime, max)),
+            text,
+            xfl,
+            header_crc,
+        }
+        .encode()
+    }
+}
+
+/// A builder structure to create a new gzip decoder.
+///
+/// This structure controls how much memory may be spent on the header of
+/// each gzip member. The `extra`, `filename` and `comment` fields are each
+/// limited to 64 KiB by default, and decoding fails with a
+/// [`HeaderLimitExceeded`] error if a field is longer than allowed.
+///
+/// [`HeaderLimitExceeded`]: enum.HeaderLimitExceeded.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, GzBuilder, GzDecoderBuilder, HeaderLimitExceeded};
+///
+/// let mut e = GzBuilder::new()
+///     .filename("a-rather-long-file-name.txt")
+///     .write(Vec::new(), Compression::default());
+/// e.write_all(b"Hello World").unwrap();
+/// let bytes = e.finish().unwrap();
+///
+/// let mut d = GzDecoderBuilder::new()
+///     .max_filename_len(16)
+///     .read(&bytes[..]);
+/// let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+/// let limit = err.get_ref().and_then(|e| e.downcast_ref::<HeaderLimitExceeded>());
+/// assert_eq!(limit, Some(&HeaderLimitExceeded::Filename(16)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct GzDecoderBuilder {
+    limits: HeaderLimits,
+    trailing: Option<TrailingData>,
+}
+
+impl GzDecoderBuilder {
+    /// Create a new builder with the default limits.
+    pub fn new() -> GzDecoderBuilder {
+        GzDecoderBuilder {
+            limits: HeaderLimits::default(),
+            trailing: None,
+        }
+    }
+
+    /// Configure the maximum length in bytes of the `extra` field.
+    pub fn max_extra_len(mut self, len: usize) -> GzDecoderBuilder {
+        self.limits.extra = len;
+        self
+    }
+
+    /// Configure the maximum length in bytes of the `filename` field, not
+    /// counting its terminating zero.
+    pub fn max_filename_len(mut self, len: usize) -> GzDecoderBuilder {
+        self.limits.filename = len;
+        self
+    }
+
+    /// Configure the maximum length in bytes of the `comment` field, not
+    /// counting its terminating zero.
+    pub fn max_comment_len(mut self, len: usize) -> GzDecoderBuilder {
+        self.limits.comment = len;
+        self
+    }
+
+    /// Configure how data following the gzip stream is handled, see
+    /// [`TrailingData`].
+    ///
+    /// By default decoders of the first member use `TrailingData::Stop` and
+    /// decoders of all members use `TrailingData::Error`. This applies to the
+    /// reader decoders only.
+    ///
+    /// [`TrailingData`]: enum.TrailingData.html
+    pub fn trailing_data(mut self, policy: TrailingData) -> GzDecoderBuilder {
+        self.trailing = Some(policy);
+        self
+    }
+
+    /// Consume this builder, creating a reader decoder for the first member
+    /// of a gzip stream.
+    pub fn read<R: Read>(self, r: R) -> read::GzDecoder<R> {
+        read::gz_decoder(self.buf_read(BufReader::new(r)))
+    }
+
+    /// Consume this builder, creating a reader decoder for all members of a
+    /// gzip stream.
+    pub fn read_multi<R: Read>(self, r: R) -> read::MultiGzDecoder<R> {
+        read::multi_gz_decoder(self.buf_read_multi(BufReader::new(r)))
+    }
+
+    /// Consume this builder, creating a buffered reader decoder for the
+    /// first member of a gzip stream.
+    pub fn buf_read<R: BufRead>(self, r: R) -> bufread::GzDecoder<R> {
+        let decoder = bufread::gz_decoder(r, self.limits);
+        match self.trailing {
+            Some(policy) => decoder.trailing_data(policy),
+            None => decoder,
+        }
+    }
+
+    /// Consume this builder, creating a buffered reader decoder for all
+    /// members of a gzip stream.
+    pub fn buf_read_multi<R: BufRead>(self, r: R) -> bufread::MultiGzDecoder<R> {
+        bufread::multi_gz_decoder(r, self.limits, self.trailing)
+    }
+
+    /// Consume this builder, creating a writer decoder in the process.
+    pub fn write<W: Write>(self, w: W) -> write::GzDecoder<W> {
+        write::gz_decoder(w, self.limits)
+    }
+
+    /// Consume this builder, creating a writer decoder for all members of a
+    /// gzip stream.
+    pub fn write_multi<W: Write>(self, w: W) -> write::MultiGzDecoder<W> {
+        write::multi_gz_decoder(w, self.limits)
+    }
+
+    /// Consume this builder, creating an in-memory decompressor for the
+    /// first member of a gzip stream.
+    pub fn decompress(self) -> GzDecompress {
+        GzDecompress::make(false, self.limits)
+    }
+
+    /// Consume this builder, creating an in-memory decompressor for all
+    /// members of a gzip stream.
+    pub fn decompress_multi(self) -> GzDecompress {
+        GzDecompress::make(true, self.limits)
+    }
+}
+
+impl Default for GzDecoderBuilder {
+    fn default() -> GzDecoderBuilder {
+        GzDecoderBuilder::new()
+    }
+}
+
+/// How a gzip decoder handles data following the end of the stream.
+///
+/// For a decoder of all members, data which starts with the gzip magic bytes
+/// is always decoded as another member.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TrailingData {
+    /// Stop at the end of the stream and leave whatever follows it unread in
+    /// the underlying reader.
+    Stop,
+
+    /// Fail with `DecompressErrorKind::TrailingGarbage` if any data follows
+    /// the end of the stream.
+    Error,
+
+    /// Skip zero bytes following the end of the stream, as written by tape
+    /// and archive tools, and fail with `DecompressErrorKind::TrailingGarbage`
+    /// on anything else. This is what GNU gzip does.
+    IgnoreZeros,
+
+    #[doc(hidden)]
+    _Nonexhaustive,
+}
+
+/// Error returned when a field of a gzip header is longer than allowed by a
+/// [`GzDecoderBuilder`].
+///
+/// [`GzDecoderBuilder`]: struct.GzDecoderBuilder.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HeaderLimitExceeded {
+    /// The `extra` field is longer than the given number of bytes.
+    Extra(usize),
+
+    /// The `filename` field is longer than the given number of bytes.
+    Filename(usize),
+
+    /// The `comment` field is longer than the given number of bytes.
+    Comment(usize),
+
+    #[doc(hidden)]
+    _Nonexhaustive,
+}
+
+impl Error for HeaderLimitExceeded {}
+
+impl From<HeaderLimitExceeded> for io::Error {
+    fn from(limit: HeaderLimitExceeded) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, limit)
+    }
+}
+
+impl fmt::Display for HeaderLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (field, max) = match *self {
+            HeaderLimitExceeded::Extra(max) => ("extra", max),
+            HeaderLimitExceeded::Filename(max) => ("filename", max),
+            HeaderLimitExceeded::Comment(max) => ("comment", max),
+            HeaderLimitExceeded::_Nonexhaustive => unreachable!(),
         };
+        write!(
+            f,
+            "gzip header {} field exceeds the limit of {} bytes",
+            field, max
+        )
+    }
+}
+
+/// Encodes a file name as ISO 8859-1 for the `filename` field.
+fn latin1_file_name(name: &OsStr) -> io::Result<CString> {
+    let bytes = name.to_str().and_then(|name| {
+        name.chars()
+            .map(|c| u8::try_from(u32::from(c)).ok().filter(|&b| b != 0))
+            .collect::<Option<Vec<u8>>>()
+    });
+    match bytes {
+        Some(bytes) => Ok(CString::new(bytes).unwrap()),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "file name can't be encoded as ISO 8859-1",
+        )),
+    }
+}
 
-        // Typically this byte indicates what OS the gz stream was created on,
-        // but in an effort to have cross-platform reproducible streams just
-        // default this value to 255. I'm not sure that if we "correctly" set
-        // this it'd do anything anyway...
-        header[9] = operating_system.unwrap_or(255);
-        return header;
+/// Parses the value of `SOURCE_DATE_EPOCH`, which is 0 when it isn't set.
+fn source_date_epoch(value: Option<OsString>) -> io::Result<u32> {
+    let value = match value {
+        Some(ref value) if !value.is_empty() => value,
+        _ => return Ok(0),
+    };
+    match value.to_str().and_then(|s| s.parse::<u64>().ok()) {
+        Some(epoch) => Ok(u32::try_from(epoch).unwrap_or(u32::max_value())),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "SOURCE_DATE_EPOCH is not a number of seconds",
+        )),
     }
 }
 
@@ -256,8 +1033,12 @@ impl GzBuilder {
 mod tests {
     use std::io::prelude::*;
 
-    use super::{read, write, GzBuilder};
-    use crate::Compression;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    use super::{bufread, read, write, GzBuilder, GzDecoderBuilder, GzHeader, HeaderLimitExceeded};
+    use crate::{Compression, Crc, DecompressError, DecompressErrorKind};
+    use crate::{DecompressLimits, LimitExceeded};
     use rand::{thread_rng, Rng};
 
     #[test]
@@ -324,6 +1105,170 @@ mod tests {
         assert_eq!(res, vec![0, 2, 4, 6]);
     }
 
+    #[test]
+    fn header_round_trip() {
+        let e = GzBuilder::new()
+            .filename("foo.rs")
+            .comment("bar")
+            .extra(vec![0, 1, 2, 3])
+            .mtime(1234)
+            .operating_system(3)
+            .text(true)
+            .xfl(7)
+            .header_crc(true)
+            .write(Vec::new(), Compression::default());
+        let bytes = e.finish().unwrap();
+
+        let (header, len) = GzHeader::parse(&bytes).unwrap();
+        assert!(header.is_text());
+        assert!(header.has_header_crc());
+        assert_eq!(header.xfl(), 7);
+        assert_eq!(header.mtime(), 1234);
+        assert_eq!(header.operating_system(), 3);
+        assert_eq!(len, 10 + 6 + 7 + 4 + 2);
+
+        let mut written = Vec::new();
+        header.write_to(&mut written).unwrap();
+        assert_eq!(written, &bytes[..len]);
+        let rebuilt = GzBuilder::from_header(&header)
+            .unwrap()
+            .write(Vec::new(), Compression::best())
+            .finish()
+            .unwrap();
+        assert_eq!(rebuilt[..len], bytes[..len]);
+
+        let mut d = read::GzDecoder::new(&bytes[..]);
+        d.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(d.header(), Some(&header));
+
+        let mut corrupt = bytes.clone();
+        corrupt[len - 1] ^= 1;
+        assert!(GzHeader::parse(&corrupt).is_err());
+        assert!(GzHeader::parse(&bytes[..len - 1]).is_err());
+    }
+
+    #[test]
+    fn header_setters() {
+        let mut header = GzHeader::new();
+        assert_eq!(header.operating_system(), 255);
+        header.set_filename(Some(b"name".to_vec()));
+        header.set_comment(Some(b"comment".to_vec()));
+        header.set_extra(Some(vec![1, 2]));
+        header.set_mtime(42);
+        header.set_operating_system(0);
+        header.set_text(true);
+        header.set_xfl(2);
+        header.set_header_crc(true);
+        let mut bytes = Vec::new();
+        header.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            GzHeader::parse(&bytes).unwrap(),
+            (header.clone(), bytes.len())
+        );
+
+        header.set_filename(Some(b"na\0me".to_vec()));
+        let err = header.write_to(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = GzBuilder::from_header(&header).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        header.set_filename(None);
+        header.set_extra(Some(vec![0; 0x10000]));
+        let err = header.write_to(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let err = GzBuilder::from_header(&header).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn header_default() {
+        assert_eq!(GzHeader::default(), GzHeader::new());
+        assert_eq!(GzHeader::default().operating_system(), 255);
+    }
+
+    #[test]
+    fn from_path() {
+        let dir = std::env::temp_dir().join(format!("flate2-from-path-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("caf\u{e9}.txt");
+        let file = std::fs::File::create(&path).unwrap();
+        let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_500_000_000);
+        file.set_modified(mtime).unwrap();
+
+        let e = GzBuilder::from_path(&path)
+            .unwrap()
+            .write(Vec::new(), Compression::default());
+        let (header, _) = GzHeader::parse(&e.finish().unwrap()).unwrap();
+        assert_eq!(header.filename(), Some(&b"caf\xe9.txt"[..]));
+        assert_eq!(header.mtime(), 1_500_000_000);
+        assert_eq!(
+            header.filename_as_path().unwrap(),
+            Path::new("caf\u{e9}.txt")
+        );
+
+        let restored = dir.join("restored");
+        let file = std::fs::File::create(&restored).unwrap();
+        header.restore_mtime(&file).unwrap();
+        assert_eq!(file.metadata().unwrap().modified().unwrap(), mtime);
+
+        let err = GzBuilder::from_path(dir.join("\u{2603}")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        std::fs::File::create(dir.join("\u{2603}")).unwrap();
+        let err = GzBuilder::from_path(dir.join("\u{2603}")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn filename_as_path() {
+        let mut header = GzHeader::new();
+        assert_eq!(header.filename_as_path(), None);
+        for &(name, path) in &[
+            (&b"../../etc/passwd"[..], Some("passwd")),
+            (b"/", None),
+            (b"..", None),
+            (b"dir/\xfc.txt", Some("\u{fc}.txt")),
+        ] {
+            header.set_filename(Some(name.to_vec()));
+            assert_eq!(header.filename_as_path(), path.map(PathBuf::from));
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        assert_eq!(super::source_date_epoch(None).unwrap(), 0);
+        assert_eq!(super::source_date_epoch(Some("".into())).unwrap(), 0);
+        assert_eq!(super::source_date_epoch(Some("1234".into())).unwrap(), 1234);
+        let err = super::source_date_epoch(Some("yesterday".into())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let header = |b: GzBuilder| {
+            let bytes = b
+                .write(Vec::new(), Compression::default())
+                .finish()
+                .unwrap();
+            GzHeader::parse(&bytes).unwrap().0
+        };
+        let b = GzBuilder::new().mtime(5000).reproducible_at(1000).unwrap();
+        let h = header(b);
+        assert_eq!((h.mtime(), h.operating_system()), (1000, 255));
+        let b = GzBuilder::new().reproducible_at(0).unwrap().mtime(5000);
+        assert_eq!(header(b).mtime(), 0);
+        let b = GzBuilder::new().mtime(500).reproducible_at(1000).unwrap();
+        assert_eq!(header(b).mtime(), 500);
+
+        let err = GzBuilder::new()
+            .operating_system(3)
+            .reproducible_at(0)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let b = GzBuilder::new()
+            .operating_system(255)
+            .reproducible_at(0)
+            .unwrap()
+            .operating_system(3);
+        assert_eq!(header(b).operating_system(), 3);
+    }
+
     #[test]
     fn keep_reading_after_end() {
         let mut e = write::GzEncoder::new(Vec::new(), Compression::default());
@@ -356,4 +1301,275 @@ mod tests {
         write!(f, "Hello world").unwrap();
         f.flush().unwrap();
     }
+
+    #[test]
+    fn multi_limits_are_cumulative() {
+        let mut data = Vec::new();
+        for _ in 0..4 {
+            let mut e = write::GzEncoder::new(Vec::new(), Compression::default());
+            e.write_all(&[1; 1000]).unwrap();
+            data.extend(e.finish().unwrap());
+        }
+
+        let mut d = read::MultiGzDecoder::new(&data[..]);
+        d.set_limits(DecompressLimits::new().max_output(4000));
+        let mut v = Vec::new();
+        d.read_to_end(&mut v).unwrap();
+        assert_eq!(v.len(), 4000);
+
+        let mut d = read::MultiGzDecoder::new(&data[..]);
+        d.set_limits(DecompressLimits::new().max_output(2500));
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        let limit = err.get_ref().unwrap().downcast_ref::<LimitExceeded>();
+        assert_eq!(limit, Some(&LimitExceeded::Output(2500)));
+    }
+
+    fn header_limit(err: &io::Error) -> Option<HeaderLimitExceeded> {
+        err.get_ref()
+            .and_then(|e| e.downcast_ref::<HeaderLimitExceeded>())
+            .cloned()
+    }
+
+    #[test]
+    fn header_limits() {
+        let mut e = GzBuilder::new()
+            .filename(vec![b'f'; 100])
+            .comment(vec![b'c'; 200])
+            .extra(vec![0; 300])
+            .write(Vec::new(), Compression::default());
+        e.write_all(b"hello").unwrap();
+        let bytes = e.finish().unwrap();
+
+        let builder = GzDecoderBuilder::new()
+            .max_filename_len(100)
+            .max_comment_len(200)
+            .max_extra_len(300);
+        let mut d = builder.clone().read(&bytes[..]);
+        let mut s = String::new();
+        d.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello");
+        assert_eq!(d.header().unwrap().filename(), Some(&[b'f'; 100][..]));
+
+        let mut d = builder.clone().max_filename_len(99).read(&bytes[..]);
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(header_limit(&err), Some(HeaderLimitExceeded::Filename(99)));
+
+        let mut d = builder.clone().max_comment_len(199).buf_read(&bytes[..]);
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(header_limit(&err), Some(HeaderLimitExceeded::Comment(199)));
+
+        let mut d = builder.clone().max_extra_len(299).write(Vec::new());
+        let err = d.write_all(&bytes).unwrap_err();
+        assert_eq!(header_limit(&err), Some(HeaderLimitExceeded::Extra(299)));
+
+        // The limits apply to the header of every member.
+        let mut multi = GzBuilder::new()
+            .filename("a")
+            .write(Vec::new(), Compression::default())
+            .finish()
+            .unwrap();
+        multi.extend(&bytes);
+        let mut d = builder.max_filename_len(10).read_multi(&multi[..]);
+        let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(header_limit(&err), Some(HeaderLimitExceeded::Filename(10)));
+    }
+
+    #[test]
+    fn header_crc() {
+        let mut header = vec![
+            0x1f,
+            0x8b,
+            8,
+            super::FHCRC | super::FNAME,
+            0,
+            0,
+            0,
+            0,
+            0,
+            255,
+        ];
+        header.extend(b"name\0");
+        let mut crc = Crc::new();
+        crc.update(&header);
+        let sum = crc.sum() as u16;
+        header.push(sum as u8);
+        header.push((sum >> 8) as u8);
+        let mut good = header.clone();
+        good.extend(&[3, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let mut d = read::GzDecoder::new(&good[..]);
+        d.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(d.header().unwrap().filename(), Some(&b"name"[..]));
+
+        let n = header.len();
+        good[n - 1] ^= 1;
+        let mut d = read::GzDecoder::new(&good[..]);
+        assert!(d.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    /// Returns a single byte per call, with a `WouldBlock` error in between.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        blocked: bool,
+    }
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.blocked = !self.blocked;
+            if self.blocked {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let n = std::cmp::min(1, buf.len());
+            (&mut self.data).read(&mut buf[..n])
+        }
+    }
+
+    impl<'a> BufRead for Trickle<'a> {
+        fn fill_buf(&mut self) -> io::Result<&[u8]> {
+            self.blocked = !self.blocked;
+            if self.blocked {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            Ok(&self.data[..std::cmp::min(1, self.data.len())])
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.data = &self.data[amt..];
+        }
+    }
+
+    #[test]
+    fn header_resumes_after_would_block() {
+        let mut e = GzBuilder::new()
+            .filename("foo.rs")
+            .comment("bar")
+            .extra(vec![0, 1, 2, 3])
+            .write(Vec::new(), Compression::default());
+        e.write_all(b"hello").unwrap();
+        let bytes = e.finish().unwrap();
+
+        let mut d = bufread::MultiGzDecoder::new(Trickle {
+            data: &bytes,
+            blocked: false,
+        });
+        let mut out = Vec::<u8>::new();
+        let mut buf = [0; 16];
+        loop {
+            match d.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => out.extend(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{}", e),
+            }
+        }
+        assert_eq!(out, b"hello");
+        assert_eq!(d.header().unwrap().comment(), Some(&b"bar"[..]));
+    }
+
+    fn decode_error(data: &[u8]) -> (io::ErrorKind, DecompressErrorKind, Option<u64>) {
+        let err = read::MultiGzDecoder::new(data)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        let inner = err.get_ref().unwrap().downcast_ref::<DecompressError>();
+        let inner = inner.unwrap();
+        (err.kind(), inner.kind(), inner.offset())
+    }
+
+    #[test]
+    fn error_kinds() {
+        let mut e = write::GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"hello world").unwrap();
+        let good = e.finish().unwrap();
+        let n = good.len();
+
+        let mut bad = good.clone();
+        bad[2] = 9;
+        let err = decode_error(&bad);
+        assert_eq!(
+            err,
+            (
+                io::ErrorKind::InvalidInput,
+                DecompressErrorKind::UnsupportedMethod,
+                Some(2)
+            )
+        );
+
+        let mut bad = good.clone();
+        bad[n - 8] ^= 1;
+        let crc = u32::from_le_bytes([good[n - 8], good[n - 7], good[n - 6], good[n - 5]]);
+        let kind = DecompressErrorKind::ChecksumMismatch {
+            expected: crc ^ 1,
+            actual: crc,
+        };
+        assert_eq!(
+            decode_error(&bad),
+            (io::ErrorKind::InvalidInput, kind, Some(n as u64 - 8))
+        );
+
+        let mut bad = good.clone();
+        bad[n - 4] ^= 1;
+        let kind = DecompressErrorKind::LengthMismatch {
+            expected: 10,
+            actual: 11,
+        };
+        assert_eq!(
+            decode_error(&bad),
+            (io::ErrorKind::InvalidInput, kind, Some(n as u64 - 4))
+        );
+
+        let err = decode_error(&good[..n - 3]);
+        assert_eq!(
+            err,
+            (
+                io::ErrorKind::UnexpectedEof,
+                DecompressErrorKind::TruncatedInput,
+                Some(n as u64 - 3)
+            )
+        );
+        let err = decode_error(&good[..5]);
+        assert_eq!(
+            err,
+            (
+                io::ErrorKind::UnexpectedEof,
+                DecompressErrorKind::TruncatedInput,
+                Some(5)
+            )
+        );
+
+        let mut bad = good.clone();
+        bad.extend(b"garbage");
+        let err = decode_error(&bad);
+        assert_eq!(
+            err,
+            (
+                io::ErrorKind::InvalidInput,
+                DecompressErrorKind::TrailingGarbage,
+                Some(n as u64)
+            )
+        );
+
+        // A second member whose data is a block of the reserved type 3.
+        let mut bad = good.clone();
+        bad.extend(&good[..10]);
+        bad.extend(&[0x07, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let (_, kind, offset) = decode_error(&bad);
+        if cfg!(feature = "any_zlib") {
+            assert_eq!(kind, DecompressErrorKind::InvalidBlockType);
+        }
+        let offset = offset.unwrap();
+        assert!(
+            offset > n as u64 + 10 && offset <= bad.len() as u64,
+            "{}",
+            offset
+        );
+
+        let mut bad = good.clone();
+        bad[n - 8] ^= 1;
+        let mut w = write::GzDecoder::new(Vec::new());
+        w.write_all(&bad).unwrap();
+        let err = w.try_finish().unwrap_err();
+        let inner = err.get_ref().unwrap().downcast_ref::<DecompressError>();
+        assert_eq!(inner.unwrap().offset(), Some(n as u64 - 8));
+    }
 }
@@ -0,0 +1,1025 @@
+//! Multi-threaded gzip compression and decompression.
+//!
+//! For compression the input is split into blocks which are compressed
+//! concurrently into raw DEFLATE data ending in a sync flush, so they can be
+//! concatenated into a single gzip member. Like pigz, each block is primed
+//! with the 32 KiB of input preceding it so that splitting the input costs
+//! very little in compression ratio.
+//!
+//! Decompression works on streams made of many gzip members, decoding several
+//! members at once. Member boundaries are found ahead of time from the BGZF
+//! block size field when present and otherwise by searching for the next gzip
+//! header, in which case a boundary is only trusted once the member before it
+//! has been decoded and found to end exactly there.
+
+use std::cmp;
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::io::prelude::*;
+use std::mem;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::bufread::{check_trailer, corrupt, member_error, read_gz_header};
+use super::{bgzf, GzBuilder, FEXTRA};
+use crate::crc::Crc;
+use crate::mem::{add_offset, io_error};
+use crate::{Compress, Compression, Decompress, DecompressErrorKind, DecompressLimits};
+use crate::{FlushCompress, FlushDecompress, Status};
+
+const DEFAULT_BLOCK_SIZE: usize = 128 * 1024;
+
+/// The amount of compressed input decoded by each thread at once.
+const INPUT_PER_THREAD: usize = 1024 * 1024;
+
+/// The amount of input read at once when decoding a member serially.
+const SERIAL_CHUNK: usize = 32 * 1024;
+
+/// The largest output of a member decoded on a thread. Larger members are
+/// decoded serially, so that memory use doesn't depend on the compression
+/// ratio.
+const MAX_MEMBER_OUTPUT: usize = 4 * 1024 * 1024;
+
+/// The size of the smallest gzip member: a header, an empty DEFLATE stream and
+/// a trailer.
+const MIN_MEMBER_SIZE: usize = 20;
+
+const WINDOW_SIZE: usize = 32 * 1024;
+
+#[derive(Debug)]
+struct Job {
+    seq: u64,
+    data: Vec<u8>,
+    dictionary: Option<Vec<u8>>,
+    last: bool,
+}
+
+#[derive(Debug)]
+struct Done {
+    seq: u64,
+    result: io::Result<(Vec<u8>, Crc)>,
+}
+
+/// A multi-threaded gzip encoder.
+///
+/// This structure exposes a [`Write`] interface like [`GzEncoder`], but
+/// compresses blocks of the input on a pool of worker threads. The output is
+/// a single gzip member which can be decoded by any gzip implementation.
+///
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`GzEncoder`]: struct.GzEncoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::Compression;
+/// use flate2::write::ParGzEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut e = ParGzEncoder::new_with_threads(Vec::new(), Compression::default(), 4, 64 * 1024);
+/// for i in 0..100_000 {
+///     writeln!(e, "line {}", i)?;
+/// }
+/// let compressed = e.finish()?;
+/// # let _ = compressed;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ParGzEncoder<W: Write> {
+    inner: Option<W>,
+    header: Vec<u8>,
+    block_size: usize,
+    buf: Vec<u8>,
+    window: Vec<u8>,
+    jobs: Option<Sender<Job>>,
+    results: Receiver<Done>,
+    workers: Vec<JoinHandle<()>>,
+    sent: u64,
+    written: u64,
+    ready: BTreeMap<u64, (Vec<u8>, Crc)>,
+    crc: Crc,
+    finished: bool,
+}
+
+pub fn par_gz_encoder<W: Write>(
+    header: Vec<u8>,
+    w: W,
+    lvl: Compression,
+    threads: usize,
+    block_size: usize,
+) -> ParGzEncoder<W> {
+    assert!(block_size > 0, "block size must be non-zero");
+    let (jobs, queue) = mpsc::channel::<Job>();
+    let (done, results) = mpsc::channel();
+    let queue = Arc::new(Mutex::new(queue));
+    let workers = (0..cmp::max(threads, 1))
+        .map(|_| {
+            let queue = queue.clone();
+            let done = done.clone();
+            thread::spawn(move || loop {
+                let job = match queue.lock().unwrap().recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                let result = compress_block(lvl, &job);
+                if done
+                    .send(Done {
+                        seq: job.seq,
+                        result,
+                    })
+                    .is_err()
+                {
+                    break;
+                }
+            })
+        })
+        .collect();
+
+    ParGzEncoder {
+        inner: Some(w),
+        header,
+        block_size,
+        buf: Vec::with_capacity(block_size),
+        window: Vec::new(),
+        jobs: Some(jobs),
+        results,
+        workers,
+        sent: 0,
+        written: 0,
+        ready: BTreeMap::new(),
+        crc: Crc::new(),
+        finished: false,
+    }
+}
+
+impl<W: Write> ParGzEncoder<W> {
+    /// Creates a new encoder which will use the given compression level, one
+    /// worker thread per available CPU and blocks of 128 KiB.
+    pub fn new(w: W, level: Compression) -> ParGzEncoder<W> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        ParGzEncoder::new_with_threads(w, level, threads, DEFAULT_BLOCK_SIZE)
+    }
+
+    /// Creates a new encoder which will use the given compression level,
+    /// compressing blocks of `block_size` bytes on `threads` worker threads.
+    ///
+    /// The encoder is not configured specially for the emitted header. For
+    /// header configuration, see the `GzBuilder` type.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero.
+    pub fn new_with_threads(
+        w: W,
+        level: Compression,
+        threads: usize,
+        block_size: usize,
+    ) -> ParGzEncoder<W> {
+        GzBuilder::new().write_parallel(w, level, threads, block_size)
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutation of the writer may result in surprising results if
+    /// this encoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Attempt to finish this output stream, waiting for all blocks to be
+    /// compressed and writing out the gzip trailer.
+    ///
+    /// # Panics
+    ///
+    /// Attempts to write data to this stream may result in a panic after this
+    /// function is called.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.send_block(true)?;
+        self.wait(0)?;
+        let mut trailer = [0; 8];
+        trailer[..4].copy_from_slice(&self.crc.sum().to_le_bytes());
+        trailer[4..].copy_from_slice(&self.crc.amount().to_le_bytes());
+        self.get_mut().write_all(&trailer)?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Finish encoding this stream, returning the underlying writer once the
+    /// encoding is done.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn send_block(&mut self, last: bool) -> io::Result<()> {
+        if self.buf.is_empty() && !last {
+            return Ok(());
+        }
+        // Bound the amount of data held in memory by the workers.
+        self.wait(2 * self.workers.len() - 1)?;
+
+        let data = mem::replace(&mut self.buf, Vec::with_capacity(self.block_size));
+        let dictionary = if !self.window.is_empty() {
+            Some(self.window.clone())
+        } else {
+            None
+        };
+        if data.len() >= WINDOW_SIZE {
+            self.window.clear();
+        } else {
+            let excess = (self.window.len() + data.len()).saturating_sub(WINDOW_SIZE);
+            self.window.drain(..excess);
+        }
+        self.window
+            .extend_from_slice(&data[data.len().saturating_sub(WINDOW_SIZE)..]);
+
+        let job = Job {
+            seq: self.sent,
+            data,
+            dictionary,
+            last,
+        };
+        self.sent += 1;
+        match self.jobs.as_ref().unwrap().send(job) {
+            Ok(()) => Ok(()),
+            Err(_) => Err(worker_died()),
+        }
+    }
+
+    /// Writes out compressed blocks until at most `in_flight` are left.
+    fn wait(&mut self, in_flight: usize) -> io::Result<()> {
+        if self.written == 0 && !self.header.is_empty() {
+            let header = mem::take(&mut self.header);
+            self.get_mut().write_all(&header)?;
+        }
+        while self.sent - self.written > in_flight as u64 {
+            let (data, crc) = match self.ready.remove(&self.written) {
+                Some(block) => block,
+                None => {
+                    let done = self.results.recv().map_err(|_| worker_died())?;
+                    self.ready.insert(done.seq, done.result?);
+                    continue;
+                }
+            };
+            self.inner.as_mut().unwrap().write_all(&data)?;
+            self.crc.combine(&crc);
+            self.written += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ParGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        assert!(!self.finished);
+        if self.buf.len() == self.block_size {
+            self.send_block(false)?;
+        }
+        let n = cmp::min(buf.len(), self.block_size - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        assert!(!self.finished);
+        self.send_block(false)?;
+        self.wait(0)?;
+        self.get_mut().flush()
+    }
+}
+
+impl<W: Write> Drop for ParGzEncoder<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+        drop(self.jobs.take());
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn compress_block(lvl: Compression, job: &Job) -> io::Result<(Vec<u8>, Crc)> {
+    let mut data = Compress::new(lvl, false);
+    if let Some(ref dictionary) = job.dictionary {
+        set_dictionary(&mut data, dictionary)?;
+    }
+    let flush = if job.last {
+        FlushCompress::Finish
+    } else {
+        FlushCompress::Sync
+    };
+
+    let mut out = Vec::with_capacity(job.data.len() / 2 + 64);
+    loop {
+        if out.len() == out.capacity() {
+            out.reserve(out.capacity());
+        }
+        let input = &job.data[data.total_in() as usize..];
+        let status = data.compress_vec(input, &mut out, flush)?;
+        let consumed = data.total_in() as usize == job.data.len();
+        match status {
+            Status::StreamEnd => break,
+            Status::Ok | Status::BufError
+                if !job.last && consumed && out.len() < out.capacity() =>
+            {
+                break
+            }
+            Status::Ok | Status::BufError => {}
+        }
+    }
+
+    let mut crc = Crc::new();
+    crc.update(&job.data);
+    Ok((out, crc))
+}
+
+#[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+fn set_dictionary(data: &mut Compress, dictionary: &[u8]) -> io::Result<()> {
+    data.set_dictionary(dictionary)?;
+    Ok(())
+}
+
+// miniz.c can't be primed with a dictionary, so blocks are compressed
+// independently of each other.
+#[cfg(not(any(feature = "any_zlib", not(feature = "miniz-sys"))))]
+fn set_dictionary(_data: &mut Compress, _dictionary: &[u8]) -> io::Result<()> {
+    Ok(())
+}
+
+fn worker_died() -> io::Error {
+    io::Error::other("gzip compression worker thread died")
+}
+
+/// A multi-threaded decoder for gzip streams made of several members.
+///
+/// This decodes the same streams as [`MultiGzDecoder`], such as files written
+/// by bgzip or several gzip files concatenated together, but decodes members
+/// on several threads at once and returns their output in order. Each
+/// member's CRC32 and length are verified.
+///
+/// Members whose end can't be found ahead of time, for example a member larger
+/// than the amount of input read at once, and members which decompress to
+/// more than 4 MiB are decoded serially. A stream consisting of a single large
+/// member is therefore decoded no faster than with `MultiGzDecoder`.
+///
+/// [`MultiGzDecoder`]: struct.MultiGzDecoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::Compression;
+/// use flate2::read::ParMultiGzDecoder;
+/// use flate2::write::GzEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut file = Vec::new();
+/// for part in &["Hello ", "World"] {
+///     let mut e = GzEncoder::new(Vec::new(), Compression::default());
+///     e.write_all(part.as_bytes())?;
+///     file.extend(e.finish()?);
+/// }
+///
+/// let mut d = ParMultiGzDecoder::new(&file[..]);
+/// let mut s = String::new();
+/// d.read_to_string(&mut s)?;
+/// assert_eq!(s, "Hello World");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ParMultiGzDecoder<R> {
+    inner: R,
+    threads: usize,
+    input: Vec<u8>,
+    start: usize,
+    eof: bool,
+    started: bool,
+    output: VecDeque<Vec<u8>>,
+    pos: usize,
+    member: Option<Member>,
+    limits: DecompressLimits,
+    total_in: u64,
+    total_out: u64,
+}
+
+/// A member which is being decoded serially.
+#[derive(Debug)]
+struct Member {
+    data: Decompress,
+    crc: Crc,
+}
+
+impl<R: Read> ParMultiGzDecoder<R> {
+    /// Creates a new decoder reading from `r` which uses one thread per
+    /// available CPU.
+    pub fn new(r: R) -> ParMultiGzDecoder<R> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        ParMultiGzDecoder::new_with_threads(r, threads)
+    }
+
+    /// Creates a new decoder reading from `r` which decodes up to `threads`
+    /// members at once.
+    pub fn new_with_threads(r: R, threads: usize) -> ParMultiGzDecoder<R> {
+        ParMultiGzDecoder {
+            inner: r,
+            threads: cmp::max(threads, 1),
+            input: Vec::new(),
+            start: 0,
+            eof: false,
+            started: false,
+            output: VecDeque::new(),
+            pos: 0,
+            member: None,
+            limits: DecompressLimits::new(),
+            total_in: 0,
+            total_out: 0,
+        }
+    }
+
+    /// Reads from the underlying reader until `input` holds `len` bytes or
+    /// the end of the stream is reached.
+    fn fill_input(&mut self, len: usize) -> io::Result<()> {
+        while !self.eof && self.input.len() < len {
+            let want = (len - self.input.len()) as u64;
+            if self
+                .inner
+                .by_ref()
+                .take(want)
+                .read_to_end(&mut self.input)?
+                == 0
+            {
+                self.eof = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the next batch of members, returning `false` at the end of
+    /// the stream.
+    fn next_batch(&mut self) -> io::Result<bool> {
+        self.input.drain(..self.start);
+        self.start = 0;
+        self.fill_input(self.threads * INPUT_PER_THREAD)?;
+        if self.input.is_empty() && self.started {
+            return Ok(false);
+        }
+        self.started = true;
+
+        // The last member may continue past the input read so far.
+        let mut members = members(&self.input);
+        if !self.eof {
+            members.pop();
+        }
+        let decoded = inflate_members(&self.input, &members, self.threads, self.limits);
+        for (output, member) in decoded.into_iter().zip(members) {
+            match output {
+                Some(output) => {
+                    self.total_in += member.len() as u64;
+                    self.total_out += output.len() as u64;
+                    self.limits.check(self.total_in, self.total_out)?;
+                    self.start = member.end;
+                    if !output.is_empty() {
+                        self.output.push_back(output);
+                    }
+                }
+                None => break,
+            }
+        }
+        if self.start == 0 {
+            self.start_member()?;
+        }
+        Ok(true)
+    }
+
+    fn start_member(&mut self) -> io::Result<()> {
+        loop {
+            let mut header = &self.input[self.start..];
+            match read_gz_header(&mut header) {
+                Ok(_) => {
+                    let start = self.input.len() - header.len();
+                    self.total_in += (start - self.start) as u64;
+                    self.start = start;
+                    break;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof && !self.eof => {
+                    let len = self.input.len() + SERIAL_CHUNK;
+                    self.fill_input(len)?;
+                }
+                Err(e) => return Err(member_error(e, self.total_in)),
+            }
+        }
+        self.member = Some(Member {
+            data: Decompress::new(false),
+            crc: Crc::new(),
+        });
+        Ok(())
+    }
+
+    fn read_member(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.start == self.input.len() && !self.eof {
+                self.input.clear();
+                self.start = 0;
+                self.fill_input(SERIAL_CHUNK)?;
+            }
+
+            let offset = self.total_in;
+            let member = self.member.as_mut().unwrap();
+            let before_in = member.data.total_in();
+            let before_out = member.data.total_out();
+            let status = member
+                .data
+                .decompress(&self.input[self.start..], into, FlushDecompress::None)
+                .map_err(|e| add_offset(io_error(e), offset))?;
+            let read = (member.data.total_in() - before_in) as usize;
+            let n = (member.data.total_out() - before_out) as usize;
+            self.start += read;
+            member.crc.update(&into[..n]);
+            self.limits.check(
+                self.total_in + member.data.total_in(),
+                self.total_out + member.data.total_out(),
+            )?;
+
+            if status == Status::StreamEnd {
+                self.finish_member()?;
+                return Ok(n);
+            }
+            if n > 0 {
+                return Ok(n);
+            }
+            if read == 0 && self.start == self.input.len() && self.eof {
+                let at = self.total_in + member.data.total_in();
+                return Err(corrupt(DecompressErrorKind::TruncatedInput, at));
+            }
+        }
+    }
+
+    fn finish_member(&mut self) -> io::Result<()> {
+        let offset = self.total_in + self.member.as_ref().unwrap().data.total_in();
+        if self.input.len() - self.start < 8 {
+            self.input.drain(..self.start);
+            self.start = 0;
+            self.fill_input(8)?;
+            if self.input.len() < 8 {
+                let at = offset + self.input.len() as u64;
+                return Err(corrupt(DecompressErrorKind::TruncatedInput, at));
+            }
+        }
+        let member = self.member.take().unwrap();
+        let trailer = &self.input[self.start..self.start + 8];
+        check_trailer(
+            le_u32(&trailer[..4]),
+            le_u32(&trailer[4..]),
+            &member.crc,
+            offset,
+        )?;
+        self.start += 8;
+        self.total_in += member.data.total_in() + 8;
+        self.total_out += member.data.total_out();
+        Ok(())
+    }
+}
+
+impl<R> ParMultiGzDecoder<R> {
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying stream.
+    ///
+    /// Note that mutation of the stream may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes this decoder, returning the underlying reader.
+    ///
+    /// Any input which has been read ahead but not yet decoded is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// The limits apply to the stream as a whole rather than to each member.
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = limits;
+    }
+}
+
+impl<R: Read> Read for ParMultiGzDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if into.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(output) = self.output.front() {
+                let n = cmp::min(into.len(), output.len() - self.pos);
+                into[..n].copy_from_slice(&output[self.pos..self.pos + n]);
+                self.pos += n;
+                if self.pos == output.len() {
+                    self.output.pop_front();
+                    self.pos = 0;
+                }
+                return Ok(n);
+            }
+            if self.member.is_some() {
+                let n = self.read_member(into)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+            } else if !self.next_batch()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+/// Splits `input`, which starts with a gzip member, into likely members.
+fn members(input: &[u8]) -> Vec<Range<usize>> {
+    let mut members = Vec::new();
+    let mut start = 0;
+    while start < input.len() {
+        let end = match bgzf_size(&input[start..]) {
+            Some(size) => cmp::min(start + size, input.len()),
+            None => find_header(input, start + MIN_MEMBER_SIZE).unwrap_or(input.len()),
+        };
+        members.push(start..end);
+        start = end;
+    }
+    members
+}
+
+fn bgzf_size(data: &[u8]) -> Option<usize> {
+    if data.len() < 12 || data[3] & FEXTRA == 0 {
+        return None;
+    }
+    let xlen = u16::from_le_bytes([data[10], data[11]]) as usize;
+    let size = bgzf::block_size(data.get(12..12 + xlen)?)? as usize + 1;
+    if size < MIN_MEMBER_SIZE {
+        return None;
+    }
+    Some(size)
+}
+
+fn find_header(data: &[u8], from: usize) -> Option<usize> {
+    (from..data.len().saturating_sub(3)).find(|&i| {
+        data[i] == 0x1f && data[i + 1] == 0x8b && data[i + 2] == 8 && data[i + 3] & 0xe0 == 0
+    })
+}
+
+/// Decodes each of `members` on up to `threads` threads, returning `None` for
+/// those which aren't exactly one valid gzip member.
+fn inflate_members(
+    input: &[u8],
+    members: &[Range<usize>],
+    threads: usize,
+    limits: DecompressLimits,
+) -> Vec<Option<Vec<u8>>> {
+    let threads = cmp::min(threads, members.len());
+    if threads <= 1 {
+        return members
+            .iter()
+            .map(|member| inflate_member(&input[member.clone()], limits))
+            .collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut decoded = (0..members.len()).map(|_| None).collect::<Vec<_>>();
+    thread::scope(|s| {
+        let workers = (0..threads)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        match members.get(i) {
+                            Some(member) => {
+                                done.push((i, inflate_member(&input[member.clone()], limits)))
+                            }
+                            None => break done,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            for (i, output) in worker.join().unwrap() {
+                decoded[i] = output;
+            }
+        }
+    });
+    decoded
+}
+
+/// Limits are applied to the member on its own. Members failing them or
+/// producing more than `MAX_MEMBER_OUTPUT` bytes are decoded again serially,
+/// which applies the limits to the stream as a whole.
+fn inflate_member(member: &[u8], limits: DecompressLimits) -> Option<Vec<u8>> {
+    let mut data = member;
+    read_gz_header(&mut data).ok()?;
+    if data.len() < 8 {
+        return None;
+    }
+    let (data, trailer) = data.split_at(data.len() - 8);
+    let size = le_u32(&trailer[4..]) as usize;
+
+    let mut output = Vec::with_capacity(cmp::min(data.len() * 4, MAX_MEMBER_OUTPUT));
+    let mut decompress = Decompress::new(false);
+    decompress.set_limits(limits);
+    loop {
+        if output.len() == output.capacity() {
+            if output.len() >= MAX_MEMBER_OUTPUT {
+                return None;
+            }
+            let more = cmp::min(output.capacity(), MAX_MEMBER_OUTPUT - output.len());
+            output.reserve_exact(cmp::max(more, 1));
+        }
+        // The output grows as needed, which `FlushDecompress::Finish` doesn't
+        // allow for.
+        let before = (decompress.total_in(), decompress.total_out());
+        let input = &data[before.0 as usize..];
+        match decompress.decompress_vec(input, &mut output, FlushDecompress::None) {
+            Ok(Status::StreamEnd) => break,
+            Ok(_) if (decompress.total_in(), decompress.total_out()) != before => {}
+            _ => return None,
+        }
+    }
+
+    let mut crc = Crc::new();
+    crc.update(&output);
+    if decompress.total_in() as usize != data.len()
+        || le_u32(&trailer[..4]) != crc.sum()
+        || size as u32 != crc.amount()
+    {
+        return None;
+    }
+    Some(output)
+}
+
+fn le_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+
+    use super::{inflate_member, ParGzEncoder, ParMultiGzDecoder, MAX_MEMBER_OUTPUT};
+    use crate::bgzf;
+    use crate::bufread;
+    use crate::read::{GzDecoder, MultiGzDecoder};
+    use crate::write::GzEncoder;
+    use crate::{Compression, DecompressLimits, GzBuilder, LimitExceeded};
+
+    fn sample() -> Vec<u8> {
+        (0..50_000)
+            .map(|i| format!("line {} of the sample\n", i * 31 % 977))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    fn decode(data: &[u8]) -> Vec<u8> {
+        // Decoding with the single-member decoder checks that the output is
+        // one gzip member rather than several concatenated ones.
+        let mut d = bufread::GzDecoder::new(data);
+        let mut v = Vec::new();
+        d.read_to_end(&mut v).unwrap();
+        assert!(d.into_inner().is_empty());
+        v
+    }
+
+    #[test]
+    fn roundtrip() {
+        let v = sample();
+        for &(threads, block_size) in &[(1, 1 << 20), (4, 4096), (3, 65_536), (8, 100_003)] {
+            let mut e = ParGzEncoder::new_with_threads(
+                Vec::new(),
+                Compression::default(),
+                threads,
+                block_size,
+            );
+            e.write_all(&v).unwrap();
+            assert_eq!(decode(&e.finish().unwrap()), v);
+        }
+    }
+
+    #[test]
+    fn roundtrip_random_writes() {
+        let v = crate::random_bytes().take(300_000).collect::<Vec<_>>();
+        let mut e = ParGzEncoder::new(Vec::new(), Compression::fast());
+        for chunk in v.chunks(7_777) {
+            e.write_all(chunk).unwrap();
+        }
+        e.flush().unwrap();
+        assert_eq!(decode(&e.finish().unwrap()), v);
+    }
+
+    #[test]
+    fn empty() {
+        let e = ParGzEncoder::new_with_threads(Vec::new(), Compression::default(), 2, 1024);
+        assert!(decode(&e.finish().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn header() {
+        let mut e = GzBuilder::new().filename("foo.txt").write_parallel(
+            Vec::new(),
+            Compression::best(),
+            2,
+            1024,
+        );
+        e.write_all(b"hello world").unwrap();
+        let data = e.finish().unwrap();
+        let mut d = GzDecoder::new(&data[..]);
+        let mut s = String::new();
+        d.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello world");
+        assert_eq!(d.header().unwrap().filename(), Some(&b"foo.txt"[..]));
+    }
+
+    #[test]
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    fn compresses_like_serial() {
+        let v = sample();
+        let mut e = ParGzEncoder::new_with_threads(Vec::new(), Compression::default(), 4, 16_384);
+        e.write_all(&v).unwrap();
+        let parallel = e.finish().unwrap();
+
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(&v).unwrap();
+        let serial = e.finish().unwrap();
+        // Blocks are primed with the preceding data, so the output shouldn't
+        // be much larger.
+        assert!(
+            parallel.len() < serial.len() * 2,
+            "{} {}",
+            parallel.len(),
+            serial.len()
+        );
+    }
+
+    fn gzip(data: &[u8], level: Compression) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), level);
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    fn par_decode(data: &[u8], threads: usize) -> std::io::Result<Vec<u8>> {
+        let mut v = Vec::new();
+        ParMultiGzDecoder::new_with_threads(data, threads).read_to_end(&mut v)?;
+        Ok(v)
+    }
+
+    #[test]
+    fn decode_many_members() {
+        let v = sample();
+        let mut file = Vec::new();
+        for (i, chunk) in v.chunks(3_333).enumerate() {
+            file.extend(gzip(chunk, Compression::new(i as u32 % 10)));
+        }
+        file.extend(gzip(b"", Compression::default()));
+        for &threads in &[1, 2, 8] {
+            assert_eq!(par_decode(&file, threads).unwrap(), v);
+        }
+    }
+
+    #[test]
+    fn decode_bgzf() {
+        let v = crate::random_bytes().take(500_000).collect::<Vec<_>>();
+        let mut w = bgzf::Writer::new(Vec::new(), Compression::fast());
+        w.write_all(&v).unwrap();
+        let file = w.finish().unwrap();
+        assert_eq!(par_decode(&file, 4).unwrap(), v);
+    }
+
+    #[test]
+    fn decode_header_inside_member() {
+        // Stored blocks copy the data verbatim, so this member contains what
+        // looks like the start of another one.
+        let mut fake = gzip(b"not a real member", Compression::default());
+        fake.extend(vec![0x1f; 100]);
+        let mut file = Vec::new();
+        let mut v = Vec::new();
+        for i in 0..20 {
+            let level = if i % 2 == 0 {
+                Compression::none()
+            } else {
+                Compression::best()
+            };
+            file.extend(gzip(&fake, level));
+            v.extend(&fake);
+        }
+        assert_eq!(par_decode(&file, 4).unwrap(), v);
+    }
+
+    #[test]
+    fn decode_large_member() {
+        let big = crate::random_bytes().take(3 << 20).collect::<Vec<_>>();
+        let mut file = gzip(b"small", Compression::default());
+        file.extend(gzip(&big, Compression::default()));
+        file.extend(gzip(b"small", Compression::default()));
+        let mut v = b"small".to_vec();
+        v.extend(&big);
+        v.extend(b"small");
+        assert!(par_decode(&file, 1).unwrap() == v);
+    }
+
+    #[test]
+    fn decode_highly_compressed_members() {
+        let v = sample();
+        let member = gzip(&v, Compression::best());
+        assert!(inflate_member(&member, DecompressLimits::new()) == Some(v));
+
+        let zeros = vec![0; MAX_MEMBER_OUTPUT + 1];
+        let member = gzip(&zeros, Compression::default());
+        assert!(inflate_member(&member, DecompressLimits::new()).is_none());
+
+        let mut file = gzip(b"small", Compression::default());
+        file.extend(&member);
+        file.extend(&member);
+        file.extend(gzip(b"small", Compression::default()));
+        let mut v = b"small".to_vec();
+        v.extend(&zeros);
+        v.extend(&zeros);
+        v.extend(b"small");
+        assert!(par_decode(&file, 4).unwrap() == v);
+    }
+
+    #[test]
+    fn decode_matches_serial_errors() {
+        let mut file = gzip(b"hello", Compression::default());
+        file.extend(gzip(b"world", Compression::default()));
+        assert_eq!(par_decode(&file, 2).unwrap(), b"helloworld");
+
+        let mut corrupt = file.clone();
+        let n = corrupt.len();
+        corrupt[n - 6] ^= 1;
+        assert!(par_decode(&corrupt, 2).is_err());
+        assert!(par_decode(&file[..file.len() - 3], 2).is_err());
+
+        let mut trailing = file.clone();
+        trailing.extend(b"garbage");
+        assert!(par_decode(&trailing, 2).is_err());
+        assert!(MultiGzDecoder::new(&trailing[..])
+            .read_to_end(&mut Vec::new())
+            .is_err());
+
+        assert!(par_decode(&[], 2).is_err());
+        assert!(MultiGzDecoder::new(&[][..])
+            .read_to_end(&mut Vec::new())
+            .is_err());
+    }
+
+    #[test]
+    fn decode_limits() {
+        let mut file = Vec::new();
+        for _ in 0..10 {
+            file.extend(gzip(&[0; 100_000], Compression::default()));
+        }
+        for &threads in &[1, 4] {
+            let mut d = ParMultiGzDecoder::new_with_threads(&file[..], threads);
+            d.set_limits(DecompressLimits::new().max_output(1_000_000));
+            let mut v = Vec::new();
+            d.read_to_end(&mut v).unwrap();
+            assert_eq!(v.len(), 1_000_000);
+
+            let mut d = ParMultiGzDecoder::new_with_threads(&file[..], threads);
+            d.set_limits(DecompressLimits::new().max_output(550_000));
+            let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+            let limit = err.get_ref().unwrap().downcast_ref::<LimitExceeded>();
+            assert_eq!(limit, Some(&LimitExceeded::Output(550_000)));
+        }
+    }
+}
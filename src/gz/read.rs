@@ -7,9 +7,9 @@ use futures::Poll;
 use tokio_io::{AsyncRead, AsyncWrite};
 
 use super::bufread;
-use super::{GzBuilder, GzHeader};
+use super::{GzBuilder, GzHeader, GzMemberInfo};
 use crate::bufreader::BufReader;
-use crate::Compression;
+use crate::{CompressOptions, Compression, DecompressLimits};
 
 /// A gzip streaming encoder
 ///
@@ -57,6 +57,15 @@ impl<R: Read> GzEncoder<R> {
     pub fn new(r: R, level: Compression) -> GzEncoder<R> {
         GzBuilder::new().read(r, level)
     }
+
+    /// Creates a new encoder like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(r: R, options: &CompressOptions) -> io::Result<GzEncoder<R>> {
+        GzBuilder::new().read_with_options(r, options)
+    }
 }
 
 impl<R> GzEncoder<R> {
@@ -134,12 +143,24 @@ pub struct GzDecoder<R> {
     inner: bufread::GzDecoder<BufReader<R>>,
 }
 
+pub fn gz_decoder<R: Read>(inner: bufread::GzDecoder<BufReader<R>>) -> GzDecoder<R> {
+    GzDecoder { inner }
+}
+
 impl<R: Read> GzDecoder<R> {
     /// Creates a new decoder from the given reader, immediately parsing the
     /// gzip header.
     pub fn new(r: R) -> GzDecoder<R> {
+        GzDecoder::new_with_buf(r, vec![0; 32 * 1024])
+    }
+
+    /// Same as `new`, but the intermediate buffer for data is specified.
+    ///
+    /// Note that the capacity of the intermediate buffer is never increased,
+    /// and it is recommended for it to be large.
+    pub fn new_with_buf(r: R, buf: Vec<u8>) -> GzDecoder<R> {
         GzDecoder {
-            inner: bufread::GzDecoder::new(BufReader::new(r)),
+            inner: bufread::GzDecoder::new(BufReader::with_buf(buf, r)),
         }
     }
 }
@@ -150,6 +171,17 @@ impl<R> GzDecoder<R> {
         self.inner.header()
     }
 
+    /// Returns the number of bytes of the compressed stream consumed so far,
+    /// including the header, the trailer and any zero padding which was
+    /// skipped.
+    ///
+    /// This doesn't count input which was read ahead into the decoder's
+    /// buffer but not decoded yet, so once the end of the stream has been
+    /// reached this is the exact length of the gzip data.
+    pub fn total_in(&self) -> u64 {
+        self.inner.total_in()
+    }
+
     /// Acquires a reference to the underlying reader.
     pub fn get_ref(&self) -> &R {
         self.inner.get_ref().get_ref()
@@ -167,6 +199,47 @@ impl<R> GzDecoder<R> {
     pub fn into_inner(self) -> R {
         self.inner.into_inner().into_inner()
     }
+
+    /// Consumes this decoder, returning the underlying reader along with the
+    /// bytes which were read from it but not decompressed.
+    ///
+    /// After the end of the stream has been reached, these bytes followed by
+    /// the rest of the underlying reader are exactly the data following the
+    /// compressed stream. To leave the underlying reader positioned right
+    /// after the stream instead, create the decoder with `new_with_buf` and a
+    /// one byte buffer, at the cost of reading the input a byte at a time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::prelude::*;
+    /// use flate2::Compression;
+    /// use flate2::read::GzDecoder;
+    /// use flate2::write::GzEncoder;
+    ///
+    /// let mut e = GzEncoder::new(Vec::new(), Compression::default());
+    /// e.write_all(b"Hello World").unwrap();
+    /// let mut bytes = e.finish().unwrap();
+    /// bytes.extend_from_slice(b"more data");
+    ///
+    /// let mut d = GzDecoder::new(&bytes[..]);
+    /// let mut s = String::new();
+    /// d.read_to_string(&mut s).unwrap();
+    /// let (rest, buffered) = d.into_parts();
+    /// assert_eq!(buffered, b"more data");
+    /// assert!(rest.is_empty());
+    /// ```
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        self.inner.into_inner().into_parts()
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
 }
 
 impl<R: Read> Read for GzDecoder<R> {
@@ -240,6 +313,12 @@ pub struct MultiGzDecoder<R> {
     inner: bufread::MultiGzDecoder<BufReader<R>>,
 }
 
+pub fn multi_gz_decoder<R: Read>(
+    inner: bufread::MultiGzDecoder<BufReader<R>>,
+) -> MultiGzDecoder<R> {
+    MultiGzDecoder { inner }
+}
+
 impl<R: Read> MultiGzDecoder<R> {
     /// Creates a new decoder from the given reader, immediately parsing the
     /// (first) gzip header. If the gzip stream contains multiple members all will
@@ -257,6 +336,15 @@ impl<R> MultiGzDecoder<R> {
         self.inner.header()
     }
 
+    /// Returns the number of bytes of the compressed stream consumed so far,
+    /// including headers, trailers and any zero padding which was skipped.
+    ///
+    /// This doesn't count input which was read ahead into the decoder's
+    /// buffer but not decoded yet.
+    pub fn total_in(&self) -> u64 {
+        self.inner.total_in()
+    }
+
     /// Acquires a reference to the underlying reader.
     pub fn get_ref(&self) -> &R {
         self.inner.get_ref().get_ref()
@@ -274,6 +362,64 @@ impl<R> MultiGzDecoder<R> {
     pub fn into_inner(self) -> R {
         self.inner.into_inner().into_inner()
     }
+
+    /// Consumes this decoder, returning the underlying reader along with the
+    /// bytes which were read from it but not decompressed.
+    ///
+    /// After the end of the stream has been reached, these bytes followed by
+    /// the rest of the underlying reader are exactly the data following the
+    /// compressed stream, see also [`TrailingData::Stop`].
+    ///
+    /// [`TrailingData::Stop`]: ../enum.TrailingData.html#variant.Stop
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        self.inner.into_inner().into_parts()
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
+}
+
+impl<R: Read> MultiGzDecoder<R> {
+    /// Returns the next member of the stream, or `None` once all members
+    /// have been decoded.
+    ///
+    /// The first call returns the member being decoded. From then on reading
+    /// from this decoder stops at the end of each member, and whatever is
+    /// left of a member is skipped and verified when moving on to the next.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::prelude::*;
+    /// use flate2::Compression;
+    /// use flate2::read::MultiGzDecoder;
+    /// use flate2::write::GzEncoder;
+    ///
+    /// let mut bytes = Vec::new();
+    /// for part in &["first", "second"] {
+    ///     let mut e = GzEncoder::new(Vec::new(), Compression::default());
+    ///     e.write_all(part.as_bytes()).unwrap();
+    ///     bytes.extend(e.finish().unwrap());
+    /// }
+    ///
+    /// let mut gz = MultiGzDecoder::new(&bytes[..]);
+    /// let mut parts = Vec::new();
+    /// while let Some(mut member) = gz.next_member().unwrap() {
+    ///     let mut data = String::new();
+    ///     member.read_to_string(&mut data).unwrap();
+    ///     assert_eq!(member.info().unwrap().size() as usize, data.len());
+    ///     parts.push(data);
+    /// }
+    /// assert_eq!(parts, ["first", "second"]);
+    /// ```
+    pub fn next_member(&mut self) -> io::Result<Option<GzMember<'_, R>>> {
+        Ok(self.inner.next_member()?.map(|inner| GzMember { inner }))
+    }
 }
 
 impl<R: Read> Read for MultiGzDecoder<R> {
@@ -301,3 +447,38 @@ impl<R: AsyncWrite + AsyncRead> AsyncWrite for MultiGzDecoder<R> {
         self.get_mut().shutdown()
     }
 }
+
+/// A single member of a multi-member gzip stream, returned by
+/// [`MultiGzDecoder::next_member`].
+///
+/// Reading from this yields the decompressed data of the member only.
+///
+/// [`MultiGzDecoder::next_member`]: struct.MultiGzDecoder.html#method.next_member
+#[derive(Debug)]
+pub struct GzMember<'a, R: 'a> {
+    inner: bufread::GzMember<'a, BufReader<R>>,
+}
+
+impl<'a, R> GzMember<'a, R> {
+    /// Returns the header of this member.
+    pub fn header(&self) -> &GzHeader {
+        self.inner.header()
+    }
+
+    /// Returns the offset of this member in the compressed stream.
+    pub fn start(&self) -> u64 {
+        self.inner.start()
+    }
+
+    /// Returns the position and checksums of this member once all of its
+    /// data has been read and its trailer has been verified.
+    pub fn info(&self) -> Option<&GzMemberInfo> {
+        self.inner.info()
+    }
+}
+
+impl<'a, R: Read> Read for GzMember<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(into)
+    }
+}
@@ -0,0 +1,592 @@
+//! Salvaging data from damaged gzip and DEFLATE streams.
+//!
+//! After an error, decoding resumes at the next point from which the stream
+//! can be decoded on its own: either the start of a gzip member, or the end
+//! of the empty stored block `00 00 ff ff` written by a full flush, followed
+//! by data which decodes without error. The bytes skipped over are reported
+//! as damaged regions.
+
+use std::cmp;
+use std::io;
+use std::io::prelude::*;
+use std::ops::Range;
+
+use super::bufread::{check_trailer, read_gz_header};
+use crate::crc::Crc;
+use crate::{Decompress, DecompressError, DecompressErrorKind, FlushDecompress, Status};
+
+/// The amount of data following a candidate resynchronization point which has
+/// to decode without error for decoding to resume there.
+const TRIAL_LEN: usize = 4 * 1024;
+
+/// How far ahead of a candidate point data is buffered, leaving room for the
+/// gzip header preceding the trial data.
+const LOOKAHEAD: usize = 16 * 1024;
+
+const FLUSH_MARKER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// What a `SalvageDecoder` emits in place of the data lost in a damaged
+/// region.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Gaps {
+    /// Emit nothing, so that the data after a damaged region directly follows
+    /// the data before it. This is the default.
+    Skip,
+
+    /// Emit zeros in place of the lost data, with a length estimated from the
+    /// compression ratio of the data decoded so far. This keeps data roughly
+    /// in place, but the estimate is rarely exact.
+    ///
+    /// Gaps at the end of the input are never filled.
+    Zeros,
+
+    #[doc(hidden)]
+    _Nonexhaustive,
+}
+
+/// A region of a stream which couldn't be decoded, or a gzip member whose
+/// trailer didn't match its data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DamagedRegion {
+    compressed: Range<u64>,
+    uncompressed: Range<u64>,
+    kind: DecompressErrorKind,
+}
+
+impl DamagedRegion {
+    /// Returns the range of compressed bytes which were skipped.
+    ///
+    /// For a checksum or length mismatch, this is the whole gzip member.
+    pub fn compressed(&self) -> Range<u64> {
+        self.compressed.clone()
+    }
+
+    /// Returns the range of the decoder's output corresponding to this region.
+    ///
+    /// When gaps are skipped this is an empty range at the point where data
+    /// is missing, and when they are filled it covers the zeros emitted. For
+    /// a checksum or length mismatch it covers the data of the member.
+    pub fn uncompressed(&self) -> Range<u64> {
+        self.uncompressed.clone()
+    }
+
+    /// Returns the error which was found at the start of this region.
+    pub fn kind(&self) -> DecompressErrorKind {
+        self.kind
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Header,
+    Body,
+    Trailer,
+    Scan,
+    Done,
+}
+
+/// A decoder which recovers as much data as possible from a damaged gzip or
+/// raw DEFLATE stream.
+///
+/// Instead of failing, a decoding error, a truncated stream or data which
+/// isn't a gzip member makes the decoder skip ahead to the next point where
+/// decoding can resume, recording the skipped bytes as a
+/// [`DamagedRegion`]. Gzip members which decode but whose trailer doesn't
+/// match their data are also recorded, after their data has been returned.
+///
+/// Compressors don't usually write full flush points, so within a damaged
+/// gzip member the rest of the member is often lost, and decoding resumes at
+/// the next member.
+///
+/// [`DamagedRegion`]: struct.DamagedRegion.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::Compression;
+/// use flate2::salvage::SalvageDecoder;
+/// use flate2::write::GzEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut bytes = Vec::new();
+/// for part in &["damaged member\n", "intact member\n"] {
+///     let mut e = GzEncoder::new(Vec::new(), Compression::default());
+///     e.write_all(part.as_bytes())?;
+///     bytes.extend(e.finish()?);
+/// }
+/// bytes[12] ^= 0xff;
+///
+/// let mut d = SalvageDecoder::new(&bytes[..]);
+/// let mut s = String::new();
+/// d.read_to_string(&mut s)?;
+/// assert!(s.ends_with("intact member\n"));
+/// assert_eq!(d.damage().len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SalvageDecoder<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+    base: u64,
+    eof: bool,
+    gzip: bool,
+    data: Decompress,
+    state: State,
+    crc: Crc,
+    member_start: u64,
+    member_out: u64,
+    member_damaged: bool,
+    out: u64,
+    zeros: u64,
+    gaps: Gaps,
+    good_in: u64,
+    good_out: u64,
+    damage_start: u64,
+    damage_kind: DecompressErrorKind,
+    scan_from: u64,
+    damage: Vec<DamagedRegion>,
+}
+
+impl<R: Read> SalvageDecoder<R> {
+    /// Creates a new decoder salvaging the gzip stream read from `r`, which
+    /// may consist of several members.
+    pub fn new(r: R) -> SalvageDecoder<R> {
+        SalvageDecoder::make(r, true)
+    }
+
+    /// Creates a new decoder salvaging the raw DEFLATE stream read from `r`.
+    pub fn new_deflate(r: R) -> SalvageDecoder<R> {
+        SalvageDecoder::make(r, false)
+    }
+
+    fn make(r: R, gzip: bool) -> SalvageDecoder<R> {
+        SalvageDecoder {
+            inner: r,
+            buf: Vec::new(),
+            pos: 0,
+            base: 0,
+            eof: false,
+            gzip,
+            data: Decompress::new(false),
+            state: if gzip { State::Header } else { State::Body },
+            crc: Crc::new(),
+            member_start: 0,
+            member_out: 0,
+            member_damaged: false,
+            out: 0,
+            zeros: 0,
+            gaps: Gaps::Skip,
+            good_in: 0,
+            good_out: 0,
+            damage_start: 0,
+            damage_kind: DecompressErrorKind::Corrupt,
+            scan_from: 0,
+            damage: Vec::new(),
+        }
+    }
+
+    /// Configures what is emitted in place of lost data, see [`Gaps`].
+    ///
+    /// [`Gaps`]: enum.Gaps.html
+    pub fn set_gaps(&mut self, gaps: Gaps) {
+        self.gaps = gaps;
+    }
+
+    /// Returns the damaged regions found so far, in stream order.
+    pub fn damage(&self) -> &[DamagedRegion] {
+        &self.damage
+    }
+
+    /// Acquires a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Acquires a mutable reference to the underlying reader.
+    ///
+    /// Note that mutation of the reader may result in surprising results if
+    /// this decoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    fn offset(&self) -> u64 {
+        self.base + self.pos as u64
+    }
+
+    /// Buffers data until at least `want` bytes are available or the input
+    /// ends.
+    fn fill(&mut self, want: usize) -> io::Result<()> {
+        while self.buf.len() - self.pos < want && !self.eof {
+            if self.pos > 0 && self.pos >= self.buf.len() / 2 {
+                self.buf.drain(..self.pos);
+                self.base += self.pos as u64;
+                self.pos = 0;
+            }
+            let len = self.buf.len();
+            self.buf.resize(len + 32 * 1024, 0);
+            let res = self.inner.read(&mut self.buf[len..]);
+            let n = *res.as_ref().unwrap_or(&0);
+            self.buf.truncate(len + n);
+            match res {
+                Ok(0) => self.eof = true,
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn header(&mut self) -> io::Result<()> {
+        self.fill(LOOKAHEAD)?;
+        let mut input = &self.buf[self.pos..];
+        if input.is_empty() {
+            self.state = State::Done;
+            return Ok(());
+        }
+        let len = input.len();
+        match read_gz_header(&mut input) {
+            Ok(_) => {
+                self.member_start = self.offset();
+                self.member_out = self.out;
+                self.member_damaged = false;
+                self.pos += len - input.len();
+                self.crc.reset();
+                self.data.reset(false);
+                self.state = State::Body;
+            }
+            Err(e) => {
+                self.damaged(error_kind(&e));
+                self.scan_from += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn body(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        self.fill(1)?;
+        let (before_in, before_out) = (self.data.total_in(), self.data.total_out());
+        let res = self
+            .data
+            .decompress(&self.buf[self.pos..], into, FlushDecompress::None);
+        let consumed = (self.data.total_in() - before_in) as usize;
+        let produced = (self.data.total_out() - before_out) as usize;
+        self.pos += consumed;
+        self.crc.update(&into[..produced]);
+        self.out += produced as u64;
+        self.good_in += consumed as u64;
+        self.good_out += produced as u64;
+        match res {
+            Ok(Status::StreamEnd) if self.gzip => self.state = State::Trailer,
+            Ok(Status::StreamEnd) => self.state = State::Done,
+            Ok(_) if consumed == 0 && produced == 0 => {
+                if self.eof {
+                    self.damaged(DecompressErrorKind::TruncatedInput);
+                } else {
+                    let want = self.buf.len() - self.pos + 1;
+                    self.fill(want)?;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => self.damaged(e.kind()),
+        }
+        Ok(produced)
+    }
+
+    fn trailer(&mut self) -> io::Result<()> {
+        self.fill(8)?;
+        if self.buf.len() - self.pos < 8 {
+            self.damaged(DecompressErrorKind::TruncatedInput);
+            return Ok(());
+        }
+        let offset = self.offset();
+        let t = &self.buf[self.pos..self.pos + 8];
+        let sum = u32::from_le_bytes([t[0], t[1], t[2], t[3]]);
+        let amt = u32::from_le_bytes([t[4], t[5], t[6], t[7]]);
+        self.pos += 8;
+        if !self.member_damaged {
+            if let Err(e) = check_trailer(sum, amt, &self.crc, offset) {
+                self.damage.push(DamagedRegion {
+                    compressed: self.member_start..offset + 8,
+                    uncompressed: self.member_out..self.out,
+                    kind: error_kind(&e),
+                });
+            }
+        }
+        self.state = State::Header;
+        Ok(())
+    }
+
+    fn damaged(&mut self, kind: DecompressErrorKind) {
+        self.damage_start = self.offset();
+        self.damage_kind = kind;
+        self.scan_from = self.damage_start;
+        self.member_damaged = true;
+        self.state = State::Scan;
+    }
+
+    fn scan(&mut self) -> io::Result<()> {
+        loop {
+            self.pos = (self.scan_from - self.base) as usize;
+            self.fill(LOOKAHEAD + FLUSH_MARKER.len())?;
+            let (found, scanned) = find_resume_point(&self.buf[self.pos..], self.gzip, self.eof);
+            if let Some((i, state)) = found {
+                let at = self.pos + i;
+                self.resume(at, state);
+                return Ok(());
+            }
+            self.scan_from += scanned as u64;
+            if self.eof {
+                let at = self.buf.len();
+                self.resume(at, State::Done);
+                return Ok(());
+            }
+        }
+    }
+
+    /// Records the current damaged region as ending at `at` in the buffer and
+    /// resumes decoding there.
+    fn resume(&mut self, at: usize, state: State) {
+        let end = self.base + at as u64;
+        let skipped = end - self.damage_start;
+        let fill = match (self.gaps, &state) {
+            (_, State::Done) => 0,
+            (Gaps::Zeros, _) if self.good_in == 0 => skipped,
+            (Gaps::Zeros, _) => {
+                (u128::from(skipped) * u128::from(self.good_out) / u128::from(self.good_in)) as u64
+            }
+            _ => 0,
+        };
+        self.damage.push(DamagedRegion {
+            compressed: self.damage_start..end,
+            uncompressed: self.out..self.out + fill,
+            kind: self.damage_kind,
+        });
+        self.zeros = fill;
+        self.pos = at;
+        if let State::Body = state {
+            self.data.reset(false);
+        }
+        self.state = state;
+    }
+}
+
+/// Looks for the first point in `buf` where decoding can resume, returning
+/// its position and the state to resume in, along with how many bytes were
+/// ruled out. Candidates are only tried with enough data after them, unless
+/// the input has ended.
+fn find_resume_point(buf: &[u8], gzip: bool, eof: bool) -> (Option<(usize, State)>, usize) {
+    let mut i = 0;
+    while i < buf.len() && (eof || i + LOOKAHEAD <= buf.len()) {
+        let rest = &buf[i..];
+        if gzip && rest.starts_with(&[0x1f, 0x8b, 0x08]) {
+            let mut input = rest;
+            if read_gz_header(&mut input).is_ok() && decodes(input) {
+                return (Some((i, State::Header)), i);
+            }
+        }
+        if rest.starts_with(&FLUSH_MARKER) && decodes(&rest[FLUSH_MARKER.len()..]) {
+            return (Some((i + FLUSH_MARKER.len(), State::Body)), i);
+        }
+        i += 1;
+    }
+    (None, i)
+}
+
+/// Returns whether the start of `input` decodes as raw DEFLATE data without
+/// error.
+fn decodes(input: &[u8]) -> bool {
+    let input = &input[..cmp::min(input.len(), TRIAL_LEN)];
+    let mut data = Decompress::new(false);
+    let mut scratch = vec![0; 32 * 1024];
+    loop {
+        let consumed = data.total_in() as usize;
+        match data.decompress(&input[consumed..], &mut scratch, FlushDecompress::None) {
+            Ok(Status::StreamEnd) => return true,
+            Ok(_) if data.total_in() as usize == input.len() => return !input.is_empty(),
+            Ok(_) if data.total_in() as usize == consumed => return false,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+}
+
+fn error_kind(e: &io::Error) -> DecompressErrorKind {
+    match e
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<DecompressError>())
+    {
+        Some(e) => e.kind(),
+        None if e.kind() == io::ErrorKind::UnexpectedEof => DecompressErrorKind::TruncatedInput,
+        None => DecompressErrorKind::InvalidHeader,
+    }
+}
+
+impl<R: Read> Read for SalvageDecoder<R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        if into.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.zeros > 0 {
+                let n = cmp::min(into.len() as u64, self.zeros) as usize;
+                for b in &mut into[..n] {
+                    *b = 0;
+                }
+                self.zeros -= n as u64;
+                self.out += n as u64;
+                return Ok(n);
+            }
+            match self.state {
+                State::Header => self.header()?,
+                State::Body => {
+                    let n = self.body(into)?;
+                    if n > 0 {
+                        return Ok(n);
+                    }
+                }
+                State::Trailer => self.trailer()?,
+                State::Scan => self.scan()?,
+                State::Done => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::prelude::*;
+
+    use super::{Gaps, SalvageDecoder};
+    use crate::write::GzEncoder;
+    use crate::{Compress, Compression, DecompressErrorKind, FlushCompress};
+
+    fn chunks() -> Vec<Vec<u8>> {
+        (0..6)
+            .map(|i| {
+                (0..2000)
+                    .flat_map(|j| format!("chunk {} line {}\n", i, j).into_bytes())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut e = GzEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    fn salvage(mut d: SalvageDecoder<&[u8]>) -> (Vec<u8>, SalvageDecoder<&[u8]>) {
+        let mut out = Vec::new();
+        d.read_to_end(&mut out).unwrap();
+        (out, d)
+    }
+
+    #[test]
+    fn skip_damaged_member() {
+        let chunks = chunks();
+        let first = gzip(&chunks[0]);
+        let mut bytes = first.clone();
+        bytes.extend(gzip(&chunks[1]));
+        // A final block of the reserved type 3.
+        bytes[10] = 0x07;
+
+        let (out, d) = salvage(SalvageDecoder::new(&bytes[..]));
+        assert!(out.ends_with(&chunks[1]));
+        assert_eq!(d.damage().len(), 1);
+        let region = &d.damage()[0];
+        assert_eq!(region.compressed().end, first.len() as u64);
+        assert!(region.compressed().start < 20);
+        assert_eq!(
+            region.uncompressed(),
+            out.len() as u64 - chunks[1].len() as u64..out.len() as u64 - chunks[1].len() as u64
+        );
+    }
+
+    #[test]
+    fn resume_at_full_flush() {
+        let chunks = chunks();
+        let mut c = Compress::new(Compression::default(), false);
+        let mut bytes = Vec::with_capacity(1 << 20);
+        let mut ends = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let flush = if i + 1 == chunks.len() {
+                FlushCompress::Finish
+            } else {
+                FlushCompress::Full
+            };
+            let start = c.total_in();
+            while c.total_in() - start < chunk.len() as u64 || bytes.len() == bytes.capacity() {
+                let pos = (c.total_in() - start) as usize;
+                c.compress_vec(&chunk[pos..], &mut bytes, flush).unwrap();
+            }
+            c.compress_vec(&[], &mut bytes, flush).unwrap();
+            ends.push(bytes.len());
+        }
+        let expected = chunks.concat();
+        let (out, d) = salvage(SalvageDecoder::new_deflate(&bytes[..]));
+        assert_eq!(out, expected);
+        assert!(d.damage().is_empty());
+
+        bytes[ends[1]] = 0x06;
+        let (out, d) = salvage(SalvageDecoder::new_deflate(&bytes[..]));
+        let tail = chunks[3..].concat();
+        assert!(out.starts_with(&chunks[0]));
+        assert!(out.ends_with(&tail));
+        assert_eq!(d.damage().len(), 1);
+        let region = d.damage()[0].compressed();
+        assert!(region.start >= ends[1] as u64 && region.end <= ends[2] as u64);
+
+        let mut d = SalvageDecoder::new_deflate(&bytes[..]);
+        d.set_gaps(Gaps::Zeros);
+        let (out, d) = salvage(d);
+        assert!(out.ends_with(&tail));
+        let gap = d.damage()[0].uncompressed();
+        assert!(gap.end > gap.start);
+        assert!(out[gap.start as usize..gap.end as usize]
+            .iter()
+            .all(|&b| b == 0));
+        assert_eq!(gap.end, (out.len() - tail.len()) as u64);
+    }
+
+    #[test]
+    fn report_checksum_and_truncation() {
+        let chunks = chunks();
+        let mut bytes = gzip(&chunks[0]);
+        let len = bytes.len();
+        bytes[len - 8] ^= 1;
+
+        let (out, d) = salvage(SalvageDecoder::new(&bytes[..]));
+        assert_eq!(out, chunks[0]);
+        assert_eq!(d.damage().len(), 1);
+        assert_eq!(d.damage()[0].compressed(), 0..len as u64);
+        assert_eq!(d.damage()[0].uncompressed(), 0..chunks[0].len() as u64);
+        match d.damage()[0].kind() {
+            DecompressErrorKind::ChecksumMismatch { .. } => {}
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+
+        let (out, d) = salvage(SalvageDecoder::new(&bytes[..len - 4]));
+        assert_eq!(out, chunks[0]);
+        assert_eq!(d.damage().len(), 1);
+        assert_eq!(d.damage()[0].kind(), DecompressErrorKind::TruncatedInput);
+        assert_eq!(d.damage()[0].compressed(), len as u64 - 8..len as u64 - 4);
+    }
+
+    #[test]
+    fn salvage_corrupt_file() {
+        let bytes = std::fs::read("tests/corrupt-file.gz").unwrap();
+        let (out, d) = salvage(SalvageDecoder::new(&bytes[..]));
+        // Depending on the backend the damage is either found while decoding
+        // or only at the trailer, but it extends to the end either way.
+        assert!(!out.is_empty());
+        assert_eq!(d.damage().len(), 1);
+        assert_eq!(d.damage()[0].compressed().end, bytes.len() as u64);
+        assert_eq!(d.damage()[0].uncompressed().end, out.len() as u64);
+    }
+}
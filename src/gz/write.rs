@@ -7,11 +7,13 @@ use futures::Poll;
 #[cfg(feature = "tokio")]
 use tokio_io::{AsyncRead, AsyncWrite};
 
-use super::bufread::{corrupt, read_gz_header};
-use super::{GzBuilder, GzHeader};
+use super::bufread::{check_trailer, corrupt, read_gz_header_with, HeaderLimits};
+use super::{GzBuilder, GzDecompress, GzHeader, GzMemberInfo};
 use crate::crc::{Crc, CrcWriter};
+use crate::mem::add_offset;
 use crate::zio;
-use crate::{Compress, Compression, Decompress, Status};
+use crate::{Compress, CompressOptions, Compression, Decompress, DecompressErrorKind};
+use crate::{DecompressLimits, Status};
 
 /// A gzip streaming encoder
 ///
@@ -43,9 +45,9 @@ pub struct GzEncoder<W: Write> {
     header: Vec<u8>,
 }
 
-pub fn gz_encoder<W: Write>(header: Vec<u8>, w: W, lvl: Compression) -> GzEncoder<W> {
+pub fn gz_encoder<W: Write>(header: Vec<u8>, w: W, data: Compress) -> GzEncoder<W> {
     GzEncoder {
-        inner: zio::Writer::new(w, Compress::new(lvl, false)),
+        inner: zio::Writer::new(w, data),
         crc: Crc::new(),
         header: header,
         crc_bytes_written: 0,
@@ -64,6 +66,15 @@ impl<W: Write> GzEncoder<W> {
         GzBuilder::new().write(w, level)
     }
 
+    /// Creates a new encoder like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(w: W, options: &CompressOptions) -> io::Result<GzEncoder<W>> {
+        GzBuilder::new().write_with_options(w, options)
+    }
+
     /// Acquires a reference to the underlying writer.
     pub fn get_ref(&self) -> &W {
         self.inner.get_ref()
@@ -183,6 +194,282 @@ impl<W: Write> Drop for GzEncoder<W> {
     }
 }
 
+/// A gzip streaming encoder which splits its output into several members
+///
+/// This structure exposes a [`Write`] interface that will emit compressed data
+/// to the underlying writer `W` as a sequence of complete gzip members, which
+/// together decode as a single stream with [`read::MultiGzDecoder`]. Each
+/// member is independently decodable, so a truncated copy of the output can
+/// still be decoded up to the end of the last complete member.
+///
+/// A member is ended explicitly with [`finish_member`], or automatically
+/// once it has consumed or produced a configured number of bytes. The next
+/// member is only started when more data is written, so no empty members are
+/// emitted. The position of each finished member in the output is reported by
+/// [`members`].
+///
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`read::MultiGzDecoder`]: ../read/struct.MultiGzDecoder.html
+/// [`finish_member`]: #method.finish_member
+/// [`members`]: #method.members
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, GzBuilder};
+/// use flate2::read::MultiGzDecoder;
+/// use flate2::write::MultiGzEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut e = MultiGzEncoder::new(Vec::new(), Compression::default());
+/// e.write_all(b"first batch\n")?;
+/// e.finish_member()?;
+/// e.set_member_header(GzBuilder::new().filename("second"));
+/// e.write_all(b"second batch\n")?;
+/// e.try_finish()?;
+///
+/// let boundary = e.members()[0].end() as usize;
+/// let bytes = e.finish()?;
+///
+/// // Everything up to a member boundary can be decoded on its own.
+/// let mut s = String::new();
+/// MultiGzDecoder::new(&bytes[..boundary]).read_to_string(&mut s)?;
+/// assert_eq!(s, "first batch\n");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MultiGzEncoder<W: Write> {
+    inner: GzEncoder<W>,
+    header: Vec<u8>,
+    next: Option<Vec<u8>>,
+    level: Compression,
+    between: bool,
+    start: u64,
+    header_len: u64,
+    members: Vec<GzMemberInfo>,
+    max_in: u64,
+    max_out: u64,
+}
+
+pub(crate) fn multi_gz_encoder<W: Write>(
+    header: Vec<u8>,
+    w: W,
+    level: Compression,
+) -> MultiGzEncoder<W> {
+    MultiGzEncoder {
+        inner: gz_encoder(header.clone(), w, Compress::new(level, false)),
+        header_len: header.len() as u64,
+        header: header,
+        next: None,
+        level: level,
+        between: false,
+        start: 0,
+        members: Vec::new(),
+        max_in: u64::max_value(),
+        max_out: u64::max_value(),
+    }
+}
+
+impl<W: Write> MultiGzEncoder<W> {
+    /// Creates a new encoder which will use the given compression level.
+    ///
+    /// Every member gets a blank header. To configure the header of all
+    /// members use [`GzBuilder::write_multi`], and for a single member use
+    /// [`set_member_header`].
+    ///
+    /// [`GzBuilder::write_multi`]: ../struct.GzBuilder.html#method.write_multi
+    /// [`set_member_header`]: #method.set_member_header
+    pub fn new(w: W, level: Compression) -> MultiGzEncoder<W> {
+        GzBuilder::new().write_multi(w, level)
+    }
+
+    /// Configures the header of the next member with the given builder,
+    /// instead of the header this encoder was created with.
+    ///
+    /// If nothing has been written to the current member yet, the header
+    /// applies to the current member.
+    pub fn set_member_header(&mut self, builder: GzBuilder) {
+        let header = builder.into_header(self.level);
+        if !self.between && self.untouched() {
+            self.header_len = header.len() as u64;
+            self.inner.header = header;
+        } else {
+            self.next = Some(header);
+        }
+    }
+
+    /// Configures the encoder to finish a member once that many bytes of
+    /// uncompressed data have been written to it, or never if `None`.
+    ///
+    /// A limit of zero is treated as a limit of one byte.
+    pub fn set_max_member_in(&mut self, bytes: Option<u64>) {
+        self.max_in = bytes.map_or(u64::max_value(), |n| cmp::max(n, 1));
+    }
+
+    /// Configures the encoder to finish a member once it has produced at
+    /// least that many bytes of compressed data, or never if `None`.
+    ///
+    /// The compressor buffers data internally, so members usually exceed this
+    /// size by up to the amount produced by a single write.
+    pub fn set_max_member_out(&mut self, bytes: Option<u64>) {
+        self.max_out = bytes.unwrap_or(u64::max_value());
+    }
+
+    /// Returns the positions and checksums of the members finished so far.
+    ///
+    /// The `end` of each member is a point at which the output can be cut and
+    /// still decode, and is where the next member starts.
+    pub fn members(&self) -> &[GzMemberInfo] {
+        &self.members
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutation of the writer may result in surprising results if
+    /// this encoder is continued to be used.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    /// Finishes the current member, writing out its trailer.
+    ///
+    /// Data written afterwards starts a new member. Nothing happens if
+    /// nothing has been written to the current member.
+    ///
+    /// Note that this doesn't flush the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete the member, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish_member(&mut self) -> io::Result<()> {
+        if self.between || self.untouched() {
+            return Ok(());
+        }
+        self.end_member()
+    }
+
+    /// Attempt to finish this output stream, writing out final chunks of data.
+    ///
+    /// This finishes the current member. If nothing was written to the
+    /// encoder at all, a single empty member is written so that the output
+    /// is a valid gzip stream.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        if self.between {
+            return Ok(());
+        }
+        self.end_member()
+    }
+
+    /// Finish encoding this stream, returning the underlying writer once the
+    /// encoding is done.
+    ///
+    /// Note that this function may not be suitable to call in a situation where
+    /// the underlying stream is an asynchronous I/O stream. To finish a stream
+    /// the `try_finish` (or `shutdown`) method should be used instead. To
+    /// re-acquire ownership of a stream it is safe to call this method after
+    /// `try_finish` or `shutdown` has returned `Ok`.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.inner.take_inner())
+    }
+
+    fn untouched(&self) -> bool {
+        !self.inner.header.is_empty() && self.inner.inner.data.total_in() == 0
+    }
+
+    fn member_full(&self) -> bool {
+        let data = &self.inner.inner.data;
+        data.total_in() >= self.max_in || self.header_len + data.total_out() >= self.max_out
+    }
+
+    fn end_member(&mut self) -> io::Result<()> {
+        self.inner.try_finish()?;
+        let end = self.start + self.header_len + self.inner.inner.data.total_out() + 8;
+        self.members.push(GzMemberInfo {
+            start: self.start,
+            end: end,
+            crc: self.inner.crc.sum(),
+            size: self.inner.crc.amount(),
+        });
+        self.start = end;
+        self.between = true;
+        Ok(())
+    }
+
+    fn begin_member(&mut self) {
+        let header = match self.next.take() {
+            Some(header) => header,
+            None => self.header.clone(),
+        };
+        self.header_len = header.len() as u64;
+        self.inner.header = header;
+        self.inner.crc.reset();
+        self.inner.crc_bytes_written = 0;
+        self.inner.inner.data.reset();
+        self.between = false;
+    }
+}
+
+impl<W: Write> Write for MultiGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if !self.between && self.member_full() {
+            self.end_member()?;
+        }
+        if self.between {
+            self.begin_member();
+        }
+        let left = self.max_in - self.inner.inner.data.total_in();
+        let n = cmp::min(buf.len() as u64, left) as usize;
+        self.inner.write(&buf[..n])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.between {
+            self.get_mut().flush()
+        } else {
+            self.inner.flush()
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncWrite> AsyncWrite for MultiGzEncoder<W> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.try_finish()?;
+        self.get_mut().shutdown()
+    }
+}
+
+impl<R: Read + Write> Read for MultiGzEncoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.get_mut().read(buf)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + AsyncWrite> AsyncRead for MultiGzEncoder<R> {}
+
 /// A gzip streaming decoder
 ///
 /// This structure exposes a [`Write`] interface that will emit compressed data
@@ -221,22 +508,30 @@ pub struct GzDecoder<W: Write> {
     crc_bytes: Vec<u8>,
     header: Option<GzHeader>,
     header_buf: Vec<u8>,
+    header_limits: HeaderLimits,
+    header_len: u64,
 }
 
 const CRC_BYTES_LEN: usize = 8;
 
+pub(crate) fn gz_decoder<W: Write>(w: W, limits: HeaderLimits) -> GzDecoder<W> {
+    GzDecoder {
+        inner: zio::Writer::new(CrcWriter::new(w), Decompress::new(false)),
+        crc_bytes: Vec::with_capacity(CRC_BYTES_LEN),
+        header: None,
+        header_buf: Vec::new(),
+        header_limits: limits,
+        header_len: 0,
+    }
+}
+
 impl<W: Write> GzDecoder<W> {
     /// Creates a new decoder which will write uncompressed data to the stream.
     ///
     /// When this encoder is dropped or unwrapped the final pieces of data will
     /// be flushed.
     pub fn new(w: W) -> GzDecoder<W> {
-        GzDecoder {
-            inner: zio::Writer::new(CrcWriter::new(w), Decompress::new(false)),
-            crc_bytes: Vec::with_capacity(CRC_BYTES_LEN),
-            header: None,
-            header_buf: Vec::new(),
-        }
+        gz_decoder(w, HeaderLimits::default())
     }
 
     /// Returns the header associated with this stream.
@@ -257,6 +552,14 @@ impl<W: Write> GzDecoder<W> {
         self.inner.get_mut().get_mut()
     }
 
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.data.set_limits(limits);
+    }
+
     /// Attempt to finish this output stream, writing out final chunks of data.
     ///
     /// Note that this function can only be used once data has finished being
@@ -298,10 +601,17 @@ impl<W: Write> GzDecoder<W> {
     }
 
     fn finish_and_check_crc(&mut self) -> io::Result<()> {
-        self.inner.finish()?;
+        let header_len = self.header_len;
+        self.inner.finish().map_err(|e| add_offset(e, header_len))?;
 
+        let trailer = self.header_len + self.inner.data.total_in();
+        if self.header.is_none() {
+            let at = self.header_buf.len() as u64;
+            return Err(corrupt(DecompressErrorKind::TruncatedInput, at));
+        }
         if self.crc_bytes.len() != 8 {
-            return Err(corrupt());
+            let at = trailer + self.crc_bytes.len() as u64;
+            return Err(corrupt(DecompressErrorKind::TruncatedInput, at));
         }
 
         let crc = ((self.crc_bytes[0] as u32) << 0)
@@ -312,13 +622,7 @@ impl<W: Write> GzDecoder<W> {
             | ((self.crc_bytes[5] as u32) << 8)
             | ((self.crc_bytes[6] as u32) << 16)
             | ((self.crc_bytes[7] as u32) << 24);
-        if crc != self.inner.get_ref().crc().sum() as u32 {
-            return Err(corrupt());
-        }
-        if amt != self.inner.get_ref().crc().amount() {
-            return Err(corrupt());
-        }
-        Ok(())
+        check_trailer(crc, amt, self.inner.get_ref().crc(), trailer)
     }
 }
 
@@ -344,7 +648,7 @@ impl<W: Write> Write for GzDecoder<W> {
                     inner: self.header_buf.chain(buf),
                     pos: 0,
                 };
-                let res = read_gz_header(&mut counter);
+                let res = read_gz_header_with(&mut counter, self.header_limits);
                 (res, counter.pos)
             };
 
@@ -360,13 +664,18 @@ impl<W: Write> Write for GzDecoder<W> {
                 }
                 Ok(header) => {
                     self.header = Some(header);
+                    self.header_len = pos as u64;
                     let pos = pos - self.header_buf.len();
                     self.header_buf.truncate(0);
                     Ok(pos)
                 }
             }
         } else {
-            let (n, status) = self.inner.write_with_status(buf)?;
+            let header_len = self.header_len;
+            let (n, status) = self
+                .inner
+                .write_with_status(buf)
+                .map_err(|e| add_offset(e, header_len))?;
 
             if status == Status::StreamEnd {
                 if n < buf.len() && self.crc_bytes.len() < 8 {
@@ -402,6 +711,176 @@ impl<W: Read + Write> Read for GzDecoder<W> {
 #[cfg(feature = "tokio")]
 impl<W: AsyncRead + AsyncWrite> AsyncRead for GzDecoder<W> {}
 
+/// A gzip streaming decoder that decodes all members of a multistream
+///
+/// This structure exposes a [`Write`] interface that will consume all gzip
+/// members written to it and write the decompressed data to the underlying
+/// writer `W`, verifying the trailer of each member. See
+/// [`read::MultiGzDecoder`] for more about multi-member streams.
+///
+/// Data following the last member which isn't a gzip member is an error.
+///
+/// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
+/// [`read::MultiGzDecoder`]: ../read/struct.MultiGzDecoder.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, GzBuilder};
+/// use flate2::write::MultiGzDecoder;
+///
+/// let mut bytes = Vec::new();
+/// for name in &["first.txt", "second.txt"] {
+///     let mut e = GzBuilder::new()
+///         .filename(*name)
+///         .write(Vec::new(), Compression::default());
+///     e.write_all(b"Hello World\n").unwrap();
+///     bytes.extend(e.finish().unwrap());
+/// }
+///
+/// let mut d = MultiGzDecoder::new(Vec::new());
+/// d.write_all(&bytes).unwrap();
+/// d.try_finish().unwrap();
+/// assert_eq!(d.headers().len(), 2);
+/// assert_eq!(d.headers()[1].filename(), Some(&b"second.txt"[..]));
+/// let data = d.finish().unwrap();
+/// assert_eq!(data, b"Hello World\nHello World\n");
+/// ```
+#[derive(Debug)]
+pub struct MultiGzDecoder<W: Write> {
+    inner: zio::Writer<W, GzDecompress>,
+}
+
+pub(crate) fn multi_gz_decoder<W: Write>(w: W, limits: HeaderLimits) -> MultiGzDecoder<W> {
+    let data = GzDecompress::make(true, limits).keep_headers();
+    MultiGzDecoder {
+        inner: zio::Writer::new(w, data),
+    }
+}
+
+impl<W: Write> MultiGzDecoder<W> {
+    /// Creates a new decoder which will write uncompressed data to the stream.
+    ///
+    /// When this decoder is dropped or unwrapped the final pieces of data will
+    /// be flushed.
+    pub fn new(w: W) -> MultiGzDecoder<W> {
+        multi_gz_decoder(w, HeaderLimits::default())
+    }
+
+    /// Returns the header of the member currently being decoded, once it has
+    /// been parsed.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.inner.data.header()
+    }
+
+    /// Returns the headers of all members parsed so far, in order.
+    pub fn headers(&self) -> &[GzHeader] {
+        self.inner.data.headers()
+    }
+
+    /// Returns the number of bytes of the compressed stream consumed so far,
+    /// including the headers and trailers of all members.
+    pub fn total_in(&self) -> u64 {
+        self.inner.data.total_in()
+    }
+
+    /// Acquires a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.get_ref()
+    }
+
+    /// Acquires a mutable reference to the underlying writer.
+    ///
+    /// Note that mutating the output/input state of the stream may corrupt this
+    /// object, so care must be taken when using this method.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.get_mut()
+    }
+
+    /// Sets limits on the data produced by this decoder, counting all members
+    /// together, see [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.data.set_limits(limits);
+    }
+
+    /// Attempt to finish this output stream, writing out final chunks of data.
+    ///
+    /// Note that this function can only be used once data has finished being
+    /// written to the output stream. After this function is called then further
+    /// calls to `write` may result in a panic.
+    ///
+    /// # Panics
+    ///
+    /// Attempts to write data to this stream may result in a panic after this
+    /// function is called.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to finish the stream, returning any
+    /// errors which happen. If the data written so far doesn't end at the
+    /// end of a member, an error with `DecompressErrorKind::TruncatedInput`
+    /// is returned.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        self.inner.finish()?;
+        if self.inner.data.at_member_end() {
+            Ok(())
+        } else {
+            let at = self.inner.data.total_in();
+            Err(corrupt(DecompressErrorKind::TruncatedInput, at))
+        }
+    }
+
+    /// Consumes this decoder, flushing the output stream.
+    ///
+    /// This will flush the underlying data stream and then return the contained
+    /// writer if the flush succeeded.
+    ///
+    /// Note that this function may not be suitable to call in a situation where
+    /// the underlying stream is an asynchronous I/O stream. To finish a stream
+    /// the `try_finish` (or `shutdown`) method should be used instead. To
+    /// re-acquire ownership of a stream it is safe to call this method after
+    /// `try_finish` or `shutdown` has returned `Ok`.
+    ///
+    /// # Errors
+    ///
+    /// This function will perform I/O to complete this stream, and any I/O
+    /// errors which occur will be returned from this function.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take_inner())
+    }
+}
+
+impl<W: Write> Write for MultiGzDecoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncWrite> AsyncWrite for MultiGzDecoder<W> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.try_finish()?;
+        self.inner.get_mut().shutdown()
+    }
+}
+
+impl<W: Read + Write> Read for MultiGzDecoder<W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.get_mut().read(buf)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: AsyncRead + AsyncWrite> AsyncRead for MultiGzDecoder<W> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -476,4 +955,162 @@ mod tests {
         let return_string = String::from_utf8(writer).expect("String parsing error");
         assert_eq!(return_string, STR);
     }
+
+    fn multi_member_stream() -> (Vec<u8>, String) {
+        let mut bytes = Vec::new();
+        let mut expected = String::new();
+        for i in 0..3 {
+            let mut e = GzBuilder::new()
+                .filename(format!("member{}", i))
+                .write(Vec::new(), Compression::default());
+            let part = format!("{} {}", i, STR);
+            e.write_all(part.as_bytes()).unwrap();
+            bytes.extend(e.finish().unwrap());
+            expected.push_str(&part);
+        }
+        (bytes, expected)
+    }
+
+    #[test]
+    fn decode_writer_multi() {
+        let (bytes, expected) = multi_member_stream();
+
+        for &chunk in &[1, 7, 100, bytes.len()] {
+            let mut decoder = MultiGzDecoder::new(Vec::new());
+            for piece in bytes.chunks(chunk) {
+                decoder.write_all(piece).unwrap();
+            }
+            decoder.try_finish().unwrap();
+            assert_eq!(decoder.total_in(), bytes.len() as u64);
+            let names = decoder
+                .headers()
+                .iter()
+                .map(|h| h.filename().unwrap().to_vec())
+                .collect::<Vec<_>>();
+            assert_eq!(names, [&b"member0"[..], b"member1", b"member2"]);
+            let writer = decoder.finish().unwrap();
+            assert_eq!(String::from_utf8(writer).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn decode_writer_multi_errors() {
+        let (mut bytes, _) = multi_member_stream();
+
+        let mut decoder = MultiGzDecoder::new(Vec::new());
+        decoder.write_all(&bytes[..bytes.len() - 3]).unwrap();
+        let err = decoder.try_finish().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        let crc = bytes.len() - 8;
+        bytes[crc] ^= 1;
+        let mut decoder = MultiGzDecoder::new(Vec::new());
+        let err = decoder
+            .write_all(&bytes)
+            .and_then(|()| decoder.try_finish())
+            .unwrap_err();
+        let err = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<crate::DecompressError>())
+            .unwrap();
+        match err.kind() {
+            DecompressErrorKind::ChecksumMismatch { .. } => {}
+            kind => panic!("unexpected error kind {:?}", kind),
+        }
+        assert_eq!(err.offset(), Some(crc as u64));
+
+        let mut decoder = MultiGzDecoder::new(Vec::new());
+        assert!(decoder.try_finish().is_err());
+    }
+
+    fn decode_members(bytes: &[u8]) -> Vec<(Option<Vec<u8>>, GzMemberInfo, String)> {
+        let mut d = crate::read::MultiGzDecoder::new(bytes);
+        let mut members = Vec::new();
+        while let Some(mut member) = d.next_member().unwrap() {
+            let name = member.header().filename().map(|f| f.to_vec());
+            let mut s = String::new();
+            member.read_to_string(&mut s).unwrap();
+            members.push((name, *member.info().unwrap(), s));
+        }
+        members
+    }
+
+    #[test]
+    fn multi_encoder_members() {
+        let mut e = GzBuilder::new()
+            .filename("default")
+            .write_multi(Vec::new(), Compression::default());
+        e.write_all(b"one").unwrap();
+        e.finish_member().unwrap();
+        e.finish_member().unwrap();
+        e.set_member_header(GzBuilder::new().filename("custom"));
+        e.write_all(b"two").unwrap();
+        e.finish_member().unwrap();
+        e.write_all(b"three").unwrap();
+        e.try_finish().unwrap();
+        let members = e.members().to_vec();
+        let bytes = e.finish().unwrap();
+
+        assert_eq!(members.len(), 3);
+        assert_eq!(members[0].start(), 0);
+        assert_eq!(members[2].end(), bytes.len() as u64);
+        let decoded = decode_members(&bytes);
+        let names = decoded.iter().map(|m| m.0.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                Some(b"default".to_vec()),
+                Some(b"custom".to_vec()),
+                Some(b"default".to_vec())
+            ]
+        );
+        let infos = decoded.iter().map(|m| m.1).collect::<Vec<_>>();
+        assert_eq!(infos, members);
+
+        let mut s = String::new();
+        let cut = &bytes[..members[1].end() as usize];
+        crate::read::MultiGzDecoder::new(cut)
+            .read_to_string(&mut s)
+            .unwrap();
+        assert_eq!(s, "onetwo");
+    }
+
+    #[test]
+    fn multi_encoder_rollover() {
+        let mut e = MultiGzEncoder::new(Vec::new(), Compression::default());
+        e.set_max_member_in(Some(100));
+        e.write_all(STR.as_bytes()).unwrap();
+        e.try_finish().unwrap();
+        let members = e.members().to_vec();
+        let bytes = e.finish().unwrap();
+        assert_eq!(members.len(), STR.len().div_ceil(100));
+        assert!(members.iter().rev().skip(1).all(|m| m.size() == 100));
+        let decoded = decode_members(&bytes);
+        let data = decoded.iter().map(|m| &m.2[..]).collect::<String>();
+        assert_eq!(data, STR);
+
+        let mut e = MultiGzEncoder::new(Vec::new(), Compression::none());
+        e.set_max_member_out(Some(64));
+        for chunk in STR.as_bytes().chunks(10) {
+            e.write_all(chunk).unwrap();
+            e.flush().unwrap();
+        }
+        let members = e.members().to_vec();
+        let bytes = e.finish().unwrap();
+        assert!(members.len() > 1);
+        assert!(members.iter().all(|m| m.end() - m.start() >= 64));
+        let decoded = decode_members(&bytes);
+        assert_eq!(decoded.len(), members.len() + 1);
+        let data = decoded.iter().map(|m| &m.2[..]).collect::<String>();
+        assert_eq!(data, STR);
+    }
+
+    #[test]
+    fn multi_encoder_empty() {
+        let e = MultiGzEncoder::new(Vec::new(), Compression::default());
+        let bytes = e.finish().unwrap();
+        let decoded = decode_members(&bytes);
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].2, "");
+    }
 }
@@ -101,21 +101,43 @@
 //! flushed/written when they are dropped, and this is not always a suitable
 //! time to perform I/O. If I/O streams are flushed before drop, however, then
 //! these operations will be a noop.
+//!
+//! The `tokio1` and `futures-io` features instead provide the [`tokio1`] and
+//! [`futures_io`] modules, with encoders and decoders implementing the
+//! `AsyncRead` and `AsyncWrite` traits of tokio 1.x and `futures-io`
+//! respectively. These never perform I/O when dropped, so writers must be shut
+//! down (or closed) to write out their trailers.
+//!
+//! [`tokio1`]: tokio1/index.html
+//! [`futures_io`]: futures_io/index.html
 #![doc(html_root_url = "https://docs.rs/flate2/0.2")]
 #![deny(missing_docs)]
 #![deny(missing_debug_implementations)]
 #![allow(trivial_numeric_casts)]
 #![cfg_attr(test, deny(warnings))]
 
+pub use crate::auto::{Fallback, Format};
 pub use crate::crc::{Crc, CrcReader, CrcWriter};
+pub use crate::dictionary::DictionaryRegistry;
 pub use crate::gz::GzBuilder;
 pub use crate::gz::GzHeader;
-pub use crate::mem::{Compress, CompressError, Decompress, DecompressError, Status};
+pub use crate::gz::GzMemberInfo;
+pub use crate::gz::{ExtraSubfield, ExtraSubfields, SubfieldRegistry};
+pub use crate::gz::{GzCompress, GzDecompress};
+pub use crate::gz::{GzDecoderBuilder, HeaderLimitExceeded, TrailingData};
+pub use crate::mem::{Compress, CompressError, CompressOptions, Strategy};
+pub use crate::mem::{Decompress, DecompressError, Status};
+pub use crate::mem::{DecompressErrorKind, DecompressLimits, LimitExceeded};
 pub use crate::mem::{FlushCompress, FlushDecompress};
+pub use crate::zlib::ZlibHeader;
 
+#[cfg(any(feature = "tokio1", feature = "futures-io"))]
+mod async_io;
+mod auto;
 mod bufreader;
 mod crc;
 mod deflate;
+mod dictionary;
 mod ffi;
 mod gz;
 mod mem;
@@ -127,10 +149,13 @@ mod zlib;
 ///
 /// [`Read`]: https://doc.rust-lang.org/std/io/trait.Read.html
 pub mod read {
+    pub use crate::auto::read::AutoDecoder;
     pub use crate::deflate::read::DeflateDecoder;
     pub use crate::deflate::read::DeflateEncoder;
+    pub use crate::gz::parallel::ParMultiGzDecoder;
     pub use crate::gz::read::GzDecoder;
     pub use crate::gz::read::GzEncoder;
+    pub use crate::gz::read::GzMember;
     pub use crate::gz::read::MultiGzDecoder;
     pub use crate::zlib::read::ZlibDecoder;
     pub use crate::zlib::read::ZlibEncoder;
@@ -141,10 +166,14 @@ pub mod read {
 ///
 /// [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
 pub mod write {
+    pub use crate::auto::write::AutoDecoder;
     pub use crate::deflate::write::DeflateDecoder;
     pub use crate::deflate::write::DeflateEncoder;
+    pub use crate::gz::parallel::ParGzEncoder;
     pub use crate::gz::write::GzDecoder;
     pub use crate::gz::write::GzEncoder;
+    pub use crate::gz::write::MultiGzDecoder;
+    pub use crate::gz::write::MultiGzEncoder;
     pub use crate::zlib::write::ZlibDecoder;
     pub use crate::zlib::write::ZlibEncoder;
 }
@@ -154,15 +183,54 @@ pub mod write {
 ///
 /// [`BufRead`]: https://doc.rust-lang.org/std/io/trait.BufRead.html
 pub mod bufread {
+    pub use crate::auto::bufread::AutoDecoder;
     pub use crate::deflate::bufread::DeflateDecoder;
     pub use crate::deflate::bufread::DeflateEncoder;
     pub use crate::gz::bufread::GzDecoder;
     pub use crate::gz::bufread::GzEncoder;
+    pub use crate::gz::bufread::GzMember;
     pub use crate::gz::bufread::MultiGzDecoder;
     pub use crate::zlib::bufread::ZlibDecoder;
     pub use crate::zlib::bufread::ZlibEncoder;
 }
 
+/// Encoders and decoders over tokio 1.x streams, enabled with the `tokio1`
+/// feature.
+#[cfg(feature = "tokio1")]
+pub mod tokio1 {
+    pub use crate::async_io::tokio::{bufread, read, write};
+}
+
+/// Encoders and decoders over `futures-io` streams, as used by async-std and
+/// smol, enabled with the `futures-io` feature.
+#[cfg(feature = "futures-io")]
+pub mod futures_io {
+    pub use crate::async_io::futures::{bufread, read, write};
+}
+
+/// Reading and writing the BGZF (blocked gzip) format used by htslib.
+pub mod bgzf {
+    pub use crate::gz::bgzf::{GziIndex, Reader, VirtualOffset, Writer};
+}
+
+/// Random access into gzip streams through a precomputed index of access
+/// points.
+pub mod index {
+    pub use crate::gz::index::{AccessPoint, GzIndex, IndexedGzDecoder, DEFAULT_SPACING};
+}
+
+/// Crash-safe append-only gzip logs, recoverable up to the last flush.
+pub mod gzlog {
+    pub use crate::gz::log::{Reader, Writer, DEFAULT_MEMBER_SIZE, DEFAULT_SYNC_INTERVAL};
+}
+
+/// Recovering data from damaged gzip and DEFLATE streams.
+pub mod salvage {
+    pub use crate::gz::salvage::{DamagedRegion, Gaps, SalvageDecoder};
+}
+
+pub mod websocket;
+
 fn _assert_send_sync() {
     fn _assert_send_sync<T: Send + Sync>() {}
 
@@ -173,12 +241,20 @@ fn _assert_send_sync() {
     _assert_send_sync::<read::GzEncoder<&[u8]>>();
     _assert_send_sync::<read::GzDecoder<&[u8]>>();
     _assert_send_sync::<read::MultiGzDecoder<&[u8]>>();
+    _assert_send_sync::<read::ParMultiGzDecoder<&[u8]>>();
+    _assert_send_sync::<read::AutoDecoder<&[u8]>>();
+    _assert_send_sync::<bgzf::Reader<&[u8]>>();
+    _assert_send_sync::<bgzf::Writer<Vec<u8>>>();
+    _assert_send_sync::<index::IndexedGzDecoder<std::io::Cursor<&[u8]>>>();
     _assert_send_sync::<write::DeflateEncoder<Vec<u8>>>();
     _assert_send_sync::<write::DeflateDecoder<Vec<u8>>>();
     _assert_send_sync::<write::ZlibEncoder<Vec<u8>>>();
     _assert_send_sync::<write::ZlibDecoder<Vec<u8>>>();
     _assert_send_sync::<write::GzEncoder<Vec<u8>>>();
     _assert_send_sync::<write::GzDecoder<Vec<u8>>>();
+    _assert_send_sync::<write::AutoDecoder<Vec<u8>>>();
+    _assert_send_sync::<websocket::MessageCompressor>();
+    _assert_send_sync::<websocket::MessageDecompressor>();
 }
 
 /// When compressing data, the compression level can be specified by a value in
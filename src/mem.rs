@@ -1,3 +1,4 @@
+use std::cmp;
 use std::error::Error;
 use std::fmt;
 use std::io;
@@ -38,6 +39,135 @@ pub struct Compress {
 #[derive(Debug)]
 pub struct Decompress {
     inner: Inflate,
+    limits: DecompressLimits,
+    // Input and output of earlier gzip members, which count towards `limits`.
+    prior_in: u64,
+    prior_out: u64,
+    // The last four bytes of input consumed, which hold the stored checksum
+    // when a zlib stream fails its Adler-32 check.
+    tail: u32,
+    // Whether the end of the stream has been reached.
+    ended: bool,
+}
+
+/// Limits on the output of a decompression stream, guarding against
+/// decompression bombs.
+///
+/// Limits can be set on a [`Decompress`] object and on all of the decoders in
+/// this crate. Once a limit is exceeded decompression fails with a
+/// [`LimitExceeded`] error, which the I/O decoders return wrapped in an
+/// `io::Error`.
+///
+/// [`Decompress`]: struct.Decompress.html
+/// [`LimitExceeded`]: enum.LimitExceeded.html
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, DecompressLimits, LimitExceeded};
+/// use flate2::read::GzDecoder;
+/// use flate2::write::GzEncoder;
+///
+/// let mut e = GzEncoder::new(Vec::new(), Compression::best());
+/// e.write_all(&vec![0; 1024 * 1024]).unwrap();
+/// let bomb = e.finish().unwrap();
+///
+/// let mut d = GzDecoder::new(&bomb[..]);
+/// d.set_limits(DecompressLimits::new().max_output(64 * 1024));
+/// let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+/// let limit = err.get_ref().and_then(|e| e.downcast_ref::<LimitExceeded>());
+/// assert_eq!(limit, Some(&LimitExceeded::Output(64 * 1024)));
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecompressLimits {
+    max_output: Option<u64>,
+    max_ratio: Option<u64>,
+    min_output: u64,
+}
+
+/// Error returned when decompression stops because it exceeded one of the
+/// configured [`DecompressLimits`].
+///
+/// [`DecompressLimits`]: struct.DecompressLimits.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+    /// The output grew past the given number of bytes.
+    Output(u64),
+
+    /// The output grew to more than the given multiple of the input.
+    Ratio(u64),
+
+    #[doc(hidden)]
+    _Nonexhaustive,
+}
+
+/// Parameters for a compression stream beyond its level.
+///
+/// Options can be passed to [`Compress::new_with_options`] and to the
+/// `new_with_options` constructors of all of the encoders in this crate. They
+/// have the same meaning on every backend, and combinations which a backend
+/// can't honor are rejected with an error of kind `InvalidInput` rather than
+/// silently ignored:
+///
+/// * Level 10, miniz's "uber" compression, is only available with the
+///   `miniz_oxide` and `miniz-sys` backends.
+/// * Window sizes below 15 bits are only available with the zlib backends.
+/// * The memory level only affects the zlib backends, but must be within
+///   1 ..= 9 everywhere.
+///
+/// [`Compress::new_with_options`]: struct.Compress.html#method.new_with_options
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{CompressOptions, Compression, Strategy};
+/// use flate2::write::ZlibEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let options = CompressOptions::new(Compression::best()).strategy(Strategy::Rle);
+/// let mut e = ZlibEncoder::new_with_options(Vec::new(), &options)?;
+/// e.write_all(&[0; 1024])?;
+/// let compressed = e.finish()?;
+/// # let _ = compressed;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CompressOptions {
+    pub(crate) level: Compression,
+    pub(crate) strategy: Strategy,
+    pub(crate) window_bits: u8,
+    pub(crate) mem_level: u8,
+}
+
+/// Strategies which tune the compression algorithm for different kinds of
+/// data, as set with [`CompressOptions::strategy`].
+///
+/// [`CompressOptions::strategy`]: struct.CompressOptions.html#method.strategy
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    /// The normal strategy, suitable for most data.
+    Default = 0,
+
+    /// Favors Huffman coding over string matching, for data consisting mostly
+    /// of small values with a somewhat random distribution.
+    Filtered = 1,
+
+    /// Doesn't search for matches at all and only Huffman codes literals.
+    HuffmanOnly = 2,
+
+    /// Only searches for matches at a distance of one byte, which compresses
+    /// runs nearly as well as the default strategy much faster.
+    Rle = 3,
+
+    /// Only emits blocks with the fixed Huffman codes of the deflate
+    /// specification, making decoding simpler.
+    Fixed = 4,
+
+    #[doc(hidden)]
+    _Nonexhaustive,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -114,17 +244,142 @@ pub enum FlushDecompress {
 }
 
 /// The inner state for an error when decompressing
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub(crate) struct DecompressErrorInner {
+    pub(crate) kind: DecompressErrorKind,
+    pub(crate) offset: Option<u64>,
     pub(crate) needs_dictionary: Option<u32>,
+    pub(crate) limit: Option<LimitExceeded>,
 }
 
 /// Error returned when a decompression object finds that the input stream of
 /// bytes was not a valid input stream of bytes.
+///
+/// Errors returned by the readers and writers of this crate carry this type
+/// as their inner error, so the reason for a failure can be retrieved from an
+/// `io::Error` with `downcast_ref`:
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{DecompressError, DecompressErrorKind};
+/// use flate2::read::GzDecoder;
+///
+/// let mut d = GzDecoder::new(&b"not a gzip stream"[..]);
+/// let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+/// let err = err.get_ref().unwrap().downcast_ref::<DecompressError>().unwrap();
+/// assert_eq!(err.kind(), DecompressErrorKind::BadMagic);
+/// assert_eq!(err.offset(), Some(0));
+/// ```
 #[derive(Debug)]
 pub struct DecompressError(pub(crate) DecompressErrorInner);
 
+/// The reason why a stream could not be decompressed.
+///
+/// Which kinds are reported for a corrupt deflate stream depends on the
+/// backend: zlib distinguishes all of them, while the other backends may only
+/// be able to report `Corrupt`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecompressErrorKind {
+    /// A deflate block uses the reserved block type.
+    InvalidBlockType,
+
+    /// The length of a stored block does not match its complement.
+    InvalidStoredBlock,
+
+    /// A Huffman code, or the table describing one, is invalid.
+    InvalidHuffmanCode,
+
+    /// A back-reference points before the start of the decompressed data.
+    DistanceTooFarBack,
+
+    /// The checksum stored in the stream does not match the one calculated
+    /// over the data.
+    ///
+    /// This is a CRC-32 for gzip streams, the low 16 bits of one for a gzip
+    /// header and an Adler-32 for zlib streams.
+    ChecksumMismatch {
+        /// The checksum stored in the stream.
+        expected: u32,
+        /// The checksum calculated over the data.
+        actual: u32,
+    },
+
+    /// The length stored in a gzip trailer does not match the length of the
+    /// decompressed data, modulo 2<sup>32</sup>.
+    LengthMismatch {
+        /// The length stored in the stream.
+        expected: u32,
+        /// The length of the decompressed data.
+        actual: u32,
+    },
+
+    /// The stream does not start with the magic bytes of its format.
+    BadMagic,
+
+    /// The stream uses a compression method other than deflate.
+    UnsupportedMethod,
+
+    /// A header field other than the magic bytes or compression method is
+    /// invalid.
+    InvalidHeader,
+
+    /// The input ended before the end of the stream.
+    TruncatedInput,
+
+    /// Data that is not a valid gzip member follows the end of a member.
+    TrailingGarbage,
+
+    /// A preset dictionary is needed, see
+    /// [`DecompressError::needs_dictionary`].
+    ///
+    /// [`DecompressError::needs_dictionary`]: struct.DecompressError.html#method.needs_dictionary
+    NeedsDictionary,
+
+    /// One of the configured limits was exceeded, see
+    /// [`DecompressError::limit_exceeded`].
+    ///
+    /// [`DecompressError::limit_exceeded`]: struct.DecompressError.html#method.limit_exceeded
+    LimitExceeded,
+
+    /// The stream is invalid for a reason not covered by the other kinds.
+    Corrupt,
+
+    #[doc(hidden)]
+    _Nonexhaustive,
+}
+
 impl DecompressError {
+    pub(crate) fn new(kind: DecompressErrorKind) -> DecompressError {
+        DecompressError(DecompressErrorInner {
+            kind,
+            offset: None,
+            needs_dictionary: None,
+            limit: None,
+        })
+    }
+
+    pub(crate) fn at(mut self, offset: u64) -> DecompressError {
+        self.0.offset = Some(offset);
+        self
+    }
+
+    /// Returns the reason why decompression failed.
+    pub fn kind(&self) -> DecompressErrorKind {
+        self.0.kind
+    }
+
+    /// Returns the offset of the compressed input at which decompression
+    /// failed, if known.
+    ///
+    /// For a [`Decompress`] this counts the input since it was created or
+    /// last reset, while for the readers and writers of this crate it counts
+    /// from the start of the compressed stream, including any headers.
+    ///
+    /// [`Decompress`]: struct.Decompress.html
+    pub fn offset(&self) -> Option<u64> {
+        self.0.offset
+    }
+
     /// Indicates whether decompression failed due to requiring a dictionary.
     ///
     /// The resulting integer is the Adler-32 checksum of the dictionary
@@ -132,18 +387,50 @@ impl DecompressError {
     pub fn needs_dictionary(&self) -> Option<u32> {
         self.0.needs_dictionary
     }
+
+    /// Indicates whether decompression stopped because it exceeded one of the
+    /// limits set with `Decompress::set_limits`.
+    pub fn limit_exceeded(&self) -> Option<LimitExceeded> {
+        self.0.limit
+    }
+}
+
+/// Converts a decompression error into the `io::Error` returned by the
+/// readers and writers of this crate.
+pub(crate) fn io_error(err: DecompressError) -> io::Error {
+    let kind = match err.kind() {
+        DecompressErrorKind::LimitExceeded => match err.limit_exceeded() {
+            Some(limit) => return limit.into(),
+            None => io::ErrorKind::InvalidData,
+        },
+        DecompressErrorKind::TruncatedInput => io::ErrorKind::UnexpectedEof,
+        _ => io::ErrorKind::InvalidInput,
+    };
+    io::Error::new(kind, err)
+}
+
+/// Moves the offset of a `DecompressError` inside `err` forward by `base`
+/// bytes, for errors of a stream embedded in a larger one.
+pub(crate) fn add_offset(mut err: io::Error, base: u64) -> io::Error {
+    if let Some(inner) = err
+        .get_mut()
+        .and_then(|e| e.downcast_mut::<DecompressError>())
+    {
+        inner.0.offset = inner.0.offset.map(|offset| offset + base);
+    }
+    err
 }
 
 #[inline]
-pub(crate) fn decompress_failed() -> Result<Status, DecompressError> {
-    Err(DecompressError(Default::default()))
+pub(crate) fn decompress_failed(kind: DecompressErrorKind) -> Result<Status, DecompressError> {
+    Err(DecompressError::new(kind))
 }
 
 #[inline]
 pub(crate) fn decompress_need_dict(adler: u32) -> Result<Status, DecompressError> {
-    Err(DecompressError(DecompressErrorInner {
-        needs_dictionary: Some(adler),
-    }))
+    let mut err = DecompressError::new(DecompressErrorKind::NeedsDictionary);
+    err.0.needs_dictionary = Some(adler);
+    Err(err)
 }
 
 /// Error returned when a compression object is used incorrectly or otherwise
@@ -188,10 +475,26 @@ impl Compress {
     /// output data should have a zlib header or not.
     pub fn new(level: Compression, zlib_header: bool) -> Compress {
         Compress {
-            inner: Deflate::make(level, zlib_header, ffi::MZ_DEFAULT_WINDOW_BITS as u8),
+            inner: Deflate::make(&CompressOptions::new(level), zlib_header),
         }
     }
 
+    /// Creates a new object ready for compressing data with the given options.
+    ///
+    /// The `zlib_header` argument indicates whether the output data should
+    /// have a zlib header or not.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the options can't be honored
+    /// by the backend this crate was built with.
+    pub fn new_with_options(options: &CompressOptions, zlib_header: bool) -> io::Result<Compress> {
+        options.check()?;
+        Ok(Compress {
+            inner: Deflate::make(options, zlib_header),
+        })
+    }
+
     /// Creates a new object ready for compressing data that it's given.
     ///
     /// The `level` argument here indicates what level of compression is going
@@ -207,16 +510,21 @@ impl Compress {
     ///
     /// # Note
     ///
-    /// This constructor is only available when the `zlib` feature is used.
-    /// Other backends currently do not support custom window bits.
-    #[cfg(feature = "any_zlib")]
+    /// Only the zlib backends support windows smaller than 15 bits, other
+    /// backends panic for them. Use [`new_with_options`] to get an error
+    /// instead.
+    ///
+    /// [`new_with_options`]: #method.new_with_options
     pub fn new_with_window_bits(
         level: Compression,
         zlib_header: bool,
         window_bits: u8,
     ) -> Compress {
         Compress {
-            inner: Deflate::make(level, zlib_header, window_bits),
+            inner: Deflate::make(
+                &CompressOptions::new(level).window_bits(window_bits),
+                zlib_header,
+            ),
         }
     }
 
@@ -235,19 +543,12 @@ impl Compress {
     /// Specifies the compression dictionary to use.
     ///
     /// Returns the Adler-32 checksum of the dictionary.
-    #[cfg(feature = "any_zlib")]
+    ///
+    /// The dictionary must be set before any data is compressed, and it is
+    /// discarded by `reset`. The `miniz-sys` backend doesn't support
+    /// dictionaries and always returns an error.
     pub fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, CompressError> {
-        let stream = &mut *self.inner.inner.stream_wrapper;
-        let rc = unsafe {
-            assert!(dictionary.len() < ffi::uInt::max_value() as usize);
-            ffi::deflateSetDictionary(stream, dictionary.as_ptr(), dictionary.len() as ffi::uInt)
-        };
-
-        match rc {
-            ffi::MZ_STREAM_ERROR => Err(CompressError(())),
-            ffi::MZ_OK => Ok(stream.adler as u32),
-            c => panic!("unknown return code: {}", c),
-        }
+        self.inner.set_dictionary(dictionary)
     }
 
     /// Quickly resets this compressor without having to reallocate anything.
@@ -267,18 +568,11 @@ impl Compress {
     /// the compression of the available input data before changing the
     /// compression level. Flushing the stream before calling this method
     /// ensures that the function will succeed on the first call.
-    #[cfg(feature = "any_zlib")]
+    ///
+    /// The strategy is reset to `Strategy::Default`. The `miniz-sys` backend
+    /// can't change the level of a stream and always returns an error.
     pub fn set_level(&mut self, level: Compression) -> Result<(), CompressError> {
-        use libc::c_int;
-        let stream = &mut *self.inner.inner.stream_wrapper;
-
-        let rc = unsafe { ffi::deflateParams(stream, level.0 as c_int, ffi::MZ_DEFAULT_STRATEGY) };
-
-        match rc {
-            ffi::MZ_OK => Ok(()),
-            ffi::MZ_BUF_ERROR => Err(CompressError(())),
-            c => panic!("unknown return code: {}", c),
-        }
+        self.inner.set_level(level)
     }
 
     /// Compresses the input data into the output, consuming only as much
@@ -333,9 +627,10 @@ impl Decompress {
     /// The `zlib_header` argument indicates whether the input data is expected
     /// to have a zlib header or not.
     pub fn new(zlib_header: bool) -> Decompress {
-        Decompress {
-            inner: Inflate::make(zlib_header, ffi::MZ_DEFAULT_WINDOW_BITS as u8),
-        }
+        Decompress::make(Inflate::make(
+            zlib_header,
+            ffi::MZ_DEFAULT_WINDOW_BITS as u8,
+        ))
     }
 
     /// Creates a new object ready for decompressing data that it's given.
@@ -348,15 +643,18 @@ impl Decompress {
     ///
     /// If `window_bits` does not fall into the range 9 ..= 15,
     /// `new_with_window_bits` will panic.
-    ///
-    /// # Note
-    ///
-    /// This constructor is only available when the `zlib` feature is used.
-    /// Other backends currently do not support custom window bits.
-    #[cfg(feature = "any_zlib")]
     pub fn new_with_window_bits(zlib_header: bool, window_bits: u8) -> Decompress {
+        Decompress::make(Inflate::make(zlib_header, window_bits))
+    }
+
+    fn make(inner: Inflate) -> Decompress {
         Decompress {
-            inner: Inflate::make(zlib_header, window_bits),
+            inner,
+            limits: DecompressLimits::new(),
+            prior_in: 0,
+            prior_out: 0,
+            tail: 0,
+            ended: false,
         }
     }
 
@@ -400,7 +698,45 @@ impl Decompress {
         output: &mut [u8],
         flush: FlushDecompress,
     ) -> Result<Status, DecompressError> {
-        self.inner.decompress(input, output, flush)
+        let output = match self.limits.max_output {
+            // Leave room for one byte past the limit so that exceeding it can
+            // be told apart from a stream which ends right at the limit.
+            Some(max) => {
+                let produced = self.prior_out + self.total_out();
+                let room = max.saturating_add(1).saturating_sub(produced);
+                let len = cmp::min(output.len() as u64, room) as usize;
+                &mut output[..len]
+            }
+            None => output,
+        };
+        let before = self.total_in();
+        let result = self.inner.decompress(input, output, flush);
+        let consumed = (self.total_in() - before) as usize;
+        for &byte in &input[consumed.saturating_sub(4)..consumed] {
+            self.tail = (self.tail << 8) | u32::from(byte);
+        }
+        let status = result.map_err(|mut err| {
+            // Backends only know the checksum they calculated.
+            if let DecompressErrorKind::ChecksumMismatch { actual, .. } = err.0.kind {
+                err.0.kind = DecompressErrorKind::ChecksumMismatch {
+                    expected: self.tail,
+                    actual,
+                };
+            }
+            err.at(self.total_in())
+        })?;
+        self.ended |= status == Status::StreamEnd;
+        match self.limits.check(
+            self.prior_in + self.total_in(),
+            self.prior_out + self.total_out(),
+        ) {
+            Ok(()) => Ok(status),
+            Err(limit) => {
+                let mut err = DecompressError::new(DecompressErrorKind::LimitExceeded);
+                err.0.limit = Some(limit);
+                Err(err.at(self.total_in()))
+            }
+        }
     }
 
     /// Decompresses the input data into the extra space in the output vector
@@ -439,22 +775,16 @@ impl Decompress {
     }
 
     /// Specifies the decompression dictionary to use.
-    #[cfg(feature = "any_zlib")]
+    ///
+    /// For zlib streams this must be called after `decompress` failed with
+    /// an error for which `DecompressError::needs_dictionary` returns the
+    /// checksum of the dictionary, and for raw deflate streams before any
+    /// data is decompressed. Returns the Adler-32 checksum of the dictionary.
+    ///
+    /// The `miniz-sys` backend doesn't support dictionaries and always returns
+    /// an error.
     pub fn set_dictionary(&mut self, dictionary: &[u8]) -> Result<u32, DecompressError> {
-        let stream = &mut *self.inner.inner.stream_wrapper;
-        let rc = unsafe {
-            assert!(dictionary.len() < ffi::uInt::max_value() as usize);
-            ffi::inflateSetDictionary(stream, dictionary.as_ptr(), dictionary.len() as ffi::uInt)
-        };
-
-        match rc {
-            ffi::MZ_STREAM_ERROR => Err(DecompressError(Default::default())),
-            ffi::MZ_DATA_ERROR => Err(DecompressError(DecompressErrorInner {
-                needs_dictionary: Some(stream.adler as u32),
-            })),
-            ffi::MZ_OK => Ok(stream.adler as u32),
-            c => panic!("unknown return code: {}", c),
-        }
+        self.inner.set_dictionary(dictionary)
     }
 
     /// Performs the equivalent of replacing this decompression state with a
@@ -465,8 +795,206 @@ impl Decompress {
     ///
     /// The argument provided here indicates whether the reset state will
     /// attempt to decode a zlib header first or not.
+    ///
+    /// Limits set with `set_limits` are kept and apply to the new stream.
     pub fn reset(&mut self, zlib_header: bool) {
         self.inner.reset(zlib_header);
+        self.prior_in = 0;
+        self.prior_out = 0;
+        self.tail = 0;
+        self.ended = false;
+    }
+
+    /// Returns whether `decompress` has reached the end of the stream since
+    /// this was created or last reset.
+    pub(crate) fn ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Resets the state to decode another stream whose input and output count
+    /// towards the same limits as the streams before it, such as the next
+    /// member of a multi-member gzip file.
+    pub(crate) fn reset_continued(&mut self, zlib_header: bool) {
+        let prior_in = self.prior_in + self.total_in();
+        let prior_out = self.prior_out + self.total_out();
+        self.reset(zlib_header);
+        self.prior_in = prior_in;
+        self.prior_out = prior_out;
+    }
+
+    /// Sets limits on the output of this stream.
+    ///
+    /// Once a limit is exceeded `decompress` returns an error for which
+    /// `DecompressError::limit_exceeded` returns the limit. When no limit is
+    /// set, which is the default, the output is unbounded.
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.limits = limits;
+    }
+
+    /// Returns the limits on the output of this stream.
+    pub fn limits(&self) -> DecompressLimits {
+        self.limits
+    }
+}
+
+impl CompressOptions {
+    /// Creates options for compressing at `level` with the default strategy,
+    /// a 32 KiB window and memory level 8.
+    pub fn new(level: Compression) -> CompressOptions {
+        CompressOptions {
+            level,
+            strategy: Strategy::Default,
+            window_bits: ffi::MZ_DEFAULT_WINDOW_BITS as u8,
+            mem_level: 8,
+        }
+    }
+
+    /// Sets the compression level.
+    ///
+    /// Besides the usual levels 0 through 9 the miniz backends accept level
+    /// 10, which searches harder for matches than level 9.
+    pub fn level(mut self, level: Compression) -> CompressOptions {
+        self.level = level;
+        self
+    }
+
+    /// Sets the compression strategy.
+    pub fn strategy(mut self, strategy: Strategy) -> CompressOptions {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets the base-2 logarithm of the size of the sliding window, between 9
+    /// and 15.
+    ///
+    /// Smaller windows use less memory, both when compressing and
+    /// decompressing, at the cost of compression ratio. Only the zlib
+    /// backends support windows smaller than 15 bits.
+    pub fn window_bits(mut self, window_bits: u8) -> CompressOptions {
+        self.window_bits = window_bits;
+        self
+    }
+
+    /// Sets how much memory is used for the internal compression state,
+    /// between 1 and 9.
+    ///
+    /// Higher levels use more memory but are faster and compress better. This
+    /// is only honored by the zlib backends.
+    pub fn mem_level(mut self, mem_level: u8) -> CompressOptions {
+        self.mem_level = mem_level;
+        self
+    }
+
+    /// Returns the compression level.
+    pub fn get_level(&self) -> Compression {
+        self.level
+    }
+
+    /// Returns the compression strategy.
+    pub fn get_strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Returns the base-2 logarithm of the window size.
+    pub fn get_window_bits(&self) -> u8 {
+        self.window_bits
+    }
+
+    /// Returns the memory level.
+    pub fn get_mem_level(&self) -> u8 {
+        self.mem_level
+    }
+
+    /// Checks that these options can be honored by the backend in use.
+    pub(crate) fn check(&self) -> io::Result<()> {
+        let max_level = if cfg!(feature = "any_zlib") { 9 } else { 10 };
+        let min_window_bits = if cfg!(feature = "any_zlib") { 9 } else { 15 };
+        let err = if self.level.level() > max_level {
+            "compression level is not supported by this backend"
+        } else if self.strategy == Strategy::_Nonexhaustive {
+            "invalid compression strategy"
+        } else if self.window_bits < min_window_bits || self.window_bits > 15 {
+            "window size is not supported by this backend"
+        } else if self.mem_level < 1 || self.mem_level > 9 {
+            "memory level must be within 1 ..= 9"
+        } else {
+            return Ok(());
+        };
+        Err(io::Error::new(io::ErrorKind::InvalidInput, err))
+    }
+}
+
+impl Default for CompressOptions {
+    fn default() -> CompressOptions {
+        CompressOptions::new(Compression::default())
+    }
+}
+
+impl DecompressLimits {
+    /// Creates a set of limits which don't restrict decompression at all.
+    pub fn new() -> DecompressLimits {
+        DecompressLimits {
+            max_output: None,
+            max_ratio: None,
+            min_output: 0,
+        }
+    }
+
+    /// Limits the total output to `bytes` bytes.
+    pub fn max_output(mut self, bytes: u64) -> DecompressLimits {
+        self.max_output = Some(bytes);
+        self
+    }
+
+    /// Limits the output to `ratio` times the compressed input once more than
+    /// `min_output` bytes have been produced.
+    ///
+    /// The minimum output keeps small, highly compressible inputs from
+    /// tripping the limit.
+    pub fn max_ratio(mut self, ratio: u64, min_output: u64) -> DecompressLimits {
+        self.max_ratio = Some(ratio);
+        self.min_output = min_output;
+        self
+    }
+
+    /// Checks the total input consumed and output produced against these
+    /// limits.
+    pub(crate) fn check(&self, total_in: u64, total_out: u64) -> Result<(), LimitExceeded> {
+        if let Some(max) = self.max_output {
+            if total_out > max {
+                return Err(LimitExceeded::Output(max));
+            }
+        }
+        if let Some(ratio) = self.max_ratio {
+            if total_out > self.min_output && total_out > total_in.saturating_mul(ratio) {
+                return Err(LimitExceeded::Ratio(ratio));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for LimitExceeded {}
+
+impl From<LimitExceeded> for io::Error {
+    fn from(limit: LimitExceeded) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, limit)
+    }
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitExceeded::Output(max) => {
+                write!(f, "decompressed output exceeds the limit of {} bytes", max)
+            }
+            LimitExceeded::Ratio(ratio) => write!(
+                f,
+                "decompressed output exceeds {} times the size of the input",
+                ratio
+            ),
+            LimitExceeded::_Nonexhaustive => unreachable!(),
+        }
     }
 }
 
@@ -474,13 +1002,53 @@ impl Error for DecompressError {}
 
 impl From<DecompressError> for io::Error {
     fn from(data: DecompressError) -> io::Error {
-        io::Error::new(io::ErrorKind::Other, data)
+        match data.limit_exceeded() {
+            Some(limit) => limit.into(),
+            None => io::Error::new(io::ErrorKind::Other, data),
+        }
     }
 }
 
 impl fmt::Display for DecompressError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "deflate decompression error")
+        if let Some(ref limit) = self.0.limit {
+            return limit.fmt(f);
+        }
+        self.0.kind.fmt(f)?;
+        match self.0.offset {
+            Some(offset) => write!(f, " at offset {}", offset),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for DecompressErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecompressErrorKind::InvalidBlockType => write!(f, "invalid deflate block type"),
+            DecompressErrorKind::InvalidStoredBlock => write!(f, "invalid stored block lengths"),
+            DecompressErrorKind::InvalidHuffmanCode => write!(f, "invalid Huffman code"),
+            DecompressErrorKind::DistanceTooFarBack => write!(f, "invalid distance too far back"),
+            DecompressErrorKind::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch: expected {:#010x}, found {:#010x}",
+                expected, actual
+            ),
+            DecompressErrorKind::LengthMismatch { expected, actual } => write!(
+                f,
+                "length mismatch: expected {} bytes, found {}",
+                expected, actual
+            ),
+            DecompressErrorKind::BadMagic => write!(f, "invalid magic bytes"),
+            DecompressErrorKind::UnsupportedMethod => write!(f, "unsupported compression method"),
+            DecompressErrorKind::InvalidHeader => write!(f, "invalid header"),
+            DecompressErrorKind::TruncatedInput => write!(f, "unexpected end of compressed input"),
+            DecompressErrorKind::TrailingGarbage => write!(f, "trailing garbage after stream"),
+            DecompressErrorKind::NeedsDictionary => write!(f, "a preset dictionary is needed"),
+            DecompressErrorKind::LimitExceeded => write!(f, "decompression limit exceeded"),
+            DecompressErrorKind::Corrupt => write!(f, "corrupt deflate stream"),
+            DecompressErrorKind::_Nonexhaustive => unreachable!(),
+        }
     }
 }
 
@@ -500,13 +1068,20 @@ impl fmt::Display for CompressError {
 
 #[cfg(test)]
 mod tests {
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    use std::cmp;
     use std::io::Write;
 
-    use crate::write;
-    use crate::{Compression, Decompress, FlushDecompress};
+    use std::io::{self, Read};
 
-    #[cfg(feature = "any_zlib")]
-    use crate::{Compress, FlushCompress};
+    use crate::{bufread, read, write};
+    use crate::{CompressOptions, Strategy};
+    use crate::{Compression, Decompress, DecompressError, DecompressErrorKind, DecompressLimits};
+    use crate::{FlushDecompress, LimitExceeded};
+
+    use crate::Compress;
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    use crate::{FlushCompress, Status};
 
     #[test]
     fn issue51() {
@@ -566,8 +1141,8 @@ mod tests {
         assert!(dst.starts_with(string));
     }
 
-    #[cfg(feature = "any_zlib")]
     #[test]
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
     fn set_dictionary_with_zlib_header() {
         let string = "hello, hello!".as_bytes();
         let dictionary = "hello".as_bytes();
@@ -615,8 +1190,8 @@ mod tests {
         assert_eq!(&decoded[..decoder.total_out() as usize], string);
     }
 
-    #[cfg(feature = "any_zlib")]
     #[test]
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
     fn set_dictionary_raw() {
         let string = "hello, hello!".as_bytes();
         let dictionary = "hello".as_bytes();
@@ -645,4 +1220,355 @@ mod tests {
 
         assert_eq!(&decoded[..decoder.total_out() as usize], string);
     }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut e = write::DeflateEncoder::new(Vec::new(), Compression::default());
+        e.write_all(data).unwrap();
+        e.finish().unwrap()
+    }
+
+    #[test]
+    fn limit_output() {
+        let data = vec![7; 10_000];
+        let encoded = deflate(&data);
+        let mut decoded = vec![0; 20_000];
+
+        let mut d = Decompress::new(false);
+        d.set_limits(DecompressLimits::new().max_output(10_000));
+        d.decompress(&encoded, &mut decoded, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(d.total_out(), 10_000);
+
+        let mut d = Decompress::new(false);
+        d.set_limits(DecompressLimits::new().max_output(9_999));
+        let err = d
+            .decompress(&encoded, &mut decoded, FlushDecompress::Finish)
+            .unwrap_err();
+        assert_eq!(err.limit_exceeded(), Some(LimitExceeded::Output(9_999)));
+        assert_eq!(err.needs_dictionary(), None);
+    }
+
+    #[test]
+    fn limit_ratio() {
+        let data = vec![7; 100_000];
+        let encoded = deflate(&data);
+        let mut decoded = vec![0; 100_000];
+
+        let mut d = Decompress::new(false);
+        d.set_limits(DecompressLimits::new().max_ratio(100, 50_000));
+        let err = d
+            .decompress(&encoded, &mut decoded, FlushDecompress::Finish)
+            .unwrap_err();
+        assert_eq!(err.limit_exceeded(), Some(LimitExceeded::Ratio(100)));
+
+        // Small outputs are allowed whatever their ratio.
+        let mut d = Decompress::new(false);
+        d.set_limits(DecompressLimits::new().max_ratio(100, 100_000));
+        d.decompress(&encoded, &mut decoded, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(&decoded[..], &data[..]);
+    }
+
+    #[test]
+    fn limits_kept_across_reset() {
+        let encoded = deflate(&[7; 1000]);
+        let mut decoded = vec![0; 2000];
+        let mut d = Decompress::new(false);
+        d.set_limits(DecompressLimits::new().max_output(1000));
+        for _ in 0..3 {
+            d.decompress(&encoded, &mut decoded, FlushDecompress::Finish)
+                .unwrap();
+            d.reset(false);
+        }
+        assert_eq!(d.limits(), DecompressLimits::new().max_output(1000));
+    }
+
+    #[test]
+    fn error_kinds() {
+        let mut decoded = vec![0; 100];
+
+        // A final block of the reserved type 3.
+        let mut d = Decompress::new(false);
+        let err = d
+            .decompress(&[0x07, 0, 0], &mut decoded, FlushDecompress::Finish)
+            .unwrap_err();
+        if cfg!(feature = "any_zlib") {
+            assert_eq!(err.kind(), DecompressErrorKind::InvalidBlockType);
+        }
+        assert_eq!(err.offset(), Some(d.total_in()));
+        assert_eq!(io::Error::from(err).kind(), io::ErrorKind::Other);
+
+        // A stored block whose length does not match its complement.
+        let mut d = Decompress::new(false);
+        let err = d
+            .decompress(&[0x01, 5, 0, 5, 0], &mut decoded, FlushDecompress::Finish)
+            .unwrap_err();
+        if cfg!(feature = "any_zlib") {
+            assert_eq!(err.kind(), DecompressErrorKind::InvalidStoredBlock);
+        }
+
+        // A fixed Huffman block starting with a back-reference.
+        let mut d = Decompress::new(false);
+        let err = d
+            .decompress(&[0x03, 0x02, 0x00], &mut decoded, FlushDecompress::Finish)
+            .unwrap_err();
+        if cfg!(feature = "any_zlib") {
+            assert_eq!(err.kind(), DecompressErrorKind::DistanceTooFarBack);
+        }
+        assert!(err.needs_dictionary().is_none());
+
+        // A stored block followed by a fixed Huffman block using the invalid
+        // literal/length code 286, given a byte at a time. The stream
+        // wrappers report it as invalid input.
+        let stream = [
+            0, 5, 0, 0xfa, 0xff, b'h', b'e', b'l', b'l', b'o', 0x4b, 0x1c, 0x03,
+        ];
+        let mut r = read::DeflateDecoder::new_with_buf(&stream[..], vec![0; 1]);
+        let err = r.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let inner = err.get_ref().unwrap();
+        let inner = inner.downcast_ref::<DecompressError>().unwrap();
+        if cfg!(feature = "any_zlib") {
+            assert_eq!(inner.kind(), DecompressErrorKind::InvalidHuffmanCode);
+        }
+    }
+
+    #[test]
+    fn adler_mismatch() {
+        let mut e = write::ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"hello world").unwrap();
+        let mut encoded = e.finish().unwrap();
+        let n = encoded.len();
+        let stored = u32::from_be_bytes([
+            encoded[n - 4],
+            encoded[n - 3],
+            encoded[n - 2],
+            encoded[n - 1],
+        ]);
+        encoded[n - 1] ^= 1;
+
+        let mut decoded = vec![0; 100];
+        let mut d = Decompress::new(true);
+        let err = d
+            .decompress(&encoded, &mut decoded, FlushDecompress::Finish)
+            .unwrap_err();
+        // miniz.c doesn't say why a stream is invalid.
+        if cfg!(all(feature = "miniz-sys", not(feature = "any_zlib"))) {
+            assert_eq!(err.kind(), DecompressErrorKind::Corrupt);
+        } else {
+            assert_eq!(
+                err.kind(),
+                DecompressErrorKind::ChecksumMismatch {
+                    expected: stored ^ 1,
+                    actual: stored,
+                }
+            );
+        }
+        assert_eq!(err.offset(), Some(n as u64));
+    }
+
+    fn roundtrip(options: &CompressOptions, data: &[u8]) -> usize {
+        let mut e = write::ZlibEncoder::new_with_options(Vec::new(), options).unwrap();
+        e.write_all(data).unwrap();
+        let zlib = e.finish().unwrap();
+        let mut decoded = Vec::new();
+        read::ZlibDecoder::new(&zlib[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let mut e = read::DeflateEncoder::new_with_options(data, options).unwrap();
+        let mut deflate = Vec::new();
+        e.read_to_end(&mut deflate).unwrap();
+        let mut decoded = Vec::new();
+        read::DeflateDecoder::new(&deflate[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let mut e = bufread::GzEncoder::new_with_options(data, options).unwrap();
+        let mut gz = Vec::new();
+        e.read_to_end(&mut gz).unwrap();
+        let mut decoded = Vec::new();
+        read::GzDecoder::new(&gz[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        zlib.len()
+    }
+
+    #[test]
+    fn compress_options() {
+        let data = b"hello hello hello hello, this is some text to compress. ".repeat(100);
+        let strategies = [
+            Strategy::Default,
+            Strategy::Filtered,
+            Strategy::HuffmanOnly,
+            Strategy::Rle,
+            Strategy::Fixed,
+        ];
+        let mut levels = vec![0, 1, 6, 9];
+        if !cfg!(feature = "any_zlib") {
+            levels.push(10);
+        }
+        for &strategy in strategies.iter() {
+            for &level in levels.iter() {
+                let options = CompressOptions::new(Compression::new(level)).strategy(strategy);
+                roundtrip(&options, &data);
+            }
+        }
+
+        let default = roundtrip(&CompressOptions::default(), &data);
+        let huffman = roundtrip(
+            &CompressOptions::default().strategy(Strategy::HuffmanOnly),
+            &data,
+        );
+        assert!(huffman > default * 4);
+    }
+
+    #[test]
+    fn compress_options_window_and_mem_level() {
+        let data = b"hello hello hello hello, this is some text to compress. ".repeat(100);
+        roundtrip(&CompressOptions::default().mem_level(1), &data);
+        roundtrip(&CompressOptions::default().mem_level(9), &data);
+        if cfg!(feature = "any_zlib") {
+            roundtrip(&CompressOptions::default().window_bits(9), &data);
+        }
+    }
+
+    #[test]
+    fn invalid_compress_options() {
+        let max_level = if cfg!(feature = "any_zlib") { 9 } else { 10 };
+        let invalid = [
+            CompressOptions::new(Compression::new(max_level + 1)),
+            CompressOptions::default().window_bits(8),
+            CompressOptions::default().window_bits(16),
+            CompressOptions::default().mem_level(0),
+            CompressOptions::default().mem_level(10),
+            CompressOptions::default().strategy(Strategy::_Nonexhaustive),
+        ];
+        for options in invalid.iter() {
+            let err = write::GzEncoder::new_with_options(Vec::new(), options).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            let err = Compress::new_with_options(options, true).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let small_window = CompressOptions::default().window_bits(12);
+        assert_eq!(
+            Compress::new_with_options(&small_window, true).is_ok(),
+            cfg!(feature = "any_zlib")
+        );
+    }
+
+    #[test]
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    fn set_dictionary_streaming() {
+        let string = b"hello, hello! hello, world! ".repeat(50);
+        let dictionary = &b"hello, world!"[..];
+
+        let mut encoder = Compress::new(Compression::default(), true);
+        let dictionary_adler = encoder.set_dictionary(dictionary).unwrap();
+        let mut encoded = Vec::new();
+        // Use a tiny output buffer to split up the header and trailer.
+        let mut out = [0; 3];
+        let mut pos = 0;
+        loop {
+            let (before_in, before_out) = (encoder.total_in(), encoder.total_out());
+            let end = cmp::min(pos + 7, string.len());
+            let flush = if end == string.len() {
+                FlushCompress::Finish
+            } else {
+                FlushCompress::None
+            };
+            let status = encoder
+                .compress(&string[pos..end], &mut out, flush)
+                .unwrap();
+            pos += (encoder.total_in() - before_in) as usize;
+            encoded.extend_from_slice(&out[..(encoder.total_out() - before_out) as usize]);
+            if status == Status::StreamEnd {
+                break;
+            }
+        }
+        assert_eq!(pos, string.len());
+
+        let mut decoder = Decompress::new(true);
+        let mut decoded = Vec::with_capacity(string.len());
+        let mut pos = 0;
+        let err = loop {
+            let before = decoder.total_in();
+            match decoder.decompress_vec(
+                &encoded[pos..pos + 1],
+                &mut decoded,
+                FlushDecompress::None,
+            ) {
+                Ok(_) => pos += (decoder.total_in() - before) as usize,
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(pos, 5);
+        assert_eq!(decoder.total_in(), 6);
+        assert_eq!(err.needs_dictionary(), Some(dictionary_adler));
+        assert!(decoder.set_dictionary(b"wrong").is_err());
+        assert_eq!(
+            decoder.set_dictionary(dictionary).unwrap(),
+            dictionary_adler
+        );
+        let status = decoder
+            .decompress_vec(&encoded[6..], &mut decoded, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(decoded, string);
+    }
+
+    #[test]
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    fn set_level() {
+        let string = b"hello, hello! hello, world! ".repeat(100);
+        let mut encoder = Compress::new(Compression::none(), true);
+        let mut encoded = Vec::with_capacity(8192);
+        let half = string.len() / 2;
+        encoder
+            .compress_vec(&string[..half], &mut encoded, FlushCompress::Sync)
+            .unwrap();
+        encoder.set_level(Compression::best()).unwrap();
+        encoder
+            .compress_vec(&string[half..], &mut encoded, FlushCompress::Finish)
+            .unwrap();
+        assert!(encoded.len() < half + string.len() / 10);
+
+        let mut decoded = Vec::new();
+        read::ZlibDecoder::new(&encoded[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, string);
+    }
+
+    #[test]
+    fn decompress_window_bits() {
+        let mut e = write::ZlibEncoder::new(Vec::new(), Compression::default());
+        e.write_all(b"hello world").unwrap();
+        let encoded = e.finish().unwrap();
+
+        let mut decoded = vec![0; 100];
+        let mut d = Decompress::new_with_window_bits(true, 15);
+        d.decompress(&encoded, &mut decoded, FlushDecompress::Finish)
+            .unwrap();
+        assert_eq!(&decoded[..d.total_out() as usize], b"hello world");
+
+        // The header asks for a larger window than the decoder has.
+        let mut d = Decompress::new_with_window_bits(true, 9);
+        let res = d.decompress(&encoded, &mut decoded, FlushDecompress::Finish);
+        if cfg!(any(feature = "any_zlib", not(feature = "miniz-sys"))) {
+            assert_eq!(res.unwrap_err().kind(), DecompressErrorKind::InvalidHeader);
+        }
+
+        // Resetting keeps the window size.
+        d.reset(true);
+        let res = d.decompress(&encoded, &mut decoded, FlushDecompress::Finish);
+        if cfg!(any(feature = "any_zlib", not(feature = "miniz-sys"))) {
+            assert_eq!(res.unwrap_err().kind(), DecompressErrorKind::InvalidHeader);
+        }
+    }
 }
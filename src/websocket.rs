@@ -0,0 +1,552 @@
+//! The WebSocket per-message compression extension, permessage-deflate, of
+//! RFC 7692.
+//!
+//! Each message is compressed as raw DEFLATE data ending in a sync flush, with
+//! the final `00 00 ff ff` of the flush stripped off. Unless the peers
+//! negotiated otherwise with the `*_no_context_takeover` parameters, the LZ77
+//! window is kept between messages so that later messages can refer back to
+//! earlier ones.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use crate::{Compress, CompressError, Compression, Decompress, DecompressError};
+use crate::{FlushCompress, FlushDecompress, Status};
+
+/// The name of the extension in the `Sec-WebSocket-Extensions` header.
+const EXTENSION_NAME: &str = "permessage-deflate";
+
+/// The bytes a sync flush ends with, which aren't sent.
+const SYNC_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// The window bits used when no smaller window was negotiated.
+const MAX_WINDOW_BITS: u8 = 15;
+
+/// The parameters of the permessage-deflate extension, as found in an
+/// extension offer or response.
+///
+/// `Params` can be parsed from, and formatted as, an element of the
+/// `Sec-WebSocket-Extensions` header.
+///
+/// # Examples
+///
+/// ```
+/// use flate2::websocket::Params;
+///
+/// let offer: Params = "permessage-deflate; client_max_window_bits; \
+///                      server_no_context_takeover"
+///     .parse()
+///     .unwrap();
+/// assert!(offer.get_server_no_context_takeover());
+/// assert_eq!(offer.get_client_max_window_bits(), Some(15));
+///
+/// let response = Params::new()
+///     .server_no_context_takeover(true)
+///     .client_max_window_bits(Some(10));
+/// assert_eq!(
+///     response.to_string(),
+///     "permessage-deflate; server_no_context_takeover; client_max_window_bits=10"
+/// );
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Params {
+    server_no_context_takeover: bool,
+    client_no_context_takeover: bool,
+    server_max_window_bits: Option<u8>,
+    client_max_window_bits: Option<u8>,
+}
+
+impl Params {
+    /// Creates parameters with none of the options set, keeping the context
+    /// between messages and using 15 bit windows.
+    pub fn new() -> Params {
+        Params::default()
+    }
+
+    /// Sets whether the server resets its compression context after every
+    /// message.
+    pub fn server_no_context_takeover(mut self, reset: bool) -> Params {
+        self.server_no_context_takeover = reset;
+        self
+    }
+
+    /// Sets whether the client resets its compression context after every
+    /// message.
+    pub fn client_no_context_takeover(mut self, reset: bool) -> Params {
+        self.client_no_context_takeover = reset;
+        self
+    }
+
+    /// Sets the base-2 logarithm of the largest window the server may
+    /// compress with.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not in the range 8 ..= 15.
+    pub fn server_max_window_bits(mut self, bits: Option<u8>) -> Params {
+        self.server_max_window_bits = bits.map(check_window_bits);
+        self
+    }
+
+    /// Sets the base-2 logarithm of the largest window the client may
+    /// compress with.
+    ///
+    /// In an offer, `Some(15)` is formatted as the parameter with an explicit
+    /// value, which means the same as the parameter without one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not in the range 8 ..= 15.
+    pub fn client_max_window_bits(mut self, bits: Option<u8>) -> Params {
+        self.client_max_window_bits = bits.map(check_window_bits);
+        self
+    }
+
+    /// Returns whether the server resets its compression context after every
+    /// message.
+    pub fn get_server_no_context_takeover(&self) -> bool {
+        self.server_no_context_takeover
+    }
+
+    /// Returns whether the client resets its compression context after every
+    /// message.
+    pub fn get_client_no_context_takeover(&self) -> bool {
+        self.client_no_context_takeover
+    }
+
+    /// Returns the largest window the server may compress with, if limited.
+    pub fn get_server_max_window_bits(&self) -> Option<u8> {
+        self.server_max_window_bits
+    }
+
+    /// Returns the largest window the client may compress with, if limited.
+    ///
+    /// A `client_max_window_bits` parameter without a value, which a client
+    /// offers to signal that it supports the parameter, is returned as
+    /// `Some(15)`.
+    pub fn get_client_max_window_bits(&self) -> Option<u8> {
+        self.client_max_window_bits
+    }
+
+    /// Returns whether the compressor of `role` resets its context after every
+    /// message, and the base-2 logarithm of its window.
+    fn compressor(&self, role: Role) -> (bool, u8) {
+        let (reset, bits) = match role {
+            Role::Server => (self.server_no_context_takeover, self.server_max_window_bits),
+            Role::Client => (self.client_no_context_takeover, self.client_max_window_bits),
+        };
+        (reset, bits.unwrap_or(MAX_WINDOW_BITS))
+    }
+}
+
+fn check_window_bits(bits: u8) -> u8 {
+    assert!(
+        (8..=MAX_WINDOW_BITS).contains(&bits),
+        "window_bits must be within 8 ..= 15"
+    );
+    bits
+}
+
+impl FromStr for Params {
+    type Err = ParseParamsError;
+
+    /// Parses a single permessage-deflate element of a
+    /// `Sec-WebSocket-Extensions` header, such as
+    /// `permessage-deflate; client_max_window_bits=10`.
+    ///
+    /// Headers listing several extensions, or several offers, separate them
+    /// by commas and should be split before parsing.
+    fn from_str(s: &str) -> Result<Params, ParseParamsError> {
+        let mut parts = s.split(';').map(|part| part.trim());
+        if parts.next() != Some(EXTENSION_NAME) {
+            return Err(ParseParamsError("not a permessage-deflate extension"));
+        }
+        let mut params = Params::new();
+        let mut seen = [false; 4];
+        for part in parts {
+            let (name, value) = match part.find('=') {
+                Some(i) => (
+                    part[..i].trim_end(),
+                    Some(unquote(part[i + 1..].trim_start())?),
+                ),
+                None => (part, None),
+            };
+            let index = match name {
+                "server_no_context_takeover" => 0,
+                "client_no_context_takeover" => 1,
+                "server_max_window_bits" => 2,
+                "client_max_window_bits" => 3,
+                _ => return Err(ParseParamsError("unknown parameter")),
+            };
+            if seen[index] {
+                return Err(ParseParamsError("duplicate parameter"));
+            }
+            seen[index] = true;
+            match (index, value) {
+                (0, None) => params.server_no_context_takeover = true,
+                (1, None) => params.client_no_context_takeover = true,
+                (2, Some(value)) => params.server_max_window_bits = Some(parse_bits(value)?),
+                (3, Some(value)) => params.client_max_window_bits = Some(parse_bits(value)?),
+                (3, None) => params.client_max_window_bits = Some(MAX_WINDOW_BITS),
+                (0, Some(_)) | (1, Some(_)) => {
+                    return Err(ParseParamsError("unexpected parameter value"))
+                }
+                _ => return Err(ParseParamsError("missing parameter value")),
+            }
+        }
+        Ok(params)
+    }
+}
+
+/// Strips the quotes from a quoted-string parameter value, which may not
+/// contain escapes for any of the valid values.
+fn unquote(value: &str) -> Result<&str, ParseParamsError> {
+    if value.starts_with('"') {
+        if value.len() < 2 || !value.ends_with('"') {
+            return Err(ParseParamsError("unterminated quoted value"));
+        }
+        return Ok(&value[1..value.len() - 1]);
+    }
+    Ok(value)
+}
+
+fn parse_bits(value: &str) -> Result<u8, ParseParamsError> {
+    // The grammar only allows a 1*DIGIT without leading zeros.
+    if value.is_empty() || value.starts_with('0') || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseParamsError("invalid window bits"));
+    }
+    match value.parse() {
+        Ok(bits) if (8..=MAX_WINDOW_BITS).contains(&bits) => Ok(bits),
+        _ => Err(ParseParamsError("invalid window bits")),
+    }
+}
+
+impl fmt::Display for Params {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(EXTENSION_NAME)?;
+        if self.server_no_context_takeover {
+            f.write_str("; server_no_context_takeover")?;
+        }
+        if self.client_no_context_takeover {
+            f.write_str("; client_no_context_takeover")?;
+        }
+        if let Some(bits) = self.server_max_window_bits {
+            write!(f, "; server_max_window_bits={}", bits)?;
+        }
+        if let Some(bits) = self.client_max_window_bits {
+            write!(f, "; client_max_window_bits={}", bits)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when parsing invalid permessage-deflate parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseParamsError(&'static str);
+
+impl fmt::Display for ParseParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid permessage-deflate parameters: {}", self.0)
+    }
+}
+
+impl Error for ParseParamsError {}
+
+/// Which end of a WebSocket connection a compressor or decompressor is used
+/// by, which determines the parameters that apply to it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The server, which compresses with the `server_*` parameters and
+    /// decompresses with the `client_*` ones.
+    Server,
+    /// The client, which compresses with the `client_*` parameters and
+    /// decompresses with the `server_*` ones.
+    Client,
+}
+
+impl Role {
+    fn peer(self) -> Role {
+        match self {
+            Role::Server => Role::Client,
+            Role::Client => Role::Server,
+        }
+    }
+}
+
+/// Compresses the payloads of WebSocket messages.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() { run().unwrap() }
+/// # fn run() -> std::io::Result<()> {
+/// use flate2::Compression;
+/// use flate2::websocket::{MessageCompressor, MessageDecompressor, Params, Role};
+///
+/// let params = Params::new();
+/// let mut compressor = MessageCompressor::new(Compression::default(), &params, Role::Server)?;
+/// let mut decompressor = MessageDecompressor::new(&params, Role::Client);
+///
+/// let mut payload = Vec::new();
+/// compressor.compress_message(b"Hello", &mut payload)?;
+/// let mut message = Vec::new();
+/// decompressor.decompress_message(&payload, &mut message)?;
+/// assert_eq!(message, b"Hello");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MessageCompressor {
+    compress: Compress,
+    no_context_takeover: bool,
+}
+
+impl MessageCompressor {
+    /// Creates a compressor for the messages sent by `role` on a connection
+    /// which negotiated `params`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the negotiated window can't
+    /// be used for compression. zlib doesn't support 8 bit windows for raw
+    /// DEFLATE, and other backends only support 15 bit windows.
+    pub fn new(level: Compression, params: &Params, role: Role) -> io::Result<MessageCompressor> {
+        let (no_context_takeover, window_bits) = params.compressor(role);
+        Ok(MessageCompressor {
+            compress: new_compress(level, window_bits)?,
+            no_context_takeover,
+        })
+    }
+
+    /// Compresses `message`, appending its payload to `out`.
+    pub fn compress_message(
+        &mut self,
+        message: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<(), CompressError> {
+        let start = out.len();
+        let mut pos = 0;
+        loop {
+            out.reserve(message.len() - pos + 64);
+            let before = self.compress.total_in();
+            self.compress
+                .compress_vec(&message[pos..], out, FlushCompress::Sync)?;
+            pos += (self.compress.total_in() - before) as usize;
+            if pos == message.len() && out.len() < out.capacity() {
+                break;
+            }
+        }
+        if out[start..].ends_with(&SYNC_TAIL) {
+            out.truncate(out.len() - SYNC_TAIL.len());
+        }
+        if self.no_context_takeover {
+            self.compress.reset();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "any_zlib")]
+fn new_compress(level: Compression, window_bits: u8) -> io::Result<Compress> {
+    if window_bits < 9 {
+        return Err(unsupported_window(window_bits));
+    }
+    Ok(Compress::new_with_window_bits(level, false, window_bits))
+}
+
+#[cfg(not(feature = "any_zlib"))]
+fn new_compress(level: Compression, window_bits: u8) -> io::Result<Compress> {
+    if window_bits < MAX_WINDOW_BITS {
+        return Err(unsupported_window(window_bits));
+    }
+    Ok(Compress::new(level, false))
+}
+
+fn unsupported_window(window_bits: u8) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "compression with {} bit windows is unsupported",
+            window_bits
+        ),
+    )
+}
+
+/// Decompresses the payloads of WebSocket messages.
+///
+/// See [`MessageCompressor`] for an example.
+///
+/// [`MessageCompressor`]: struct.MessageCompressor.html
+#[derive(Debug)]
+pub struct MessageDecompressor {
+    decompress: Decompress,
+    no_context_takeover: bool,
+}
+
+impl MessageDecompressor {
+    /// Creates a decompressor for the messages received by `role` on a
+    /// connection which negotiated `params`.
+    pub fn new(params: &Params, role: Role) -> MessageDecompressor {
+        let (no_context_takeover, window_bits) = params.compressor(role.peer());
+        MessageDecompressor {
+            decompress: new_decompress(window_bits),
+            no_context_takeover,
+        }
+    }
+
+    /// Decompresses the payload of a message, appending the message to `out`.
+    pub fn decompress_message(
+        &mut self,
+        payload: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<(), DecompressError> {
+        let mut ended = false;
+        for input in [payload, &SYNC_TAIL[..]].iter() {
+            let mut pos = 0;
+            loop {
+                out.reserve(2 * (input.len() - pos) + 64);
+                let before = self.decompress.total_in();
+                let status =
+                    self.decompress
+                        .decompress_vec(&input[pos..], out, FlushDecompress::Sync)?;
+                pos += (self.decompress.total_in() - before) as usize;
+                if status == Status::StreamEnd {
+                    ended = true;
+                    break;
+                }
+                if pos == input.len() && out.len() < out.capacity() {
+                    break;
+                }
+            }
+            if ended {
+                break;
+            }
+        }
+        // A final block ends the stream, so the next message starts a new one.
+        if ended || self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+        Ok(())
+    }
+}
+
+fn new_decompress(window_bits: u8) -> Decompress {
+    // Larger windows accept all data compressed with smaller ones.
+    Decompress::new_with_window_bits(false, window_bits.max(9))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MessageCompressor, MessageDecompressor, Params, Role};
+    use crate::Compression;
+
+    #[test]
+    fn parse() {
+        let params: Params = "permessage-deflate;client_max_window_bits=\"10\" ; \
+                              server_no_context_takeover"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            params,
+            Params::new()
+                .client_max_window_bits(Some(10))
+                .server_no_context_takeover(true)
+        );
+        let params: Params = params.to_string().parse().unwrap();
+        assert_eq!(params.get_client_max_window_bits(), Some(10));
+
+        for bad in [
+            "x-webkit-deflate-frame",
+            "permessage-deflate; foo",
+            "permessage-deflate; server_no_context_takeover; server_no_context_takeover",
+            "permessage-deflate; server_no_context_takeover=1",
+            "permessage-deflate; server_max_window_bits",
+            "permessage-deflate; server_max_window_bits=7",
+            "permessage-deflate; server_max_window_bits=16",
+            "permessage-deflate; server_max_window_bits=010",
+            "permessage-deflate; client_max_window_bits=\"10",
+        ]
+        .iter()
+        {
+            assert!(bad.parse::<Params>().is_err(), "{}", bad);
+        }
+    }
+
+    // The examples of RFC 7692 section 7.2.3.
+    #[test]
+    fn rfc_examples() {
+        let mut d = MessageDecompressor::new(&Params::new(), Role::Client);
+        let mut out = Vec::new();
+        d.decompress_message(&[0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00], &mut out)
+            .unwrap();
+        // The second message refers back to the first.
+        d.decompress_message(&[0xf2, 0x00, 0x11, 0x00, 0x00], &mut out)
+            .unwrap();
+        // A stored block.
+        d.decompress_message(
+            &[
+                0x00, 0x05, 0x00, 0xfa, 0xff, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x00,
+            ],
+            &mut out,
+        )
+        .unwrap();
+        // A final block.
+        d.decompress_message(&[0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00, 0x00], &mut out)
+            .unwrap();
+        d.decompress_message(&[0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00], &mut out)
+            .unwrap();
+        assert_eq!(out, b"HelloHelloHelloHelloHello");
+    }
+
+    #[test]
+    fn context_takeover() {
+        let message = b"a message which is longer than its back reference";
+        for &reset in [false, true].iter() {
+            let params = Params::new().client_no_context_takeover(reset);
+            let mut c =
+                MessageCompressor::new(Compression::default(), &params, Role::Client).unwrap();
+            let mut d = MessageDecompressor::new(&params, Role::Server);
+
+            let mut first = Vec::new();
+            c.compress_message(message, &mut first).unwrap();
+            assert!(!first.ends_with(&[0x00, 0x00, 0xff, 0xff]));
+            let mut second = Vec::new();
+            c.compress_message(message, &mut second).unwrap();
+            assert_eq!(reset, first == second);
+            let mut empty = Vec::new();
+            c.compress_message(b"", &mut empty).unwrap();
+
+            let mut out = Vec::new();
+            for payload in [&first, &second, &empty].iter() {
+                d.decompress_message(payload, &mut out).unwrap();
+            }
+            assert_eq!(out, [&message[..], &message[..]].concat());
+        }
+    }
+
+    #[test]
+    fn large_message() {
+        let message = crate::random_bytes().take(300_000).collect::<Vec<_>>();
+        let params = Params::new();
+        let mut c = MessageCompressor::new(Compression::fast(), &params, Role::Server).unwrap();
+        let mut d = MessageDecompressor::new(&params, Role::Client);
+        let mut payload = Vec::new();
+        c.compress_message(&message, &mut payload).unwrap();
+        let mut out = Vec::new();
+        d.decompress_message(&payload, &mut out).unwrap();
+        assert!(out == message);
+    }
+
+    #[test]
+    fn window_bits() {
+        let params = Params::new().server_max_window_bits(Some(8));
+        assert!(MessageCompressor::new(Compression::default(), &params, Role::Server).is_err());
+        assert!(MessageCompressor::new(Compression::default(), &params, Role::Client).is_ok());
+
+        let params = Params::new().server_max_window_bits(Some(10));
+        let c = MessageCompressor::new(Compression::default(), &params, Role::Server);
+        assert_eq!(c.is_ok(), cfg!(feature = "any_zlib"));
+    }
+}
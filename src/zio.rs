@@ -2,6 +2,8 @@ use std::io;
 use std::io::prelude::*;
 use std::mem;
 
+use crate::mem::io_error;
+use crate::GzDecompress;
 use crate::{Compress, Decompress, DecompressError, FlushCompress, FlushDecompress, Status};
 
 #[derive(Debug)]
@@ -27,6 +29,11 @@ pub trait Ops {
         output: &mut Vec<u8>,
         flush: Self::Flush,
     ) -> Result<Status, DecompressError>;
+    /// Whether the end of the stream has been reached, after which `read`
+    /// doesn't touch its input anymore.
+    fn ended(&self) -> bool {
+        false
+    }
 }
 
 impl Ops for Compress {
@@ -79,6 +86,35 @@ impl Ops for Decompress {
     ) -> Result<Status, DecompressError> {
         self.decompress_vec(input, output, flush)
     }
+    fn ended(&self) -> bool {
+        self.ended()
+    }
+}
+
+impl Ops for GzDecompress {
+    type Flush = FlushDecompress;
+    fn total_in(&self) -> u64 {
+        self.total_in()
+    }
+    fn total_out(&self) -> u64 {
+        self.total_out()
+    }
+    fn run(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: FlushDecompress,
+    ) -> Result<Status, DecompressError> {
+        self.decompress(input, output, flush)
+    }
+    fn run_vec(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        flush: FlushDecompress,
+    ) -> Result<Status, DecompressError> {
+        self.decompress_vec(input, output, flush)
+    }
 }
 
 pub trait Flush {
@@ -120,6 +156,9 @@ where
     R: BufRead,
     D: Ops,
 {
+    if data.ended() {
+        return Ok(0);
+    }
     loop {
         let (read, consumed, ret, eof);
         {
@@ -146,12 +185,7 @@ where
             Ok(Status::Ok) | Ok(Status::BufError) if read == 0 && !eof && dst.len() > 0 => continue,
             Ok(Status::Ok) | Ok(Status::BufError) | Ok(Status::StreamEnd) => return Ok(read),
 
-            Err(..) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "corrupt deflate stream",
-                ))
-            }
+            Err(e) => return Err(io_error(e)),
         }
     }
 }
@@ -170,7 +204,9 @@ impl<W: Write, D: Ops> Writer<W, D> {
             self.dump()?;
 
             let before = self.data.total_out();
-            self.data.run_vec(&[], &mut self.buf, D::Flush::finish())?;
+            self.data
+                .run_vec(&[], &mut self.buf, D::Flush::finish())
+                .map_err(io_error)?;
             if before == self.data.total_out() {
                 return Ok(());
             }
@@ -229,10 +265,7 @@ impl<W: Write, D: Ops> Writer<W, D> {
                 Ok(st) => match st {
                     Status::Ok | Status::BufError | Status::StreamEnd => Ok((written, st)),
                 },
-                Err(..) => Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    "corrupt deflate stream",
-                )),
+                Err(e) => Err(io_error(e)),
             };
         }
     }
@@ -259,7 +292,7 @@ impl<W: Write, D: Ops> Write for Writer<W, D> {
     fn flush(&mut self) -> io::Result<()> {
         self.data
             .run_vec(&[], &mut self.buf, D::Flush::sync())
-            .unwrap();
+            .map_err(io_error)?;
 
         // Unfortunately miniz doesn't actually tell us when we're done with
         // pulling out all the data from the internal stream. To remedy this we
@@ -271,7 +304,7 @@ impl<W: Write, D: Ops> Write for Writer<W, D> {
             let before = self.data.total_out();
             self.data
                 .run_vec(&[], &mut self.buf, D::Flush::none())
-                .unwrap();
+                .map_err(io_error)?;
             if before == self.data.total_out() {
                 break;
             }
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::io;
 use std::io::prelude::*;
 use std::mem;
@@ -7,8 +8,12 @@ use futures::Poll;
 #[cfg(feature = "tokio")]
 use tokio_io::{AsyncRead, AsyncWrite};
 
+use super::HeaderBytes;
+use crate::dictionary;
 use crate::zio;
-use crate::{Compress, Decompress};
+use crate::{
+    Compress, CompressOptions, Decompress, DecompressLimits, DictionaryRegistry, ZlibHeader,
+};
 
 /// A ZLIB encoder, or compressor.
 ///
@@ -52,6 +57,40 @@ impl<R: BufRead> ZlibEncoder<R> {
             data: Compress::new(level, true),
         }
     }
+
+    /// Creates a new encoder like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(r: R, options: &CompressOptions) -> io::Result<ZlibEncoder<R>> {
+        Ok(ZlibEncoder {
+            obj: r,
+            data: Compress::new_with_options(options, true)?,
+        })
+    }
+
+    /// Creates a new encoder like `new`, which compresses with the given
+    /// preset dictionary.
+    ///
+    /// Its Adler-32 checksum is written to the header, and the same dictionary
+    /// is needed to decompress the data. It is discarded
+    /// by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the backend in use doesn't
+    /// support dictionaries.
+    pub fn new_with_dictionary(
+        r: R,
+        level: crate::Compression,
+        dictionary: &[u8],
+    ) -> io::Result<ZlibEncoder<R>> {
+        Ok(ZlibEncoder {
+            obj: r,
+            data: dictionary::compressor(level, true, dictionary)?,
+        })
+    }
 }
 
 pub fn reset_encoder_data<R>(zlib: &mut ZlibEncoder<R>) {
@@ -104,6 +143,22 @@ impl<R> ZlibEncoder<R> {
     pub fn total_out(&self) -> u64 {
         self.data.total_out()
     }
+
+    /// Sets the preset dictionary to compress with, returning its Adler-32
+    /// checksum, which is written to the header as the dictionary identifier.
+    ///
+    /// The dictionary must be set before any data is compressed, and it is
+    /// discarded by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if data has already been
+    /// compressed or the backend in use doesn't support dictionaries.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> io::Result<u32> {
+        self.data
+            .set_dictionary(dictionary)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
 }
 
 impl<R: BufRead> Read for ZlibEncoder<R> {
@@ -169,6 +224,8 @@ impl<R: AsyncWrite + BufRead> AsyncWrite for ZlibEncoder<R> {
 pub struct ZlibDecoder<R> {
     obj: R,
     data: Decompress,
+    header: HeaderBytes,
+    dictionaries: DictionaryRegistry,
 }
 
 impl<R: BufRead> ZlibDecoder<R> {
@@ -178,12 +235,15 @@ impl<R: BufRead> ZlibDecoder<R> {
         ZlibDecoder {
             obj: r,
             data: Decompress::new(true),
+            header: HeaderBytes::default(),
+            dictionaries: DictionaryRegistry::new(),
         }
     }
 }
 
 pub fn reset_decoder_data<R>(zlib: &mut ZlibDecoder<R>) {
-    zlib.data = Decompress::new(true);
+    zlib.data.reset(true);
+    zlib.header = HeaderBytes::default();
 }
 
 impl<R> ZlibDecoder<R> {
@@ -229,11 +289,100 @@ impl<R> ZlibDecoder<R> {
     pub fn total_out(&self) -> u64 {
         self.data.total_out()
     }
+
+    /// Returns the header of the zlib stream, once it has been read.
+    ///
+    /// The header is available as soon as decompression starts, even if it
+    /// then fails because a preset dictionary is needed.
+    pub fn header(&self) -> Option<&ZlibHeader> {
+        self.header.header()
+    }
+
+    /// Sets the preset dictionaries this decoder looks up the dictionary
+    /// identified in the header of a stream in, see [`DictionaryRegistry`].
+    ///
+    /// Without the dictionary a stream needs, decoding fails with an error
+    /// wrapping a [`DecompressError`] whose [`needs_dictionary`] returns
+    /// its Adler-32 checksum.
+    ///
+    /// [`DictionaryRegistry`]: ../struct.DictionaryRegistry.html
+    /// [`DecompressError`]: ../struct.DecompressError.html
+    /// [`needs_dictionary`]: ../struct.DecompressError.html#method.needs_dictionary
+    pub fn set_dictionaries(&mut self, dictionaries: DictionaryRegistry) {
+        self.dictionaries = dictionaries;
+    }
+
+    fn read_data(&mut self, into: &mut [u8]) -> io::Result<usize>
+    where
+        R: BufRead,
+    {
+        if self.header.wanted() == 0 {
+            return zio::read(&mut self.obj, &mut self.data, into);
+        }
+        let mut obj = HeaderReader {
+            inner: &mut self.obj,
+            header: &mut self.header,
+            peeked: [0; 6],
+            peeked_len: 0,
+        };
+        zio::read(&mut obj, &mut self.data, into)
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.data.set_limits(limits);
+    }
 }
 
 impl<R: BufRead> Read for ZlibDecoder<R> {
     fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
-        zio::read(&mut self.obj, &mut self.data, into)
+        loop {
+            match self.read_data(into) {
+                Err(ref e) if self.dictionaries.supply(&mut self.data, e) => continue,
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Passes the input through to the decompressor, collecting the zlib header
+/// from the bytes it consumes.
+struct HeaderReader<'a, R> {
+    inner: &'a mut R,
+    header: &'a mut HeaderBytes,
+    peeked: [u8; 6],
+    peeked_len: usize,
+}
+
+impl<'a, R: BufRead> Read for HeaderReader<'a, R> {
+    fn read(&mut self, into: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let buf = self.fill_buf()?;
+            let n = cmp::min(buf.len(), into.len());
+            into[..n].copy_from_slice(&buf[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<'a, R: BufRead> BufRead for HeaderReader<'a, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buf = self.inner.fill_buf()?;
+        self.peeked_len = cmp::min(buf.len(), self.header.wanted());
+        self.peeked[..self.peeked_len].copy_from_slice(&buf[..self.peeked_len]);
+        Ok(buf)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let n = cmp::min(amt, self.peeked_len);
+        self.header.push(&self.peeked[..n]);
+        self.peeked_len = 0;
+        self.inner.consume(amt);
     }
 }
 
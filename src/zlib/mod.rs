@@ -1,7 +1,177 @@
+use std::io;
+
+use crate::mem::io_error;
+use crate::{DecompressError, DecompressErrorKind};
+
 pub mod bufread;
 pub mod read;
 pub mod write;
 
+const FDICT: u8 = 1 << 5;
+
+/// The header of a zlib stream, as described in RFC 1950.
+///
+/// The header declares the size of the window needed to decompress the
+/// stream, hints at the compression level used, and says whether a preset
+/// dictionary is needed. The zlib decoders parse it as the stream starts,
+/// so a needed dictionary is known before decompression fails on it.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use flate2::{Compression, ZlibHeader};
+/// use flate2::read::ZlibDecoder;
+/// use flate2::write::ZlibEncoder;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let mut e = ZlibEncoder::new(Vec::new(), Compression::best());
+/// e.write_all(b"Hello World")?;
+/// let bytes = e.finish()?;
+///
+/// let (header, len) = ZlibHeader::parse(&bytes)?;
+/// assert_eq!((header.window_size(), header.level(), len), (32768, 3, 2));
+///
+/// let mut d = ZlibDecoder::new(&bytes[..]);
+/// d.read_to_end(&mut Vec::new())?;
+/// assert_eq!(d.header(), Some(&header));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ZlibHeader {
+    cmf: u8,
+    flg: u8,
+    dictionary_id: Option<u32>,
+}
+
+impl ZlibHeader {
+    /// Parses a complete zlib header from the start of `bytes`, returning it
+    /// along with the length of the header in bytes, which is 6 if it has a
+    /// dictionary identifier and 2 otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `UnexpectedEof` if the header is truncated,
+    /// or of kind `InvalidInput` if it isn't a valid zlib header. Either
+    /// wraps a [`DecompressError`] telling the two apart.
+    ///
+    /// [`DecompressError`]: struct.DecompressError.html
+    pub fn parse(bytes: &[u8]) -> io::Result<(ZlibHeader, usize)> {
+        let truncated = || io_error(DecompressError::new(DecompressErrorKind::TruncatedInput));
+        let (cmf, flg) = match *bytes {
+            [cmf, flg, ..] => (cmf, flg),
+            _ => return Err(truncated()),
+        };
+        if let Some(kind) = header_error(cmf, flg, 15) {
+            return Err(io_error(DecompressError::new(kind).at(0)));
+        }
+        if flg & FDICT == 0 {
+            let header = ZlibHeader {
+                cmf,
+                flg,
+                dictionary_id: None,
+            };
+            return Ok((header, 2));
+        }
+        match bytes.get(2..6) {
+            Some(id) => {
+                let header = ZlibHeader {
+                    cmf,
+                    flg,
+                    dictionary_id: Some(u32::from_be_bytes([id[0], id[1], id[2], id[3]])),
+                };
+                Ok((header, 6))
+            }
+            None => Err(truncated()),
+        }
+    }
+
+    /// Returns the base-2 logarithm of the window size the stream was
+    /// compressed with, between 8 and 15.
+    pub fn window_bits(&self) -> u8 {
+        (self.cmf >> 4) + 8
+    }
+
+    /// Returns the size in bytes of the window the stream was compressed
+    /// with, which is needed to decompress it.
+    pub fn window_size(&self) -> usize {
+        1 << self.window_bits()
+    }
+
+    /// Returns the `FLEVEL` hint of the compression level used, which is
+    /// 0 for the fastest compression, 1 for fast, 2 for the default and 3
+    /// for the best.
+    ///
+    /// The hint isn't needed for decompression, and is only useful to decide
+    /// whether recompressing the data could be worthwhile.
+    pub fn level(&self) -> u8 {
+        self.flg >> 6
+    }
+
+    /// Returns the Adler-32 checksum of the preset dictionary the stream was
+    /// compressed with, if any (the `DICTID` field).
+    pub fn dictionary_id(&self) -> Option<u32> {
+        self.dictionary_id
+    }
+}
+
+/// Returns why the first two bytes of a zlib stream aren't a valid header
+/// for a window of at most `window_bits`, if they aren't.
+pub(crate) fn header_error(cmf: u8, flg: u8, window_bits: u8) -> Option<DecompressErrorKind> {
+    if ((u16::from(cmf) << 8) | u16::from(flg)) % 31 != 0 {
+        Some(DecompressErrorKind::BadMagic)
+    } else if cmf & 0x0f != 8 {
+        Some(DecompressErrorKind::UnsupportedMethod)
+    } else if (cmf >> 4) + 8 > window_bits {
+        Some(DecompressErrorKind::InvalidHeader)
+    } else {
+        None
+    }
+}
+
+/// Collects the header of a zlib stream from the bytes the decompressor
+/// consumes.
+#[derive(Debug, Default)]
+pub(crate) struct HeaderBytes {
+    buf: [u8; 6],
+    len: usize,
+    done: bool,
+    header: Option<ZlibHeader>,
+}
+
+impl HeaderBytes {
+    /// Returns how many more bytes the header could need at most.
+    pub(crate) fn wanted(&self) -> usize {
+        if self.done {
+            0
+        } else {
+            self.buf.len() - self.len
+        }
+    }
+
+    /// Adds bytes consumed by the decompressor, ignoring any past the header.
+    pub(crate) fn push(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() && self.wanted() > 0 {
+            self.buf[self.len] = bytes[0];
+            self.len += 1;
+            bytes = &bytes[1..];
+            match ZlibHeader::parse(&self.buf[..self.len]) {
+                Ok((header, _)) => {
+                    self.header = Some(header);
+                    self.done = true;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+                Err(_) => self.done = true,
+            }
+        }
+    }
+
+    pub(crate) fn header(&self) -> Option<&ZlibHeader> {
+        self.header.as_ref()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io;
@@ -9,8 +179,8 @@ mod tests {
 
     use rand::{thread_rng, Rng};
 
-    use crate::zlib::{read, write};
-    use crate::Compression;
+    use crate::zlib::{bufread, read, write};
+    use crate::{Compression, DecompressError, DecompressErrorKind, ZlibHeader};
 
     #[test]
     fn roundtrip() {
@@ -130,6 +300,100 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_header() {
+        let (header, len) = ZlibHeader::parse(&[0x78, 0x9c, 0xff]).unwrap();
+        assert_eq!(len, 2);
+        assert_eq!(header.window_bits(), 15);
+        assert_eq!(header.level(), 2);
+        assert_eq!(header.dictionary_id(), None);
+
+        let (header, len) = ZlibHeader::parse(&[0x28, 0xee, 0, 0, 1, 2]).unwrap();
+        assert_eq!(len, 6);
+        assert_eq!(header.window_size(), 1024);
+        assert_eq!(header.level(), 3);
+        assert_eq!(header.dictionary_id(), Some(0x0102));
+
+        for bytes in &[&[0x78][..], &[0x78, 0xf9, 0, 0, 1]] {
+            let err = ZlibHeader::parse(bytes).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+        let bad = [
+            (&[0x78, 0x9d], DecompressErrorKind::BadMagic),
+            (&[0x79, 0x94], DecompressErrorKind::UnsupportedMethod),
+            (&[0x88, 0x98], DecompressErrorKind::InvalidHeader),
+        ];
+        for &(bytes, kind) in &bad {
+            let err = ZlibHeader::parse(bytes).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            let inner = err.get_ref().unwrap();
+            assert_eq!(
+                inner.downcast_ref::<DecompressError>().unwrap().kind(),
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn decoder_header() {
+        for &(level, hint) in &[(0, 0), (1, 0), (3, 1), (5, 1), (6, 2), (8, 3), (9, 3)] {
+            let mut e = write::ZlibEncoder::new(Vec::new(), Compression::new(level));
+            e.write_all(b"hello world").unwrap();
+            let data = e.finish().unwrap();
+            let expected = ZlibHeader::parse(&data).unwrap().0;
+            assert_eq!(expected.level(), hint);
+
+            let mut r = read::ZlibDecoder::new_with_buf(&data[..], vec![0; 1]);
+            assert_eq!(r.header(), None);
+            r.read_to_end(&mut Vec::new()).unwrap();
+            assert_eq!(r.header(), Some(&expected));
+
+            let mut r = bufread::ZlibDecoder::new(&data[..]);
+            r.read_exact(&mut [0; 1]).unwrap();
+            assert_eq!(r.header(), Some(&expected));
+
+            let mut w = write::ZlibDecoder::new(Vec::new());
+            for b in data.chunks(1) {
+                w.write_all(b).unwrap();
+            }
+            assert_eq!(w.header(), Some(&expected));
+            w.reset(Vec::new()).unwrap();
+            assert_eq!(w.header(), None);
+        }
+    }
+
+    #[test]
+    #[cfg(any(feature = "any_zlib", not(feature = "miniz-sys")))]
+    fn dictionary_header() {
+        let dictionary = b"hello world hello world";
+        let mut e = write::ZlibEncoder::new(Vec::new(), Compression::default());
+        let id = e.set_dictionary(dictionary).unwrap();
+        e.write_all(b"hello world").unwrap();
+        let err = e.set_dictionary(dictionary).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let data = e.finish().unwrap();
+
+        let mut r = read::ZlibEncoder::new(&b"hello world"[..], Compression::default());
+        r.set_dictionary(dictionary).unwrap();
+        let mut read_data = Vec::new();
+        r.read_to_end(&mut read_data).unwrap();
+        assert_eq!(read_data, data);
+
+        let (header, len) = ZlibHeader::parse(&data).unwrap();
+        assert_eq!(
+            (header.dictionary_id(), header.level(), len),
+            (Some(id), 2, 6)
+        );
+
+        let mut r = read::ZlibDecoder::new(&data[..]);
+        assert!(r.read_to_end(&mut Vec::new()).is_err());
+        assert_eq!(r.header(), Some(&header));
+
+        let mut w = write::ZlibDecoder::new(Vec::new());
+        assert!(w.write_all(&data).is_err());
+        assert_eq!(w.header(), Some(&header));
+    }
+
     #[test]
     fn qc_reader() {
         ::quickcheck::quickcheck(test as fn(_) -> _);
@@ -8,6 +8,7 @@ use tokio_io::{AsyncRead, AsyncWrite};
 
 use super::bufread;
 use crate::bufreader::BufReader;
+use crate::{CompressOptions, DecompressLimits, DictionaryRegistry, ZlibHeader};
 
 /// A ZLIB encoder, or compressor.
 ///
@@ -47,6 +48,38 @@ impl<R: Read> ZlibEncoder<R> {
             inner: bufread::ZlibEncoder::new(BufReader::new(r), level),
         }
     }
+
+    /// Creates a new encoder like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(r: R, options: &CompressOptions) -> io::Result<ZlibEncoder<R>> {
+        Ok(ZlibEncoder {
+            inner: bufread::ZlibEncoder::new_with_options(BufReader::new(r), options)?,
+        })
+    }
+
+    /// Creates a new encoder like `new`, which compresses with the given
+    /// preset dictionary.
+    ///
+    /// Its Adler-32 checksum is written to the header, and the same dictionary
+    /// is needed to decompress the data. It is discarded
+    /// by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the backend in use doesn't
+    /// support dictionaries.
+    pub fn new_with_dictionary(
+        r: R,
+        level: crate::Compression,
+        dictionary: &[u8],
+    ) -> io::Result<ZlibEncoder<R>> {
+        let inner =
+            bufread::ZlibEncoder::new_with_dictionary(BufReader::new(r), level, dictionary)?;
+        Ok(ZlibEncoder { inner })
+    }
 }
 
 impl<R> ZlibEncoder<R> {
@@ -102,6 +135,20 @@ impl<R> ZlibEncoder<R> {
     pub fn total_out(&self) -> u64 {
         self.inner.total_out()
     }
+
+    /// Sets the preset dictionary to compress with, returning its Adler-32
+    /// checksum, which is written to the header as the dictionary identifier.
+    ///
+    /// The dictionary must be set before any data is compressed, and it is
+    /// discarded by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if data has already been
+    /// compressed or the backend in use doesn't support dictionaries.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> io::Result<u32> {
+        self.inner.set_dictionary(dictionary)
+    }
 }
 
 impl<R: Read> Read for ZlibEncoder<R> {
@@ -219,11 +266,44 @@ impl<R> ZlibDecoder<R> {
     ///
     /// Note that there may be buffered bytes which are not re-acquired as part
     /// of this transition. It's recommended to only call this function after
-    /// EOF has been reached.
+    /// EOF has been reached, or to use `into_parts` instead.
     pub fn into_inner(self) -> R {
         self.inner.into_inner().into_inner()
     }
 
+    /// Consumes this decoder, returning the underlying reader along with the
+    /// bytes which were read from it but not decompressed.
+    ///
+    /// After the end of the stream has been reached, these bytes followed by
+    /// the rest of the underlying reader are exactly the data following the
+    /// compressed stream. To leave the underlying reader positioned right
+    /// after the stream instead, create the decoder with `new_with_buf` and a
+    /// one byte buffer, at the cost of reading the input a byte at a time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::io::prelude::*;
+    /// use flate2::Compression;
+    /// use flate2::read::ZlibDecoder;
+    /// use flate2::write::ZlibEncoder;
+    ///
+    /// let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+    /// e.write_all(b"Hello World").unwrap();
+    /// let mut bytes = e.finish().unwrap();
+    /// bytes.extend_from_slice(b"more data");
+    ///
+    /// let mut d = ZlibDecoder::new(&bytes[..]);
+    /// let mut s = String::new();
+    /// d.read_to_string(&mut s).unwrap();
+    /// let (rest, buffered) = d.into_parts();
+    /// assert_eq!(buffered, b"more data");
+    /// assert!(rest.is_empty());
+    /// ```
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        self.inner.into_inner().into_parts()
+    }
+
     /// Returns the number of bytes that the decompressor has consumed.
     ///
     /// Note that this will likely be smaller than what the decompressor
@@ -236,6 +316,36 @@ impl<R> ZlibDecoder<R> {
     pub fn total_out(&self) -> u64 {
         self.inner.total_out()
     }
+
+    /// Returns the header of the zlib stream, once it has been read.
+    ///
+    /// The header is available as soon as decompression starts, even if it
+    /// then fails because a preset dictionary is needed.
+    pub fn header(&self) -> Option<&ZlibHeader> {
+        self.inner.header()
+    }
+
+    /// Sets the preset dictionaries this decoder looks up the dictionary
+    /// identified in the header of a stream in, see [`DictionaryRegistry`].
+    ///
+    /// Without the dictionary a stream needs, decoding fails with an error
+    /// wrapping a [`DecompressError`] whose [`needs_dictionary`] returns
+    /// its Adler-32 checksum.
+    ///
+    /// [`DictionaryRegistry`]: ../struct.DictionaryRegistry.html
+    /// [`DecompressError`]: ../struct.DecompressError.html
+    /// [`needs_dictionary`]: ../struct.DecompressError.html#method.needs_dictionary
+    pub fn set_dictionaries(&mut self, dictionaries: DictionaryRegistry) {
+        self.inner.set_dictionaries(dictionaries);
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.set_limits(limits);
+    }
 }
 
 impl<R: Read> Read for ZlibDecoder<R> {
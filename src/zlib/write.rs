@@ -6,8 +6,12 @@ use futures::Poll;
 #[cfg(feature = "tokio")]
 use tokio_io::{AsyncRead, AsyncWrite};
 
+use super::HeaderBytes;
+use crate::dictionary;
 use crate::zio;
-use crate::{Compress, Decompress};
+use crate::{
+    Compress, CompressOptions, Decompress, DecompressLimits, DictionaryRegistry, ZlibHeader,
+};
 
 /// A ZLIB encoder, or compressor.
 ///
@@ -49,6 +53,38 @@ impl<W: Write> ZlibEncoder<W> {
         }
     }
 
+    /// Creates a new encoder like `new`, but with the given options instead
+    /// of just a compression level.
+    ///
+    /// Returns an error of kind `InvalidInput` if the options aren't supported
+    /// by the backend in use.
+    pub fn new_with_options(w: W, options: &CompressOptions) -> io::Result<ZlibEncoder<W>> {
+        Ok(ZlibEncoder {
+            inner: zio::Writer::new(w, Compress::new_with_options(options, true)?),
+        })
+    }
+
+    /// Creates a new encoder like `new`, which compresses with the given
+    /// preset dictionary.
+    ///
+    /// Its Adler-32 checksum is written to the header, and the same dictionary
+    /// is needed to decompress the data. It is discarded
+    /// by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the backend in use doesn't
+    /// support dictionaries.
+    pub fn new_with_dictionary(
+        w: W,
+        level: crate::Compression,
+        dictionary: &[u8],
+    ) -> io::Result<ZlibEncoder<W>> {
+        Ok(ZlibEncoder {
+            inner: zio::Writer::new(w, dictionary::compressor(level, true, dictionary)?),
+        })
+    }
+
     /// Acquires a reference to the underlying writer.
     pub fn get_ref(&self) -> &W {
         self.inner.get_ref()
@@ -154,6 +190,23 @@ impl<W: Write> ZlibEncoder<W> {
     pub fn total_out(&self) -> u64 {
         self.inner.data.total_out()
     }
+
+    /// Sets the preset dictionary to compress with, returning its Adler-32
+    /// checksum, which is written to the header as the dictionary identifier.
+    ///
+    /// The dictionary must be set before any data is compressed, and it is
+    /// discarded by `reset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if data has already been
+    /// compressed or the backend in use doesn't support dictionaries.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> io::Result<u32> {
+        self.inner
+            .data
+            .set_dictionary(dictionary)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
 }
 
 impl<W: Write> Write for ZlibEncoder<W> {
@@ -221,6 +274,8 @@ impl<W: AsyncRead + AsyncWrite> AsyncRead for ZlibEncoder<W> {}
 #[derive(Debug)]
 pub struct ZlibDecoder<W: Write> {
     inner: zio::Writer<W, Decompress>,
+    header: HeaderBytes,
+    dictionaries: DictionaryRegistry,
 }
 
 impl<W: Write> ZlibDecoder<W> {
@@ -231,6 +286,8 @@ impl<W: Write> ZlibDecoder<W> {
     pub fn new(w: W) -> ZlibDecoder<W> {
         ZlibDecoder {
             inner: zio::Writer::new(w, Decompress::new(true)),
+            header: HeaderBytes::default(),
+            dictionaries: DictionaryRegistry::new(),
         }
     }
 
@@ -262,6 +319,7 @@ impl<W: Write> ZlibDecoder<W> {
     pub fn reset(&mut self, w: W) -> io::Result<W> {
         self.inner.finish()?;
         self.inner.data = Decompress::new(true);
+        self.header = HeaderBytes::default();
         Ok(self.inner.replace(w))
     }
 
@@ -318,11 +376,54 @@ impl<W: Write> ZlibDecoder<W> {
     pub fn total_out(&self) -> u64 {
         self.inner.data.total_out()
     }
+
+    /// Returns the header of the zlib stream, once it has been read.
+    ///
+    /// The header is available as soon as decompression starts, even if it
+    /// then fails because a preset dictionary is needed.
+    pub fn header(&self) -> Option<&ZlibHeader> {
+        self.header.header()
+    }
+
+    /// Sets the preset dictionaries this decoder looks up the dictionary
+    /// identified in the header of a stream in, see [`DictionaryRegistry`].
+    ///
+    /// Without the dictionary a stream needs, decoding fails with an error
+    /// wrapping a [`DecompressError`] whose [`needs_dictionary`] returns
+    /// its Adler-32 checksum.
+    ///
+    /// [`DictionaryRegistry`]: ../struct.DictionaryRegistry.html
+    /// [`DecompressError`]: ../struct.DecompressError.html
+    /// [`needs_dictionary`]: ../struct.DecompressError.html#method.needs_dictionary
+    pub fn set_dictionaries(&mut self, dictionaries: DictionaryRegistry) {
+        self.dictionaries = dictionaries;
+    }
+
+    /// Sets limits on the data produced by this decoder, see
+    /// [`DecompressLimits`].
+    ///
+    /// [`DecompressLimits`]: ../struct.DecompressLimits.html
+    pub fn set_limits(&mut self, limits: DecompressLimits) {
+        self.inner.data.set_limits(limits);
+    }
 }
 
 impl<W: Write> Write for ZlibDecoder<W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner.write(buf)
+        loop {
+            let before = self.inner.data.total_in();
+            let res = self.inner.write(buf);
+            let consumed = (self.inner.data.total_in() - before) as usize;
+            self.header.push(&buf[..consumed]);
+            match res {
+                Err(ref e) if self.dictionaries.supply(&mut self.inner.data, e) => {
+                    if consumed > 0 {
+                        return Ok(consumed);
+                    }
+                }
+                res => return res,
+            }
+        }
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -0,0 +1,309 @@
+#![cfg(any(feature = "tokio1", feature = "futures-io"))]
+
+extern crate flate2;
+
+use flate2::LimitExceeded;
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::ptr;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+const DATA: &[u8] = b"hello hello hello hello hello hello hello hello";
+
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    unsafe { Waker::from_raw(RawWaker::new(ptr::null(), &VTABLE)) }
+}
+
+/// Polls `f` until it completes, which only terminates for streams which
+/// always make progress eventually.
+fn block_on<F: Future>(mut f: F) -> F::Output {
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut f = unsafe { Pin::new_unchecked(&mut f) };
+    loop {
+        if let Poll::Ready(out) = f.as_mut().poll(&mut cx) {
+            return out;
+        }
+    }
+}
+
+fn gzip() -> Vec<u8> {
+    let mut e = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    io::Write::write_all(&mut e, DATA).unwrap();
+    e.finish().unwrap()
+}
+
+fn assert_limit(err: io::Error, max: u64) {
+    let limit = err.get_ref().unwrap().downcast_ref::<LimitExceeded>();
+    assert_eq!(limit, Some(&LimitExceeded::Output(max)));
+}
+
+/// A stream which is pending on every other operation.
+struct Stutter<T> {
+    inner: T,
+    ready: bool,
+}
+
+impl<T> Stutter<T> {
+    fn new(inner: T) -> Stutter<T> {
+        Stutter {
+            inner,
+            ready: false,
+        }
+    }
+
+    fn poll<U>(&mut self, cx: &mut Context<'_>, f: impl FnOnce(&mut T) -> U) -> Poll<U> {
+        self.ready = !self.ready;
+        if self.ready {
+            Poll::Ready(f(&mut self.inner))
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(feature = "tokio1")]
+mod tokio1 {
+    use std::future::poll_fn;
+    use std::io;
+    use std::io::Write;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use flate2::tokio1::{bufread, read, write};
+    use flate2::{Compression, DecompressLimits};
+    use tokio1::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::{block_on, Stutter, DATA};
+
+    impl AsyncRead for Stutter<&[u8]> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.get_mut().poll(cx, |inner| {
+                let n = inner.len().min(buf.remaining());
+                buf.put_slice(&inner[..n]);
+                *inner = &inner[n..];
+                Ok(())
+            })
+        }
+    }
+
+    impl AsyncWrite for Stutter<Vec<u8>> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.get_mut().poll(cx, |inner| inner.write(buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.get_mut().poll(cx, |_| Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.get_mut().poll(cx, |_| Ok(()))
+        }
+    }
+
+    fn read_to_end<R: AsyncRead + Unpin>(mut r: R) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            let n = block_on(poll_fn(|cx| {
+                let mut buf = ReadBuf::new(&mut buf);
+                Pin::new(&mut r)
+                    .poll_read(cx, &mut buf)
+                    .map_ok(|()| buf.filled().len())
+            }))?;
+            if n == 0 {
+                return Ok(out);
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    fn write_all<W: AsyncWrite + Unpin>(w: &mut W, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let n = block_on(poll_fn(|cx| Pin::new(&mut *w).poll_write(cx, data)))?;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    fn shutdown<W: AsyncWrite + Unpin>(w: &mut W) -> io::Result<()> {
+        block_on(poll_fn(|cx| Pin::new(&mut *w).poll_shutdown(cx)))
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut w = write::GzEncoder::new(Stutter::new(Vec::new()), Compression::default());
+        write_all(&mut w, DATA).unwrap();
+        shutdown(&mut w).unwrap();
+        let gz = w.get_ref().inner.clone();
+
+        let r = read::GzDecoder::new(Stutter::new(&gz[..]));
+        assert_eq!(read_to_end(r).unwrap(), DATA);
+        let r = bufread::MultiGzDecoder::new(&gz[..]);
+        assert_eq!(read_to_end(r).unwrap(), DATA);
+
+        let mut w = write::AutoDecoder::new(Stutter::new(Vec::new()));
+        write_all(&mut w, &gz).unwrap();
+        shutdown(&mut w).unwrap();
+        assert_eq!(w.get_ref().inner, DATA);
+
+        let r = read::ZlibEncoder::new(Stutter::new(DATA), Compression::fast());
+        let zlib = read_to_end(r).unwrap();
+        let r = read::AutoDecoder::new(Stutter::new(&zlib[..]));
+        assert_eq!(read_to_end(r).unwrap(), DATA);
+    }
+
+    #[test]
+    fn drop_does_not_finish() {
+        let mut out = Vec::new();
+        {
+            let mut w = write::GzEncoder::new(&mut out, Compression::default());
+            write_all(&mut w, DATA).unwrap();
+        }
+        let r = read::GzDecoder::new(&out[..]);
+        assert!(read_to_end(r).is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let gz = super::gzip();
+        let mut r = read::GzDecoder::new(Stutter::new(&gz[..]));
+        r.set_limits(DecompressLimits::new().max_output(10));
+        super::assert_limit(read_to_end(r).unwrap_err(), 10);
+
+        let mut w = write::GzDecoder::new(Stutter::new(Vec::new()));
+        w.set_limits(DecompressLimits::new().max_output(10));
+        let err = write_all(&mut w, &gz)
+            .and_then(|()| shutdown(&mut w))
+            .unwrap_err();
+        super::assert_limit(err, 10);
+    }
+}
+
+#[cfg(feature = "futures-io")]
+mod futures_io {
+    use std::future::poll_fn;
+    use std::io;
+    use std::io::Write;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use flate2::futures_io::{bufread, read, write};
+    use flate2::{Compression, DecompressLimits};
+    use futures_io::{AsyncRead, AsyncWrite};
+
+    use super::{block_on, Stutter, DATA};
+
+    impl AsyncRead for Stutter<&[u8]> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.get_mut().poll(cx, |inner| io::Read::read(inner, buf))
+        }
+    }
+
+    impl AsyncWrite for Stutter<Vec<u8>> {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.get_mut().poll(cx, |inner| inner.write(buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.get_mut().poll(cx, |_| Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.get_mut().poll(cx, |_| Ok(()))
+        }
+    }
+
+    fn read_to_end<R: AsyncRead + Unpin>(mut r: R) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut buf = [0; 7];
+        loop {
+            let n = block_on(poll_fn(|cx| Pin::new(&mut r).poll_read(cx, &mut buf)))?;
+            if n == 0 {
+                return Ok(out);
+            }
+            out.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    fn write_all<W: AsyncWrite + Unpin>(w: &mut W, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            let n = block_on(poll_fn(|cx| Pin::new(&mut *w).poll_write(cx, data)))?;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    fn close<W: AsyncWrite + Unpin>(w: &mut W) -> io::Result<()> {
+        block_on(poll_fn(|cx| Pin::new(&mut *w).poll_close(cx)))
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut w = write::ZlibEncoder::new(Stutter::new(Vec::new()), Compression::default());
+        write_all(&mut w, DATA).unwrap();
+        close(&mut w).unwrap();
+        let zlib = w.get_ref().inner.clone();
+
+        let r = read::ZlibDecoder::new(Stutter::new(&zlib[..]));
+        assert_eq!(read_to_end(r).unwrap(), DATA);
+        let r = bufread::AutoDecoder::new(&zlib[..]);
+        assert_eq!(read_to_end(r).unwrap(), DATA);
+
+        let r = read::GzEncoder::new(Stutter::new(DATA), Compression::fast());
+        let gz = read_to_end(r).unwrap();
+        let mut w = write::GzDecoder::new(Stutter::new(Vec::new()));
+        write_all(&mut w, &gz).unwrap();
+        close(&mut w).unwrap();
+        assert_eq!(w.get_ref().inner, DATA);
+    }
+
+    #[test]
+    fn drop_does_not_finish() {
+        let mut out = Vec::new();
+        {
+            let mut w = write::DeflateEncoder::new(&mut out, Compression::default());
+            write_all(&mut w, DATA).unwrap();
+        }
+        let r = read::DeflateDecoder::new(&out[..]);
+        assert!(read_to_end(r).is_err());
+    }
+    #[test]
+    fn limits() {
+        let gz = super::gzip();
+        let mut r = bufread::MultiGzDecoder::new(&gz[..]);
+        r.set_limits(DecompressLimits::new().max_output(10));
+        super::assert_limit(read_to_end(r).unwrap_err(), 10);
+
+        let mut w = write::AutoDecoder::new(Stutter::new(Vec::new()));
+        w.set_limits(DecompressLimits::new().max_output(10));
+        let err = write_all(&mut w, &gz)
+            .and_then(|()| close(&mut w))
+            .unwrap_err();
+        super::assert_limit(err, 10);
+    }
+}
@@ -75,3 +75,174 @@ fn empty_error_once() {
     assert!(stream.next().unwrap().is_err());
     assert!(stream.next().is_none());
 }
+
+// test iterating over the members of a multistream gzipped file
+#[test]
+fn test_multi_members() {
+    let mut compressed = Vec::new();
+    File::open("tests/multi.gz")
+        .unwrap()
+        .read_to_end(&mut compressed)
+        .unwrap();
+    let mut expected = String::new();
+    File::open("tests/multi.txt")
+        .unwrap()
+        .read_to_string(&mut expected)
+        .unwrap();
+
+    let mut d = MultiGzDecoder::new(&compressed[..]);
+    let mut lines = Vec::new();
+    let mut end = 0;
+    while let Some(mut member) = d.next_member().unwrap() {
+        assert_eq!(member.start(), end);
+        assert!(member.info().is_none());
+        let mut line = String::new();
+        member.read_to_string(&mut line).unwrap();
+        let info = *member.info().unwrap();
+        assert_eq!(info.start(), end);
+        assert_eq!(info.size() as usize, line.len());
+        let mut crc = flate2::Crc::new();
+        crc.update(line.as_bytes());
+        assert_eq!(info.crc(), crc.sum());
+        end = info.end();
+        lines.push(line);
+    }
+    assert_eq!(end, compressed.len() as u64);
+    assert_eq!(lines.concat(), expected);
+    assert!(lines.len() > 1);
+    assert!(d.next_member().unwrap().is_none());
+}
+
+// test skipping over the rest of members which aren't read completely
+#[test]
+fn test_multi_members_skip() {
+    let mut compressed = Vec::new();
+    File::open("tests/multi.gz")
+        .unwrap()
+        .read_to_end(&mut compressed)
+        .unwrap();
+    let mut expected = Vec::new();
+    BufReader::new(File::open("tests/multi.txt").unwrap())
+        .read_until(b'\n', &mut expected)
+        .unwrap();
+
+    let mut d = flate2::bufread::MultiGzDecoder::new(&compressed[..]);
+    let mut firsts = Vec::new();
+    while let Some(mut member) = d.next_member().unwrap() {
+        let mut byte = [0];
+        member.read_exact(&mut byte).unwrap();
+        firsts.push(byte[0]);
+    }
+    assert_eq!(firsts[0], expected[0]);
+    assert!(firsts.len() > 1);
+
+    // Corrupting the last member's trailer is noticed even when skipping it.
+    let last = compressed.len() - 8;
+    compressed[last] ^= 1;
+    let mut d = MultiGzDecoder::new(&compressed[..]);
+    let mut result = Ok(());
+    loop {
+        match d.next_member() {
+            Ok(Some(_)) => {}
+            Ok(None) => break,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+    assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut e = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    e.write_all(data).unwrap();
+    e.finish().unwrap()
+}
+
+fn trailing_garbage_at(err: io::Error) -> u64 {
+    let err = err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<flate2::DecompressError>())
+        .unwrap();
+    assert_eq!(err.kind(), flate2::DecompressErrorKind::TrailingGarbage);
+    err.offset().unwrap()
+}
+
+// test the trailing data policies of the single member decoders
+#[test]
+fn test_trailing_data() {
+    use flate2::{GzDecoderBuilder, TrailingData};
+
+    let member = gzip(b"hello");
+    let mut stream = member.clone();
+    stream.extend_from_slice(b"rest of the file");
+
+    // The default stops right after the member.
+    let mut input = &stream[..];
+    let mut d = flate2::bufread::GzDecoder::new(&mut input);
+    let mut s = String::new();
+    d.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "hello");
+    assert_eq!(d.total_in(), member.len() as u64);
+    assert_eq!(input, b"rest of the file");
+
+    let mut d = GzDecoder::new(&stream[..]);
+    d.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(d.total_in(), member.len() as u64);
+
+    let mut d = GzDecoderBuilder::new()
+        .trailing_data(TrailingData::Error)
+        .read(&stream[..]);
+    let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(trailing_garbage_at(err), member.len() as u64);
+
+    let mut padded = member.clone();
+    padded.extend_from_slice(&[0; 100]);
+    let mut d = GzDecoderBuilder::new()
+        .trailing_data(TrailingData::IgnoreZeros)
+        .read(&padded[..]);
+    d.read_to_end(&mut Vec::new()).unwrap();
+    assert_eq!(d.total_in(), padded.len() as u64);
+
+    padded.push(1);
+    let mut d = GzDecoderBuilder::new()
+        .trailing_data(TrailingData::IgnoreZeros)
+        .read(&padded[..]);
+    let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(trailing_garbage_at(err), padded.len() as u64 - 1);
+}
+
+// test the trailing data policies of the multi member decoders
+#[test]
+fn test_trailing_data_multi() {
+    use flate2::{GzDecoderBuilder, TrailingData};
+
+    let mut stream = gzip(b"one");
+    stream.extend(gzip(b"two"));
+    let len = stream.len();
+    stream.extend_from_slice(&[0; 512]);
+
+    // Zero padding isn't a member.
+    let mut d = MultiGzDecoder::new(&stream[..]);
+    let err = d.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(trailing_garbage_at(err), len as u64);
+
+    let mut d = GzDecoderBuilder::new()
+        .trailing_data(TrailingData::IgnoreZeros)
+        .read_multi(&stream[..]);
+    let mut s = String::new();
+    d.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "onetwo");
+    assert_eq!(d.total_in(), stream.len() as u64);
+
+    let mut input = &stream[..];
+    let mut d = GzDecoderBuilder::new()
+        .trailing_data(TrailingData::Stop)
+        .buf_read_multi(&mut input);
+    let mut s = String::new();
+    d.read_to_string(&mut s).unwrap();
+    assert_eq!(s, "onetwo");
+    assert_eq!(d.total_in(), len as u64);
+    assert_eq!(input, &[0; 512][..]);
+}
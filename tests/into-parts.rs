@@ -0,0 +1,94 @@
+extern crate flate2;
+
+use std::io::prelude::*;
+use std::io::Cursor;
+
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+use flate2::Compression;
+
+fn sample() -> Vec<u8> {
+    (0..100_000u32)
+        .map(|i| (i % 253) as u8 ^ (i >> 9) as u8)
+        .collect()
+}
+
+fn trailer() -> Vec<u8> {
+    (0..70_000u32).map(|i| (i * 7) as u8).collect()
+}
+
+#[test]
+fn leftover_bytes() {
+    let data = sample();
+
+    let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+    e.write_all(&data).unwrap();
+    let mut zlib = e.finish().unwrap();
+    let len = zlib.len();
+    zlib.extend(trailer());
+
+    let mut d = ZlibDecoder::new(Cursor::new(&zlib[..]));
+    let mut out = Vec::new();
+    d.read_to_end(&mut out).unwrap();
+    assert_eq!(out, data);
+    assert_eq!(d.total_in(), len as u64);
+    let (mut rest, mut leftover) = d.into_parts();
+    rest.read_to_end(&mut leftover).unwrap();
+    assert_eq!(leftover, trailer());
+
+    let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+    e.write_all(&data).unwrap();
+    let mut deflate = e.finish().unwrap();
+    deflate.extend(trailer());
+
+    let mut d = DeflateDecoder::new(Cursor::new(&deflate[..]));
+    let mut out = Vec::new();
+    d.read_to_end(&mut out).unwrap();
+    assert_eq!(out, data);
+    let (mut rest, mut leftover) = d.into_parts();
+    rest.read_to_end(&mut leftover).unwrap();
+    assert_eq!(leftover, trailer());
+
+    let mut e = GzEncoder::new(Vec::new(), Compression::default());
+    e.write_all(&data).unwrap();
+    let mut gz = e.finish().unwrap();
+    gz.extend(trailer());
+
+    let mut d = GzDecoder::new(Cursor::new(&gz[..]));
+    let mut out = Vec::new();
+    d.read_to_end(&mut out).unwrap();
+    assert_eq!(out, data);
+    let (mut rest, mut leftover) = d.into_parts();
+    rest.read_to_end(&mut leftover).unwrap();
+    assert_eq!(leftover, trailer());
+}
+
+#[test]
+fn stop_at_stream_end() {
+    let data = sample();
+
+    let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+    e.write_all(&data).unwrap();
+    let mut zlib = e.finish().unwrap();
+    let len = zlib.len() as u64;
+    zlib.extend(trailer());
+
+    let mut d = ZlibDecoder::new_with_buf(Cursor::new(&zlib[..]), vec![0; 1]);
+    let mut out = Vec::new();
+    d.read_to_end(&mut out).unwrap();
+    assert_eq!(out, data);
+    assert_eq!(d.into_inner().position(), len);
+
+    let mut e = GzEncoder::new(Vec::new(), Compression::default());
+    e.write_all(&data).unwrap();
+    let mut gz = e.finish().unwrap();
+    let len = gz.len() as u64;
+    gz.extend(trailer());
+
+    let mut d = GzDecoder::new_with_buf(Cursor::new(&gz[..]), vec![0; 1]);
+    let mut out = Vec::new();
+    d.read_to_end(&mut out).unwrap();
+    assert_eq!(out, data);
+    assert_eq!(d.total_in(), len);
+    assert_eq!(d.into_inner().position(), len);
+}